@@ -0,0 +1,84 @@
+-- Append-only double-entry ledger. Every entry's lines sum to zero
+-- (debits positive, credits negative) and a card's balance equals the
+-- credit balance of its card_liability lines.
+CREATE TABLE ledger_entries (
+    id CHAR(36) PRIMARY KEY,
+    kind VARCHAR(20) NOT NULL,
+    description VARCHAR(255) NOT NULL,
+    transaction_id CHAR(36) NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (transaction_id) REFERENCES gift_card_transactions(id),
+    CONSTRAINT chk_ledger_entries_kind CHECK (
+        kind IN ('issuance', 'redemption', 'refund', 'breakage', 'adjustment')
+    )
+);
+
+CREATE TABLE ledger_lines (
+    entry_id CHAR(36) NOT NULL,
+    line_no SMALLINT NOT NULL,
+    account VARCHAR(40) NOT NULL,
+    gift_card_id CHAR(36) NULL,
+    amount INT NOT NULL CHECK (amount <> 0),
+    PRIMARY KEY (entry_id, line_no),
+    FOREIGN KEY (entry_id) REFERENCES ledger_entries(id),
+    FOREIGN KEY (gift_card_id) REFERENCES gift_cards(id),
+    CONSTRAINT chk_ledger_lines_account CHECK (
+        account IN ('card_liability', 'issuer_funding', 'merchant_settlement', 'breakage_income', 'adjustments')
+    ),
+    CONSTRAINT chk_ledger_lines_card CHECK ((account = 'card_liability') = (gift_card_id IS NOT NULL))
+);
+
+-- Create index for per-card balance and history lookups
+CREATE INDEX idx_ledger_lines_gift_card_id ON ledger_lines(gift_card_id, account);
+
+-- Entries and lines can be appended but never changed or removed.
+-- MySQL cannot defer a balance check to commit, so the application
+-- verifies each entry before writing it.
+CREATE TRIGGER ledger_entries_no_update BEFORE UPDATE ON ledger_entries
+FOR EACH ROW SIGNAL SQLSTATE '45000' SET MESSAGE_TEXT = 'ledger_entries is append-only';
+
+CREATE TRIGGER ledger_entries_no_delete BEFORE DELETE ON ledger_entries
+FOR EACH ROW SIGNAL SQLSTATE '45000' SET MESSAGE_TEXT = 'ledger_entries is append-only';
+
+CREATE TRIGGER ledger_lines_no_update BEFORE UPDATE ON ledger_lines
+FOR EACH ROW SIGNAL SQLSTATE '45000' SET MESSAGE_TEXT = 'ledger_lines is append-only';
+
+CREATE TRIGGER ledger_lines_no_delete BEFORE DELETE ON ledger_lines
+FOR EACH ROW SIGNAL SQLSTATE '45000' SET MESSAGE_TEXT = 'ledger_lines is append-only';
+
+-- Open the ledger for existing cards: their issued value, and whatever
+-- was spent before the ledger existed as one aggregate redemption.
+-- Entry IDs are derived from the card ID so the lines can refer to them.
+INSERT INTO ledger_entries (id, kind, description, created_at)
+SELECT INSERT(INSERT(INSERT(INSERT(MD5(CONCAT(id, ':issuance')), 21, 0, '-'), 17, 0, '-'), 13, 0, '-'), 9, 0, '-'),
+       'issuance', CONCAT('Issued by ', issuer_name), created_at
+FROM gift_cards
+WHERE initial_balance > 0;
+
+INSERT INTO ledger_lines (entry_id, line_no, account, gift_card_id, amount)
+SELECT INSERT(INSERT(INSERT(INSERT(MD5(CONCAT(id, ':issuance')), 21, 0, '-'), 17, 0, '-'), 13, 0, '-'), 9, 0, '-'),
+       0, 'issuer_funding', NULL, initial_balance
+FROM gift_cards
+WHERE initial_balance > 0
+UNION ALL
+SELECT INSERT(INSERT(INSERT(INSERT(MD5(CONCAT(id, ':issuance')), 21, 0, '-'), 17, 0, '-'), 13, 0, '-'), 9, 0, '-'),
+       1, 'card_liability', id, -initial_balance
+FROM gift_cards
+WHERE initial_balance > 0;
+
+INSERT INTO ledger_entries (id, kind, description, created_at)
+SELECT INSERT(INSERT(INSERT(INSERT(MD5(CONCAT(id, ':redemption')), 21, 0, '-'), 17, 0, '-'), 13, 0, '-'), 9, 0, '-'),
+       'redemption', 'Redeemed before the ledger was introduced', updated_at
+FROM gift_cards
+WHERE initial_balance > balance;
+
+INSERT INTO ledger_lines (entry_id, line_no, account, gift_card_id, amount)
+SELECT INSERT(INSERT(INSERT(INSERT(MD5(CONCAT(id, ':redemption')), 21, 0, '-'), 17, 0, '-'), 13, 0, '-'), 9, 0, '-'),
+       0, 'card_liability', id, initial_balance - balance
+FROM gift_cards
+WHERE initial_balance > balance
+UNION ALL
+SELECT INSERT(INSERT(INSERT(INSERT(MD5(CONCAT(id, ':redemption')), 21, 0, '-'), 17, 0, '-'), 13, 0, '-'), 9, 0, '-'),
+       1, 'merchant_settlement', NULL, balance - initial_balance
+FROM gift_cards
+WHERE initial_balance > balance;
@@ -0,0 +1,22 @@
+-- Amounts become BIGINT minor units, and each card, transaction and
+-- journal entry records its ISO-4217 currency. Existing amounts were
+-- US cents.
+ALTER TABLE gift_cards
+    ADD COLUMN currency CHAR(3) NOT NULL DEFAULT 'USD' AFTER initial_balance,
+    MODIFY COLUMN balance BIGINT NOT NULL,
+    MODIFY COLUMN initial_balance BIGINT NOT NULL;
+
+ALTER TABLE gift_card_transactions
+    ADD COLUMN currency CHAR(3) NOT NULL DEFAULT 'USD' AFTER amount,
+    MODIFY COLUMN amount BIGINT NOT NULL;
+
+ALTER TABLE ledger_entries
+    ADD COLUMN currency CHAR(3) NOT NULL DEFAULT 'USD' AFTER description;
+
+ALTER TABLE ledger_lines
+    MODIFY COLUMN amount BIGINT NOT NULL;
+
+-- New rows must name their currency
+ALTER TABLE gift_cards ALTER COLUMN currency DROP DEFAULT;
+ALTER TABLE gift_card_transactions ALTER COLUMN currency DROP DEFAULT;
+ALTER TABLE ledger_entries ALTER COLUMN currency DROP DEFAULT;
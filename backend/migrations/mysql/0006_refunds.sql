@@ -0,0 +1,17 @@
+-- Transactions are now either redemptions or refunds; a refund points at
+-- the redemption it reverses. Existing transactions are all redemptions.
+ALTER TABLE gift_card_transactions
+    ADD COLUMN kind VARCHAR(20) NOT NULL DEFAULT 'redemption' AFTER gift_card_id,
+    ADD COLUMN original_transaction_id CHAR(36) NULL AFTER merchant,
+    ADD CONSTRAINT fk_gift_card_transactions_original
+        FOREIGN KEY (original_transaction_id) REFERENCES gift_card_transactions(id),
+    ADD CONSTRAINT chk_gift_card_transactions_kind CHECK (
+        (kind = 'redemption' AND original_transaction_id IS NULL)
+        OR (kind = 'refund' AND original_transaction_id IS NOT NULL)
+    );
+
+ALTER TABLE gift_card_transactions ALTER COLUMN kind DROP DEFAULT;
+
+-- Create index on original_transaction_id for summing refunds
+CREATE INDEX idx_gift_card_transactions_original_transaction_id
+    ON gift_card_transactions(original_transaction_id);
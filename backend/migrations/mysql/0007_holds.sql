@@ -0,0 +1,23 @@
+-- Authorization holds reserve part of a card's balance until they are
+-- captured into a redemption, voided, or pass expires_at
+CREATE TABLE holds (
+    id CHAR(36) PRIMARY KEY,
+    gift_card_id CHAR(36) NOT NULL,
+    amount BIGINT NOT NULL CHECK (amount > 0),
+    captured_amount BIGINT NULL,
+    currency CHAR(3) NOT NULL,
+    merchant VARCHAR(100) NOT NULL,
+    status VARCHAR(20) NOT NULL,
+    transaction_id CHAR(36) NULL,
+    expires_at DATETIME NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (gift_card_id) REFERENCES gift_cards(id) ON DELETE CASCADE,
+    FOREIGN KEY (transaction_id) REFERENCES gift_card_transactions(id),
+    CONSTRAINT chk_holds_captured_amount CHECK (captured_amount > 0 AND captured_amount <= amount),
+    CONSTRAINT chk_holds_status CHECK (status IN ('active', 'captured', 'voided', 'expired')),
+    CONSTRAINT chk_holds_capture CHECK ((status = 'captured') = (transaction_id IS NOT NULL))
+);
+
+-- Create index for summing the open holds on a card
+CREATE INDEX idx_holds_gift_card_id_status ON holds(gift_card_id, status);
@@ -0,0 +1,43 @@
+-- Merchants become a table of their own, referenced by the transactions
+-- and holds that used to carry only a merchant name
+CREATE TABLE merchants (
+    id CHAR(36) PRIMARY KEY,
+    name VARCHAR(100) NOT NULL,
+    contact_email VARCHAR(255) NULL,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create index on name for listing merchants
+CREATE INDEX idx_merchants_name ON merchants(name);
+
+-- Every merchant name already on record becomes an inactive merchant, with
+-- an ID derived from the name; activate them to take new payments
+INSERT INTO merchants (id, name, active)
+SELECT INSERT(INSERT(INSERT(INSERT(MD5(CONCAT('merchant:', merchant)), 21, 0, '-'), 17, 0, '-'), 13, 0, '-'), 9, 0, '-'),
+       merchant, FALSE
+FROM (
+    SELECT merchant FROM gift_card_transactions
+    UNION
+    SELECT merchant FROM holds
+) names;
+
+ALTER TABLE gift_card_transactions ADD COLUMN merchant_id CHAR(36) NULL AFTER amount;
+ALTER TABLE holds ADD COLUMN merchant_id CHAR(36) NULL AFTER captured_amount;
+
+UPDATE gift_card_transactions
+SET merchant_id = INSERT(INSERT(INSERT(INSERT(MD5(CONCAT('merchant:', merchant)), 21, 0, '-'), 17, 0, '-'), 13, 0, '-'), 9, 0, '-');
+UPDATE holds
+SET merchant_id = INSERT(INSERT(INSERT(INSERT(MD5(CONCAT('merchant:', merchant)), 21, 0, '-'), 17, 0, '-'), 13, 0, '-'), 9, 0, '-');
+
+ALTER TABLE gift_card_transactions
+    MODIFY COLUMN merchant_id CHAR(36) NOT NULL,
+    ADD CONSTRAINT fk_gift_card_transactions_merchant FOREIGN KEY (merchant_id) REFERENCES merchants(id);
+ALTER TABLE holds
+    MODIFY COLUMN merchant_id CHAR(36) NOT NULL,
+    ADD CONSTRAINT fk_holds_merchant FOREIGN KEY (merchant_id) REFERENCES merchants(id);
+
+-- Create index on merchant_id for per-merchant history
+CREATE INDEX idx_gift_card_transactions_merchant_id
+    ON gift_card_transactions(merchant_id, transaction_date);
@@ -0,0 +1,19 @@
+-- Issuer accounts become accounts with a role: admin, issuer, merchant or
+-- recipient. Existing accounts are issuers.
+RENAME TABLE issuer_accounts TO accounts;
+ALTER TABLE accounts RENAME INDEX idx_issuer_accounts_email TO idx_accounts_email;
+
+ALTER TABLE accounts ADD COLUMN role VARCHAR(20) NOT NULL DEFAULT 'issuer' AFTER name;
+ALTER TABLE accounts ALTER COLUMN role DROP DEFAULT;
+
+-- Merchant accounts act for one merchant, recipient accounts for one phone number
+ALTER TABLE accounts
+    ADD COLUMN merchant_id CHAR(36) NULL AFTER role,
+    ADD COLUMN phone VARCHAR(20) NULL AFTER merchant_id,
+    ADD CONSTRAINT fk_accounts_merchant FOREIGN KEY (merchant_id) REFERENCES merchants(id);
+
+ALTER TABLE accounts ADD CONSTRAINT chk_accounts_role CHECK (
+    role IN ('admin', 'issuer', 'merchant', 'recipient')
+    AND (role = 'merchant') = (merchant_id IS NOT NULL)
+    AND (role = 'recipient') = (phone IS NOT NULL)
+);
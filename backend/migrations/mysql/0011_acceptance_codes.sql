@@ -0,0 +1,15 @@
+-- One-time passcodes sent to a card's recipient phone to confirm acceptance;
+-- only a hash of each code is stored
+CREATE TABLE acceptance_codes (
+    id CHAR(36) PRIMARY KEY,
+    gift_card_id CHAR(36) NOT NULL,
+    code_hash VARCHAR(255) NOT NULL,
+    attempts INT NOT NULL DEFAULT 0 CHECK (attempts >= 0),
+    expires_at DATETIME(6) NOT NULL,
+    used_at DATETIME(6) NULL,
+    created_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6),
+    FOREIGN KEY (gift_card_id) REFERENCES gift_cards(id) ON DELETE CASCADE
+);
+
+-- Create index for finding the latest code sent for a card
+CREATE INDEX idx_acceptance_codes_gift_card_id ON acceptance_codes(gift_card_id, created_at);
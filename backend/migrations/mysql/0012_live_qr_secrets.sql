@@ -0,0 +1,8 @@
+-- Per-card secrets behind rotating live QR codes; a card with a row here only
+-- accepts its live code when scanned
+CREATE TABLE live_qr_secrets (
+    gift_card_id CHAR(36) PRIMARY KEY,
+    secret VARCHAR(64) NOT NULL,
+    created_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6),
+    FOREIGN KEY (gift_card_id) REFERENCES gift_cards(id) ON DELETE CASCADE
+);
@@ -0,0 +1,11 @@
+-- Short codes typed in when a card's QR code will not scan, each with an
+-- optional PIN; wrong PINs are counted and lock the code out for a while
+CREATE TABLE card_codes (
+    gift_card_id CHAR(36) PRIMARY KEY,
+    code VARCHAR(16) NOT NULL UNIQUE,
+    pin_hash VARCHAR(255),
+    pin_attempts INT NOT NULL DEFAULT 0,
+    locked_until DATETIME(6),
+    created_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6),
+    FOREIGN KEY (gift_card_id) REFERENCES gift_cards(id) ON DELETE CASCADE
+);
@@ -0,0 +1,16 @@
+-- Batches of gift cards issued from one upload; the per-row results are
+-- kept as a JSON array so they can be read back while the batch runs
+CREATE TABLE issuance_batches (
+    id CHAR(36) PRIMARY KEY,
+    issuer_id CHAR(36) NOT NULL,
+    mode VARCHAR(20) NOT NULL,
+    status VARCHAR(20) NOT NULL,
+    total_rows INT NOT NULL,
+    issued INT NOT NULL DEFAULT 0,
+    failed INT NOT NULL DEFAULT 0,
+    results MEDIUMTEXT NOT NULL,
+    error TEXT NULL,
+    created_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6),
+    completed_at DATETIME(6) NULL,
+    FOREIGN KEY (issuer_id) REFERENCES accounts(id)
+);
@@ -0,0 +1,4 @@
+-- Exports page through cards and transactions in time order, with the ID
+-- breaking ties
+CREATE INDEX idx_gift_cards_created_at_id ON gift_cards(created_at, id);
+CREATE INDEX idx_gift_card_transactions_date_id ON gift_card_transactions(transaction_date, id);
@@ -0,0 +1,16 @@
+-- Expired cards record the balance they lose as an `expiry` transaction,
+-- which no merchant took and which reverses nothing
+ALTER TABLE gift_card_transactions
+    MODIFY COLUMN merchant_id CHAR(36) NULL,
+    MODIFY COLUMN merchant VARCHAR(100) NULL,
+    DROP CHECK chk_gift_card_transactions_kind;
+
+ALTER TABLE gift_card_transactions
+    ADD CONSTRAINT chk_gift_card_transactions_kind CHECK (
+        (kind = 'redemption' AND original_transaction_id IS NULL AND merchant_id IS NOT NULL)
+        OR (kind = 'refund' AND original_transaction_id IS NOT NULL AND merchant_id IS NOT NULL)
+        OR (kind = 'expiry' AND original_transaction_id IS NULL AND merchant_id IS NULL)
+    );
+
+-- The expiry sweeper pages through cards past their expiration date
+CREATE INDEX idx_gift_cards_expiration_date_id ON gift_cards(expiration_date, id);
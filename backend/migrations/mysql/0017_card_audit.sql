@@ -0,0 +1,48 @@
+-- Support staff can extend a card's expiration date or reactivate an expired
+-- card, optionally for a fee. Reactivation gives back the balance written
+-- off at expiry, and fees are booked as income.
+ALTER TABLE gift_card_transactions DROP CHECK chk_gift_card_transactions_kind;
+
+ALTER TABLE gift_card_transactions
+    ADD CONSTRAINT chk_gift_card_transactions_kind CHECK (
+        (kind = 'redemption' AND original_transaction_id IS NULL AND merchant_id IS NOT NULL)
+        OR (kind = 'refund' AND original_transaction_id IS NOT NULL AND merchant_id IS NOT NULL)
+        OR (kind IN ('expiry', 'reactivation', 'fee') AND original_transaction_id IS NULL AND merchant_id IS NULL)
+    );
+
+ALTER TABLE ledger_entries DROP CHECK chk_ledger_entries_kind;
+
+ALTER TABLE ledger_entries
+    ADD CONSTRAINT chk_ledger_entries_kind CHECK (
+        kind IN ('issuance', 'redemption', 'refund', 'breakage', 'adjustment', 'reactivation', 'fee')
+    );
+
+ALTER TABLE ledger_lines DROP CHECK chk_ledger_lines_account;
+
+ALTER TABLE ledger_lines
+    ADD CONSTRAINT chk_ledger_lines_account CHECK (
+        account IN ('card_liability', 'issuer_funding', 'merchant_settlement', 'breakage_income', 'adjustments', 'fee_income')
+    );
+
+-- Who changed a card by hand, why, and what changed; `currency` is that of
+-- the restored amount and fee, when either is set
+CREATE TABLE card_audit_log (
+    id CHAR(36) PRIMARY KEY,
+    gift_card_id CHAR(36) NOT NULL,
+    account_id CHAR(36) NOT NULL,
+    action VARCHAR(20) NOT NULL,
+    reason VARCHAR(255) NOT NULL,
+    previous_status VARCHAR(20) NOT NULL,
+    status VARCHAR(20) NOT NULL,
+    previous_expiration_date DATETIME NOT NULL,
+    expiration_date DATETIME NOT NULL,
+    currency CHAR(3) NULL,
+    restored_amount BIGINT NULL,
+    fee_amount BIGINT NULL,
+    created_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6),
+    FOREIGN KEY (gift_card_id) REFERENCES gift_cards(id),
+    FOREIGN KEY (account_id) REFERENCES accounts(id)
+);
+
+-- Create index on gift_card_id for a card's audit history
+CREATE INDEX idx_card_audit_log_gift_card_id ON card_audit_log(gift_card_id, created_at);
@@ -0,0 +1,16 @@
+-- Support can replace a card whose ID or QR code has leaked. The old card is
+-- marked replaced for good and its balance moves to a successor card, which
+-- points back at the card it replaced.
+ALTER TABLE gift_cards
+    ADD COLUMN replaces_id CHAR(36) NULL AFTER accepted_at,
+    ADD CONSTRAINT fk_gift_cards_replaces_id FOREIGN KEY (replaces_id) REFERENCES gift_cards(id);
+
+-- A card is replaced at most once
+CREATE UNIQUE INDEX idx_gift_cards_replaces_id ON gift_cards(replaces_id);
+
+ALTER TABLE ledger_entries DROP CHECK chk_ledger_entries_kind;
+
+ALTER TABLE ledger_entries
+    ADD CONSTRAINT chk_ledger_entries_kind CHECK (
+        kind IN ('issuance', 'redemption', 'refund', 'breakage', 'adjustment', 'reactivation', 'fee', 'transfer', 'replacement')
+    );
@@ -0,0 +1,8 @@
+-- Remember the state a card expired from, so reactivating a card that was
+-- suspended when it expired leaves it suspended. Cards that expired before
+-- this was recorded resume as if they had not been suspended.
+ALTER TABLE gift_cards
+    ADD COLUMN status_before_expiry VARCHAR(20) NULL AFTER status,
+    ADD CONSTRAINT chk_gift_cards_status_before_expiry CHECK (
+        status_before_expiry IN ('issued', 'accepted', 'suspended')
+    );
@@ -0,0 +1,38 @@
+-- Cards made by a transfer or a replacement are new rows, but the lifetime
+-- limit on extensions runs from when the first card in the line was issued.
+-- Existing cards are traced back through `replaces_id` and their transfer_in
+-- transactions to find it.
+ALTER TABLE gift_cards ADD COLUMN first_issued_at DATETIME NULL AFTER replaces_id;
+
+UPDATE gift_cards SET first_issued_at = created_at;
+
+-- MySQL cannot update a table it reads in the same statement, so the lineage
+-- is worked out first
+CREATE TEMPORARY TABLE card_lineage AS
+WITH RECURSIVE parents (card_id, parent_id) AS (
+    SELECT id, replaces_id FROM gift_cards WHERE replaces_id IS NOT NULL
+    UNION
+    SELECT received.gift_card_id, sent.gift_card_id
+    FROM gift_card_transactions received
+    JOIN gift_card_transactions sent ON sent.id = received.original_transaction_id
+    WHERE received.kind = 'transfer_in'
+),
+lineage (card_id, first_issued_at) AS (
+    SELECT cards.id, cards.created_at
+    FROM gift_cards cards
+    LEFT JOIN parents ON parents.card_id = cards.id
+    WHERE parents.card_id IS NULL
+    UNION ALL
+    SELECT parents.card_id, lineage.first_issued_at
+    FROM lineage
+    JOIN parents ON parents.parent_id = lineage.card_id
+)
+SELECT card_id, first_issued_at FROM lineage;
+
+UPDATE gift_cards
+JOIN card_lineage ON card_lineage.card_id = gift_cards.id
+SET gift_cards.first_issued_at = card_lineage.first_issued_at;
+
+DROP TEMPORARY TABLE card_lineage;
+
+ALTER TABLE gift_cards MODIFY COLUMN first_issued_at DATETIME NOT NULL;
@@ -0,0 +1,26 @@
+-- Replace the is_accepted/is_active flags with an explicit lifecycle status
+ALTER TABLE gift_cards
+    ADD COLUMN status VARCHAR(20) NOT NULL DEFAULT 'issued',
+    ADD COLUMN accepted_at TIMESTAMP WITH TIME ZONE;
+
+-- Backfill from the old flags; inactive cards with money left had no
+-- recorded reason, so they are treated as suspended for review
+UPDATE gift_cards SET
+    status = CASE
+        WHEN is_active = FALSE AND balance = 0 THEN 'depleted'
+        WHEN is_active = FALSE THEN 'suspended'
+        WHEN expiration_date < CURRENT_TIMESTAMP THEN 'expired'
+        WHEN is_accepted THEN 'accepted'
+        ELSE 'issued'
+    END,
+    accepted_at = CASE WHEN is_accepted THEN updated_at END;
+
+ALTER TABLE gift_cards
+    DROP COLUMN is_accepted,
+    DROP COLUMN is_active,
+    ADD CONSTRAINT chk_gift_cards_status CHECK (
+        status IN ('issued', 'accepted', 'depleted', 'expired', 'suspended', 'cancelled', 'replaced')
+    );
+
+-- Create index on status for lifecycle sweeps and reporting
+CREATE INDEX idx_gift_cards_status ON gift_cards(status);
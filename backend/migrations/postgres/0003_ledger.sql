@@ -0,0 +1,95 @@
+-- Append-only double-entry ledger. Every entry's lines sum to zero
+-- (debits positive, credits negative) and a card's balance equals the
+-- credit balance of its card_liability lines.
+CREATE TABLE ledger_entries (
+    id UUID PRIMARY KEY,
+    kind VARCHAR(20) NOT NULL,
+    description VARCHAR(255) NOT NULL,
+    transaction_id UUID REFERENCES gift_card_transactions(id),
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT chk_ledger_entries_kind CHECK (
+        kind IN ('issuance', 'redemption', 'refund', 'breakage', 'adjustment')
+    )
+);
+
+CREATE TABLE ledger_lines (
+    entry_id UUID NOT NULL REFERENCES ledger_entries(id),
+    line_no SMALLINT NOT NULL,
+    account VARCHAR(40) NOT NULL,
+    gift_card_id UUID REFERENCES gift_cards(id),
+    amount INTEGER NOT NULL CHECK (amount <> 0),
+    PRIMARY KEY (entry_id, line_no),
+    CONSTRAINT chk_ledger_lines_account CHECK (
+        account IN ('card_liability', 'issuer_funding', 'merchant_settlement', 'breakage_income', 'adjustments')
+    ),
+    CONSTRAINT chk_ledger_lines_card CHECK ((account = 'card_liability') = (gift_card_id IS NOT NULL))
+);
+
+-- Create index for per-card balance and history lookups
+CREATE INDEX idx_ledger_lines_gift_card_id ON ledger_lines(gift_card_id, account);
+
+-- Entries and lines can be appended but never changed or removed
+CREATE OR REPLACE FUNCTION reject_ledger_change()
+RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'ledger is append-only: % on % is not allowed', TG_OP, TG_TABLE_NAME;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER ledger_entries_append_only
+BEFORE UPDATE OR DELETE ON ledger_entries
+FOR EACH ROW
+EXECUTE FUNCTION reject_ledger_change();
+
+CREATE TRIGGER ledger_lines_append_only
+BEFORE UPDATE OR DELETE ON ledger_lines
+FOR EACH ROW
+EXECUTE FUNCTION reject_ledger_change();
+
+-- Check at commit that every entry touched by the transaction balances
+CREATE OR REPLACE FUNCTION check_ledger_entry_balanced()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF (SELECT SUM(amount) FROM ledger_lines WHERE entry_id = NEW.entry_id) <> 0 THEN
+        RAISE EXCEPTION 'ledger entry % is not balanced', NEW.entry_id;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE CONSTRAINT TRIGGER ledger_lines_balanced
+AFTER INSERT ON ledger_lines
+DEFERRABLE INITIALLY DEFERRED
+FOR EACH ROW
+EXECUTE FUNCTION check_ledger_entry_balanced();
+
+-- Open the ledger for existing cards: their issued value, and whatever
+-- was spent before the ledger existed as one aggregate redemption.
+-- Entry IDs are derived from the card ID so the lines can refer to them.
+INSERT INTO ledger_entries (id, kind, description, created_at)
+SELECT md5(id::text || ':issuance')::uuid, 'issuance', 'Issued by ' || issuer_name, created_at
+FROM gift_cards
+WHERE initial_balance > 0;
+
+INSERT INTO ledger_lines (entry_id, line_no, account, gift_card_id, amount)
+SELECT md5(id::text || ':issuance')::uuid, 0, 'issuer_funding', NULL, initial_balance
+FROM gift_cards
+WHERE initial_balance > 0
+UNION ALL
+SELECT md5(id::text || ':issuance')::uuid, 1, 'card_liability', id, -initial_balance
+FROM gift_cards
+WHERE initial_balance > 0;
+
+INSERT INTO ledger_entries (id, kind, description, created_at)
+SELECT md5(id::text || ':redemption')::uuid, 'redemption', 'Redeemed before the ledger was introduced', updated_at
+FROM gift_cards
+WHERE initial_balance > balance;
+
+INSERT INTO ledger_lines (entry_id, line_no, account, gift_card_id, amount)
+SELECT md5(id::text || ':redemption')::uuid, 0, 'card_liability', id, initial_balance - balance
+FROM gift_cards
+WHERE initial_balance > balance
+UNION ALL
+SELECT md5(id::text || ':redemption')::uuid, 1, 'merchant_settlement', NULL, balance - initial_balance
+FROM gift_cards
+WHERE initial_balance > balance;
@@ -0,0 +1,22 @@
+-- Amounts become BIGINT minor units, and each card, transaction and
+-- journal entry records its ISO-4217 currency. Existing amounts were
+-- US cents.
+ALTER TABLE gift_cards
+    ADD COLUMN currency CHAR(3) NOT NULL DEFAULT 'USD',
+    ALTER COLUMN balance TYPE BIGINT,
+    ALTER COLUMN initial_balance TYPE BIGINT;
+
+ALTER TABLE gift_card_transactions
+    ADD COLUMN currency CHAR(3) NOT NULL DEFAULT 'USD',
+    ALTER COLUMN amount TYPE BIGINT;
+
+ALTER TABLE ledger_entries
+    ADD COLUMN currency CHAR(3) NOT NULL DEFAULT 'USD';
+
+ALTER TABLE ledger_lines
+    ALTER COLUMN amount TYPE BIGINT;
+
+-- New rows must name their currency
+ALTER TABLE gift_cards ALTER COLUMN currency DROP DEFAULT;
+ALTER TABLE gift_card_transactions ALTER COLUMN currency DROP DEFAULT;
+ALTER TABLE ledger_entries ALTER COLUMN currency DROP DEFAULT;
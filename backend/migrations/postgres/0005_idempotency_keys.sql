@@ -0,0 +1,12 @@
+-- Responses stored for Idempotency-Key request headers; the response
+-- columns are empty while the first request is still being processed
+CREATE TABLE idempotency_keys (
+    idempotency_key VARCHAR(255) PRIMARY KEY,
+    fingerprint CHAR(64) NOT NULL,
+    response_status SMALLINT,
+    response_body TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create index on created_at for purging expired keys
+CREATE INDEX idx_idempotency_keys_created_at ON idempotency_keys(created_at);
@@ -0,0 +1,20 @@
+-- Authorization holds reserve part of a card's balance until they are
+-- captured into a redemption, voided, or pass expires_at
+CREATE TABLE holds (
+    id UUID PRIMARY KEY,
+    gift_card_id UUID NOT NULL REFERENCES gift_cards(id) ON DELETE CASCADE,
+    amount BIGINT NOT NULL CHECK (amount > 0),
+    captured_amount BIGINT CHECK (captured_amount > 0 AND captured_amount <= amount),
+    currency CHAR(3) NOT NULL,
+    merchant VARCHAR(100) NOT NULL,
+    status VARCHAR(20) NOT NULL,
+    transaction_id UUID REFERENCES gift_card_transactions(id),
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT chk_holds_status CHECK (status IN ('active', 'captured', 'voided', 'expired')),
+    CONSTRAINT chk_holds_capture CHECK ((status = 'captured') = (transaction_id IS NOT NULL))
+);
+
+-- Create index for summing the open holds on a card
+CREATE INDEX idx_holds_gift_card_id_status ON holds(gift_card_id, status);
@@ -0,0 +1,36 @@
+-- Merchants become a table of their own, referenced by the transactions
+-- and holds that used to carry only a merchant name
+CREATE TABLE merchants (
+    id UUID PRIMARY KEY,
+    name VARCHAR(100) NOT NULL,
+    contact_email VARCHAR(255),
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create index on name for listing merchants
+CREATE INDEX idx_merchants_name ON merchants(name);
+
+-- Every merchant name already on record becomes an inactive merchant, with
+-- an ID derived from the name; activate them to take new payments
+INSERT INTO merchants (id, name, active)
+SELECT md5('merchant:' || merchant)::uuid, merchant, FALSE
+FROM (
+    SELECT merchant FROM gift_card_transactions
+    UNION
+    SELECT merchant FROM holds
+) names;
+
+ALTER TABLE gift_card_transactions ADD COLUMN merchant_id UUID REFERENCES merchants(id);
+ALTER TABLE holds ADD COLUMN merchant_id UUID REFERENCES merchants(id);
+
+UPDATE gift_card_transactions SET merchant_id = md5('merchant:' || merchant)::uuid;
+UPDATE holds SET merchant_id = md5('merchant:' || merchant)::uuid;
+
+ALTER TABLE gift_card_transactions ALTER COLUMN merchant_id SET NOT NULL;
+ALTER TABLE holds ALTER COLUMN merchant_id SET NOT NULL;
+
+-- Create index on merchant_id for per-merchant history
+CREATE INDEX idx_gift_card_transactions_merchant_id
+    ON gift_card_transactions(merchant_id, transaction_date);
@@ -0,0 +1,19 @@
+-- Issuer accounts sign in to issue gift cards; each new card records the
+-- account that issued it
+CREATE TABLE issuer_accounts (
+    id UUID PRIMARY KEY,
+    email VARCHAR(254) NOT NULL,
+    name VARCHAR(100) NOT NULL,
+    password_hash VARCHAR(255) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Emails are stored lower-cased, so this makes them unique regardless of case
+CREATE UNIQUE INDEX idx_issuer_accounts_email ON issuer_accounts(email);
+
+-- Cards issued before accounts existed keep only their issuer name
+ALTER TABLE gift_cards ADD COLUMN issuer_id UUID REFERENCES issuer_accounts(id);
+
+-- Create index on issuer_id for listing an issuer's cards
+CREATE INDEX idx_gift_cards_issuer_id ON gift_cards(issuer_id);
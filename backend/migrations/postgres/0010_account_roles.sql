@@ -0,0 +1,17 @@
+-- Issuer accounts become accounts with a role: admin, issuer, merchant or
+-- recipient. Existing accounts are issuers.
+ALTER TABLE issuer_accounts RENAME TO accounts;
+ALTER INDEX idx_issuer_accounts_email RENAME TO idx_accounts_email;
+
+ALTER TABLE accounts ADD COLUMN role VARCHAR(20) NOT NULL DEFAULT 'issuer';
+ALTER TABLE accounts ALTER COLUMN role DROP DEFAULT;
+
+-- Merchant accounts act for one merchant, recipient accounts for one phone number
+ALTER TABLE accounts ADD COLUMN merchant_id UUID REFERENCES merchants(id);
+ALTER TABLE accounts ADD COLUMN phone VARCHAR(20);
+
+ALTER TABLE accounts ADD CONSTRAINT chk_accounts_role CHECK (
+    role IN ('admin', 'issuer', 'merchant', 'recipient')
+    AND (role = 'merchant') = (merchant_id IS NOT NULL)
+    AND (role = 'recipient') = (phone IS NOT NULL)
+);
@@ -0,0 +1,14 @@
+-- One-time passcodes sent to a card's recipient phone to confirm acceptance;
+-- only a hash of each code is stored
+CREATE TABLE acceptance_codes (
+    id UUID PRIMARY KEY,
+    gift_card_id UUID NOT NULL REFERENCES gift_cards(id) ON DELETE CASCADE,
+    code_hash VARCHAR(255) NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0 CHECK (attempts >= 0),
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    used_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create index for finding the latest code sent for a card
+CREATE INDEX idx_acceptance_codes_gift_card_id ON acceptance_codes(gift_card_id, created_at);
@@ -0,0 +1,7 @@
+-- Per-card secrets behind rotating live QR codes; a card with a row here only
+-- accepts its live code when scanned
+CREATE TABLE live_qr_secrets (
+    gift_card_id UUID PRIMARY KEY REFERENCES gift_cards(id) ON DELETE CASCADE,
+    secret VARCHAR(64) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
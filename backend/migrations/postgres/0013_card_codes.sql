@@ -0,0 +1,10 @@
+-- Short codes typed in when a card's QR code will not scan, each with an
+-- optional PIN; wrong PINs are counted and lock the code out for a while
+CREATE TABLE card_codes (
+    gift_card_id UUID PRIMARY KEY REFERENCES gift_cards(id) ON DELETE CASCADE,
+    code VARCHAR(16) NOT NULL UNIQUE,
+    pin_hash VARCHAR(255),
+    pin_attempts INTEGER NOT NULL DEFAULT 0,
+    locked_until TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,15 @@
+-- Batches of gift cards issued from one upload; the per-row results are
+-- kept as a JSON array so they can be read back while the batch runs
+CREATE TABLE issuance_batches (
+    id UUID PRIMARY KEY,
+    issuer_id UUID NOT NULL REFERENCES accounts(id),
+    mode VARCHAR(20) NOT NULL,
+    status VARCHAR(20) NOT NULL,
+    total_rows INTEGER NOT NULL,
+    issued INTEGER NOT NULL DEFAULT 0,
+    failed INTEGER NOT NULL DEFAULT 0,
+    results TEXT NOT NULL,
+    error TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    completed_at TIMESTAMP WITH TIME ZONE
+);
@@ -0,0 +1,14 @@
+-- Expired cards record the balance they lose as an `expiry` transaction,
+-- which no merchant took and which reverses nothing
+ALTER TABLE gift_card_transactions
+    ALTER COLUMN merchant_id DROP NOT NULL,
+    ALTER COLUMN merchant DROP NOT NULL,
+    DROP CONSTRAINT chk_gift_card_transactions_kind,
+    ADD CONSTRAINT chk_gift_card_transactions_kind CHECK (
+        (kind = 'redemption' AND original_transaction_id IS NULL AND merchant_id IS NOT NULL)
+        OR (kind = 'refund' AND original_transaction_id IS NOT NULL AND merchant_id IS NOT NULL)
+        OR (kind = 'expiry' AND original_transaction_id IS NULL AND merchant_id IS NULL)
+    );
+
+-- The expiry sweeper pages through cards past their expiration date
+CREATE INDEX idx_gift_cards_expiration_date_id ON gift_cards(expiration_date, id);
@@ -0,0 +1,16 @@
+-- Recipients can move a card's balance to a new card for someone else. The
+-- card it arrives on links back to the transfer that left the original card.
+ALTER TABLE gift_card_transactions
+    DROP CONSTRAINT chk_gift_card_transactions_kind,
+    ADD CONSTRAINT chk_gift_card_transactions_kind CHECK (
+        (kind = 'redemption' AND original_transaction_id IS NULL AND merchant_id IS NOT NULL)
+        OR (kind = 'refund' AND original_transaction_id IS NOT NULL AND merchant_id IS NOT NULL)
+        OR (kind IN ('expiry', 'reactivation', 'fee', 'transfer_out') AND original_transaction_id IS NULL AND merchant_id IS NULL)
+        OR (kind = 'transfer_in' AND original_transaction_id IS NOT NULL AND merchant_id IS NULL)
+    );
+
+ALTER TABLE ledger_entries
+    DROP CONSTRAINT chk_ledger_entries_kind,
+    ADD CONSTRAINT chk_ledger_entries_kind CHECK (
+        kind IN ('issuance', 'redemption', 'refund', 'breakage', 'adjustment', 'reactivation', 'fee', 'transfer')
+    );
@@ -0,0 +1,23 @@
+-- Cards can be topped up after issue. `total_loaded` counts everything ever
+-- loaded onto a card, its initial balance included, so it starts out equal to
+-- the initial balance.
+ALTER TABLE gift_cards ADD COLUMN total_loaded BIGINT;
+
+UPDATE gift_cards SET total_loaded = initial_balance;
+
+ALTER TABLE gift_cards ALTER COLUMN total_loaded SET NOT NULL;
+
+ALTER TABLE gift_card_transactions
+    DROP CONSTRAINT chk_gift_card_transactions_kind,
+    ADD CONSTRAINT chk_gift_card_transactions_kind CHECK (
+        (kind = 'redemption' AND original_transaction_id IS NULL AND merchant_id IS NOT NULL)
+        OR (kind = 'refund' AND original_transaction_id IS NOT NULL AND merchant_id IS NOT NULL)
+        OR (kind IN ('expiry', 'reactivation', 'fee', 'transfer_out', 'load') AND original_transaction_id IS NULL AND merchant_id IS NULL)
+        OR (kind = 'transfer_in' AND original_transaction_id IS NOT NULL AND merchant_id IS NULL)
+    );
+
+ALTER TABLE ledger_entries
+    DROP CONSTRAINT chk_ledger_entries_kind,
+    ADD CONSTRAINT chk_ledger_entries_kind CHECK (
+        kind IN ('issuance', 'redemption', 'refund', 'breakage', 'adjustment', 'reactivation', 'fee', 'transfer', 'replacement', 'load')
+    );
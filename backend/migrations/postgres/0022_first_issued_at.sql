@@ -0,0 +1,31 @@
+-- Cards made by a transfer or a replacement are new rows, but the lifetime
+-- limit on extensions runs from when the first card in the line was issued.
+-- Existing cards are traced back through `replaces_id` and their transfer_in
+-- transactions to find it.
+ALTER TABLE gift_cards ADD COLUMN first_issued_at TIMESTAMP WITH TIME ZONE;
+
+UPDATE gift_cards SET first_issued_at = created_at;
+
+WITH RECURSIVE parents (card_id, parent_id) AS (
+    SELECT id, replaces_id FROM gift_cards WHERE replaces_id IS NOT NULL
+    UNION
+    SELECT received.gift_card_id, sent.gift_card_id
+    FROM gift_card_transactions received
+    JOIN gift_card_transactions sent ON sent.id = received.original_transaction_id
+    WHERE received.kind = 'transfer_in'
+),
+lineage (card_id, first_issued_at) AS (
+    SELECT cards.id, cards.created_at
+    FROM gift_cards cards
+    LEFT JOIN parents ON parents.card_id = cards.id
+    WHERE parents.card_id IS NULL
+    UNION ALL
+    SELECT parents.card_id, lineage.first_issued_at
+    FROM lineage
+    JOIN parents ON parents.parent_id = lineage.card_id
+)
+UPDATE gift_cards SET first_issued_at = lineage.first_issued_at
+FROM lineage
+WHERE gift_cards.id = lineage.card_id;
+
+ALTER TABLE gift_cards ALTER COLUMN first_issued_at SET NOT NULL;
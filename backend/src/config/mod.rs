@@ -1,57 +1,512 @@
+use std::collections::HashMap;
 use std::env;
+use std::fmt;
+use std::fs;
+use image::Rgba;
+
+use crate::repository::Backend;
+use crate::sms::SmsBackend;
+use crate::utils::qr_render::{parse_color, QrLogo};
+use crate::utils::qr_token::QrKeyRing;
+use crate::voucher::{load_brand_image, load_font, VoucherFonts, VoucherTemplate};
+
+/// Placeholder JWT secrets shipped in defaults and `.env.example`
+const PLACEHOLDER_JWT_SECRETS: &[&str] = &[
+    "your_jwt_secret_key_please_change_in_production",
+    "change_this_to_a_secure_random_string_in_production",
+];
+
+/// Development-only QR signing key shipped in defaults and `.env.example`
+const PLACEHOLDER_QR_SIGNING_KEY: &str = "dev:ZGV2ZWxvcG1lbnQtb25seS1xci1zaWduaW5nLWtleSE=";
+
+/// Deployment profile, read from `APP_ENV`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    Development,
+    Production,
+}
 
 /// Application configuration
 #[derive(Debug, Clone)]
 pub struct Config {
+    pub profile: Profile,
     pub database_url: String,
+    pub database_max_connections: u32,
+    pub database_auto_migrate: bool,  // Apply pending migrations at startup
     pub server_host: String,
     pub server_port: u16,
     pub jwt_secret: String,
     pub jwt_expiration: i64,  // JWT expiration in seconds
+    pub jwt_refresh_expiration: i64,  // Refresh token expiration in seconds
     pub cors_allowed_origins: Vec<String>,
+    pub idempotency_key_ttl: i64,  // How long Idempotency-Key responses are kept, in seconds
+    pub hold_ttl: i64,  // How long an uncaptured authorization hold lasts, in seconds
+    pub expiry_sweep_interval: u64,  // How often expired cards are swept, in seconds; 0 turns the sweeper off
+    pub expiry_sweep_batch: i64,  // Cards fetched per sweeper query
+    pub card_max_lifetime_days: i64,  // Latest a card can be extended to, in days after its line was first issued
+    pub card_max_balance: i64,  // Most a top-up can take a card's balance to, in whole units of its currency
+    pub card_daily_load_limit: i64,  // Most that can be loaded onto a card in 24 hours, in whole units of its currency
+    pub sms_sender: String,  // "log" or "file:<path>"
+    pub otp_ttl: i64,  // How long an acceptance code stays valid, in seconds
+    pub otp_max_attempts: i32,  // Wrong guesses allowed per acceptance code
+    pub pin_max_attempts: i32,  // Wrong PINs in a row before a card's short code is locked out
+    pub pin_lockout: i64,  // How long a locked out short code refuses PINs, in seconds
+    pub batch_max_rows: usize,  // Most rows accepted in one issuance batch
+    pub batch_async_threshold: usize,  // Batches with more rows than this are issued in the background
+    pub qr_signing_keys: QrKeyRing,  // Ed25519 keys for QR tokens; the first signs
+    pub qr_live_window: i64,  // How often live QR codes change, in seconds
+    pub qr_logo: Option<QrLogo>,  // Brand logo QR codes may be rendered with, from QR_LOGO_PATH
+    pub voucher: VoucherTemplate,  // Brand image, fonts and accent colour of printable vouchers
+}
+
+/// A single invalid configuration value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Every problem found while loading the configuration
+#[derive(Debug)]
+pub struct ConfigError {
+    pub errors: Vec<FieldError>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid configuration:")?;
+        for error in &self.errors {
+            write!(f, "\n  - {}: {}", error.field, error.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Collects raw values and parse errors while building a [`Config`]
+///
+/// Environment variables take precedence over values from the TOML file.
+struct Loader<'a> {
+    file: HashMap<String, String>,
+    env: &'a dyn Fn(&str) -> Option<String>,
+    errors: Vec<FieldError>,
+}
+
+impl Loader<'_> {
+    fn raw(&self, key: &str) -> Option<String> {
+        (self.env)(key).or_else(|| self.file.get(&key.to_lowercase()).cloned())
+    }
+
+    fn error(&mut self, field: &str, message: impl Into<String>) {
+        self.errors.push(FieldError {
+            field: field.to_string(),
+            message: message.into(),
+        });
+    }
+
+    fn string(&mut self, key: &str, default: &str) -> String {
+        self.raw(key).unwrap_or_else(|| default.to_string())
+    }
+
+    fn parse<T: std::str::FromStr>(&mut self, key: &str, default: T, expected: &str) -> T {
+        match self.raw(key) {
+            None => default,
+            Some(value) => match value.trim().parse() {
+                Ok(parsed) => parsed,
+                Err(_) => {
+                    self.error(key, format!("expected {}, got {:?}", expected, value));
+                    default
+                }
+            },
+        }
+    }
+
+    /// Load the file named by `key` with `load`, or nothing when it is unset or empty
+    fn file<T>(&mut self, key: &str, load: impl FnOnce(&str) -> Result<T, String>) -> Option<T> {
+        let path = self.string(key, "");
+        if path.trim().is_empty() {
+            return None;
+        }
+        match load(path.trim()) {
+            Ok(loaded) => Some(loaded),
+            Err(e) => {
+                self.error(key, e);
+                None
+            }
+        }
+    }
+}
+
+/// Flatten a parsed TOML document into lower-case keys with string values
+fn flatten_file(table: toml::Table) -> HashMap<String, String> {
+    table
+        .into_iter()
+        .filter_map(|(key, value)| {
+            let value = match value {
+                toml::Value::String(s) => s,
+                toml::Value::Array(items) => items
+                    .iter()
+                    .map(|item| item.as_str().map(str::to_string).unwrap_or_else(|| item.to_string()))
+                    .collect::<Vec<_>>()
+                    .join(","),
+                toml::Value::Table(_) => return None,
+                other => other.to_string(),
+            };
+            Some((key.to_lowercase(), value))
+        })
+        .collect()
 }
 
 impl Config {
     /// Load configuration from environment variables
-    pub fn from_env() -> Self {
-        // Get environment variables with defaults
-        let database_url = env::var("DATABASE_URL")
-            .expect("DATABASE_URL must be set");
-            
-        let server_host = env::var("SERVER_HOST")
-            .unwrap_or_else(|_| "0.0.0.0".to_string());
-            
-        let server_port = env::var("SERVER_PORT")
-            .unwrap_or_else(|_| "8080".to_string())
-            .parse::<u16>()
-            .expect("SERVER_PORT must be a valid port number");
-            
-        let jwt_secret = env::var("JWT_SECRET")
-            .unwrap_or_else(|_| "your_jwt_secret_key_please_change_in_production".to_string());
-            
-        let jwt_expiration = env::var("JWT_EXPIRATION")
-            .unwrap_or_else(|_| "86400".to_string())  // Default: 24 hours
-            .parse::<i64>()
-            .expect("JWT_EXPIRATION must be a valid number");
-            
-        let cors_allowed_origins = env::var("CORS_ALLOWED_ORIGINS")
-            .unwrap_or_else(|_| "*".to_string())
+    ///
+    /// If `CONFIG_FILE` is set, that TOML file is read first and any
+    /// environment variable overrides the matching key from the file.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let file = match env::var("CONFIG_FILE") {
+            Ok(path) => match fs::read_to_string(&path) {
+                Ok(contents) => Some(contents),
+                Err(e) => {
+                    return Err(ConfigError {
+                        errors: vec![FieldError {
+                            field: "CONFIG_FILE".to_string(),
+                            message: format!("cannot read {}: {}", path, e),
+                        }],
+                    })
+                }
+            },
+            Err(_) => None,
+        };
+
+        Self::from_sources(file.as_deref(), &|key| env::var(key).ok())
+    }
+
+    /// Build the configuration from optional TOML contents and a variable lookup
+    pub fn from_sources(
+        file: Option<&str>,
+        env: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<Self, ConfigError> {
+        let mut loader = Loader {
+            file: HashMap::new(),
+            env,
+            errors: Vec::new(),
+        };
+
+        if let Some(contents) = file {
+            match contents.parse::<toml::Table>() {
+                Ok(table) => loader.file = flatten_file(table),
+                Err(e) => loader.error("CONFIG_FILE", format!("invalid TOML: {}", e.message())),
+            }
+        }
+
+        let profile = match loader.string("APP_ENV", "development").trim().to_lowercase().as_str() {
+            "development" | "dev" => Profile::Development,
+            "production" | "prod" => Profile::Production,
+            other => {
+                loader.error("APP_ENV", format!("expected \"development\" or \"production\", got {:?}", other));
+                Profile::Development
+            }
+        };
+
+        let database_url = loader.string("DATABASE_URL", "");
+        if database_url.is_empty() {
+            loader.error("DATABASE_URL", "must be set");
+        } else if Backend::from_url(&database_url).is_none() {
+            loader.error("DATABASE_URL", "scheme must be mysql://, postgres:// or memory://");
+        }
+
+        let database_max_connections = loader.parse("DATABASE_MAX_CONNECTIONS", 5u32, "a positive integer");
+        if database_max_connections == 0 {
+            loader.error("DATABASE_MAX_CONNECTIONS", "must be at least 1");
+        }
+
+        // Migrate automatically in development, require `migrate` in production
+        let database_auto_migrate = match loader.raw("DATABASE_AUTO_MIGRATE") {
+            None => profile == Profile::Development,
+            Some(value) => match value.trim().to_lowercase().as_str() {
+                "true" | "1" | "yes" => true,
+                "false" | "0" | "no" => false,
+                _ => {
+                    loader.error("DATABASE_AUTO_MIGRATE", format!("expected true or false, got {:?}", value));
+                    false
+                }
+            },
+        };
+
+        let server_host = loader.string("SERVER_HOST", "0.0.0.0");
+        if server_host.trim().is_empty() {
+            loader.error("SERVER_HOST", "must not be empty");
+        }
+
+        let server_port = loader.parse("SERVER_PORT", 8080u16, "a port number between 1 and 65535");
+        if server_port == 0 {
+            loader.error("SERVER_PORT", "must be between 1 and 65535");
+        }
+
+        let jwt_secret = loader.string("JWT_SECRET", PLACEHOLDER_JWT_SECRETS[0]);
+        if jwt_secret.is_empty() {
+            loader.error("JWT_SECRET", "must not be empty");
+        } else if profile == Profile::Production && PLACEHOLDER_JWT_SECRETS.contains(&jwt_secret.as_str()) {
+            loader.error("JWT_SECRET", "the placeholder secret cannot be used in production");
+        }
+
+        let jwt_expiration = loader.parse("JWT_EXPIRATION", 86400i64, "a number of seconds");  // Default: 24 hours
+        if jwt_expiration <= 0 {
+            loader.error("JWT_EXPIRATION", "must be positive");
+        }
+
+        let jwt_refresh_expiration = loader.parse("JWT_REFRESH_EXPIRATION", 2592000i64, "a number of seconds");  // Default: 30 days
+        if jwt_refresh_expiration <= 0 {
+            loader.error("JWT_REFRESH_EXPIRATION", "must be positive");
+        }
+
+        let cors_allowed_origins: Vec<String> = loader
+            .string("CORS_ALLOWED_ORIGINS", "*")
             .split(',')
             .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
             .collect();
-            
-        Self {
+        for origin in &cors_allowed_origins {
+            if origin != "*" && !(origin.starts_with("http://") || origin.starts_with("https://")) {
+                loader.error("CORS_ALLOWED_ORIGINS", format!("{:?} is not an http(s) origin", origin));
+            }
+        }
+
+        let idempotency_key_ttl = loader.parse("IDEMPOTENCY_KEY_TTL", 86400i64, "a number of seconds");  // Default: 24 hours
+        if idempotency_key_ttl <= 0 {
+            loader.error("IDEMPOTENCY_KEY_TTL", "must be positive");
+        }
+
+        let hold_ttl = loader.parse("HOLD_TTL", 604800i64, "a number of seconds");  // Default: 7 days
+        if hold_ttl <= 0 {
+            loader.error("HOLD_TTL", "must be positive");
+        }
+
+        let expiry_sweep_interval = loader.parse("EXPIRY_SWEEP_INTERVAL", 300u64, "a number of seconds");  // Default: 5 minutes
+        let expiry_sweep_batch = loader.parse("EXPIRY_SWEEP_BATCH", 100i64, "a positive integer");
+        if expiry_sweep_batch <= 0 {
+            loader.error("EXPIRY_SWEEP_BATCH", "must be at least 1");
+        }
+        let card_max_lifetime_days = loader.parse("CARD_MAX_LIFETIME_DAYS", 1825i64, "a number of days");  // Default: 5 years
+        if card_max_lifetime_days <= 0 {
+            loader.error("CARD_MAX_LIFETIME_DAYS", "must be positive");
+        }
+        let card_max_balance = loader.parse("CARD_MAX_BALANCE", 10_000i64, "a whole number of currency units");
+        if card_max_balance <= 0 {
+            loader.error("CARD_MAX_BALANCE", "must be positive");
+        }
+        let card_daily_load_limit = loader.parse("CARD_DAILY_LOAD_LIMIT", 2_000i64, "a whole number of currency units");
+        if card_daily_load_limit <= 0 {
+            loader.error("CARD_DAILY_LOAD_LIMIT", "must be positive");
+        }
+
+        let sms_sender = loader.string("SMS_SENDER", "log");
+        if SmsBackend::from_spec(&sms_sender).is_none() {
+            loader.error("SMS_SENDER", "expected \"log\" or \"file:<path>\"");
+        }
+
+        let otp_ttl = loader.parse("OTP_TTL", 300i64, "a number of seconds");  // Default: 5 minutes
+        if otp_ttl <= 0 {
+            loader.error("OTP_TTL", "must be positive");
+        }
+
+        let otp_max_attempts = loader.parse("OTP_MAX_ATTEMPTS", 5i32, "a positive integer");
+        if otp_max_attempts <= 0 {
+            loader.error("OTP_MAX_ATTEMPTS", "must be at least 1");
+        }
+
+        let pin_max_attempts = loader.parse("PIN_MAX_ATTEMPTS", 5i32, "a positive integer");
+        if pin_max_attempts <= 0 {
+            loader.error("PIN_MAX_ATTEMPTS", "must be at least 1");
+        }
+        let pin_lockout = loader.parse("PIN_LOCKOUT", 900i64, "a number of seconds");
+        if pin_lockout <= 0 {
+            loader.error("PIN_LOCKOUT", "must be positive");
+        }
+
+        let batch_max_rows = loader.parse("BATCH_MAX_ROWS", 5000usize, "a positive integer");
+        if batch_max_rows == 0 {
+            loader.error("BATCH_MAX_ROWS", "must be at least 1");
+        }
+        let batch_async_threshold = loader.parse("BATCH_ASYNC_THRESHOLD", 100usize, "a number of rows");
+
+        let qr_signing_keys_spec = loader.string("QR_SIGNING_KEYS", PLACEHOLDER_QR_SIGNING_KEY);
+        if profile == Profile::Production && qr_signing_keys_spec.contains(PLACEHOLDER_QR_SIGNING_KEY) {
+            loader.error("QR_SIGNING_KEYS", "the development key cannot be used in production");
+        }
+        let qr_signing_keys = match QrKeyRing::from_spec(&qr_signing_keys_spec) {
+            Ok(keys) => Some(keys),
+            Err(e) => {
+                loader.error("QR_SIGNING_KEYS", e);
+                None
+            }
+        };
+
+        let qr_live_window = loader.parse("QR_LIVE_WINDOW", 30i64, "a number of seconds");
+        if qr_live_window <= 0 {
+            loader.error("QR_LIVE_WINDOW", "must be positive");
+        }
+
+        let qr_logo = loader.file("QR_LOGO_PATH", QrLogo::load);
+
+        let brand_image = loader.file("VOUCHER_BRAND_IMAGE_PATH", load_brand_image);
+        let regular_font = loader.file("VOUCHER_FONT_PATH", load_font);
+        let bold_font = loader.file("VOUCHER_BOLD_FONT_PATH", load_font);
+        if bold_font.is_some() && regular_font.is_none() {
+            loader.error("VOUCHER_BOLD_FONT_PATH", "needs VOUCHER_FONT_PATH as well");
+        }
+        let accent_color = loader.string("VOUCHER_ACCENT_COLOR", "1f3a93");
+        let accent = parse_color(&accent_color).unwrap_or_else(|_| {
+            loader.error("VOUCHER_ACCENT_COLOR", format!("expected a colour like 1a2b3c, got {:?}", accent_color));
+            Rgba([0, 0, 0, 255])
+        });
+        let voucher = VoucherTemplate {
+            brand_image,
+            fonts: regular_font.map(|regular| VoucherFonts {
+                bold: bold_font.unwrap_or_else(|| regular.clone()),
+                regular,
+            }),
+            accent,
+        };
+
+        if !loader.errors.is_empty() {
+            return Err(ConfigError { errors: loader.errors });
+        }
+
+        Ok(Self {
+            profile,
             database_url,
+            database_max_connections,
+            database_auto_migrate,
             server_host,
             server_port,
             jwt_secret,
             jwt_expiration,
+            jwt_refresh_expiration,
             cors_allowed_origins,
-        }
+            idempotency_key_ttl,
+            hold_ttl,
+            expiry_sweep_interval,
+            expiry_sweep_batch,
+            card_max_lifetime_days,
+            card_max_balance,
+            card_daily_load_limit,
+            sms_sender,
+            otp_ttl,
+            otp_max_attempts,
+            pin_max_attempts,
+            pin_lockout,
+            batch_max_rows,
+            batch_async_threshold,
+            qr_signing_keys: qr_signing_keys.expect("QR_SIGNING_KEYS errors are reported above"),
+            qr_live_window,
+            qr_logo,
+            voucher,
+        })
+    }
+
+    /// Whether CORS should accept any origin
+    pub fn allows_any_origin(&self) -> bool {
+        self.cors_allowed_origins.iter().any(|origin| origin == "*")
     }
 }
 
 /// Get configuration instance
-pub fn get_config() -> Config {
+pub fn get_config() -> Result<Config, ConfigError> {
     Config::from_env()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |key| vars.get(key).cloned()
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = Config::from_sources(None, &lookup(&[("DATABASE_URL", "memory://")])).unwrap();
+
+        assert_eq!(config.profile, Profile::Development);
+        assert_eq!(config.server_port, 8080);
+        assert_eq!(config.database_max_connections, 5);
+        assert!(config.database_auto_migrate);
+        assert!(config.allows_any_origin());
+    }
+
+    #[test]
+    fn test_env_overrides_file() {
+        let file = r#"
+            database_url = "postgres://localhost/gift_card_db"
+            server_port = 9000
+            cors_allowed_origins = ["http://localhost:3000", "https://shop.example.com"]
+        "#;
+        let config = Config::from_sources(Some(file), &lookup(&[("SERVER_PORT", "9100")])).unwrap();
+
+        assert_eq!(config.database_url, "postgres://localhost/gift_card_db");
+        assert_eq!(config.server_port, 9100);
+        assert_eq!(config.cors_allowed_origins.len(), 2);
+        assert!(!config.allows_any_origin());
+    }
+
+    #[test]
+    fn test_reports_every_invalid_field() {
+        let err = Config::from_sources(
+            None,
+            &lookup(&[
+                ("SERVER_PORT", "eighty"),
+                ("JWT_EXPIRATION", "-5"),
+                ("CORS_ALLOWED_ORIGINS", "localhost:3000"),
+                ("SMS_SENDER", "carrier-pigeon"),
+                ("QR_SIGNING_KEYS", "k1:not-a-key"),
+                ("QR_LOGO_PATH", "/nonexistent/logo.png"),
+                ("VOUCHER_ACCENT_COLOR", "blue"),
+            ]),
+        )
+        .unwrap_err();
+
+        let fields: Vec<&str> = err.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "DATABASE_URL",
+                "SERVER_PORT",
+                "JWT_EXPIRATION",
+                "CORS_ALLOWED_ORIGINS",
+                "SMS_SENDER",
+                "QR_SIGNING_KEYS",
+                "QR_LOGO_PATH",
+                "VOUCHER_ACCENT_COLOR",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_production_rejects_placeholder_secret() {
+        let err = Config::from_sources(
+            None,
+            &lookup(&[("APP_ENV", "production"), ("DATABASE_URL", "memory://")]),
+        )
+        .unwrap_err();
+        let fields: Vec<&str> = err.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["JWT_SECRET", "QR_SIGNING_KEYS"]);
+
+        let config = Config::from_sources(
+            None,
+            &lookup(&[
+                ("APP_ENV", "production"),
+                ("DATABASE_URL", "memory://"),
+                ("JWT_SECRET", "a-real-secret-from-the-vault"),
+                ("QR_SIGNING_KEYS", "2026-10:Y2hhbmdlZC1rZXktZm9yLXByb2R1Y3Rpb24tdXNlISE="),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(config.profile, Profile::Production);
+    }
+}
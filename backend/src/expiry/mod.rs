@@ -0,0 +1,229 @@
+use actix_web::web;
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::models::card_status::CardStatus;
+use crate::models::gift_card::GiftCardTransaction;
+use crate::models::hold::HoldStatus;
+use crate::models::ledger::JournalEntry;
+use crate::models::money::Money;
+use crate::repository::{CardUpdate, GiftCardRepository};
+use crate::utils::error::AppError;
+
+/// What one sweep did
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SweepReport {
+    pub expired: usize,  // Cards moved to expired by this sweep
+    pub skipped: usize,  // Cards that changed before they were locked, e.g. expired by another instance
+    pub failed: usize,   // Cards that could not be expired; the next sweep tries again
+}
+
+/// Sweep expired cards every `interval` until the server stops
+///
+/// Every instance runs its own sweeper; see [`expire_card`] for why that is safe.
+pub fn spawn(repo: web::Data<dyn GiftCardRepository>, interval: Duration, batch: i64) {
+    actix_web::rt::spawn(async move {
+        loop {
+            match sweep(repo.get_ref(), Utc::now(), batch).await {
+                Ok(report) if report.expired > 0 || report.failed > 0 => {
+                    log::info!(
+                        "Expiry sweep expired {} card(s), {} failed",
+                        report.expired,
+                        report.failed
+                    );
+                }
+                Ok(_) => {}
+                Err(e) => log::error!("Expiry sweep failed: {}", e),
+            }
+            actix_web::rt::time::sleep(interval).await;
+        }
+    });
+}
+
+/// Expire every card that is past its expiration date at `now`, fetching
+/// `batch` cards at a time
+pub async fn sweep(repo: &dyn GiftCardRepository, now: DateTime<Utc>, batch: i64) -> Result<SweepReport, AppError> {
+    let mut report = SweepReport::default();
+    let mut after = None;
+
+    loop {
+        let cards = repo.find_expiring_cards(now, after, batch).await?;
+        for card in &cards {
+            match expire_card(repo, card.id, now).await {
+                Ok(true) => report.expired += 1,
+                Ok(false) => report.skipped += 1,
+                Err(e) => {
+                    log::warn!("Failed to expire gift card {}: {}", card.id, e);
+                    report.failed += 1;
+                }
+            }
+        }
+
+        // The cursor steps past cards that failed, so they cannot stall the sweep
+        match cards.last() {
+            Some(last) if cards.len() as i64 == batch => after = Some((last.expiration_date, last.id)),
+            _ => return Ok(report),
+        }
+    }
+}
+
+/// Expire card `id` if it is still due at `now`, returning whether it expired
+///
+/// Its open holds lapse, and any balance left is written off: an `expiry`
+/// transaction records it and a breakage entry moves it to income. The card
+/// is checked again once it is locked, so when several instances sweep at
+/// once only the first to lock a card expires it.
+pub async fn expire_card(repo: &dyn GiftCardRepository, id: Uuid, now: DateTime<Utc>) -> Result<bool, AppError> {
+    // New holds need an unexpired card, so none can appear before the lock
+    let holds = repo.find_open_holds(id, now).await?;
+
+    let changes = repo
+        .update_cards(
+            &[id],
+            Box::new(move |cards| {
+                let mut card = cards.into_iter().next().expect("the locked card is passed in");
+                if card.expiration_date > now || !card.status.can_transition_to(CardStatus::Expired) {
+                    return Ok(CardUpdate::default());
+                }
+                card.status_before_expiry = Some(card.status);
+                card.transition(CardStatus::Expired, now)?;
+
+                let mut changes = CardUpdate::default();
+                for mut hold in holds {
+                    hold.status = HoldStatus::Expired;
+                    hold.updated_at = now;
+                    changes.holds.push(hold);
+                }
+                if card.balance.is_positive() {
+                    let txn = GiftCardTransaction::expiry(card.id, card.balance, now);
+                    let mut entry = JournalEntry::breakage(card.id, card.balance, now);
+                    entry.transaction_id = Some(txn.id);
+                    changes.transactions.push(txn);
+                    changes.entries.push(entry);
+                    card.balance = Money::zero(card.currency());
+                }
+                card.held = Money::zero(card.currency());
+                changes.cards.push(card);
+                Ok(changes)
+            }),
+        )
+        .await?;
+
+    Ok(!changes.cards.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::test_support::test_repo;
+    use crate::models::account::{Account, Role};
+    use crate::models::gift_card::{CreateGiftCardDto, GiftCard, TransactionKind};
+    use crate::models::hold::Hold;
+    use crate::models::ledger::EntryKind;
+    use crate::models::merchant::Merchant;
+    use crate::models::money::Currency;
+    use chrono::Duration;
+
+    /// A $50 card in `status` that expired, or will expire, `days_ago` days before now
+    ///
+    /// Each card gets an issuer account of its own, as SQL backends require one.
+    async fn card(repo: &dyn GiftCardRepository, status: CardStatus, days_ago: i64) -> GiftCard {
+        let now = Utc::now();
+        let id = Uuid::new_v4();
+        let issuer = Account {
+            id,
+            email: format!("alice-{}@issuer.example", id),
+            name: "Alice".to_string(),
+            role: Role::Issuer,
+            merchant_id: None,
+            phone: None,
+            password_hash: String::new(),
+            created_at: now,
+            updated_at: now,
+        };
+        repo.insert_account(&issuer).await.unwrap();
+        let dto = CreateGiftCardDto {
+            recipient_name: "Bob".to_string(),
+            recipient_phone: "1234567890".to_string(),
+            balance: Money::new(5000, Currency::USD),
+            expiration_days: 30,
+            pin: None,
+        };
+        let mut card = GiftCard::issue(&issuer, &dto, now - Duration::days(30 + days_ago));
+        card.status = status;
+        repo.insert_card(&card, &JournalEntry::issuance(&card)).await.unwrap();
+        card
+    }
+
+    #[actix_web::test]
+    async fn test_sweep_writes_off_the_balance_and_lapses_holds() {
+        let repo = test_repo();
+        let now = Utc::now();
+        let expired = card(repo.get_ref(), CardStatus::Accepted, 1).await;
+        let current = card(repo.get_ref(), CardStatus::Accepted, -1).await;
+        let cancelled = card(repo.get_ref(), CardStatus::Cancelled, 1).await;
+        let merchant = Merchant::new("Fuel", None, now);
+        repo.insert_merchant(&merchant).await.unwrap();
+
+        let hold = Hold {
+            id: Uuid::new_v4(),
+            gift_card_id: expired.id,
+            amount: Money::new(2000, Currency::USD),
+            captured_amount: None,
+            merchant_id: merchant.id,
+            merchant: merchant.name.clone(),
+            status: HoldStatus::Active,
+            transaction_id: None,
+            expires_at: now + Duration::days(1),
+            created_at: now,
+            updated_at: now,
+        };
+        let changes = CardUpdate { holds: vec![hold.clone()], ..Default::default() };
+        repo.update_cards(&[], Box::new(move |_| Ok(changes))).await.unwrap();
+
+        let report = sweep(repo.get_ref(), now, 100).await.unwrap();
+        assert_eq!(report, SweepReport { expired: 1, skipped: 0, failed: 0 });
+
+        let card = repo.find_card(expired.id).await.unwrap().unwrap();
+        assert_eq!(card.status, CardStatus::Expired);
+        assert!(card.balance.is_zero() && card.held.is_zero());
+        assert_eq!(repo.find_hold(hold.id).await.unwrap().unwrap().status, HoldStatus::Expired);
+
+        let transactions = repo.list_transactions(expired.id, 10, 0).await.unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].kind, TransactionKind::Expiry);
+        assert_eq!(transactions[0].amount, Money::new(5000, Currency::USD));
+        assert_eq!(transactions[0].merchant_id, None);
+        let entries = repo.list_journal_entries(expired.id, 10, 0).await.unwrap();
+        assert!(entries.iter().any(|e| e.kind == EntryKind::Breakage && e.transaction_id == Some(transactions[0].id)));
+        assert_eq!(repo.ledger_balance(expired.id).await.unwrap(), 0);
+
+        for untouched in [&current, &cancelled] {
+            let card = repo.find_card(untouched.id).await.unwrap().unwrap();
+            assert_eq!((card.status, card.balance), (untouched.status, untouched.balance));
+        }
+
+        // Nothing is left to do on the next run
+        assert_eq!(sweep(repo.get_ref(), now, 100).await.unwrap(), SweepReport::default());
+    }
+
+    #[actix_web::test]
+    async fn test_a_card_found_by_two_sweeps_expires_once() {
+        let repo = test_repo();
+        let now = Utc::now();
+        for _ in 0..3 {
+            card(repo.get_ref(), CardStatus::Issued, 2).await;
+        }
+
+        // Another instance fetched the same page before this one expired it
+        let stale = repo.find_expiring_cards(now, None, 10).await.unwrap();
+        assert_eq!(sweep(repo.get_ref(), now, 2).await.unwrap().expired, 3);
+
+        for card in stale {
+            assert!(!expire_card(repo.get_ref(), card.id, now).await.unwrap());
+            assert_eq!(repo.find_card(card.id).await.unwrap().unwrap().status, CardStatus::Expired);
+            assert_eq!(repo.list_transactions(card.id, 10, 0).await.unwrap().len(), 1);
+        }
+    }
+}
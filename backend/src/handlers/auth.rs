@@ -0,0 +1,348 @@
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::account::{
+    Account, AccountSessionDto, CreateAccountDto, LoginDto, RefreshTokenDto, RegisterIssuerDto, Role,
+};
+use crate::repository::GiftCardRepository;
+use crate::utils::auth::{decode_token, hash_password, issue_tokens, verify_password, TokenKind};
+use crate::utils::error::AppError;
+use crate::utils::validation::{validate_email, validate_phone};
+use super::{error_response, ApiResponse};
+
+const MIN_PASSWORD_LENGTH: usize = 8;
+const MAX_PASSWORD_LENGTH: usize = 128;
+
+/// Register an issuer account and sign it in
+///
+/// Accounts with other roles are created by an admin.
+pub async fn register(
+    repo: web::Data<dyn GiftCardRepository>,
+    config: web::Data<Config>,
+    register_dto: web::Json<RegisterIssuerDto>,
+) -> HttpResponse {
+    let dto = register_dto.into_inner();
+    let result = async {
+        let account = new_account(dto.email, dto.name, &dto.password, Role::Issuer, None, None)?;
+        repo.insert_account(&account).await?;
+        
+        let tokens = issue_tokens(&config, account.id, account.created_at)?;
+        Ok(AccountSessionDto { account, tokens })
+    }
+    .await;
+    
+    match result {
+        Ok(session) => HttpResponse::Created().json(ApiResponse {
+            success: true,
+            data: Some(session),
+            message: Some("Account created".to_string()),
+        }),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Create an account of any role (admin only)
+pub async fn create_account(
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
+    account_dto: web::Json<CreateAccountDto>,
+) -> HttpResponse {
+    let dto = account_dto.into_inner();
+    let result = async {
+        user.require(&[Role::Admin])?;
+        
+        // Merchant accounts act for an existing merchant, recipient accounts for a phone number
+        let merchant_id = match (dto.role, dto.merchant_id) {
+            (Role::Merchant, Some(merchant_id)) => {
+                repo.find_merchant(merchant_id)
+                    .await?
+                    .ok_or_else(|| AppError::NotFoundError("Merchant not found".to_string()))?;
+                Some(merchant_id)
+            }
+            (Role::Merchant, None) => {
+                return Err(AppError::ValidationError("Merchant accounts need a merchant_id".to_string()));
+            }
+            (_, Some(_)) => {
+                return Err(AppError::ValidationError("Only merchant accounts have a merchant_id".to_string()));
+            }
+            (_, None) => None,
+        };
+        let phone = match (dto.role, dto.phone) {
+            (Role::Recipient, Some(phone)) if validate_phone(&phone) => Some(phone),
+            (Role::Recipient, _) => {
+                return Err(AppError::ValidationError("Recipient accounts need a valid phone".to_string()));
+            }
+            (_, Some(_)) => {
+                return Err(AppError::ValidationError("Only recipient accounts have a phone".to_string()));
+            }
+            (_, None) => None,
+        };
+        
+        let account = new_account(dto.email, dto.name, &dto.password, dto.role, merchant_id, phone)?;
+        repo.insert_account(&account).await?;
+        Ok(account)
+    }
+    .await;
+    
+    match result {
+        Ok(account) => HttpResponse::Created().json(ApiResponse {
+            success: true,
+            data: Some(account),
+            message: Some("Account created".to_string()),
+        }),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Sign in with an email and password
+pub async fn login(
+    repo: web::Data<dyn GiftCardRepository>,
+    config: web::Data<Config>,
+    login_dto: web::Json<LoginDto>,
+) -> HttpResponse {
+    let dto = login_dto.into_inner();
+    let result = async {
+        // The same error for an unknown email and a wrong password
+        let account = repo
+            .find_account_by_email(&normalize_email(&dto.email))
+            .await?
+            .filter(|account| verify_password(&dto.password, &account.password_hash))
+            .ok_or_else(|| AppError::UnauthorizedError("Invalid email or password".to_string()))?;
+        
+        let tokens = issue_tokens(&config, account.id, Utc::now())?;
+        Ok(AccountSessionDto { account, tokens })
+    }
+    .await;
+    
+    match result {
+        Ok(session) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(session),
+            message: None,
+        }),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Exchange a refresh token for a new access and refresh token
+pub async fn refresh(
+    repo: web::Data<dyn GiftCardRepository>,
+    config: web::Data<Config>,
+    refresh_dto: web::Json<RefreshTokenDto>,
+) -> HttpResponse {
+    let result = async {
+        let claims = decode_token(&config, &refresh_dto.refresh_token, TokenKind::Refresh)?;
+        
+        // Tokens of an account that no longer exists are not renewed
+        if repo.find_account(claims.sub).await?.is_none() {
+            return Err(AppError::UnauthorizedError("Invalid or expired token".to_string()));
+        }
+        issue_tokens(&config, claims.sub, Utc::now())
+    }
+    .await;
+    
+    match result {
+        Ok(tokens) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(tokens),
+            message: None,
+        }),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Show the signed-in account
+pub async fn me(
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
+) -> HttpResponse {
+    match repo.find_account(user.id).await {
+        Ok(Some(account)) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(account),
+            message: None,
+        }),
+        Ok(None) => error_response(AppError::UnauthorizedError("Account not found".to_string())),
+        Err(e) => error_response(e),
+    }
+}
+
+// Helper functions
+
+/// Emails are compared case-insensitively, so they are stored lower-cased
+fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Validate the fields every account has and hash its password
+pub fn new_account(
+    email: String,
+    name: String,
+    password: &str,
+    role: Role,
+    merchant_id: Option<Uuid>,
+    phone: Option<String>,
+) -> Result<Account, AppError> {
+    let email = normalize_email(&email);
+    if !validate_email(&email) {
+        return Err(AppError::ValidationError("Invalid email".to_string()));
+    }
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > 100 {
+        return Err(AppError::ValidationError("Name must be 1 to 100 characters".to_string()));
+    }
+    let password_length = password.chars().count();
+    if !(MIN_PASSWORD_LENGTH..=MAX_PASSWORD_LENGTH).contains(&password_length) {
+        return Err(AppError::ValidationError(format!(
+            "Password must be {} to {} characters",
+            MIN_PASSWORD_LENGTH, MAX_PASSWORD_LENGTH
+        )));
+    }
+    
+    let now = Utc::now();
+    Ok(Account {
+        id: Uuid::new_v4(),
+        email,
+        name: name.to_string(),
+        role,
+        merchant_id,
+        phone,
+        password_hash: hash_password(password)?,
+        created_at: now,
+        updated_at: now,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::test_support::{admin_auth, issuer_auth, test_config, test_repo};
+    use actix_web::http::header::AUTHORIZATION;
+    use actix_web::{test, App};
+    use serde_json::{json, Value};
+
+    #[actix_web::test]
+    async fn test_register_login_and_issue_a_card() {
+        let app = test::init_service(
+            App::new()
+                .app_data(test_repo())
+                .app_data(test_config())
+                .configure(crate::routes::auth::config)
+                .configure(crate::routes::gift_cards::config),
+        )
+        .await;
+
+        let register = json!({ "email": "Alice@Example.com", "name": "Alice's Bakery", "password": "correct horse" });
+        let req = test::TestRequest::post().uri("/auth/register").set_json(&register).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 201);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["data"]["account"]["email"], "alice@example.com");
+        assert!(body["data"]["account"].get("password_hash").is_none());
+        let issuer_id = body["data"]["account"]["id"].as_str().unwrap().to_string();
+
+        let req = test::TestRequest::post().uri("/auth/register").set_json(&register).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 409);
+
+        let req = test::TestRequest::post()
+            .uri("/auth/login")
+            .set_json(json!({ "email": "alice@example.com", "password": "wrong horse" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+
+        let req = test::TestRequest::post()
+            .uri("/auth/login")
+            .set_json(json!({ "email": "alice@example.com", "password": "correct horse" }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        let access = body["data"]["tokens"]["access_token"].as_str().unwrap().to_string();
+        let refresh = body["data"]["tokens"]["refresh_token"].as_str().unwrap().to_string();
+
+        // A refresh token is not accepted as a bearer token, but renews the pair
+        let req = test::TestRequest::get()
+            .uri("/auth/me")
+            .insert_header((AUTHORIZATION, format!("Bearer {}", refresh)))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+
+        let req = test::TestRequest::post()
+            .uri("/auth/refresh")
+            .set_json(json!({ "refresh_token": refresh }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert!(body["data"]["access_token"].is_string());
+
+        let card = json!({
+            "recipient_name": "Bob",
+            "recipient_phone": "1234567890",
+            "balance": { "amount": 5000, "currency": "USD" },
+            "expiration_days": 30
+        });
+        let req = test::TestRequest::post().uri("/gift-cards").set_json(&card).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+
+        // The card is linked to the account, and named after it
+        let req = test::TestRequest::post()
+            .uri("/gift-cards")
+            .insert_header((AUTHORIZATION, format!("Bearer {}", access)))
+            .set_json(&card)
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["issuer_id"], issuer_id.as_str());
+        assert_eq!(body["data"]["issuer_name"], "Alice's Bakery");
+    }
+
+    #[actix_web::test]
+    async fn test_only_admins_create_accounts() {
+        let repo = test_repo();
+        let config = test_config();
+        let issuer = issuer_auth(&repo, &config, "Alice").await;
+        let admin = admin_auth(&repo, &config).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(repo)
+                .app_data(config)
+                .configure(crate::routes::auth::config),
+        )
+        .await;
+
+        let recipient = json!({
+            "email": "bob@example.com",
+            "name": "Bob",
+            "password": "correct horse",
+            "role": "recipient",
+            "phone": "1234567890"
+        });
+        let req = test::TestRequest::post()
+            .uri("/auth/accounts")
+            .insert_header((AUTHORIZATION, issuer.as_str()))
+            .set_json(&recipient)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+
+        let req = test::TestRequest::post()
+            .uri("/auth/accounts")
+            .insert_header((AUTHORIZATION, admin.as_str()))
+            .set_json(&recipient)
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["role"], "recipient");
+        assert_eq!(body["data"]["phone"], "1234567890");
+
+        // Merchant accounts must act for a known merchant
+        let req = test::TestRequest::post()
+            .uri("/auth/accounts")
+            .insert_header((AUTHORIZATION, admin.as_str()))
+            .set_json(json!({ "email": "till@example.com", "name": "Till", "password": "correct horse", "role": "merchant" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+    }
+}
@@ -0,0 +1,455 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::account::{Account, Role};
+use crate::models::batch::{BatchCsvRow, BatchMode, BatchQuery, BatchRowResult, BatchStatus, IssuanceBatch};
+use crate::models::card_code::CardCode;
+use crate::models::gift_card::{CreateGiftCardDto, GiftCard};
+use crate::models::ledger::JournalEntry;
+use crate::repository::{CardUpdate, GiftCardRepository};
+use crate::utils::error::AppError;
+use crate::utils::short_code::format_short_code;
+use crate::utils::validation::{validate_amount, validate_expiration_days, validate_name, validate_phone, validate_pin};
+use super::gift_cards::hash_card_pin;
+use super::{error_response, ApiResponse};
+
+/// Largest batch upload accepted, in bytes
+pub const MAX_BODY_BYTES: usize = 4 * 1024 * 1024;
+
+/// Best-effort batches save their progress after this many rows
+const PROGRESS_EVERY: usize = 50;
+
+/// A row ready to be written: the card, its issuance entry and its short code
+type PreparedRow = (GiftCard, JournalEntry, CardCode);
+
+/// Issue gift cards from a CSV or JSON batch
+///
+/// Every row is validated before anything is issued. Batches of up to
+/// `BATCH_ASYNC_THRESHOLD` rows are issued before responding; larger ones are
+/// accepted with `202 Accepted` and issued in the background.
+pub async fn create_batch(
+    repo: web::Data<dyn GiftCardRepository>,
+    config: web::Data<Config>,
+    user: AuthenticatedUser,
+    req: HttpRequest,
+    query: web::Query<BatchQuery>,
+    body: web::Bytes,
+) -> HttpResponse {
+    if let Err(e) = user.require(&[Role::Admin, Role::Issuer]) {
+        return error_response(e);
+    }
+    
+    // The cards are issued in the name of the signed-in account
+    let issuer = match repo.find_account(user.id).await {
+        Ok(Some(account)) => account,
+        Ok(None) => return error_response(AppError::UnauthorizedError("Account not found".to_string())),
+        Err(e) => return error_response(e),
+    };
+    
+    let rows = match parse_rows(req.content_type(), &body) {
+        Ok(rows) => rows,
+        Err(e) => return error_response(e),
+    };
+    if rows.is_empty() {
+        return error_response(AppError::ValidationError("The batch has no rows".to_string()));
+    }
+    if rows.len() > config.batch_max_rows {
+        return error_response(AppError::ValidationError(format!(
+            "A batch can have at most {} rows",
+            config.batch_max_rows
+        )));
+    }
+    
+    let mut batch = IssuanceBatch {
+        id: Uuid::new_v4(),
+        issuer_id: issuer.id,
+        mode: query.mode.unwrap_or_default(),
+        status: BatchStatus::Pending,
+        total_rows: rows.len() as i32,
+        issued: 0,
+        failed: 0,
+        results: Vec::with_capacity(rows.len()),
+        error: None,
+        created_at: Utc::now(),
+        completed_at: None,
+    };
+    
+    // Rows that pass validation go on to be issued, by their index in the batch
+    let mut valid = Vec::with_capacity(rows.len());
+    for (index, row) in rows.into_iter().enumerate() {
+        let errors = match row {
+            Ok(dto) => {
+                let errors = check_row(&dto);
+                if errors.is_empty() {
+                    valid.push((index, dto));
+                }
+                errors
+            }
+            Err(e) => vec![e],
+        };
+        batch.results.push(BatchRowResult { row: index + 1, errors, ..Default::default() });
+    }
+    batch.failed = (batch.results.len() - valid.len()) as i32;
+    
+    if batch.mode == BatchMode::AllOrNothing && batch.failed > 0 {
+        batch.status = BatchStatus::Failed;
+        batch.error = Some(format!("{} row(s) are invalid, so no gift cards were issued", batch.failed));
+        batch.completed_at = Some(Utc::now());
+        if let Err(e) = repo.insert_batch(&batch).await {
+            return error_response(e);
+        }
+        return HttpResponse::BadRequest().json(ApiResponse {
+            success: false,
+            message: batch.error.clone(),
+            data: Some(batch),
+        });
+    }
+    
+    if let Err(e) = repo.insert_batch(&batch).await {
+        return error_response(e);
+    }
+    
+    if valid.len() > config.batch_async_threshold {
+        let response = HttpResponse::Accepted().json(ApiResponse {
+            success: true,
+            data: Some(batch.clone()),
+            message: Some("Batch accepted; its gift cards are being issued".to_string()),
+        });
+        let repo = repo.clone();
+        actix_web::rt::spawn(async move {
+            run_batch(repo.get_ref(), &issuer, batch, valid).await;
+        });
+        return response;
+    }
+    
+    let batch = run_batch(repo.get_ref(), &issuer, batch, valid).await;
+    match batch.status {
+        BatchStatus::Completed => HttpResponse::Created().json(ApiResponse {
+            success: true,
+            message: Some(format!("Issued {} gift card(s); {} row(s) failed", batch.issued, batch.failed)),
+            data: Some(batch),
+        }),
+        _ => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false,
+            message: batch.error.clone(),
+            data: Some(batch),
+        }),
+    }
+}
+
+/// Get an issuance batch, with the result of each row so far
+pub async fn get_batch(
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let batch_id = match Uuid::parse_str(&path.into_inner()) {
+        Ok(id) => id,
+        Err(_) => return error_response(AppError::ValidationError("Invalid batch ID".to_string())),
+    };
+    
+    let result = async {
+        let batch = repo
+            .find_batch(batch_id)
+            .await?
+            .ok_or_else(|| AppError::NotFoundError("Batch not found".to_string()))?;
+        user.require_account_or_admin(batch.issuer_id)?;
+        Ok(batch)
+    }
+    .await;
+    
+    match result {
+        Ok(batch) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(batch),
+            message: None,
+        }),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Read the rows of a batch from a `text/csv` or `application/json` body
+///
+/// A row that cannot be read becomes its error message, so it is reported
+/// with the others; only a body that cannot be read at all is an error.
+fn parse_rows(content_type: &str, body: &[u8]) -> Result<Vec<Result<CreateGiftCardDto, String>>, AppError> {
+    match content_type {
+        "text/csv" => {
+            let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).flexible(true).from_reader(body);
+            Ok(reader
+                .deserialize::<BatchCsvRow>()
+                .map(|row| {
+                    row.map(CreateGiftCardDto::from).map_err(|e| match e.kind() {
+                        csv::ErrorKind::Deserialize { err, .. } => err.to_string(),
+                        _ => e.to_string(),
+                    })
+                })
+                .collect())
+        }
+        "application/json" => {
+            let rows: Vec<serde_json::Value> = serde_json::from_slice(body)
+                .map_err(|e| AppError::ValidationError(format!("Expected a JSON array of gift cards: {}", e)))?;
+            Ok(rows
+                .into_iter()
+                .map(|row| serde_json::from_value(row).map_err(|e| e.to_string()))
+                .collect())
+        }
+        _ => Err(AppError::ValidationError(
+            "Send the batch as text/csv or application/json".to_string(),
+        )),
+    }
+}
+
+/// Everything wrong with a row, or nothing if it can be issued
+fn check_row(dto: &CreateGiftCardDto) -> Vec<String> {
+    let mut errors = Vec::new();
+    if !validate_name(&dto.recipient_name) {
+        errors.push("Recipient name must be 2 to 50 letters, spaces, hyphens, apostrophes or dots".to_string());
+    }
+    if !validate_phone(&dto.recipient_phone) {
+        errors.push("Recipient phone must be 10 to 15 digits".to_string());
+    }
+    if !validate_amount(dto.balance) {
+        errors.push(format!("Balance must be positive and at most 10000 {}", dto.balance.currency()));
+    }
+    if !validate_expiration_days(dto.expiration_days) {
+        errors.push("Expiration days must be between 1 and 1825".to_string());
+    }
+    if dto.pin.as_deref().is_some_and(|pin| !validate_pin(pin)) {
+        errors.push("PIN must be 4 to 8 digits".to_string());
+    }
+    errors
+}
+
+/// Issue the valid rows of `batch`, saving its progress and final state
+///
+/// All-or-nothing batches write every card in one transaction; best-effort
+/// batches write each card on its own and carry on past failures.
+async fn run_batch(
+    repo: &dyn GiftCardRepository,
+    issuer: &Account,
+    mut batch: IssuanceBatch,
+    rows: Vec<(usize, CreateGiftCardDto)>,
+) -> IssuanceBatch {
+    batch.status = BatchStatus::Running;
+    save_batch(repo, &batch).await;
+
+    match batch.mode {
+        BatchMode::AllOrNothing => {
+            if let Err(e) = issue_together(repo, issuer, &mut batch, rows).await {
+                log::error!("Error issuing batch {}: {:?}", batch.id, e);
+                batch.status = BatchStatus::Failed;
+                batch.error = Some("Failed to issue gift cards, so none were issued".to_string());
+            }
+        }
+        BatchMode::BestEffort => {
+            for (done, (index, dto)) in rows.into_iter().enumerate() {
+                let result = async {
+                    let (card, issuance, card_code) = prepare_row(issuer, &dto)?;
+                    repo.insert_card(&card, &issuance).await?;
+                    Ok::<_, AppError>((card.id, card_code))
+                }
+                .await;
+
+                match result {
+                    Ok((gift_card_id, card_code)) => {
+                        store_card_code(repo, &mut batch.results[index], gift_card_id, &card_code).await;
+                        batch.issued += 1;
+                    }
+                    Err(e) => {
+                        log::error!("Error issuing row {} of batch {}: {:?}", index + 1, batch.id, e);
+                        batch.results[index].errors.push("Failed to issue gift card".to_string());
+                        batch.failed += 1;
+                    }
+                }
+
+                if (done + 1) % PROGRESS_EVERY == 0 {
+                    save_batch(repo, &batch).await;
+                }
+            }
+        }
+    }
+
+    if batch.status == BatchStatus::Running {
+        batch.status = BatchStatus::Completed;
+    }
+    batch.completed_at = Some(Utc::now());
+    save_batch(repo, &batch).await;
+    batch
+}
+
+/// Write every row's card in one transaction, then their short codes
+async fn issue_together(
+    repo: &dyn GiftCardRepository,
+    issuer: &Account,
+    batch: &mut IssuanceBatch,
+    rows: Vec<(usize, CreateGiftCardDto)>,
+) -> Result<(), AppError> {
+    let prepared = rows
+        .iter()
+        .map(|(index, dto)| prepare_row(issuer, dto).map(|prepared| (*index, prepared)))
+        .collect::<Result<Vec<(usize, PreparedRow)>, AppError>>()?;
+
+    let changes = CardUpdate {
+        cards: prepared.iter().map(|(_, (card, _, _))| card.clone()).collect(),
+        entries: prepared.iter().map(|(_, (_, issuance, _))| issuance.clone()).collect(),
+        ..Default::default()
+    };
+    repo.update_cards(&[], Box::new(move |_| Ok(changes))).await?;
+
+    for (index, (card, _, card_code)) in &prepared {
+        store_card_code(repo, &mut batch.results[*index], card.id, card_code).await;
+    }
+    batch.issued = prepared.len() as i32;
+    Ok(())
+}
+
+/// Build a valid row's card, issuance entry and short code, hashing its PIN
+fn prepare_row(issuer: &Account, dto: &CreateGiftCardDto) -> Result<PreparedRow, AppError> {
+    let now = Utc::now();
+    let pin_hash = dto.pin.as_deref().map(hash_card_pin).transpose()?;
+    let card = GiftCard::issue(issuer, dto, now);
+    let issuance = JournalEntry::issuance(&card);
+    let card_code = CardCode::new(card.id, pin_hash, now);
+    Ok((card, issuance, card_code))
+}
+
+/// Store an issued card's short code and record the card in its row
+///
+/// The card exists either way; if its code cannot be stored the row says so,
+/// and the card gets a code without a PIN when it is next viewed.
+async fn store_card_code(repo: &dyn GiftCardRepository, result: &mut BatchRowResult, gift_card_id: Uuid, card_code: &CardCode) {
+    result.gift_card_id = Some(gift_card_id);
+    match repo.insert_card_code(card_code).await {
+        Ok(stored) => result.code = Some(format_short_code(&stored.code)),
+        Err(e) => {
+            log::error!("Error storing short code of gift card {}: {:?}", gift_card_id, e);
+            result.errors.push("Gift card issued, but its short code and PIN were not stored".to_string());
+        }
+    }
+}
+
+/// Save a batch's progress, logging failures so the batch carries on
+async fn save_batch(repo: &dyn GiftCardRepository, batch: &IssuanceBatch) {
+    if let Err(e) = repo.update_batch(batch).await {
+        log::error!("Error saving batch {}: {:?}", batch.id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::test_support::{issuer_auth, test_config, test_repo};
+    use actix_web::http::header::{AUTHORIZATION, CONTENT_TYPE};
+    use actix_web::{test, App};
+    use serde_json::{json, Value};
+
+    const CSV: &str = "recipient_name,recipient_phone,amount,currency,expiration_days,pin\n\
+        Bob,1234567890,5000,USD,30,\n\
+        \"Ann O'Neil\",0987654321,2500,EUR,365,1234\n\
+        Carol,12345,100,USD,0,12\n";
+
+    #[actix_web::test]
+    async fn test_csv_batches_report_each_row() {
+        let repo = test_repo();
+        let config = test_config();
+        let issuer = issuer_auth(&repo, &config, "Alice").await;
+        let other = issuer_auth(&repo, &config, "Mallory").await;
+        let app = test::init_service(
+            App::new()
+                .app_data(repo.clone())
+                .app_data(config)
+                .configure(crate::routes::batches::config),
+        )
+        .await;
+
+        let submit = |mode: &str| {
+            test::TestRequest::post()
+                .uri(&format!("/gift-card-batches?mode={}", mode))
+                .insert_header((AUTHORIZATION, issuer.as_str()))
+                .insert_header((CONTENT_TYPE, "text/csv"))
+                .set_payload(CSV)
+                .to_request()
+        };
+
+        // One bad row rejects an all-or-nothing batch, and names every problem with it
+        let resp = test::call_service(&app, submit("all_or_nothing")).await;
+        assert_eq!(resp.status(), 400);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["data"]["status"], "failed");
+        assert_eq!(body["data"]["issued"], 0);
+        assert_eq!(body["data"]["results"][1]["errors"], json!([]));
+        assert_eq!(body["data"]["results"][2]["errors"].as_array().unwrap().len(), 3);
+        assert_eq!(repo.list_cards_by_recipient("1234567890", 10, 0).await.unwrap().len(), 0);
+
+        let resp = test::call_service(&app, submit("best_effort")).await;
+        assert_eq!(resp.status(), 201);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["data"]["status"], "completed");
+        assert_eq!(body["data"]["issued"], 2);
+        assert_eq!(body["data"]["failed"], 1);
+        let row = &body["data"]["results"][1];
+        assert_eq!(row["code"].as_str().unwrap().len(), 19);
+        let card = repo.find_card(row["gift_card_id"].as_str().unwrap().parse().unwrap()).await.unwrap().unwrap();
+        assert_eq!(card.recipient_name, "Ann O'Neil");
+        assert_eq!(card.issuer_name, "Alice");
+        assert!(repo.find_card_code(card.id).await.unwrap().unwrap().pin_hash.is_some());
+
+        // Batches are only shown to the issuer that sent them
+        let batch = |auth: &str| {
+            test::TestRequest::get()
+                .uri(&format!("/gift-card-batches/{}", body["data"]["id"].as_str().unwrap()))
+                .insert_header((AUTHORIZATION, auth))
+                .to_request()
+        };
+        let resp = test::call_service(&app, batch(&issuer)).await;
+        assert_eq!(resp.status(), 200);
+        let resp = test::call_service(&app, batch(&other)).await;
+        assert_eq!(resp.status(), 403);
+    }
+
+    #[actix_web::test]
+    async fn test_large_batches_run_in_the_background() {
+        let repo = test_repo();
+        let config = web::Data::new(Config { batch_async_threshold: 1, ..test_config().get_ref().clone() });
+        let issuer = issuer_auth(&repo, &config, "Alice").await;
+        let app = test::init_service(
+            App::new()
+                .app_data(repo.clone())
+                .app_data(config)
+                .configure(crate::routes::batches::config),
+        )
+        .await;
+
+        let row = json!({
+            "recipient_name": "Bob",
+            "recipient_phone": "1234567890",
+            "balance": { "amount": 5000, "currency": "USD" },
+            "expiration_days": 30
+        });
+        let req = test::TestRequest::post()
+            .uri("/gift-card-batches")
+            .insert_header((AUTHORIZATION, issuer.as_str()))
+            .set_json(json!([row, row, row]))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 202);
+        let body: Value = test::read_body_json(resp).await;
+        let id: Uuid = body["data"]["id"].as_str().unwrap().parse().unwrap();
+
+        let mut batch = repo.find_batch(id).await.unwrap().unwrap();
+        for _ in 0..100 {
+            if batch.status == BatchStatus::Completed {
+                break;
+            }
+            actix_web::rt::time::sleep(std::time::Duration::from_millis(10)).await;
+            batch = repo.find_batch(id).await.unwrap().unwrap();
+        }
+        assert_eq!(batch.status, BatchStatus::Completed);
+        assert_eq!(batch.issued, 3);
+        assert_eq!(repo.list_cards_by_recipient("1234567890", 10, 0).await.unwrap().len(), 3);
+    }
+}
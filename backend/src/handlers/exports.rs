@@ -0,0 +1,397 @@
+use actix_web::http::header::{CACHE_CONTROL, CONTENT_DISPOSITION};
+use actix_web::web::Bytes;
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use futures_util::stream;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::account::Role;
+use crate::models::export::{ExportFilter, ExportFormat, ExportQuery, ExportRecord};
+use crate::models::gift_card::{GiftCard, GiftCardTransaction};
+use crate::repository::GiftCardRepository;
+use crate::utils::error::AppError;
+use super::error_response;
+
+/// Records fetched per query while an export streams
+const PAGE_SIZE: i64 = 500;
+
+/// Export gift cards as CSV or NDJSON
+///
+/// Filters by creation date, status and issuer. Issuers can only export their
+/// own cards.
+pub async fn export_gift_cards(
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
+    query: web::Query<ExportQuery>,
+) -> HttpResponse {
+    let query = query.into_inner();
+    
+    if query.kind.is_some() || query.merchant_id.is_some() {
+        return error_response(AppError::ValidationError(
+            "Gift cards can be filtered by date, status and issuer".to_string(),
+        ));
+    }
+    
+    let result = async {
+        user.require(&[Role::Admin, Role::Issuer])?;
+        let columns = parse_columns::<GiftCard>(query.columns.as_deref())?;
+        let filter = export_filter(&user, &query)?;
+        Ok((columns, filter))
+    }
+    .await;
+    
+    match result {
+        Ok((columns, filter)) => stream_export::<GiftCard>(repo, filter, query.format.unwrap_or_default(), columns).await,
+        Err(e) => error_response(e),
+    }
+}
+
+/// Export transactions as CSV or NDJSON
+///
+/// Filters by date, kind, the issuer of the card and merchant. Issuers can
+/// only export transactions on their own cards, and merchants their own
+/// transactions.
+pub async fn export_transactions(
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
+    query: web::Query<ExportQuery>,
+) -> HttpResponse {
+    let query = query.into_inner();
+    
+    if query.status.is_some() {
+        return error_response(AppError::ValidationError(
+            "Transactions can be filtered by date, kind, issuer and merchant".to_string(),
+        ));
+    }
+    
+    let result = async {
+        user.require(&[Role::Admin, Role::Issuer, Role::Merchant])?;
+        let columns = parse_columns::<GiftCardTransaction>(query.columns.as_deref())?;
+        let filter = export_filter(&user, &query)?;
+        Ok((columns, filter))
+    }
+    .await;
+    
+    match result {
+        Ok((columns, filter)) => {
+            stream_export::<GiftCardTransaction>(repo, filter, query.format.unwrap_or_default(), columns).await
+        }
+        Err(e) => error_response(e),
+    }
+}
+
+/// Records the export endpoints stream, fetched a page at a time
+trait Exportable: ExportRecord + Sized + 'static {
+    /// Name of the downloaded file, without its extension
+    const FILE_NAME: &'static str;
+
+    /// The page of records after `after`
+    async fn page(
+        repo: &dyn GiftCardRepository,
+        filter: &ExportFilter,
+        after: Option<(DateTime<Utc>, Uuid)>,
+    ) -> Result<Vec<Self>, AppError>;
+}
+
+impl Exportable for GiftCard {
+    const FILE_NAME: &'static str = "gift-cards";
+
+    async fn page(
+        repo: &dyn GiftCardRepository,
+        filter: &ExportFilter,
+        after: Option<(DateTime<Utc>, Uuid)>,
+    ) -> Result<Vec<Self>, AppError> {
+        repo.export_cards(filter, after, PAGE_SIZE).await
+    }
+}
+
+impl Exportable for GiftCardTransaction {
+    const FILE_NAME: &'static str = "transactions";
+
+    async fn page(
+        repo: &dyn GiftCardRepository,
+        filter: &ExportFilter,
+        after: Option<(DateTime<Utc>, Uuid)>,
+    ) -> Result<Vec<Self>, AppError> {
+        repo.export_transactions(filter, after, PAGE_SIZE).await
+    }
+}
+
+/// Where a streaming export has got to
+struct ExportState<T> {
+    repo: web::Data<dyn GiftCardRepository>,
+    filter: ExportFilter,
+    format: ExportFormat,
+    columns: Vec<&'static str>,
+    first: Option<Vec<T>>,                   // The page fetched before the response started
+    after: Option<(DateTime<Utc>, Uuid)>,    // Cursor of the last record sent
+    header: bool,                            // Whether the CSV header still has to be sent
+    done: bool,
+}
+
+/// Stream the records matching `filter` as `format`, one chunk per page
+///
+/// The first page is fetched before responding, so a failing query still gets
+/// an error response; a failure after that cuts the download short.
+async fn stream_export<T: Exportable>(
+    repo: web::Data<dyn GiftCardRepository>,
+    filter: ExportFilter,
+    format: ExportFormat,
+    columns: Vec<&'static str>,
+) -> HttpResponse {
+    let first = match T::page(repo.get_ref(), &filter, None).await {
+        Ok(page) => page,
+        Err(e) => return error_response(e),
+    };
+
+    let state = ExportState {
+        repo,
+        filter,
+        format,
+        columns,
+        first: Some(first),
+        after: None,
+        header: format == ExportFormat::Csv,
+        done: false,
+    };
+    let body = stream::unfold(state, |mut state| async move {
+        if state.done {
+            return None;
+        }
+
+        let page = match state.first.take() {
+            Some(page) => page,
+            None => match T::page(state.repo.get_ref(), &state.filter, state.after).await {
+                Ok(page) => page,
+                Err(e) => {
+                    log::error!("Error streaming {} export: {:?}", T::FILE_NAME, e);
+                    state.done = true;
+                    return Some((Err(actix_web::error::ErrorInternalServerError("Export failed")), state));
+                }
+            },
+        };
+        state.done = (page.len() as i64) < PAGE_SIZE;
+        state.after = page.last().map(ExportRecord::cursor).or(state.after);
+
+        let chunk = encode_page(&page, &state.columns, state.format, std::mem::take(&mut state.header));
+        Some((chunk.map(Bytes::from).map_err(actix_web::Error::from), state))
+    });
+
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .insert_header((
+            CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}.{}\"", T::FILE_NAME, format.extension()),
+        ))
+        .streaming(body)
+}
+
+/// Encode `records` as lines of `format`, after the CSV header if `header` is set
+fn encode_page<T: ExportRecord>(
+    records: &[T],
+    columns: &[&'static str],
+    format: ExportFormat,
+    header: bool,
+) -> Result<Vec<u8>, AppError> {
+    let failed = |e: &dyn std::fmt::Display| AppError::InternalServerError(format!("Failed to encode export: {}", e));
+
+    match format {
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            if header {
+                writer.write_record(columns).map_err(|e| failed(&e))?;
+            }
+            for record in records {
+                let cells = columns.iter().map(|column| match record.value(column) {
+                    Value::String(text) => text,
+                    Value::Null => String::new(),
+                    value => value.to_string(),
+                });
+                writer.write_record(cells).map_err(|e| failed(&e))?;
+            }
+            writer.into_inner().map_err(|e| failed(&e))
+        }
+        ExportFormat::Ndjson => {
+            let mut lines = Vec::new();
+            for record in records {
+                let object: serde_json::Map<String, Value> =
+                    columns.iter().map(|column| (column.to_string(), record.value(column))).collect();
+                serde_json::to_writer(&mut lines, &object).map_err(|e| failed(&e))?;
+                lines.push(b'\n');
+            }
+            Ok(lines)
+        }
+    }
+}
+
+/// The columns named in a comma separated `spec`, or every column when it is unset
+fn parse_columns<T: ExportRecord>(spec: Option<&str>) -> Result<Vec<&'static str>, AppError> {
+    let names: Vec<&str> = spec
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect();
+    if names.is_empty() {
+        return Ok(T::COLUMNS.to_vec());
+    }
+
+    names
+        .into_iter()
+        .map(|name| {
+            T::COLUMNS.iter().copied().find(|column| *column == name).ok_or_else(|| {
+                AppError::ValidationError(format!("Unknown column {}; choose from {}", name, T::COLUMNS.join(", ")))
+            })
+        })
+        .collect()
+}
+
+/// The filter for `query`, narrowed to what `user` may see
+fn export_filter(user: &AuthenticatedUser, query: &ExportQuery) -> Result<ExportFilter, AppError> {
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        if from >= to {
+            return Err(AppError::ValidationError("from must be before to".to_string()));
+        }
+    }
+
+    let mut filter = ExportFilter {
+        from: query.from,
+        to: query.to,
+        status: query.status,
+        kind: query.kind,
+        issuer_id: query.issuer_id,
+        merchant_id: query.merchant_id,
+    };
+    match user.role {
+        Role::Issuer => {
+            user.require_account_or_admin(filter.issuer_id.unwrap_or(user.id))?;
+            filter.issuer_id = Some(user.id);
+        }
+        Role::Merchant => {
+            let merchant_id = user.merchant_id.unwrap_or_default();
+            user.require_merchant(filter.merchant_id.unwrap_or(merchant_id))?;
+            filter.merchant_id = Some(merchant_id);
+        }
+        _ => {}
+    }
+    Ok(filter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::test_support::{admin_auth, issuer_auth, merchant_auth, test_config, test_repo};
+    use crate::models::card_status::CardStatus;
+    use crate::models::gift_card::CreateGiftCardDto;
+    use crate::models::ledger::JournalEntry;
+    use crate::models::merchant::Merchant;
+    use crate::models::money::{Currency, Money};
+    use crate::repository::CardUpdate;
+    use actix_web::http::header::AUTHORIZATION;
+    use actix_web::{test, App};
+
+    /// Issue a card from the issuer behind `auth`, returning it
+    async fn issue(repo: &web::Data<dyn GiftCardRepository>, issuer_name: &str, amount: i64) -> GiftCard {
+        let issuer = repo.find_account_by_email(&format!("{}@issuer.example", issuer_name.to_lowercase())).await;
+        let dto = CreateGiftCardDto {
+            recipient_name: "Bob".to_string(),
+            recipient_phone: "1234567890".to_string(),
+            balance: Money::new(amount, Currency::USD),
+            expiration_days: 30,
+            pin: None,
+        };
+        let card = GiftCard::issue(&issuer.unwrap().unwrap(), &dto, Utc::now());
+        repo.insert_card(&card, &JournalEntry::issuance(&card)).await.unwrap();
+        card
+    }
+
+    #[actix_web::test]
+    async fn test_gift_card_exports_page_through_every_card() {
+        let repo = test_repo();
+        let config = test_config();
+        let admin = admin_auth(&repo, &config).await;
+        let alice = issuer_auth(&repo, &config, "Alice").await;
+        issuer_auth(&repo, &config, "Carol").await;
+        let app = test::init_service(
+            App::new()
+                .app_data(repo.clone())
+                .app_data(config)
+                .configure(crate::routes::exports::config),
+        )
+        .await;
+
+        for _ in 0..PAGE_SIZE + 1 {
+            issue(&repo, "Carol", 1000).await;
+        }
+        let own = issue(&repo, "Alice", 5000).await;
+
+        let export = |auth: &str, query: &str| {
+            test::TestRequest::get()
+                .uri(&format!("/exports/gift-cards{}", query))
+                .insert_header((AUTHORIZATION, auth))
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, export(&admin, "?format=ndjson&status=issued")).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers().get("content-type").unwrap(), "application/x-ndjson");
+        let body = test::read_body(resp).await;
+        let lines: Vec<Value> = body.split(|b| *b == b'\n').filter(|l| !l.is_empty()).map(|l| serde_json::from_slice(l).unwrap()).collect();
+        assert_eq!(lines.len() as i64, PAGE_SIZE + 2);
+        assert!(lines.windows(2).all(|pair| pair[0]["created_at"].as_str() <= pair[1]["created_at"].as_str()));
+
+        // Issuers only see their own cards, in the columns they ask for
+        let body = test::call_and_read_body(&app, export(&alice, "?columns=id,%20balance,status")).await;
+        assert_eq!(String::from_utf8(body.to_vec()).unwrap(), format!("id,balance,status\n{},$50.00,issued\n", own.id));
+
+        let resp = test::call_service(&app, export(&admin, "?columns=id,secret")).await;
+        assert_eq!(resp.status(), 400);
+
+        // An empty export is still a CSV file with its header
+        let resp = test::call_service(&app, export(&admin, &format!("?status={}", CardStatus::Cancelled))).await;
+        assert_eq!(test::read_body(resp).await, format!("{}\n", GiftCard::COLUMNS.join(",")));
+    }
+
+    #[actix_web::test]
+    async fn test_merchants_only_export_their_own_transactions() {
+        let repo = test_repo();
+        let config = test_config();
+        issuer_auth(&repo, &config, "Alice").await;
+        let cafe = Merchant::new("Cafe", None, Utc::now());
+        let bakery = Merchant::new("Bakery", None, Utc::now());
+        repo.insert_merchant(&cafe).await.unwrap();
+        repo.insert_merchant(&bakery).await.unwrap();
+        let merchant = merchant_auth(&repo, &config, cafe.id).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(repo.clone())
+                .app_data(config)
+                .configure(crate::routes::exports::config),
+        )
+        .await;
+
+        let card = issue(&repo, "Alice", 5000).await;
+        for (merchant, amount) in [(&cafe, 1250), (&bakery, 500)] {
+            let txn = GiftCardTransaction::redemption(card.id, Money::new(amount, Currency::USD), merchant, Utc::now());
+            let changes = CardUpdate { transactions: vec![txn], ..Default::default() };
+            repo.update_cards(&[], Box::new(move |_| Ok(changes))).await.unwrap();
+        }
+
+        let export = |query: &str| {
+            test::TestRequest::get()
+                .uri(&format!("/exports/transactions{}", query))
+                .insert_header((AUTHORIZATION, merchant.as_str()))
+                .to_request()
+        };
+
+        let body = test::call_and_read_body(&app, export("?columns=merchant,amount")).await;
+        assert_eq!(String::from_utf8(body.to_vec()).unwrap(), "merchant,amount\nCafe,$12.50\n");
+        let resp = test::call_service(&app, export(&format!("?merchant_id={}", bakery.id))).await;
+        assert_eq!(resp.status(), 403);
+        let resp = test::call_service(&app, export("?status=issued")).await;
+        assert_eq!(resp.status(), 400);
+    }
+}
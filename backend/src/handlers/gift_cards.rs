@@ -1,41 +1,51 @@
+use actix_web::http::header::{CACHE_CONTROL, CONTENT_DISPOSITION};
 use actix_web::{web, HttpResponse};
-use chrono::{Duration, Utc};
-use serde::{Deserialize, Serialize};
-use sqlx::{MySqlPool, MySql, Transaction};
+use chrono::{DateTime, Duration, Utc};
 use std::str::FromStr;
 use uuid::Uuid;
-use qrcode::QrCode;
-use qrcode::render::svg;
-use base64::{engine::general_purpose, Engine};
-use image::{Rgba, DynamicImage};
 
+use crate::config::Config;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::acceptance_code::{AcceptanceCode, AcceptanceCodeSentDto};
+use crate::models::account::Role;
+use crate::models::audit::{AuditAction, CardAudit};
+use crate::models::card_code::{CardCode, SetPinDto, UseByCodeDto, VerifyByCodeDto};
+use crate::models::card_status::CardStatus;
 use crate::models::gift_card::{
-    AcceptGiftCardDto, CreateGiftCardDto, CreateTransactionDto, GiftCard, GiftCardResponseDto,
-    GiftCardTransaction, GiftCardVerificationDto, UseGiftCardDto,
+    AcceptGiftCardDto, CreateGiftCardDto, ExtendGiftCardDto, GiftCard, GiftCardResponseDto,
+    GiftCardTransaction, GiftCardVerificationDto, RefundGiftCardDto, ReplaceGiftCardDto, TopUpGiftCardDto,
+    TransactionKind, TransferGiftCardDto, UseGiftCardDto,
 };
-
-#[derive(Debug, Serialize)]
-struct ApiResponse<T> {
-    success: bool,
-    data: Option<T>,
-    message: Option<String>,
-}
-
-#[derive(Debug, Deserialize)]
-struct PaginationParams {
-    page: Option<u32>,
-    per_page: Option<u32>,
-}
+use crate::models::hold::{AuthorizeHoldDto, CaptureHoldDto, Hold, HoldStatus};
+use crate::models::ledger::{AdjustBalanceDto, CardLedgerDto, JournalEntry};
+use crate::models::live_qr::{LiveQrCodeDto, LiveQrSecret};
+use crate::models::money::Money;
+use crate::repository::{CardUpdate, GiftCardRepository};
+use crate::sms::SmsSender;
+use crate::utils::auth::{generate_otp, hash_password, verify_password};
+use crate::utils::qr_render::{QrCodeQuery, QrStyle};
+use crate::utils::qr_token::{generate_live_qr_secret, live_qr_window, LiveQrCode, QR_PREFIX};
+use crate::utils::short_code::{format_short_code, normalize_short_code};
+use crate::utils::validation::{validate_name, validate_phone, validate_pin};
+use crate::utils::error::AppError;
+use crate::voucher::{Voucher, VoucherQuery};
+use super::merchants::fetch_active_merchant;
+use super::{error_response, ApiResponse, PaginationParams};
 
 /// Create a new gift card
 pub async fn create_gift_card(
-    pool: web::Data<MySqlPool>,
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
     gift_card_dto: web::Json<CreateGiftCardDto>,
 ) -> HttpResponse {
     let dto = gift_card_dto.into_inner();
     
+    if let Err(e) = user.require(&[Role::Admin, Role::Issuer]) {
+        return error_response(e);
+    }
+    
     // Validate input data
-    if dto.balance <= 0 {
+    if !dto.balance.is_positive() {
         return HttpResponse::BadRequest().json(ApiResponse {
             success: false,
             data: None::<()>,
@@ -51,58 +61,42 @@ pub async fn create_gift_card(
         });
     }
     
-    // Calculate expiration date
-    let expiration_date = Utc::now() + Duration::days(dto.expiration_days as i64);
+    let pin_hash = match dto.pin.as_deref().map(hash_card_pin).transpose() {
+        Ok(pin_hash) => pin_hash,
+        Err(e) => return error_response(e),
+    };
+    
+    // The card is issued in the name of the signed-in account
+    let issuer = match repo.find_account(user.id).await {
+        Ok(Some(account)) => account,
+        Ok(None) => return error_response(AppError::UnauthorizedError("Account not found".to_string())),
+        Err(e) => return error_response(e),
+    };
     
-    // Generate a new UUID for the gift card
-    let gift_card_id = Uuid::new_v4();
+    let now = Utc::now();
+    let gift_card = GiftCard::issue(&issuer, &dto, now);
     
-    // Insert the gift card into the database
-        let result = sqlx::query!(
-            r#"
-            INSERT INTO gift_cards (
-                id, issuer_name, recipient_name, recipient_phone, 
-                balance, initial_balance, expiration_date, 
-                is_accepted, is_active, created_at, updated_at
-            )
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
-            "#,
-            gift_card_id,
-            dto.issuer_name,
-            dto.recipient_name,
-            dto.recipient_phone,
-            dto.balance,
-            dto.balance,
-            expiration_date,
-            false, // is_accepted
-            true,  // is_active
-            Utc::now(),
-            Utc::now()
-    )
-    .execute(pool.get_ref())
+    let issuance = JournalEntry::issuance(&gift_card);
+    
+    // The card gets its short code, with the PIN if one was given, once it exists
+    let result = async {
+        repo.insert_card(&gift_card, &issuance).await?;
+        repo.insert_card_code(&CardCode::new(gift_card.id, pin_hash, now)).await
+    }
     .await;
     
     match result {
-        Ok(_) => {
-            // Fetch the created gift card to return in response
-            let gift_card = fetch_gift_card_by_id(pool.get_ref(), gift_card_id).await;
+        Ok(card_code) => {
+            let response_dto = GiftCardResponseDto {
+                code: Some(format_short_code(&card_code.code)),
+                ..to_gift_card_response_dto(gift_card, None)
+            };
             
-            match gift_card {
-                Ok(card) => {
-                    let response_dto = to_gift_card_response_dto(card, None);
-                    
-                    HttpResponse::Created().json(ApiResponse {
-                        success: true,
-                        data: Some(response_dto),
-                        message: Some("Gift card created successfully".to_string()),
-                    })
-                }
-                Err(_) => HttpResponse::InternalServerError().json(ApiResponse {
-                    success: false,
-                    data: None::<()>,
-                    message: Some("Failed to retrieve created gift card".to_string()),
-                }),
-            }
+            HttpResponse::Created().json(ApiResponse {
+                success: true,
+                data: Some(response_dto),
+                message: Some("Gift card created successfully".to_string()),
+            })
         }
         Err(e) => {
             log::error!("Error creating gift card: {:?}", e);
@@ -116,504 +110,1428 @@ pub async fn create_gift_card(
 }
 
 /// Get a gift card by ID
+///
+/// Merchants may look up any card, but without the recipient's details or QR code.
 pub async fn get_gift_card(
-    pool: web::Data<MySqlPool>,
+    repo: web::Data<dyn GiftCardRepository>,
+    config: web::Data<Config>,
+    user: AuthenticatedUser,
     path: web::Path<String>,
 ) -> HttpResponse {
-    let gift_card_id = match Uuid::from_str(&path.into_inner()) {
+    let gift_card_id = match parse_gift_card_id(&path.into_inner()) {
         Ok(id) => id,
-        Err(_) => {
-            return HttpResponse::BadRequest().json(ApiResponse {
-                success: false,
-                data: None::<()>,
-                message: Some("Invalid gift card ID".to_string()),
-            });
-        }
+        Err(e) => return error_response(e),
+    };
+    
+    let result = async {
+        let card = fetch_gift_card(repo.get_ref(), gift_card_id).await?;
+        let (qr_code, code) = if user.owns_card(&card) {
+            let card_code = ensure_card_code(repo.get_ref(), card.id).await?;
+            (card_qr_code(repo.get_ref(), &config, &card).await?, Some(format_short_code(&card_code.code)))
+        } else {
+            (None, None)
+        };
+        card_view(&user, card, qr_code).map(|response_dto| GiftCardResponseDto { code, ..response_dto })
+    }
+    .await;
+    
+    match result {
+        Ok(response_dto) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(response_dto),
+            message: None,
+        }),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Send a one-time code to a gift card's recipient phone, to be confirmed by accepting the card
+pub async fn send_acceptance_code(
+    repo: web::Data<dyn GiftCardRepository>,
+    config: web::Data<Config>,
+    sms: web::Data<dyn SmsSender>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let gift_card_id = match parse_gift_card_id(&path.into_inner()) {
+        Ok(id) => id,
+        Err(e) => return error_response(e),
+    };
+    
+    let result = async {
+        let card = fetch_gift_card(repo.get_ref(), gift_card_id).await?;
+        user.require_card_recipient(&card)?;
+        let now = Utc::now();
+        check_acceptable(&card, now)?;
+        
+        // Only a hash of the code is kept; a newer code replaces any sent before it
+        let code = generate_otp();
+        let acceptance_code = AcceptanceCode {
+            id: Uuid::new_v4(),
+            gift_card_id,
+            code_hash: hash_password(&code)?,
+            attempts: 0,
+            expires_at: now + Duration::seconds(config.otp_ttl),
+            used_at: None,
+            created_at: now,
+        };
+        repo.insert_acceptance_code(&acceptance_code).await?;
+        
+        let message = format!(
+            "{} is your code to accept the gift card from {}. It expires in {} minutes.",
+            code,
+            card.issuer_name,
+            (config.otp_ttl + 59) / 60
+        );
+        sms.send(&card.recipient_phone, &message).await?;
+        
+        Ok::<_, AppError>(AcceptanceCodeSentDto {
+            gift_card_id,
+            sent_to: mask_phone(&card.recipient_phone),
+            expires_at: acceptance_code.expires_at,
+            max_attempts: config.otp_max_attempts,
+        })
+    }
+    .await;
+    
+    match result {
+        Ok(sent) => HttpResponse::Created().json(ApiResponse {
+            success: true,
+            data: Some(sent),
+            message: Some("Acceptance code sent".to_string()),
+        }),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Accept a gift card with the code sent to its recipient phone
+pub async fn accept_gift_card(
+    repo: web::Data<dyn GiftCardRepository>,
+    config: web::Data<Config>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    accept_dto: web::Json<AcceptGiftCardDto>,
+) -> HttpResponse {
+    let gift_card_id = match parse_gift_card_id(&path.into_inner()) {
+        Ok(id) => id,
+        Err(e) => return error_response(e),
     };
+    let code = accept_dto.into_inner().code;
+    
+    let confirmed = async {
+        let card = fetch_gift_card(repo.get_ref(), gift_card_id).await?;
+        user.require_card_recipient(&card)?;
+        confirm_acceptance_code(repo.get_ref(), &config, gift_card_id, code.trim()).await
+    }
+    .await;
+    if let Err(e) = confirmed {
+        return error_response(e);
+    }
     
-    // Fetch the gift card from the database
-    let gift_card = fetch_gift_card_by_id(pool.get_ref(), gift_card_id).await;
+    let result = repo
+        .update_cards(
+            &[gift_card_id],
+            Box::new(move |mut cards| {
+                let mut card = cards.remove(0);
+                let now = Utc::now();
+                
+                user.require_card_recipient(&card)?;
+                check_acceptable(&card, now)?;
+                card.transition(CardStatus::Accepted, now)?;
+                
+                Ok(CardUpdate { cards: vec![card], ..Default::default() })
+            }),
+        )
+        .await;
     
-    match gift_card {
-        Ok(card) => {
+    match result {
+        Ok(mut changes) => {
+            let card = changes.cards.remove(0);
+            
             // Generate QR code for the gift card
-            let qr_code = generate_gift_card_qr(&card);
+            let qr_code = card_qr_code(repo.get_ref(), &config, &card).await.ok().flatten();
             
             let response_dto = to_gift_card_response_dto(card, qr_code);
             
             HttpResponse::Ok().json(ApiResponse {
                 success: true,
                 data: Some(response_dto),
-                message: None,
+                message: Some("Gift card accepted successfully".to_string()),
             })
         }
-        Err(_) => HttpResponse::NotFound().json(ApiResponse {
+        Err(e) => error_response(e),
+    }
+}
+
+/// Use a gift card for payment
+///
+/// The card's QR code must be scanned and passed as `qr_code`: its signed
+/// token, or its current live code if live QR codes are on.
+pub async fn use_gift_card(
+    repo: web::Data<dyn GiftCardRepository>,
+    config: web::Data<Config>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    use_dto: web::Json<UseGiftCardDto>,
+) -> HttpResponse {
+    let gift_card_id = match parse_gift_card_id(&path.into_inner()) {
+        Ok(id) => id,
+        Err(e) => return error_response(e),
+    };
+    let amount = use_dto.amount;
+    
+    if !amount.is_positive() {
+        return HttpResponse::BadRequest().json(ApiResponse {
             success: false,
             data: None::<()>,
-            message: Some("Gift card not found".to_string()),
-        }),
+            message: Some("Amount must be positive".to_string()),
+        });
+    }
+    
+    if let Err(e) = user.require_merchant(use_dto.merchant_id) {
+        return error_response(e);
+    }
+    
+    if let Err(e) = check_scanned_code(repo.get_ref(), &config, gift_card_id, use_dto.qr_code.as_deref()).await {
+        return error_response(e);
     }
+    
+    take_payment(repo.get_ref(), &user, gift_card_id, amount, use_dto.merchant_id).await
 }
 
-/// Accept a gift card
-pub async fn accept_gift_card(
-    pool: web::Data<MySqlPool>,
+/// Pay with a gift card by its short code, and its PIN if it has one
+///
+/// For when a card's QR code will not scan. Cards with live QR codes on refuse
+/// their short code.
+pub async fn use_by_code(
+    repo: web::Data<dyn GiftCardRepository>,
+    config: web::Data<Config>,
+    user: AuthenticatedUser,
     path: web::Path<String>,
-    accept_dto: web::Json<AcceptGiftCardDto>,
+    use_dto: web::Json<UseByCodeDto>,
 ) -> HttpResponse {
-    let gift_card_id = match Uuid::from_str(&path.into_inner()) {
+    let UseByCodeDto { pin, amount, merchant_id } = use_dto.into_inner();
+    
+    if !amount.is_positive() {
+        return error_response(AppError::ValidationError("Amount must be positive".to_string()));
+    }
+    
+    if let Err(e) = user.require_merchant(merchant_id) {
+        return error_response(e);
+    }
+    
+    let gift_card_id = match unlock_card_code(repo.get_ref(), &config, &path.into_inner(), pin.as_deref()).await {
         Ok(id) => id,
-        Err(_) => {
-            return HttpResponse::BadRequest().json(ApiResponse {
-                success: false,
-                data: None::<()>,
-                message: Some("Invalid gift card ID".to_string()),
-            });
-        }
+        Err(e) => return error_response(e),
     };
     
-    // Fetch the gift card from the database
-    let gift_card = fetch_gift_card_by_id(pool.get_ref(), gift_card_id).await;
+    take_payment(repo.get_ref(), &user, gift_card_id, amount, merchant_id).await
+}
+
+/// Debit `amount` from a card for a payment at the merchant of `user`
+async fn take_payment(
+    repo: &dyn GiftCardRepository,
+    user: &AuthenticatedUser,
+    gift_card_id: Uuid,
+    amount: Money,
+    merchant_id: Uuid,
+) -> HttpResponse {
+    // Payments are only taken by a known, active merchant
+    let merchant = match fetch_active_merchant(repo, merchant_id).await {
+        Ok(merchant) => merchant,
+        Err(e) => return error_response(e),
+    };
     
-    match gift_card {
-        Ok(card) => {
-            // Verify recipient phone matches
-            if card.recipient_phone != accept_dto.recipient_phone {
-                return HttpResponse::BadRequest().json(ApiResponse {
-                    success: false,
-                    data: None::<()>,
-                    message: Some("Phone number does not match".to_string()),
-                });
-            }
-            
-            // Check if gift card is already accepted
-            if card.is_accepted {
-                return HttpResponse::BadRequest().json(ApiResponse {
-                    success: false,
-                    data: None::<()>,
-                    message: Some("Gift card already accepted".to_string()),
-                });
-            }
-            
-            // Check if gift card is expired
-            if card.expiration_date < Utc::now() {
-                return HttpResponse::BadRequest().json(ApiResponse {
-                    success: false,
-                    data: None::<()>,
-                    message: Some("Gift card has expired".to_string()),
-                });
-            }
-            
-            // Update gift card to mark as accepted
-            let result = sqlx::query!(
-                r#"
-                UPDATE gift_cards
-                SET is_accepted = true, updated_at = ?
-                WHERE id = ?
-                "#,
-                Utc::now(),
-                gift_card_id
-            )
-            .execute(pool.get_ref())
-            .await;
-            
-            match result {
-                Ok(_) => {
-                    // Fetch the updated gift card
-                    let updated_card = fetch_gift_card_by_id(pool.get_ref(), gift_card_id).await;
-                    
-                    match updated_card {
-                        Ok(card) => {
-                            // Generate QR code for the gift card
-                            let qr_code = generate_gift_card_qr(&card);
-                            
-                            let response_dto = to_gift_card_response_dto(card, qr_code);
-                            
-                            HttpResponse::Ok().json(ApiResponse {
-                                success: true,
-                                data: Some(response_dto),
-                                message: Some("Gift card accepted successfully".to_string()),
-                            })
-                        }
-                        Err(_) => HttpResponse::InternalServerError().json(ApiResponse {
-                            success: false,
-                            data: None::<()>,
-                            message: Some("Failed to retrieve updated gift card".to_string()),
-                        }),
-                    }
+    // Check and debit the card while it is locked
+    let result = repo
+        .update_cards(
+            &[gift_card_id],
+            Box::new(move |mut cards| {
+                let mut card = cards.remove(0);
+                let now = Utc::now();
+                
+                // Validate the gift card can be used
+                check_spendable(&card, now)?;
+                
+                // Check if there's sufficient balance outside of holds, in the card's currency
+                if card.available()?.checked_sub(amount)?.is_negative() {
+                    return Err(AppError::ValidationError("Insufficient balance".to_string()));
                 }
-                Err(e) => {
-                    log::error!("Error accepting gift card: {:?}", e);
-                    HttpResponse::InternalServerError().json(ApiResponse {
-                        success: false,
-                        data: None::<()>,
-                        message: Some("Failed to accept gift card".to_string()),
-                    })
+                
+                card.balance = card.balance.checked_sub(amount)?;
+                card.updated_at = now;
+                if card.balance.is_zero() {
+                    card.transition(CardStatus::Depleted, now)?;
                 }
-            }
-        }
-        Err(_) => HttpResponse::NotFound().json(ApiResponse {
-            success: false,
-            data: None::<()>,
-            message: Some("Gift card not found".to_string()),
+                
+                let transaction = GiftCardTransaction::redemption(card.id, amount, &merchant, now);
+                
+                let entry = JournalEntry::redemption(&transaction);
+                
+                Ok(CardUpdate {
+                    cards: vec![card],
+                    transactions: vec![transaction],
+                    entries: vec![entry],
+                    ..Default::default()
+                })
+            }),
+        )
+        .await;
+    
+    match result.and_then(|mut changes| card_view(user, changes.cards.remove(0), None)) {
+        Ok(response_dto) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(response_dto),
+            message: Some(format!("Payment of {} processed successfully", amount)),
         }),
+        Err(e) => error_response(e),
     }
 }
 
-/// Use a gift card for payment
-pub async fn use_gift_card(
-    pool: web::Data<MySqlPool>,
+/// Reserve an amount on a gift card, to be captured or voided later
+///
+/// Like a payment, this needs the card's scanned QR code as `qr_code`.
+pub async fn authorize_hold(
+    repo: web::Data<dyn GiftCardRepository>,
+    config: web::Data<Config>,
+    user: AuthenticatedUser,
     path: web::Path<String>,
-    use_dto: web::Json<UseGiftCardDto>,
+    authorize_dto: web::Json<AuthorizeHoldDto>,
 ) -> HttpResponse {
-    let gift_card_id = match Uuid::from_str(&path.into_inner()) {
+    let gift_card_id = match parse_gift_card_id(&path.into_inner()) {
         Ok(id) => id,
-        Err(_) => {
-            return HttpResponse::BadRequest().json(ApiResponse {
-                success: false,
-                data: None::<()>,
-                message: Some("Invalid gift card ID".to_string()),
-            });
-        }
+        Err(e) => return error_response(e),
     };
+    let AuthorizeHoldDto { amount, merchant_id, qr_code } = authorize_dto.into_inner();
+    let ttl = config.hold_ttl;
     
-    // Start a transaction
-    let mut tx = match pool.begin().await {
-        Ok(tx) => tx,
-        Err(e) => {
-            log::error!("Error starting transaction: {:?}", e);
-            return HttpResponse::InternalServerError().json(ApiResponse {
-                success: false,
-                data: None::<()>,
-                message: Some("Database error".to_string()),
-            });
-        }
+    if !amount.is_positive() {
+        return error_response(AppError::ValidationError("Amount must be positive".to_string()));
+    }
+    
+    if let Err(e) = user.require_merchant(merchant_id) {
+        return error_response(e);
+    }
+    
+    if let Err(e) = check_scanned_code(repo.get_ref(), &config, gift_card_id, qr_code.as_deref()).await {
+        return error_response(e);
+    }
+    
+    let merchant = match fetch_active_merchant(repo.get_ref(), merchant_id).await {
+        Ok(merchant) => merchant,
+        Err(e) => return error_response(e),
     };
     
-    // Fetch the gift card within the transaction
-    let gift_card = fetch_gift_card_by_id_tx(&mut tx, gift_card_id).await;
+    let result = repo
+        .update_cards(
+            &[gift_card_id],
+            Box::new(move |mut cards| {
+                let mut card = cards.remove(0);
+                let now = Utc::now();
+                
+                check_spendable(&card, now)?;
+                if card.available()?.checked_sub(amount)?.is_negative() {
+                    return Err(AppError::ValidationError("Insufficient balance".to_string()));
+                }
+                
+                let hold = Hold {
+                    id: Uuid::new_v4(),
+                    gift_card_id: card.id,
+                    amount,
+                    captured_amount: None,
+                    merchant_id: merchant.id,
+                    merchant: merchant.name,
+                    status: HoldStatus::Active,
+                    transaction_id: None,
+                    expires_at: now + Duration::seconds(ttl),
+                    created_at: now,
+                    updated_at: now,
+                };
+                
+                card.held = card.held.checked_add(amount)?;
+                card.updated_at = now;
+                
+                Ok(CardUpdate { cards: vec![card], holds: vec![hold], ..Default::default() })
+            }),
+        )
+        .await;
     
-    match gift_card {
-        Ok(card) => {
-            // Validate the gift card can be used
-            if !card.is_active {
-                return HttpResponse::BadRequest().json(ApiResponse {
-                    success: false,
-                    data: None::<()>,
-                    message: Some("Gift card is not active".to_string()),
-                });
-            }
-            
-            if !card.is_accepted {
-                return HttpResponse::BadRequest().json(ApiResponse {
-                    success: false,
-                    data: None::<()>,
-                    message: Some("Gift card has not been accepted".to_string()),
-                });
-            }
-            
-            if card.expiration_date < Utc::now() {
-                return HttpResponse::BadRequest().json(ApiResponse {
-                    success: false,
-                    data: None::<()>,
-                    message: Some("Gift card has expired".to_string()),
-                });
-            }
-            
-            // Check if there's sufficient balance
-            if card.balance < use_dto.amount {
-                return HttpResponse::BadRequest().json(ApiResponse {
-                    success: false,
-                    data: None::<()>,
-                    message: Some("Insufficient balance".to_string()),
-                });
-            }
-            
-            // Calculate new balance
-            let new_balance = card.balance - use_dto.amount;
-            
-            // Update the gift card balance
-            let update_result = sqlx::query!(
-                r#"
-                UPDATE gift_cards
-                SET balance = ?, updated_at = ?, is_active = ?
-                WHERE id = ?
-                "#,
-                new_balance,
-                Utc::now(),
-                new_balance > 0, // Deactivate if balance is zero
-                gift_card_id
-            )
-            .execute(&mut tx)
-            .await;
-            
-            match update_result {
-                Ok(_) => {
-                    // Create a transaction record
-                    let transaction_id = Uuid::new_v4();
-                    let transaction_result = sqlx::query!(
-                        r#"
-                        INSERT INTO gift_card_transactions (
-                            id, gift_card_id, amount, merchant, transaction_date
-                        )
-                        VALUES (?, ?, ?, ?, ?)
-                        "#,
-                        transaction_id,
-                        gift_card_id,
-                        use_dto.amount,
-                        "Payment",  // Default merchant name, could be passed in the DTO
-                        Utc::now()
-                    )
-                    .execute(&mut tx)
-                    .await;
-                    
-                    match transaction_result {
-                        Ok(_) => {
-                            // Commit the transaction
-                            match tx.commit().await {
-                                Ok(_) => {
-                                    // Fetch the updated gift card
-                                    let updated_card = fetch_gift_card_by_id(pool.get_ref(), gift_card_id).await;
-                                    
-                                    match updated_card {
-                                        Ok(card) => {
-                                            HttpResponse::Ok().json(ApiResponse {
-                                                success: true,
-                                                data: Some(card),
-                                                message: Some(format!("Payment of {} processed successfully", use_dto.amount)),
-                                            })
-                                        }
-                                        Err(_) => HttpResponse::InternalServerError().json(ApiResponse {
-                                            success: false,
-                                            data: None::<()>,
-                                            message: Some("Failed to retrieve updated gift card".to_string()),
-                                        }),
-                                    }
-                                }
-                                Err(e) => {
-                                    log::error!("Error committing transaction: {:?}", e);
-                                    HttpResponse::InternalServerError().json(ApiResponse {
-                                        success: false,
-                                        data: None::<()>,
-                                        message: Some("Database error".to_string()),
-                                    })
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            log::error!("Error creating transaction record: {:?}", e);
-                            HttpResponse::InternalServerError().json(ApiResponse {
-                                success: false,
-                                data: None::<()>,
-                                message: Some("Failed to record transaction".to_string()),
-                            })
-                        }
-                    }
+    match result {
+        Ok(mut changes) => HttpResponse::Created().json(ApiResponse {
+            success: true,
+            data: Some(changes.holds.remove(0)),
+            message: Some(format!("Hold of {} placed", amount)),
+        }),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Charge all or part of a hold to the gift card, releasing the rest
+pub async fn capture_hold(
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
+    path: web::Path<(String, String)>,
+    capture_dto: web::Json<CaptureHoldDto>,
+) -> HttpResponse {
+    // Only the merchant that placed a hold settles it
+    let hold = match fetch_merchant_hold(repo.get_ref(), &user, path.into_inner()).await {
+        Ok(hold) => hold,
+        Err(e) => return error_response(e),
+    };
+    let amount = capture_dto.amount.unwrap_or(hold.amount);
+    
+    if !amount.is_positive() {
+        return error_response(AppError::ValidationError("Amount must be positive".to_string()));
+    }
+    
+    let merchant = match fetch_active_merchant(repo.get_ref(), hold.merchant_id).await {
+        Ok(merchant) => merchant,
+        Err(e) => return error_response(e),
+    };
+    
+    let result = repo
+        .update_cards(
+            &[hold.gift_card_id],
+            Box::new(move |mut cards| {
+                let mut card = cards.remove(0);
+                let mut hold = hold;
+                let now = Utc::now();
+                
+                check_open(&hold, now)?;
+                check_spendable(&card, now)?;
+                if hold.amount.checked_sub(amount)?.is_negative() {
+                    return Err(AppError::ValidationError(format!(
+                        "Capture exceeds the held amount of {}",
+                        hold.amount
+                    )));
                 }
-                Err(e) => {
-                    log::error!("Error updating gift card balance: {:?}", e);
-                    HttpResponse::InternalServerError().json(ApiResponse {
-                        success: false,
-                        data: None::<()>,
-                        message: Some("Failed to update gift card balance".to_string()),
-                    })
+                
+                card.held = card.held.checked_sub(hold.amount)?;
+                card.balance = card.balance.checked_sub(amount)?;
+                card.updated_at = now;
+                if card.balance.is_zero() {
+                    card.transition(CardStatus::Depleted, now)?;
                 }
-            }
-        }
-        Err(_) => HttpResponse::NotFound().json(ApiResponse {
-            success: false,
-            data: None::<()>,
-            message: Some("Gift card not found".to_string()),
+                
+                let transaction = GiftCardTransaction::redemption(card.id, amount, &merchant, now);
+                let entry = JournalEntry::redemption(&transaction);
+                
+                hold.status = HoldStatus::Captured;
+                hold.captured_amount = Some(amount);
+                hold.transaction_id = Some(transaction.id);
+                hold.updated_at = now;
+                
+                Ok(CardUpdate {
+                    cards: vec![card],
+                    transactions: vec![transaction],
+                    entries: vec![entry],
+                    holds: vec![hold],
+                    ..Default::default()
+                })
+            }),
+        )
+        .await;
+    
+    match result {
+        Ok(mut changes) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(changes.holds.remove(0)),
+            message: Some(format!("Payment of {} processed successfully", amount)),
         }),
+        Err(e) => error_response(e),
     }
 }
 
-/// Generate QR code for a gift card
-pub async fn generate_qr_code(
-    pool: web::Data<MySqlPool>,
+/// Release a hold without charging the gift card
+pub async fn void_hold(
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    let hold = match fetch_merchant_hold(repo.get_ref(), &user, path.into_inner()).await {
+        Ok(hold) => hold,
+        Err(e) => return error_response(e),
+    };
+    
+    let result = repo
+        .update_cards(
+            &[hold.gift_card_id],
+            Box::new(move |_| {
+                let mut hold = hold;
+                let now = Utc::now();
+                
+                check_open(&hold, now)?;
+                hold.status = HoldStatus::Voided;
+                hold.updated_at = now;
+                
+                Ok(CardUpdate { holds: vec![hold], ..Default::default() })
+            }),
+        )
+        .await;
+    
+    match result {
+        Ok(mut changes) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(changes.holds.remove(0)),
+            message: Some("Hold released".to_string()),
+        }),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Refund all or part of a redemption back to the gift card
+pub async fn refund_gift_card(
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
     path: web::Path<String>,
+    refund_dto: web::Json<RefundGiftCardDto>,
 ) -> HttpResponse {
-    let gift_card_id = match Uuid::from_str(&path.into_inner()) {
+    let gift_card_id = match parse_gift_card_id(&path.into_inner()) {
         Ok(id) => id,
-        Err(_) => {
-            return HttpResponse::BadRequest().json(ApiResponse {
-                success: false,
-                data: None::<()>,
-                message: Some("Invalid gift card ID".to_string()),
-            });
+        Err(e) => return error_response(e),
+    };
+    let RefundGiftCardDto { transaction_id, amount } = refund_dto.into_inner();
+    
+    // Find the redemption and what is left to refund; the repository checks
+    // the remainder again while the card is locked
+    let original = async {
+        let original = repo
+            .find_transaction(transaction_id)
+            .await?
+            .filter(|txn| txn.gift_card_id == gift_card_id)
+            .ok_or_else(|| AppError::NotFoundError("Transaction not found".to_string()))?;
+        // Expiries were taken by no merchant, so only admins get as far as rejecting them
+        match original.merchant_id {
+            Some(merchant_id) => user.require_merchant_or_admin(merchant_id)?,
+            None => user.require(&[Role::Admin])?,
+        }
+        if original.kind != TransactionKind::Redemption {
+            return Err(AppError::ValidationError("Only redemptions can be refunded".to_string()));
         }
+        
+        let refunded = Money::new(repo.refunded_amount(original.id).await?, original.amount.currency());
+        let refundable = original.amount.checked_sub(refunded)?;
+        Ok((original, refundable))
+    }
+    .await;
+    let (original, refundable) = match original {
+        Ok(found) => found,
+        Err(e) => return error_response(e),
     };
     
-    // Fetch the gift card from the database
-    let gift_card = fetch_gift_card_by_id(pool.get_ref(), gift_card_id).await;
+    let amount = amount.unwrap_or(refundable);
+    if refundable.is_zero() {
+        return error_response(AppError::ValidationError("Transaction has already been fully refunded".to_string()));
+    }
+    if !amount.is_positive() {
+        return error_response(AppError::ValidationError("Amount must be positive".to_string()));
+    }
     
-    match gift_card {
-        Ok(card) => {
-            // Generate QR code for the gift card
-            let qr_code = match generate_gift_card_qr(&card) {
-                Some(qr) => qr,
-                None => {
-                    return HttpResponse::InternalServerError().json(ApiResponse {
-                        success: false,
-                        data: None::<()>,
-                        message: Some("Failed to generate QR code".to_string()),
-                    });
+    let result = repo
+        .update_cards(
+            &[gift_card_id],
+            Box::new(move |mut cards| {
+                let mut card = cards.remove(0);
+                let now = Utc::now();
+                
+                // Suspended cards take the refund but stay suspended
+                if !matches!(card.status, CardStatus::Accepted | CardStatus::Depleted | CardStatus::Suspended) {
+                    return Err(AppError::ValidationError(format!("Gift card is {}", card.status)));
                 }
-            };
-            
-            HttpResponse::Ok().json(ApiResponse {
-                success: true,
-                data: Some(qr_code),
-                message: None,
-            })
-        }
-        Err(_) => HttpResponse::NotFound().json(ApiResponse {
-            success: false,
-            data: None::<()>,
-            message: Some("Gift card not found".to_string()),
+                
+                card.balance = card.balance.checked_add(amount)?;
+                card.updated_at = now;
+                if card.status == CardStatus::Depleted {
+                    card.transition(CardStatus::Accepted, now)?;
+                }
+                
+                let refund = original.refund(amount, now);
+                let entry = JournalEntry::refund(&refund);
+                
+                Ok(CardUpdate {
+                    cards: vec![card],
+                    transactions: vec![refund],
+                    entries: vec![entry],
+                    ..Default::default()
+                })
+            }),
+        )
+        .await;
+    
+    match result {
+        Ok(mut changes) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(changes.transactions.remove(0)),
+            message: Some(format!("Refund of {} processed successfully", amount)),
         }),
+        Err(e) => error_response(e),
     }
 }
 
-/// List gift cards by recipient phone
-pub async fn list_by_recipient(
-    pool: web::Data<MySqlPool>,
+/// Add value to a gift card
+///
+/// The balance may not go above `CARD_MAX_BALANCE`, and no more than
+/// `CARD_DAILY_LOAD_LIMIT` can be loaded onto a card in any 24 hours. Each
+/// top-up is recorded as a `load` transaction and added to the card's
+/// `total_loaded`.
+pub async fn top_up_gift_card(
+    repo: web::Data<dyn GiftCardRepository>,
+    config: web::Data<Config>,
+    user: AuthenticatedUser,
     path: web::Path<String>,
-    query: web::Query<PaginationParams>,
+    top_up_dto: web::Json<TopUpGiftCardDto>,
 ) -> HttpResponse {
-    let recipient_phone = path.into_inner();
+    let gift_card_id = match parse_gift_card_id(&path.into_inner()) {
+        Ok(id) => id,
+        Err(e) => return error_response(e),
+    };
+    let amount = top_up_dto.amount;
+    
+    if !amount.is_positive() {
+        return error_response(AppError::ValidationError("Amount must be positive".to_string()));
+    }
     
-    // Parse pagination parameters
-    let page = query.page.unwrap_or(1);
-    let per_page = query.per_page.unwrap_or(10);
-    let offset = (page - 1) * per_page;
-    
-    // Fetch gift cards from the database
-    let gift_cards = sqlx::query_as!(
-        GiftCard,
-        r#"
-        SELECT *
-        FROM gift_cards
-        WHERE recipient_phone = ?
-        ORDER BY created_at DESC
-        LIMIT ? OFFSET ?
-        "#,
-        recipient_phone,
-        per_page as i64,
-        offset as i64
-    )
-    .fetch_all(pool.get_ref())
+    // Loads in the last day as of the card's `total_loaded` at the time; the
+    // closure adds whatever was loaded between then and the lock
+    let now = Utc::now();
+    let loaded_before = async {
+        let card = fetch_gift_card(repo.get_ref(), gift_card_id).await?;
+        user.require_card_issuer(&card)?;
+        let loaded = repo.loaded_amount(gift_card_id, now - Duration::days(1)).await?;
+        Ok::<_, AppError>((card.total_loaded, Money::new(loaded, card.currency())))
+    }
     .await;
+    let (total_loaded_before, loaded_before) = match loaded_before {
+        Ok(loaded) => loaded,
+        Err(e) => return error_response(e),
+    };
+    let (max_balance, daily_load_limit) = (config.card_max_balance, config.card_daily_load_limit);
     
-    match gift_cards {
-        Ok(cards) => {
-            // Convert to response DTOs
-            let response_dtos: Vec<GiftCardResponseDto> = cards
-                .into_iter()
-                .map(|card| to_gift_card_response_dto(card, None))
-                .collect();
-            
-            HttpResponse::Ok().json(ApiResponse {
-                success: true,
-                data: Some(response_dtos),
-                message: None,
-            })
-        }
-        Err(e) => {
-            log::error!("Error fetching gift cards: {:?}", e);
-            HttpResponse::InternalServerError().json(ApiResponse {
-                success: false,
-                data: None::<()>,
-                message: Some("Failed to fetch gift cards".to_string()),
-            })
-        }
+    let result = repo
+        .update_cards(
+            &[gift_card_id],
+            Box::new(move |mut cards| {
+                let mut card = cards.remove(0);
+                let now = Utc::now();
+                
+                if !matches!(card.status, CardStatus::Issued | CardStatus::Accepted | CardStatus::Depleted) {
+                    return Err(AppError::ValidationError(format!("Gift card is {}", card.status)));
+                }
+                if card.expiration_date < now {
+                    return Err(AppError::ValidationError("Gift card has expired".to_string()));
+                }
+                
+                let balance = card.balance.checked_add(amount)?;
+                let max_balance = Money::whole(max_balance, card.currency());
+                if balance.checked_sub(max_balance)?.is_positive() {
+                    return Err(AppError::ValidationError(format!(
+                        "Top-up would take the balance above the maximum of {}",
+                        max_balance
+                    )));
+                }
+                
+                let loaded_since = card.total_loaded.checked_sub(total_loaded_before)?;
+                let loaded_today = loaded_before.checked_add(loaded_since)?.checked_add(amount)?;
+                let daily_load_limit = Money::whole(daily_load_limit, card.currency());
+                if loaded_today.checked_sub(daily_load_limit)?.is_positive() {
+                    return Err(AppError::ValidationError(format!(
+                        "Top-up would load more than {} onto the card in 24 hours",
+                        daily_load_limit
+                    )));
+                }
+                
+                card.balance = balance;
+                card.total_loaded = card.total_loaded.checked_add(amount)?;
+                card.updated_at = now;
+                if card.status == CardStatus::Depleted {
+                    card.transition(CardStatus::Accepted, now)?;
+                }
+                
+                let load = GiftCardTransaction::load(card.id, amount, now);
+                let entry = JournalEntry::load(&load);
+                
+                Ok(CardUpdate {
+                    cards: vec![card],
+                    transactions: vec![load],
+                    entries: vec![entry],
+                    ..Default::default()
+                })
+            }),
+        )
+        .await;
+    
+    match result {
+        Ok(mut changes) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(to_gift_card_response_dto(changes.cards.remove(0), None)),
+            message: Some(format!("Gift card topped up with {}", amount)),
+        }),
+        Err(e) => error_response(e),
     }
 }
 
-/// Verify gift card (used when scanning QR code)
-pub async fn verify_gift_card(
-    pool: web::Data<MySqlPool>,
+/// Move all or part of a gift card's balance to a new card for someone else
+///
+/// Both cards are written in one transaction, and the new card's transfer
+/// links back to the one that left this card. The new card keeps the original
+/// issuer and expiration date, and must be accepted by its recipient.
+pub async fn transfer_gift_card(
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
     path: web::Path<String>,
+    transfer_dto: web::Json<TransferGiftCardDto>,
 ) -> HttpResponse {
-    let gift_card_id = match Uuid::from_str(&path.into_inner()) {
+    if let Err(e) = user.require(&[Role::Admin, Role::Recipient]) {
+        return error_response(e);
+    }
+    let gift_card_id = match parse_gift_card_id(&path.into_inner()) {
         Ok(id) => id,
-        Err(_) => {
-            return HttpResponse::BadRequest().json(ApiResponse {
-                success: false,
-                data: None::<()>,
-                message: Some("Invalid gift card ID".to_string()),
-            });
-        }
+        Err(e) => return error_response(e),
     };
+    let TransferGiftCardDto { recipient_name, recipient_phone, amount } = transfer_dto.into_inner();
     
-    // Fetch the gift card from the database
-    let gift_card = fetch_gift_card_by_id(pool.get_ref(), gift_card_id).await;
+    if !validate_name(&recipient_name) {
+        return error_response(AppError::ValidationError(
+            "Recipient name must be 2 to 50 letters, spaces, hyphens, apostrophes or dots".to_string(),
+        ));
+    }
+    if !validate_phone(&recipient_phone) {
+        return error_response(AppError::ValidationError("Recipient phone must be 10 to 15 digits".to_string()));
+    }
+    if amount.is_some_and(|amount| !amount.is_positive()) {
+        return error_response(AppError::ValidationError("Amount must be positive".to_string()));
+    }
     
-    match gift_card {
-        Ok(card) => {
-            // Create verification DTO
-            let verification_dto = GiftCardVerificationDto {
-                id: card.id,
-                balance: card.balance,
-                is_active: card.is_active,
-                is_accepted: card.is_accepted,
-                expiration_date: card.expiration_date,
-            };
-            
-            HttpResponse::Ok().json(ApiResponse {
-                success: true,
-                data: Some(verification_dto),
-                message: None,
-            })
-        }
-        Err(_) => HttpResponse::NotFound().json(ApiResponse {
-            success: false,
-            data: None::<()>,
-            message: Some("Gift card not found".to_string()),
+    let result = repo
+        .update_cards(
+            &[gift_card_id],
+            Box::new(move |mut cards| {
+                let mut card = cards.remove(0);
+                let now = Utc::now();
+                
+                user.require_card_owner(&card)?;
+                check_spendable(&card, now)?;
+                if card.recipient_phone == recipient_phone {
+                    return Err(AppError::ValidationError("Gift card already belongs to this recipient".to_string()));
+                }
+                
+                // Money on hold stays behind for the merchant holding it
+                let available = card.available()?;
+                let amount = amount.unwrap_or(available);
+                if !amount.is_positive() {
+                    return Err(AppError::ValidationError("Gift card has no balance to transfer".to_string()));
+                }
+                if available.checked_sub(amount)?.is_negative() {
+                    return Err(AppError::ValidationError(format!(
+                        "Amount exceeds the available balance of {}",
+                        available
+                    )));
+                }
+                
+                card.balance = card.balance.checked_sub(amount)?;
+                card.updated_at = now;
+                if card.balance.is_zero() {
+                    card.transition(CardStatus::Depleted, now)?;
+                }
+                
+                let new_card = card.regift(&recipient_name, &recipient_phone, amount, now);
+                let sent = GiftCardTransaction::transfer_out(card.id, amount, now);
+                let received = sent.transfer_in(new_card.id);
+                let entry = JournalEntry::transfer(&sent, new_card.id);
+                
+                Ok(CardUpdate {
+                    cards: vec![card, new_card],
+                    transactions: vec![sent, received],
+                    entries: vec![entry],
+                    ..Default::default()
+                })
+            }),
+        )
+        .await;
+    
+    // The new card gets its short code once it exists; only its recipient is shown it
+    let result = async {
+        let new_card = result?.cards.remove(1);
+        repo.insert_card_code(&CardCode::new(new_card.id, None, new_card.created_at)).await?;
+        Ok::<_, AppError>(new_card)
+    }
+    .await;
+    
+    match result {
+        Ok(new_card) => HttpResponse::Created().json(ApiResponse {
+            success: true,
+            message: Some(format!("{} transferred to a new gift card", new_card.balance)),
+            data: Some(to_gift_card_response_dto(new_card, None)),
         }),
+        Err(e) => error_response(e),
     }
 }
 
-/// List transactions for a gift card
-pub async fn list_transactions(
-    pool: web::Data<MySqlPool>,
+/// Correct a gift card's balance with a manual ledger adjustment
+pub async fn adjust_balance(
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
     path: web::Path<String>,
-    query: web::Query<PaginationParams>,
+    adjust_dto: web::Json<AdjustBalanceDto>,
 ) -> HttpResponse {
-    let gift_card_id = match Uuid::from_str(&path.into_inner()) {
+    if let Err(e) = user.require(&[Role::Admin]) {
+        return error_response(e);
+    }
+    let gift_card_id = match parse_gift_card_id(&path.into_inner()) {
         Ok(id) => id,
-        Err(_) => {
-            return HttpResponse::BadRequest().json(ApiResponse {
-                success: false,
-                data: None::<()>,
-                message: Some("Invalid gift card ID".to_string()),
-            });
-        }
+        Err(e) => return error_response(e),
     };
+    let AdjustBalanceDto { amount, reason } = adjust_dto.into_inner();
     
-    // Parse pagination parameters
-    let page = query.page.unwrap_or(1);
-    let per_page = query.per_page.unwrap_or(10);
-    let offset = (page - 1) * per_page;
-    
-    // Fetch transactions from the database
-    let transactions = sqlx::query_as!(
-        GiftCardTransaction,
-        r#"
-        SELECT *
-        FROM gift_card_transactions
-        WHERE gift_card_id = ?
-        ORDER BY transaction_date DESC
-        LIMIT ? OFFSET ?
-        "#,
-        gift_card_id,
-        per_page as i64,
-        offset as i64
-    )
-    .fetch_all(pool.get_ref())
-    .await;
-    
-    match transactions {
+    if amount.is_zero() {
+        return error_response(AppError::ValidationError("Amount must not be zero".to_string()));
+    }
+    if reason.trim().is_empty() {
+        return error_response(AppError::ValidationError("A reason is required".to_string()));
+    }
+    
+    let result = repo
+        .update_cards(
+            &[gift_card_id],
+            Box::new(move |mut cards| {
+                let mut card = cards.remove(0);
+                let now = Utc::now();
+                
+                if card.status.is_terminal() {
+                    return Err(AppError::ValidationError(format!("Gift card is {}", card.status)));
+                }
+                
+                let balance = card.balance.checked_add(amount)?;
+                if balance.is_negative() {
+                    return Err(AppError::ValidationError("Adjustment would make the balance negative".to_string()));
+                }
+                
+                card.balance = balance;
+                card.updated_at = now;
+                match card.status {
+                    CardStatus::Accepted if balance.is_zero() => card.transition(CardStatus::Depleted, now)?,
+                    CardStatus::Depleted if balance.is_positive() => card.transition(CardStatus::Accepted, now)?,
+                    _ => {}
+                }
+                
+                let entry = JournalEntry::adjustment(card.id, amount, reason.trim(), now);
+                
+                Ok(CardUpdate { cards: vec![card], entries: vec![entry], ..Default::default() })
+            }),
+        )
+        .await;
+    
+    match result {
+        Ok(mut changes) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(to_gift_card_response_dto(changes.cards.remove(0), None)),
+            message: Some("Balance adjusted".to_string()),
+        }),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Extend a gift card's expiration date, reactivating it if it has expired
+///
+/// Reactivation gives back the balance written off at expiry, and a card that
+/// was suspended when it expired comes back suspended. The optional fee is
+/// charged after that, and every change is written to the card's audit log.
+/// Cards cannot be extended past `CARD_MAX_LIFETIME_DAYS` after the first card
+/// they were replaced or transferred from was issued.
+pub async fn extend_gift_card(
+    repo: web::Data<dyn GiftCardRepository>,
+    config: web::Data<Config>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    extend_dto: web::Json<ExtendGiftCardDto>,
+) -> HttpResponse {
+    if let Err(e) = user.require(&[Role::Admin]) {
+        return error_response(e);
+    }
+    let gift_card_id = match parse_gift_card_id(&path.into_inner()) {
+        Ok(id) => id,
+        Err(e) => return error_response(e),
+    };
+    let ExtendGiftCardDto { expiration_date, reason, fee } = extend_dto.into_inner();
+    let reason = reason.trim().to_string();
+    
+    if reason.is_empty() {
+        return error_response(AppError::ValidationError("A reason is required".to_string()));
+    }
+    if reason.chars().count() > 255 {
+        return error_response(AppError::ValidationError("Reason must be at most 255 characters".to_string()));
+    }
+    if fee.is_some_and(|fee| !fee.is_positive()) {
+        return error_response(AppError::ValidationError("Fee must be positive".to_string()));
+    }
+    
+    // Whether the card had expired and what it lost then, checked again once it is locked
+    let prefetched = async {
+        let card = fetch_gift_card(repo.get_ref(), gift_card_id).await?;
+        let written_off = written_off_at_expiry(repo.get_ref(), gift_card_id).await?;
+        Ok::<_, AppError>((card.status == CardStatus::Expired, written_off))
+    };
+    let (was_expired, written_off) = match prefetched.await {
+        Ok(prefetched) => prefetched,
+        Err(e) => return error_response(e),
+    };
+    let max_lifetime = Duration::days(config.card_max_lifetime_days);
+    let account_id = user.id;
+    
+    let result = repo
+        .update_cards(
+            &[gift_card_id],
+            Box::new(move |mut cards| {
+                let mut card = cards.remove(0);
+                let now = Utc::now();
+                let (previous_status, previous_expiration_date) = (card.status, card.expiration_date);
+                
+                if card.status.is_terminal() {
+                    return Err(AppError::ValidationError(format!("Gift card is {}", card.status)));
+                }
+                if expiration_date <= card.expiration_date.max(now) {
+                    return Err(AppError::ValidationError(
+                        "Expiration date must be in the future and later than the current one".to_string(),
+                    ));
+                }
+                if expiration_date > card.first_issued_at + max_lifetime {
+                    return Err(AppError::ValidationError(format!(
+                        "Gift cards cannot last more than {} days from issue",
+                        max_lifetime.num_days()
+                    )));
+                }
+                
+                let mut changes = CardUpdate::default();
+                card.expiration_date = expiration_date;
+                card.updated_at = now;
+                
+                // The write-off was looked up before the sweeper got to the card
+                if card.status == CardStatus::Expired && !was_expired {
+                    return Err(AppError::ConflictError(
+                        "Gift card expired while it was being extended, please try again".to_string(),
+                    ));
+                }
+                
+                let (action, restored) = if card.status == CardStatus::Expired {
+                    card.transition(card.resume_status(), now)?;
+                    if card.status_before_expiry.take() == Some(CardStatus::Suspended) {
+                        // A suspension is not lifted by the card expiring and coming back
+                        card.transition(CardStatus::Suspended, now)?;
+                    }
+                    if let Some(amount) = written_off {
+                        let txn = GiftCardTransaction::reactivation(card.id, amount, now);
+                        changes.entries.push(JournalEntry::reactivation(&txn, &reason));
+                        changes.transactions.push(txn);
+                        card.balance = card.balance.checked_add(amount)?;
+                    }
+                    (AuditAction::Reactivation, written_off)
+                } else {
+                    (AuditAction::Extension, None)
+                };
+                
+                if let Some(fee) = fee {
+                    if card.available()?.checked_sub(fee)?.is_negative() {
+                        return Err(AppError::ValidationError("Fee is more than the card's available balance".to_string()));
+                    }
+                    let txn = GiftCardTransaction::fee(card.id, fee, now);
+                    changes.entries.push(JournalEntry::fee(&txn, &reason));
+                    changes.transactions.push(txn);
+                    card.balance = card.balance.checked_sub(fee)?;
+                }
+                if card.status == CardStatus::Accepted && card.balance.is_zero() {
+                    card.transition(CardStatus::Depleted, now)?;
+                }
+                
+                changes.audits.push(CardAudit {
+                    id: Uuid::new_v4(),
+                    gift_card_id: card.id,
+                    account_id,
+                    action,
+                    reason,
+                    previous_status,
+                    status: card.status,
+                    previous_expiration_date,
+                    expiration_date,
+                    restored,
+                    fee,
+                    created_at: now,
+                });
+                changes.cards.push(card);
+                Ok(changes)
+            }),
+        )
+        .await;
+    
+    match result {
+        Ok(mut changes) => {
+            let message = if changes.audits[0].action == AuditAction::Reactivation {
+                "Gift card reactivated"
+            } else {
+                "Gift card extended"
+            };
+            HttpResponse::Ok().json(ApiResponse {
+                success: true,
+                data: Some(to_gift_card_response_dto(changes.cards.remove(0), None)),
+                message: Some(message.to_string()),
+            })
+        }
+        Err(e) => error_response(e),
+    }
+}
+
+/// Suspend a gift card, blocking acceptance and payments until resumed
+pub async fn suspend_gift_card(
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+) -> HttpResponse {
+    change_status(repo.get_ref(), user, &path.into_inner(), |_| CardStatus::Suspended, "Gift card suspended").await
+}
+
+/// Resume a suspended gift card
+pub async fn resume_gift_card(
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+) -> HttpResponse {
+    change_status(repo.get_ref(), user, &path.into_inner(), GiftCard::resume_status, "Gift card resumed").await
+}
+
+/// Cancel a gift card permanently
+pub async fn cancel_gift_card(
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+) -> HttpResponse {
+    change_status(repo.get_ref(), user, &path.into_inner(), |_| CardStatus::Cancelled, "Gift card cancelled").await
+}
+
+/// Replace a gift card whose ID or QR code has leaked
+///
+/// The card is marked replaced for good, and its whole balance moves to a
+/// successor card for the same recipient with the same expiration date and
+/// issuer. Its open holds are voided, so merchants must authorize again
+/// against the successor.
+pub async fn replace_gift_card(
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    replace_dto: web::Json<ReplaceGiftCardDto>,
+) -> HttpResponse {
+    if let Err(e) = user.require(&[Role::Admin]) {
+        return error_response(e);
+    }
+    let gift_card_id = match parse_gift_card_id(&path.into_inner()) {
+        Ok(id) => id,
+        Err(e) => return error_response(e),
+    };
+    let reason = replace_dto.into_inner().reason.trim().to_string();
+    
+    if reason.is_empty() {
+        return error_response(AppError::ValidationError("A reason is required".to_string()));
+    }
+    if reason.chars().count() > 255 {
+        return error_response(AppError::ValidationError("Reason must be at most 255 characters".to_string()));
+    }
+    
+    // A hold taken after this makes the write fail the repository's hold check
+    let holds = match repo.find_open_holds(gift_card_id, Utc::now()).await {
+        Ok(holds) => holds,
+        Err(e) => return error_response(e),
+    };
+    let account_id = user.id;
+    
+    let result = repo
+        .update_cards(
+            &[gift_card_id],
+            Box::new(move |mut cards| {
+                let mut card = cards.remove(0);
+                let now = Utc::now();
+                let previous_status = card.status;
+                let successor = card.successor(now);
+                
+                card.transition(CardStatus::Replaced, now)?;
+                if !card.balance.is_positive() {
+                    return Err(AppError::ValidationError("Gift card has no balance to carry over".to_string()));
+                }
+                
+                let entry = JournalEntry::replacement(&successor);
+                card.balance = Money::zero(card.currency());
+                card.held = Money::zero(card.currency());
+                
+                let mut changes = CardUpdate::default();
+                for mut hold in holds {
+                    hold.status = HoldStatus::Voided;
+                    hold.updated_at = now;
+                    changes.holds.push(hold);
+                }
+                changes.audits.push(CardAudit {
+                    id: Uuid::new_v4(),
+                    gift_card_id: card.id,
+                    account_id,
+                    action: AuditAction::Replacement,
+                    reason,
+                    previous_status,
+                    status: card.status,
+                    previous_expiration_date: card.expiration_date,
+                    expiration_date: card.expiration_date,
+                    restored: None,
+                    fee: None,
+                    created_at: now,
+                });
+                changes.entries.push(entry);
+                changes.cards = vec![card, successor];
+                Ok(changes)
+            }),
+        )
+        .await;
+    
+    // The successor gets its own short code; the replaced card's stays dead
+    let result = async {
+        let successor = result?.cards.remove(1);
+        let card_code = repo.insert_card_code(&CardCode::new(successor.id, None, successor.created_at)).await?;
+        Ok::<_, AppError>((successor, card_code))
+    }
+    .await;
+    
+    match result {
+        Ok((successor, card_code)) => HttpResponse::Created().json(ApiResponse {
+            success: true,
+            data: Some(GiftCardResponseDto {
+                code: Some(format_short_code(&card_code.code)),
+                ..to_gift_card_response_dto(successor, None)
+            }),
+            message: Some("Gift card replaced".to_string()),
+        }),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Generate QR code for a gift card
+///
+/// The query picks the format, size, quiet zone, error correction, colours and
+/// logo. The image is returned as a base64 data URI in JSON, or on its own with
+/// its content type when `raw=true`.
+pub async fn generate_qr_code(
+    repo: web::Data<dyn GiftCardRepository>,
+    config: web::Data<Config>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    query: web::Query<QrCodeQuery>,
+) -> HttpResponse {
+    let gift_card_id = match parse_gift_card_id(&path.into_inner()) {
+        Ok(id) => id,
+        Err(e) => return error_response(e),
+    };
+    
+    let style = match QrStyle::from_query(&query, config.qr_logo.as_ref()) {
+        Ok(style) => style,
+        Err(e) => return error_response(e),
+    };
+    
+    let result = async {
+        let card = fetch_owned_gift_card(repo.get_ref(), &user, gift_card_id).await?;
+        let qr_content = card_qr_content(repo.get_ref(), &config, &card).await?;
+        style.render(&qr_content)
+    }
+    .await;
+    
+    // Live codes change every window, so no QR code is cached
+    match result {
+        Ok(image) if query.raw.unwrap_or(false) => HttpResponse::Ok()
+            .insert_header((CACHE_CONTROL, "no-store"))
+            .content_type(style.format.content_type())
+            .body(image),
+        Ok(image) => HttpResponse::Ok()
+            .insert_header((CACHE_CONTROL, "no-store"))
+            .json(ApiResponse {
+                success: true,
+                data: Some(style.data_uri(&image)),
+                message: None,
+            }),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Download a printable voucher for a gift card, as a PDF or PNG
+///
+/// The voucher shows the brand image, recipient, balance, expiry date, QR code
+/// and the card's code. Cards with live QR codes have none, since a printed
+/// code would stop working within a minute.
+pub async fn get_voucher(
+    repo: web::Data<dyn GiftCardRepository>,
+    config: web::Data<Config>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    query: web::Query<VoucherQuery>,
+) -> HttpResponse {
+    let gift_card_id = match parse_gift_card_id(&path.into_inner()) {
+        Ok(id) => id,
+        Err(e) => return error_response(e),
+    };
+    let format = query.format.unwrap_or_default();
+    
+    let result = async {
+        let card = fetch_owned_gift_card(repo.get_ref(), &user, gift_card_id).await?;
+        if !matches!(card.status, CardStatus::Issued | CardStatus::Accepted) {
+            return Err(AppError::ValidationError(format!("Gift card is {}", card.status)));
+        }
+        if repo.find_live_qr_secret(card.id).await?.is_some() {
+            return Err(AppError::ConflictError(
+                "This gift card uses live QR codes, which cannot be printed".to_string(),
+            ));
+        }
+        
+        let card_code = ensure_card_code(repo.get_ref(), card.id).await?;
+        let qr_content = format!("{}{}", QR_PREFIX, config.qr_signing_keys.sign(card.id, Utc::now())?);
+        config.voucher.render(&Voucher::for_card(&card, format_short_code(&card_code.code), qr_content), format)
+    }
+    .await;
+    
+    match result {
+        Ok(file) => HttpResponse::Ok()
+            .insert_header((CACHE_CONTROL, "no-store"))
+            .insert_header((
+                CONTENT_DISPOSITION,
+                format!("inline; filename=\"gift-card-{}.{}\"", gift_card_id, format.extension()),
+            ))
+            .content_type(format.content_type())
+            .body(file),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Turn on rotating live QR codes for a gift card, returning the current code
+///
+/// From then on the card can only be verified or paid with by scanning a live
+/// code from the current or previous window; its static QR code and ID stop
+/// working for that. Turning them on again keeps the card's secret.
+pub async fn enable_live_qr(
+    repo: web::Data<dyn GiftCardRepository>,
+    config: web::Data<Config>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let gift_card_id = match parse_gift_card_id(&path.into_inner()) {
+        Ok(id) => id,
+        Err(e) => return error_response(e),
+    };
+    
+    let result = async {
+        let card = fetch_gift_card(repo.get_ref(), gift_card_id).await?;
+        user.require_card_recipient(&card)?;
+        
+        let secret = LiveQrSecret {
+            gift_card_id,
+            secret: generate_live_qr_secret(),
+            created_at: Utc::now(),
+        };
+        repo.insert_live_qr_secret(&secret).await
+    }
+    .await;
+    
+    match result {
+        Ok(secret) => live_qr_response(&config, &secret, "Live QR codes enabled"),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Current live QR code for a gift card
+///
+/// The recipient's device polls this, again at `refresh_at` each time, to keep
+/// its QR code current.
+pub async fn get_live_qr(
+    repo: web::Data<dyn GiftCardRepository>,
+    config: web::Data<Config>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let gift_card_id = match parse_gift_card_id(&path.into_inner()) {
+        Ok(id) => id,
+        Err(e) => return error_response(e),
+    };
+    
+    let result = async {
+        let card = fetch_gift_card(repo.get_ref(), gift_card_id).await?;
+        user.require_card_recipient(&card)?;
+        
+        repo.find_live_qr_secret(gift_card_id)
+            .await?
+            .ok_or_else(|| AppError::NotFoundError("Live QR codes are not enabled for this gift card".to_string()))
+    }
+    .await;
+    
+    match result {
+        Ok(secret) => live_qr_response(&config, &secret, "Live QR code"),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Set, change or remove the PIN asked for with a gift card's short code
+pub async fn set_card_pin(
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    pin_dto: web::Json<SetPinDto>,
+) -> HttpResponse {
+    let gift_card_id = match parse_gift_card_id(&path.into_inner()) {
+        Ok(id) => id,
+        Err(e) => return error_response(e),
+    };
+    
+    let result = async {
+        let card = fetch_gift_card(repo.get_ref(), gift_card_id).await?;
+        user.require_card_recipient(&card)?;
+        
+        let pin_hash = pin_dto.pin.as_deref().map(hash_card_pin).transpose()?;
+        ensure_card_code(repo.get_ref(), card.id).await?;
+        repo.set_card_pin(card.id, pin_hash.as_deref()).await?;
+        Ok(pin_hash.is_some())
+    }
+    .await;
+    
+    match result {
+        Ok(set) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: None::<()>,
+            message: Some(if set { "PIN set" } else { "PIN removed" }.to_string()),
+        }),
+        Err(e) => error_response(e),
+    }
+}
+
+/// List gift cards by recipient phone
+pub async fn list_by_recipient(
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    query: web::Query<PaginationParams>,
+) -> HttpResponse {
+    let recipient_phone = path.into_inner();
+    let (limit, offset) = query.limit_offset();
+    
+    if let Err(e) = user.require_recipient_or_admin(&recipient_phone) {
+        return error_response(e);
+    }
+    
+    match repo.list_cards_by_recipient(&recipient_phone, limit, offset).await {
+        Ok(cards) => {
+            // Convert to response DTOs
+            let response_dtos: Vec<GiftCardResponseDto> = cards
+                .into_iter()
+                .map(|card| to_gift_card_response_dto(card, None))
+                .collect();
+            
+            HttpResponse::Ok().json(ApiResponse {
+                success: true,
+                data: Some(response_dtos),
+                message: None,
+            })
+        }
+        Err(e) => {
+            log::error!("Error fetching gift cards: {:?}", e);
+            HttpResponse::InternalServerError().json(ApiResponse {
+                success: false,
+                data: None::<()>,
+                message: Some("Failed to fetch gift cards".to_string()),
+            })
+        }
+    }
+}
+
+/// Verify gift card (used when scanning QR code)
+///
+/// Takes the signed token from the card's QR code or its live QR code; a token
+/// is only accepted if it was signed with one of the configured keys. Cards
+/// with live QR codes on only accept a current live code.
+pub async fn verify_gift_card(
+    repo: web::Data<dyn GiftCardRepository>,
+    config: web::Data<Config>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+) -> HttpResponse {
+    if let Err(e) = user.require(&[Role::Admin, Role::Merchant]) {
+        return error_response(e);
+    }
+    
+    let scan = match scan_gift_card(&config, &path.into_inner()) {
+        Ok(scan) => scan,
+        Err(e) => return error_response(e),
+    };
+    let (gift_card_id, key_id) = (scan.gift_card_id, scan.key_id.clone());
+    
+    if let Err(e) = check_live_qr(repo.get_ref(), &config, gift_card_id, Some(&scan)).await {
+        return error_response(e);
+    }
+    
+    let result = async {
+        let card = fetch_gift_card(repo.get_ref(), gift_card_id).await?;
+        check_not_replaced(&card)?;
+        to_verification_dto(card, key_id)
+    }
+    .await;
+    
+    match result {
+        Ok(verification_dto) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(verification_dto),
+            message: None,
+        }),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Verify a gift card by its short code, and its PIN if it has one
+///
+/// For when a card's QR code will not scan. After `PIN_MAX_ATTEMPTS` wrong PINs
+/// in a row the code refuses every PIN for `PIN_LOCKOUT` seconds.
+pub async fn verify_by_code(
+    repo: web::Data<dyn GiftCardRepository>,
+    config: web::Data<Config>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    verify_dto: web::Json<VerifyByCodeDto>,
+) -> HttpResponse {
+    if let Err(e) = user.require(&[Role::Admin, Role::Merchant]) {
+        return error_response(e);
+    }
+    
+    let result = async {
+        let gift_card_id = unlock_card_code(repo.get_ref(), &config, &path.into_inner(), verify_dto.pin.as_deref()).await?;
+        let card = fetch_gift_card(repo.get_ref(), gift_card_id).await?;
+        check_not_replaced(&card)?;
+        to_verification_dto(card, None)
+    }
+    .await;
+    
+    match result {
+        Ok(verification_dto) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(verification_dto),
+            message: None,
+        }),
+        Err(e) => error_response(e),
+    }
+}
+
+/// List transactions for a gift card
+pub async fn list_transactions(
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    query: web::Query<PaginationParams>,
+) -> HttpResponse {
+    let gift_card_id = match parse_gift_card_id(&path.into_inner()) {
+        Ok(id) => id,
+        Err(e) => return error_response(e),
+    };
+    let (limit, offset) = query.limit_offset();
+    
+    if let Err(e) = fetch_owned_gift_card(repo.get_ref(), &user, gift_card_id).await {
+        return error_response(e);
+    }
+    
+    match repo.list_transactions(gift_card_id, limit, offset).await {
         Ok(txns) => {
             HttpResponse::Ok().json(ApiResponse {
                 success: true,
@@ -632,73 +1550,1877 @@ pub async fn list_transactions(
     }
 }
 
+/// Show a gift card's ledger entries and check its balance against them
+pub async fn get_ledger(
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    query: web::Query<PaginationParams>,
+) -> HttpResponse {
+    if let Err(e) = user.require(&[Role::Admin]) {
+        return error_response(e);
+    }
+    let gift_card_id = match parse_gift_card_id(&path.into_inner()) {
+        Ok(id) => id,
+        Err(e) => return error_response(e),
+    };
+    let (limit, offset) = query.limit_offset();
+    
+    let result = async {
+        let card = fetch_gift_card(repo.get_ref(), gift_card_id).await?;
+        let ledger_balance = repo.ledger_balance(gift_card_id).await?;
+        let entries = repo.list_journal_entries(gift_card_id, limit, offset).await?;
+        
+        Ok::<_, AppError>(CardLedgerDto {
+            gift_card_id,
+            balance: card.balance,
+            ledger_balance: Money::new(ledger_balance, card.currency()),
+            balanced: card.balance.amount() == ledger_balance,
+            entries,
+        })
+    }
+    .await;
+    
+    match result {
+        Ok(ledger) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(ledger),
+            message: None,
+        }),
+        Err(e) => error_response(e),
+    }
+}
+
+/// List the changes support staff made to a gift card by hand, newest first
+pub async fn list_card_audits(
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    query: web::Query<PaginationParams>,
+) -> HttpResponse {
+    if let Err(e) = user.require(&[Role::Admin]) {
+        return error_response(e);
+    }
+    let gift_card_id = match parse_gift_card_id(&path.into_inner()) {
+        Ok(id) => id,
+        Err(e) => return error_response(e),
+    };
+    let (limit, offset) = query.limit_offset();
+    
+    let result = async {
+        fetch_gift_card(repo.get_ref(), gift_card_id).await?;
+        repo.list_card_audits(gift_card_id, limit, offset).await
+    }
+    .await;
+    
+    match result {
+        Ok(audits) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(audits),
+            message: None,
+        }),
+        Err(e) => error_response(e),
+    }
+}
+
 // Helper functions
 
-/// Fetch a gift card by ID
-async fn fetch_gift_card_by_id(pool: &MySqlPool, gift_card_id: Uuid) -> Result<GiftCard, sqlx::Error> {
-    sqlx::query_as!(
-        GiftCard,
-        r#"
-        SELECT *
-        FROM gift_cards
-        WHERE id = ?
-        "#,
-        gift_card_id
-    )
-    .fetch_one(pool)
-    .await
-}
-
-/// Fetch a gift card by ID within a transaction
-async fn fetch_gift_card_by_id_tx(
-    tx: &mut Transaction<'_, MySql>,
-    gift_card_id: Uuid,
-) -> Result<GiftCard, sqlx::Error> {
-    sqlx::query_as!(
-        GiftCard,
-        r#"
-        SELECT *
-        FROM gift_cards
-        WHERE id = ?
-        FOR UPDATE
-        "#,
-        gift_card_id
-    )
-    .fetch_one(tx)
-    .await
-}
-
-/// Generate a QR code for a gift card
-fn generate_gift_card_qr(gift_card: &GiftCard) -> Option<String> {
-    let qr_content = format!("giftcard:{}", gift_card.id);
-    
-    // Generate QR code
-    let code = QrCode::new(qr_content.as_bytes()).ok()?;
-    
-    // Render as SVG
-    let svg = code.render()
-        .min_dimensions(200, 200)
-        .dark_color(svg::Color("#000000"))
-        .light_color(svg::Color("#ffffff"))
-        .build();
-    
-    // Convert SVG to base64
-    Some(format!("data:image/svg+xml;base64,{}", general_purpose::STANDARD.encode(svg)))
+/// Parse a gift card ID from the request path
+fn parse_gift_card_id(raw: &str) -> Result<Uuid, AppError> {
+    Uuid::from_str(raw).map_err(|_| AppError::ValidationError("Invalid gift card ID".to_string()))
+}
+
+/// A card identified from scanned QR text
+struct ScannedCard {
+    gift_card_id: Uuid,
+    key_id: Option<String>,            // Key that signed a static QR token
+    live: Option<LiveQrCode>,          // Set for a live QR code, which is checked against the card later
+}
+
+/// Work out which card scanned text names: a live QR code or a signed QR
+/// token. A bare card ID is not a scan, as anyone can write one into a QR code.
+fn scan_gift_card(config: &Config, scanned: &str) -> Result<ScannedCard, AppError> {
+    if let Some(code) = LiveQrCode::parse(scanned) {
+        return Ok(ScannedCard { gift_card_id: code.gift_card_id, key_id: None, live: Some(code) });
+    }
+    
+    let token = config.qr_signing_keys.verify(scanned)?;
+    Ok(ScannedCard { gift_card_id: token.gift_card_id, key_id: Some(token.key_id), live: None })
+}
+
+/// Check a scan of a card against its live QR secret
+///
+/// A card with live QR codes on needs a live code from the current or previous
+/// window; a card without them refuses live codes.
+async fn check_live_qr(
+    repo: &dyn GiftCardRepository,
+    config: &Config,
+    gift_card_id: Uuid,
+    scan: Option<&ScannedCard>,
+) -> Result<(), AppError> {
+    let not_genuine = || AppError::ValidationError("QR code is not a genuine gift card code".to_string());
+    let live = scan.and_then(|scan| scan.live.as_ref());
+    
+    let secret = match repo.find_live_qr_secret(gift_card_id).await? {
+        Some(secret) => secret,
+        None if live.is_some() => return Err(not_genuine()),
+        None => return Ok(()),
+    };
+    let code = live.ok_or_else(|| AppError::ValidationError("This gift card only accepts its live QR code".to_string()))?;
+    
+    let current = live_qr_window(Utc::now(), config.qr_live_window);
+    if code.gift_card_id != gift_card_id || code.window > current || !code.is_signed_by(&secret.secret) {
+        return Err(not_genuine());
+    }
+    if code.window < current - 1 {
+        return Err(AppError::ValidationError("QR code has expired, scan the current one".to_string()));
+    }
+    Ok(())
+}
+
+/// Check the QR text scanned for a payment is genuine, names `gift_card_id`
+/// and satisfies the card's live QR codes
+///
+/// A payment without a scan is refused; cards whose QR code will not scan are
+/// paid with by short code and PIN instead.
+async fn check_scanned_code(
+    repo: &dyn GiftCardRepository,
+    config: &Config,
+    gift_card_id: Uuid,
+    qr_code: Option<&str>,
+) -> Result<(), AppError> {
+    let scan = match qr_code {
+        Some(scanned) => scan_gift_card(config, scanned)?,
+        None => {
+            check_live_qr(repo, config, gift_card_id, None).await?;
+            return Err(AppError::ValidationError(
+                "Scan the gift card's QR code, or pay with its short code".to_string(),
+            ));
+        }
+    };
+    if scan.gift_card_id != gift_card_id {
+        return Err(AppError::ValidationError("QR code is for a different gift card".to_string()));
+    }
+    check_live_qr(repo, config, gift_card_id, Some(&scan)).await
+}
+
+/// Respond with the live QR code for the current window, which must not be cached
+fn live_qr_response(config: &Config, secret: &LiveQrSecret, message: &str) -> HttpResponse {
+    let now = Utc::now();
+    let window = live_qr_window(now, config.qr_live_window);
+    let code = LiveQrCode::new(&secret.secret, secret.gift_card_id, window).to_string();
+    let qr_code = match render_qr(&code) {
+        Some(qr_code) => qr_code,
+        None => return error_response(AppError::InternalServerError("Failed to generate QR code".to_string())),
+    };
+    
+    let window_start = |window: i64| DateTime::from_timestamp(window * config.qr_live_window, 0).unwrap_or(now);
+    HttpResponse::Ok()
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .json(ApiResponse {
+            success: true,
+            data: Some(LiveQrCodeDto {
+                gift_card_id: secret.gift_card_id,
+                code,
+                qr_code,
+                window,
+                refresh_at: window_start(window + 1),
+                expires_at: window_start(window + 2),
+            }),
+            message: Some(message.to_string()),
+        })
+}
+
+/// Fetch a gift card by ID, mapping a missing card to `NotFoundError`
+async fn fetch_gift_card(repo: &dyn GiftCardRepository, gift_card_id: Uuid) -> Result<GiftCard, AppError> {
+    repo.find_card(gift_card_id)
+        .await?
+        .ok_or_else(|| AppError::NotFoundError("Gift card not found".to_string()))
+}
+
+/// The balance a card lost when it last expired, unless it has been given back
+async fn written_off_at_expiry(repo: &dyn GiftCardRepository, gift_card_id: Uuid) -> Result<Option<Money>, AppError> {
+    const PAGE: i64 = 100;
+
+    let mut offset = 0;
+    loop {
+        let page = repo.list_transactions(gift_card_id, PAGE, offset).await?;
+        let latest = page
+            .iter()
+            .find(|txn| matches!(txn.kind, TransactionKind::Expiry | TransactionKind::Reactivation));
+        if let Some(txn) = latest {
+            return Ok((txn.kind == TransactionKind::Expiry).then_some(txn.amount));
+        }
+        if (page.len() as i64) < PAGE {
+            return Ok(None);
+        }
+        offset += PAGE;
+    }
+}
+
+/// Fetch a gift card that `user` is its issuer or recipient of, or any card for an admin
+async fn fetch_owned_gift_card(
+    repo: &dyn GiftCardRepository,
+    user: &AuthenticatedUser,
+    gift_card_id: Uuid,
+) -> Result<GiftCard, AppError> {
+    let card = fetch_gift_card(repo, gift_card_id).await?;
+    user.require_card_owner(&card)?;
+    Ok(card)
+}
+
+/// Fetch a card's short code, giving cards issued before short codes existed one
+async fn ensure_card_code(repo: &dyn GiftCardRepository, gift_card_id: Uuid) -> Result<CardCode, AppError> {
+    match repo.find_card_code(gift_card_id).await? {
+        Some(card_code) => Ok(card_code),
+        None => repo.insert_card_code(&CardCode::new(gift_card_id, None, Utc::now())).await,
+    }
+}
+
+/// Check a new PIN's format and hash it for storage
+pub(crate) fn hash_card_pin(pin: &str) -> Result<String, AppError> {
+    if !validate_pin(pin) {
+        return Err(AppError::ValidationError("PIN must be 4 to 8 digits".to_string()));
+    }
+    hash_password(pin)
+}
+
+/// Find the card a typed short code belongs to, checking its PIN if it has one
+///
+/// Cards with live QR codes on refuse their short code like their other static
+/// codes. The attempt is counted before the PIN is checked, so guesses made in
+/// parallel cannot get past the lockout.
+async fn unlock_card_code(
+    repo: &dyn GiftCardRepository,
+    config: &Config,
+    typed: &str,
+    pin: Option<&str>,
+) -> Result<Uuid, AppError> {
+    let code = normalize_short_code(typed)
+        .ok_or_else(|| AppError::ValidationError("Not a valid gift card code, check it was typed correctly".to_string()))?;
+    let card_code = repo
+        .find_card_code_by_code(&code)
+        .await?
+        .ok_or_else(|| AppError::NotFoundError("Gift card not found".to_string()))?;
+    check_live_qr(repo, config, card_code.gift_card_id, None).await?;
+    
+    let Some(pin_hash) = &card_code.pin_hash else {
+        return Ok(card_code.gift_card_id);
+    };
+    let pin = pin.ok_or_else(|| AppError::ValidationError("This gift card needs its PIN".to_string()))?;
+    
+    let now = Utc::now();
+    let lock_until = now + Duration::seconds(config.pin_lockout);
+    let locked_out = || AppError::TooManyRequestsError("Too many wrong PINs, try again later".to_string());
+    if !repo.claim_pin_attempt(card_code.gift_card_id, config.pin_max_attempts, now, lock_until).await? {
+        return Err(locked_out());
+    }
+    if !verify_password(pin, pin_hash) {
+        let remaining = config.pin_max_attempts - card_code.pin_attempts - 1;
+        if remaining <= 0 {
+            return Err(locked_out());
+        }
+        return Err(AppError::ValidationError(format!("Wrong PIN, {} attempt(s) left", remaining)));
+    }
+    
+    repo.reset_pin_attempts(card_code.gift_card_id).await?;
+    Ok(card_code.gift_card_id)
+}
+
+/// Fetch a hold placed by `user`'s merchant from `(card ID, hold ID)` path segments
+async fn fetch_merchant_hold(
+    repo: &dyn GiftCardRepository,
+    user: &AuthenticatedUser,
+    (raw_card_id, raw_hold_id): (String, String),
+) -> Result<Hold, AppError> {
+    let gift_card_id = parse_gift_card_id(&raw_card_id)?;
+    let hold_id = Uuid::from_str(&raw_hold_id).map_err(|_| AppError::ValidationError("Invalid hold ID".to_string()))?;
+    
+    let hold = repo
+        .find_hold(hold_id)
+        .await?
+        .filter(|hold| hold.gift_card_id == gift_card_id)
+        .ok_or_else(|| AppError::NotFoundError("Hold not found".to_string()))?;
+    user.require_merchant(hold.merchant_id)?;
+    Ok(hold)
+}
+
+/// Reject a hold that has been settled or has lapsed
+fn check_open(hold: &Hold, now: DateTime<Utc>) -> Result<(), AppError> {
+    if hold.is_open(now) {
+        Ok(())
+    } else {
+        Err(AppError::ValidationError(format!("Hold is {}", hold.clone().as_of(now).status)))
+    }
+}
+
+/// Reject acceptance of a card that is already accepted, has expired, or
+/// cannot move to accepted from its current status
+fn check_acceptable(card: &GiftCard, now: DateTime<Utc>) -> Result<(), AppError> {
+    if card.status == CardStatus::Accepted {
+        return Err(AppError::ValidationError("Gift card already accepted".to_string()));
+    }
+    if card.expiration_date < now {
+        return Err(AppError::ValidationError("Gift card has expired".to_string()));
+    }
+    
+    card.clone().transition(CardStatus::Accepted, now)?;
+    Ok(())
+}
+
+/// Check `code` against the latest code sent for a card, counting the attempt
+/// and using the code up when it matches
+async fn confirm_acceptance_code(
+    repo: &dyn GiftCardRepository,
+    config: &Config,
+    gift_card_id: Uuid,
+    code: &str,
+) -> Result<(), AppError> {
+    let now = Utc::now();
+    let no_code = || AppError::ValidationError("No acceptance code is pending, request a new one".to_string());
+    
+    let pending = repo
+        .find_latest_acceptance_code(gift_card_id)
+        .await?
+        .filter(|pending| pending.is_pending(now))
+        .ok_or_else(no_code)?;
+    
+    // The attempt is counted before the code is checked, so guesses made in
+    // parallel cannot get past the limit
+    if !repo.claim_acceptance_attempt(pending.id, config.otp_max_attempts).await? {
+        return Err(AppError::ValidationError("Too many attempts, request a new code".to_string()));
+    }
+    if !verify_password(code, &pending.code_hash) {
+        let remaining = (config.otp_max_attempts - pending.attempts - 1).max(0);
+        return Err(AppError::ValidationError(format!("Invalid code, {} attempt(s) left", remaining)));
+    }
+    
+    if !repo.use_acceptance_code(pending.id, now).await? {
+        return Err(no_code());
+    }
+    Ok(())
+}
+
+/// Reject a card that has been replaced; its ID, QR codes and short code are
+/// never accepted again
+fn check_not_replaced(card: &GiftCard) -> Result<(), AppError> {
+    if card.status == CardStatus::Replaced {
+        return Err(AppError::ValidationError("Gift card has been replaced and can no longer be used".to_string()));
+    }
+    Ok(())
+}
+
+/// Reject payments and holds on a card that is not accepted or has expired
+fn check_spendable(card: &GiftCard, now: DateTime<Utc>) -> Result<(), AppError> {
+    check_not_replaced(card)?;
+    match card.status {
+        CardStatus::Accepted => {}
+        CardStatus::Issued => {
+            return Err(AppError::ValidationError("Gift card has not been accepted".to_string()));
+        }
+        status => {
+            return Err(AppError::ValidationError(format!("Gift card is {}", status)));
+        }
+    }
+    
+    if card.expiration_date < now {
+        return Err(AppError::ValidationError("Gift card has expired".to_string()));
+    }
+    Ok(())
+}
+
+/// Move a card to the status chosen by `next`, validated by the lifecycle rules
+async fn change_status(
+    repo: &dyn GiftCardRepository,
+    user: AuthenticatedUser,
+    raw_id: &str,
+    next: fn(&GiftCard) -> CardStatus,
+    success_message: &str,
+) -> HttpResponse {
+    let gift_card_id = match parse_gift_card_id(raw_id) {
+        Ok(id) => id,
+        Err(e) => return error_response(e),
+    };
+    
+    let result = repo
+        .update_cards(
+            &[gift_card_id],
+            Box::new(move |mut cards| {
+                let mut card = cards.remove(0);
+                user.require_card_issuer(&card)?;
+                let status = next(&card);
+                card.transition(status, Utc::now())?;
+                Ok(CardUpdate { cards: vec![card], ..Default::default() })
+            }),
+        )
+        .await;
+    
+    match result {
+        Ok(mut changes) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(to_gift_card_response_dto(changes.cards.remove(0), None)),
+            message: Some(success_message.to_string()),
+        }),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Mask all but the last four digits of a phone number
+fn mask_phone(phone: &str) -> String {
+    let masked = phone.len().saturating_sub(4);
+    phone
+        .chars()
+        .enumerate()
+        .map(|(i, c)| if i < masked && c.is_ascii_digit() { '*' } else { c })
+        .collect()
+}
+
+/// The text a card's QR code holds: its current live code if live QR codes
+/// are on, and otherwise a token signed with the active key
+async fn card_qr_content(repo: &dyn GiftCardRepository, config: &Config, gift_card: &GiftCard) -> Result<String, AppError> {
+    let now = Utc::now();
+    Ok(match repo.find_live_qr_secret(gift_card.id).await? {
+        Some(secret) => LiveQrCode::new(&secret.secret, gift_card.id, live_qr_window(now, config.qr_live_window)).to_string(),
+        None => format!("{}{}", QR_PREFIX, config.qr_signing_keys.sign(gift_card.id, now)?),
+    })
+}
+
+/// The QR code image a card's owners are shown, in the default style
+async fn card_qr_code(repo: &dyn GiftCardRepository, config: &Config, gift_card: &GiftCard) -> Result<Option<String>, AppError> {
+    let qr_content = card_qr_content(repo, config, gift_card).await?;
+    Ok(render_qr(&qr_content))
+}
+
+/// Render text as a QR code image in the default style, as a base64 data URI
+fn render_qr(qr_content: &str) -> Option<String> {
+    let style = QrStyle::default();
+    style.render(qr_content).ok().map(|image| style.data_uri(&image))
+}
+
+/// Show a card to `user`: in full to its owners, with `qr_code` if given, and
+/// to merchants without the recipient's details or QR code; anyone else is refused
+fn card_view(user: &AuthenticatedUser, card: GiftCard, qr_code: Option<String>) -> Result<GiftCardResponseDto, AppError> {
+    if user.owns_card(&card) {
+        return Ok(to_gift_card_response_dto(card, qr_code));
+    }
+    
+    user.require(&[Role::Merchant])?;
+    Ok(GiftCardResponseDto {
+        recipient_name: None,
+        recipient_phone: None,
+        ..to_gift_card_response_dto(card, None)
+    })
+}
+
+/// Convert GiftCard to GiftCardVerificationDto, for a scan signed by `key_id` if any
+fn to_verification_dto(card: GiftCard, key_id: Option<String>) -> Result<GiftCardVerificationDto, AppError> {
+    Ok(GiftCardVerificationDto {
+        id: card.id,
+        balance: card.balance,
+        available: card.available()?,
+        held: card.held,
+        status: card.status,
+        expiration_date: card.expiration_date,
+        key_id,
+    })
 }
 
 /// Convert GiftCard to GiftCardResponseDto
 fn to_gift_card_response_dto(gift_card: GiftCard, qr_code: Option<String>) -> GiftCardResponseDto {
     GiftCardResponseDto {
         id: gift_card.id,
+        issuer_id: gift_card.issuer_id,
         issuer_name: gift_card.issuer_name,
-        recipient_name: gift_card.recipient_name,
-        recipient_phone: gift_card.recipient_phone,
+        recipient_name: Some(gift_card.recipient_name),
+        recipient_phone: Some(gift_card.recipient_phone),
         balance: gift_card.balance,
         initial_balance: gift_card.initial_balance,
+        total_loaded: gift_card.total_loaded,
         expiration_date: gift_card.expiration_date,
-        is_accepted: gift_card.is_accepted,
-        is_active: gift_card.is_active,
+        status: gift_card.status,
+        accepted_at: gift_card.accepted_at,
+        replaces_id: gift_card.replaces_id,
         qr_code,
+        code: None,
         created_at: gift_card.created_at,
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::test_support::{
+        accept_card, admin_auth, issue_card, issuer_auth, merchant_auth, recipient_auth, signed_qr, test_config,
+        test_repo, test_sms,
+    };
+    use crate::models::merchant::Merchant;
+    use crate::utils::qr_token::QrKeyRing;
+    use actix_web::http::header::AUTHORIZATION;
+    use actix_web::{test, App};
+    use serde_json::{json, Value};
+
+    /// Register an active merchant to take payments in a test
+    async fn test_merchant(repo: &web::Data<dyn GiftCardRepository>, name: &str) -> Uuid {
+        let merchant = Merchant::new(name, None, Utc::now());
+        repo.insert_merchant(&merchant).await.unwrap();
+        merchant.id
+    }
+
+    #[actix_web::test]
+    async fn test_create_accept_and_use_gift_card() {
+        let repo = test_repo();
+        let merchant_id = test_merchant(&repo, "Cafe").await;
+        let config = test_config();
+        let issuer = issuer_auth(&repo, &config, "Alice").await;
+        let merchant = merchant_auth(&repo, &config, merchant_id).await;
+        let recipient = recipient_auth(&repo, &config, "1234567890").await;
+        let admin = admin_auth(&repo, &config).await;
+        let (sms, sms_data) = test_sms();
+        let app = test::init_service(
+            App::new()
+                .app_data(repo)
+                .app_data(config)
+                .app_data(sms_data)
+                .configure(crate::routes::gift_cards::config),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/gift-cards")
+            .insert_header((AUTHORIZATION, issuer.as_str()))
+            .set_json(json!({
+                "recipient_name": "Bob",
+                "recipient_phone": "1234567890",
+                "balance": { "amount": 5000, "currency": "USD" },
+                "expiration_days": 30
+            }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["success"], true);
+        let id = body["data"]["id"].as_str().unwrap().to_string();
+
+        // Payments are refused until the recipient accepts the card
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/use", id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .set_json(json!({ "gift_card_id": id, "amount": { "amount": 1000, "currency": "USD" }, "merchant_id": merchant_id, "qr_code": signed_qr(&id) }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+
+        let body: Value = test::read_body_json(accept_card!(&app, sms, id, recipient.as_str())).await;
+        assert_eq!(body["data"]["status"], "accepted");
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/use", id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .set_json(json!({ "gift_card_id": id, "amount": { "amount": 1500, "currency": "USD" }, "merchant_id": merchant_id, "qr_code": signed_qr(&id) }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["balance"]["amount"], 3500);
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/use", id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .set_json(json!({ "gift_card_id": id, "amount": { "amount": 4000, "currency": "USD" }, "merchant_id": merchant_id, "qr_code": signed_qr(&id) }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["message"], "Insufficient balance");
+        
+        // The card is in USD, so a EUR payment is rejected
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/use", id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .set_json(json!({ "gift_card_id": id, "amount": { "amount": 100, "currency": "EUR" }, "merchant_id": merchant_id, "qr_code": signed_qr(&id) }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["message"], "Currency mismatch: expected USD, got EUR");
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/gift-cards/{}/transactions", id))
+            .insert_header((AUTHORIZATION, issuer.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"].as_array().unwrap().len(), 1);
+        assert_eq!(body["data"][0]["merchant"], "Cafe");
+        assert_eq!(body["data"][0]["merchant_id"], merchant_id.to_string());
+        
+        // Issuance and the one redemption are in the ledger and agree with the card
+        let req = test::TestRequest::get()
+            .uri(&format!("/gift-cards/{}/ledger", id))
+            .insert_header((AUTHORIZATION, admin.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["ledger_balance"]["amount"], 3500);
+        assert_eq!(body["data"]["balanced"], true);
+        assert_eq!(body["data"]["entries"][0]["kind"], "redemption");
+        assert_eq!(body["data"]["entries"][1]["kind"], "issuance");
+    }
+
+    #[actix_web::test]
+    async fn test_acceptance_needs_the_code_sent_to_the_phone() {
+        let repo = test_repo();
+        let config = test_config();
+        let issuer = issuer_auth(&repo, &config, "Alice").await;
+        let recipient = recipient_auth(&repo, &config, "1234567890").await;
+        let (sms, sms_data) = test_sms();
+        let app = test::init_service(
+            App::new()
+                .app_data(repo.clone())
+                .app_data(config)
+                .app_data(sms_data)
+                .configure(crate::routes::gift_cards::config),
+        )
+        .await;
+
+        let id = issue_card!(&app, issuer.as_str());
+
+        let accept = |code: &str| {
+            test::TestRequest::post()
+                .uri(&format!("/gift-cards/{}/accept", id))
+                .insert_header((AUTHORIZATION, recipient.as_str()))
+                .set_json(json!({ "gift_card_id": id, "code": code }))
+                .to_request()
+        };
+        let body: Value = test::call_and_read_body_json(&app, accept("123456")).await;
+        assert_eq!(body["message"], "No acceptance code is pending, request a new one");
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/acceptance-code", id))
+            .insert_header((AUTHORIZATION, recipient.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["sent_to"], "******7890");
+        assert_eq!(body["data"]["max_attempts"], 5);
+
+        // The code goes to the card's phone and only its hash is stored
+        let code = sms.last_code();
+        assert_eq!(sms.messages.lock().unwrap()[0].0, "1234567890");
+        let stored = repo.find_latest_acceptance_code(id.parse().unwrap()).await.unwrap().unwrap();
+        assert_ne!(stored.code_hash, code);
+
+        let wrong = if code == "000000" { "111111" } else { "000000" };
+        for left in (0..5).rev() {
+            let body: Value = test::call_and_read_body_json(&app, accept(wrong)).await;
+            assert_eq!(body["message"], format!("Invalid code, {} attempt(s) left", left));
+        }
+        let body: Value = test::call_and_read_body_json(&app, accept(&code)).await;
+        assert_eq!(body["message"], "Too many attempts, request a new code");
+
+        // A new code starts over, and works only once
+        let body: Value = test::read_body_json(accept_card!(&app, sms, id, recipient.as_str())).await;
+        assert_eq!(body["data"]["status"], "accepted");
+
+        let body: Value = test::call_and_read_body_json(&app, accept(&sms.last_code())).await;
+        assert_eq!(body["message"], "No acceptance code is pending, request a new one");
+    }
+
+    #[actix_web::test]
+    async fn test_top_ups_respect_the_balance_and_daily_limits() {
+        let repo = test_repo();
+        let config = test_config();
+        let issuer = issuer_auth(&repo, &config, "Alice").await;
+        let recipient = recipient_auth(&repo, &config, "1234567890").await;
+        let app = test::init_service(
+            App::new()
+                .app_data(repo.clone())
+                .app_data(config)
+                .configure(crate::routes::gift_cards::config),
+        )
+        .await;
+
+        let mut ids = Vec::new();
+        for balance in [5000, 950_000] {
+            let req = test::TestRequest::post()
+                .uri("/gift-cards")
+                .insert_header((AUTHORIZATION, issuer.as_str()))
+                .set_json(json!({
+                    "recipient_name": "Bob",
+                    "recipient_phone": "1234567890",
+                    "balance": { "amount": balance, "currency": "USD" },
+                    "expiration_days": 30
+                }))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, req).await;
+            ids.push(body["data"]["id"].as_str().unwrap().to_string());
+        }
+        let top_up = |token: &str, id: &str, amount: i64| {
+            test::TestRequest::post()
+                .uri(&format!("/gift-cards/{}/top-ups", id))
+                .insert_header((AUTHORIZATION, token))
+                .set_json(json!({ "amount": { "amount": amount, "currency": "USD" } }))
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, top_up(&recipient, &ids[0], 2000)).await;
+        assert_eq!(resp.status(), 403);
+
+        let body: Value = test::call_and_read_body_json(&app, top_up(&issuer, &ids[0], 2000)).await;
+        assert_eq!(body["message"], "Gift card topped up with 20.00 USD");
+        assert_eq!(body["data"]["balance"]["amount"], 7000);
+        assert_eq!(body["data"]["initial_balance"]["amount"], 5000);
+        assert_eq!(body["data"]["total_loaded"]["amount"], 7000);
+
+        // The issuance does not count towards the daily limit, but earlier top-ups do
+        let body: Value = test::call_and_read_body_json(&app, top_up(&issuer, &ids[0], 199_000)).await;
+        assert_eq!(body["message"], "Top-up would load more than 2000.00 USD onto the card in 24 hours");
+        let body: Value = test::call_and_read_body_json(&app, top_up(&issuer, &ids[1], 60_000)).await;
+        assert_eq!(body["message"], "Top-up would take the balance above the maximum of 10000.00 USD");
+
+        let id = Uuid::parse_str(&ids[0]).unwrap();
+        let transactions = repo.list_transactions(id, 10, 0).await.unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].kind, TransactionKind::Load);
+        assert_eq!(repo.ledger_balance(id).await.unwrap(), 7000);
+    }
+
+    #[actix_web::test]
+    async fn test_transfers_move_the_balance_to_a_new_card() {
+        let repo = test_repo();
+        let config = test_config();
+        let issuer = issuer_auth(&repo, &config, "Alice").await;
+        let bob = recipient_auth(&repo, &config, "1234567890").await;
+        let carol = recipient_auth(&repo, &config, "5551234567").await;
+        let (sms, sms_data) = test_sms();
+        let app = test::init_service(
+            App::new()
+                .app_data(repo.clone())
+                .app_data(config)
+                .app_data(sms_data)
+                .configure(crate::routes::gift_cards::config),
+        )
+        .await;
+
+        let id = issue_card!(&app, issuer.as_str());
+        let transfer = |token: &str, amount: i64| {
+            test::TestRequest::post()
+                .uri(&format!("/gift-cards/{}/transfers", id))
+                .insert_header((AUTHORIZATION, token))
+                .set_json(json!({
+                    "recipient_name": "Carol",
+                    "recipient_phone": "5551234567",
+                    "amount": { "amount": amount, "currency": "USD" }
+                }))
+                .to_request()
+        };
+
+        // Only the recipient can pass the card on, once they have accepted it
+        let resp = test::call_service(&app, transfer(&issuer, 2000)).await;
+        assert_eq!(resp.status(), 403);
+        let body: Value = test::call_and_read_body_json(&app, transfer(&bob, 2000)).await;
+        assert_eq!(body["message"], "Gift card has not been accepted");
+        accept_card!(&app, sms, id, bob.as_str());
+        let resp = test::call_service(&app, transfer(&carol, 2000)).await;
+        assert_eq!(resp.status(), 403);
+        let body: Value = test::call_and_read_body_json(&app, transfer(&bob, 6000)).await;
+        assert_eq!(body["message"], "Amount exceeds the available balance of 50.00 USD");
+
+        let resp = test::call_service(&app, transfer(&bob, 2000)).await;
+        assert_eq!(resp.status(), 201);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["data"]["status"], "issued");
+        assert_eq!(body["data"]["balance"]["amount"], 2000);
+        assert_eq!(body["data"]["issuer_name"], "Alice");
+        assert!(body["data"]["code"].is_null());
+        let new_id = body["data"]["id"].as_str().unwrap().to_string();
+
+        // The transfers on both cards are linked, and both ledgers agree
+        let (old_card, new_card) = (Uuid::parse_str(&id).unwrap(), Uuid::parse_str(&new_id).unwrap());
+        let sent = repo.list_transactions(old_card, 10, 0).await.unwrap();
+        let received = repo.list_transactions(new_card, 10, 0).await.unwrap();
+        assert_eq!(sent[0].kind, TransactionKind::TransferOut);
+        assert_eq!(received[0].kind, TransactionKind::TransferIn);
+        assert_eq!(received[0].original_transaction_id, Some(sent[0].id));
+        assert_eq!(repo.ledger_balance(old_card).await.unwrap(), 3000);
+        assert_eq!(repo.ledger_balance(new_card).await.unwrap(), 2000);
+        let stored = repo.find_card(new_card).await.unwrap().unwrap();
+        assert_eq!(stored.issuer_id, repo.find_card(old_card).await.unwrap().unwrap().issuer_id);
+
+        // Carol has to accept the new card before spending it; the rest of
+        // Bob's balance moves without an amount
+        let resp = accept_card!(&app, sms, new_id, carol.as_str());
+        assert_eq!(resp.status(), 200);
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/transfers", id))
+            .insert_header((AUTHORIZATION, bob.as_str()))
+            .set_json(json!({ "recipient_name": "Dan", "recipient_phone": "5559876543" }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["balance"]["amount"], 3000);
+        assert_eq!(repo.find_card(old_card).await.unwrap().unwrap().status, CardStatus::Depleted);
+    }
+
+    #[actix_web::test]
+    async fn test_replaced_cards_are_dead_and_their_balance_moves_on() {
+        let repo = test_repo();
+        let merchant_id = test_merchant(&repo, "Fuel").await;
+        let config = test_config();
+        let issuer = issuer_auth(&repo, &config, "Alice").await;
+        let admin = admin_auth(&repo, &config).await;
+        let merchant = merchant_auth(&repo, &config, merchant_id).await;
+        let recipient = recipient_auth(&repo, &config, "1234567890").await;
+        let (sms, sms_data) = test_sms();
+        let app = test::init_service(
+            App::new()
+                .app_data(repo.clone())
+                .app_data(config)
+                .app_data(sms_data)
+                .configure(crate::routes::gift_cards::config),
+        )
+        .await;
+
+        let id = issue_card!(&app, issuer.as_str());
+        accept_card!(&app, sms, id, recipient.as_str());
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/holds", id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .set_json(json!({ "amount": { "amount": 3000, "currency": "USD" }, "merchant_id": merchant_id, "qr_code": signed_qr(&id) }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        let hold_id = Uuid::parse_str(body["data"]["id"].as_str().unwrap()).unwrap();
+
+        let replace = |token: &str, reason: &str| {
+            test::TestRequest::post()
+                .uri(&format!("/gift-cards/{}/replacement", id))
+                .insert_header((AUTHORIZATION, token))
+                .set_json(json!({ "reason": reason }))
+                .to_request()
+        };
+        let resp = test::call_service(&app, replace(&issuer, "QR code leaked")).await;
+        assert_eq!(resp.status(), 403);
+        let resp = test::call_service(&app, replace(&admin, "")).await;
+        assert_eq!(resp.status(), 400);
+
+        // The successor takes the whole balance, held money included
+        let resp = test::call_service(&app, replace(&admin, "QR code leaked")).await;
+        assert_eq!(resp.status(), 201);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["data"]["replaces_id"], id);
+        assert_eq!(body["data"]["status"], "accepted");
+        assert_eq!(body["data"]["balance"]["amount"], 5000);
+        assert_eq!(body["data"]["issuer_name"], "Alice");
+        assert!(body["data"]["code"].is_string());
+        let successor_id = body["data"]["id"].as_str().unwrap().to_string();
+
+        let old_card = Uuid::parse_str(&id).unwrap();
+        assert_eq!(repo.find_hold(hold_id).await.unwrap().unwrap().status, HoldStatus::Voided);
+        assert_eq!(repo.ledger_balance(old_card).await.unwrap(), 0);
+        assert_eq!(repo.ledger_balance(Uuid::parse_str(&successor_id).unwrap()).await.unwrap(), 5000);
+        let audits = repo.list_card_audits(old_card, 10, 0).await.unwrap();
+        assert_eq!(audits[0].action, AuditAction::Replacement);
+        assert_eq!(audits[0].previous_status, CardStatus::Accepted);
+
+        // The old card is refused everywhere, and cannot be replaced twice
+        let req = test::TestRequest::get()
+            .uri(&format!("/gift-cards/{}/verify", signed_qr(&id)))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["message"], "Gift card has been replaced and can no longer be used");
+
+        let pay = |card_id: &str| {
+            test::TestRequest::post()
+                .uri(&format!("/gift-cards/{}/use", card_id))
+                .insert_header((AUTHORIZATION, merchant.as_str()))
+                .set_json(json!({ "gift_card_id": card_id, "amount": { "amount": 100, "currency": "USD" }, "merchant_id": merchant_id, "qr_code": signed_qr(card_id) }))
+                .to_request()
+        };
+        let body: Value = test::call_and_read_body_json(&app, pay(&id)).await;
+        assert_eq!(body["message"], "Gift card has been replaced and can no longer be used");
+        let resp = test::call_service(&app, replace(&admin, "Again")).await;
+        assert_eq!(resp.status(), 409);
+
+        let body: Value = test::call_and_read_body_json(&app, pay(&successor_id)).await;
+        assert_eq!(body["data"]["balance"]["amount"], 4900);
+    }
+
+    #[actix_web::test]
+    async fn test_replacing_a_suspended_card_keeps_the_successor_suspended() {
+        let repo = test_repo();
+        let config = test_config();
+        let issuer = issuer_auth(&repo, &config, "Alice").await;
+        let admin = admin_auth(&repo, &config).await;
+        let recipient = recipient_auth(&repo, &config, "1234567890").await;
+        let (sms, sms_data) = test_sms();
+        let app = test::init_service(
+            App::new()
+                .app_data(repo.clone())
+                .app_data(config)
+                .app_data(sms_data)
+                .configure(crate::routes::gift_cards::config),
+        )
+        .await;
+
+        let id = issue_card!(&app, issuer.as_str());
+        accept_card!(&app, sms, id, recipient.as_str());
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/suspend", id))
+            .insert_header((AUTHORIZATION, admin.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["status"], "suspended");
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/replacement", id))
+            .insert_header((AUTHORIZATION, admin.as_str()))
+            .set_json(json!({ "reason": "Card stolen" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 201);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["data"]["status"], "suspended");
+        let successor_id = body["data"]["id"].as_str().unwrap().to_string();
+
+        // The suspension is lifted on the successor the usual way
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/resume", successor_id))
+            .insert_header((AUTHORIZATION, admin.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["status"], "accepted");
+    }
+
+    #[actix_web::test]
+    async fn test_replacements_cannot_outlive_the_original_card() {
+        let repo = test_repo();
+        let config = test_config();
+        issuer_auth(&repo, &config, "Alice").await;
+        let admin = admin_auth(&repo, &config).await;
+        let max_lifetime = config.card_max_lifetime_days;
+        let app = test::init_service(
+            App::new()
+                .app_data(repo.clone())
+                .app_data(config)
+                .configure(crate::routes::gift_cards::config),
+        )
+        .await;
+
+        // Issued long ago, with ten days of its lifetime left
+        let issuer = repo.find_account_by_email("alice@issuer.example").await.unwrap().unwrap();
+        let dto: CreateGiftCardDto = serde_json::from_value(json!({
+            "recipient_name": "Bob",
+            "recipient_phone": "1234567890",
+            "balance": { "amount": 5000, "currency": "USD" },
+            "expiration_days": 30
+        }))
+        .unwrap();
+        let mut card = GiftCard::issue(&issuer, &dto, Utc::now() - Duration::days(max_lifetime - 10));
+        card.expiration_date = Utc::now() + Duration::days(5);
+        repo.insert_card(&card, &JournalEntry::issuance(&card)).await.unwrap();
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/replacement", card.id))
+            .insert_header((AUTHORIZATION, admin.as_str()))
+            .set_json(json!({ "reason": "Card stolen" }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        let successor_id = body["data"]["id"].as_str().unwrap().to_string();
+
+        let extend = |days: i64| {
+            test::TestRequest::post()
+                .uri(&format!("/gift-cards/{}/extension", successor_id))
+                .insert_header((AUTHORIZATION, admin.as_str()))
+                .set_json(json!({ "expiration_date": (Utc::now() + Duration::days(days)).to_rfc3339(), "reason": "Goodwill" }))
+                .to_request()
+        };
+        let resp = test::call_service(&app, extend(60)).await;
+        assert_eq!(resp.status(), 400);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["message"], format!("Gift cards cannot last more than {} days from issue", max_lifetime));
+
+        let body: Value = test::call_and_read_body_json(&app, extend(8)).await;
+        assert_eq!(body["message"], "Gift card extended");
+    }
+
+    #[actix_web::test]
+    async fn test_adjustments_are_recorded_in_the_ledger() {
+        let repo = test_repo();
+        let config = test_config();
+        let issuer = issuer_auth(&repo, &config, "Alice").await;
+        let admin = admin_auth(&repo, &config).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(repo)
+                .app_data(config)
+                .configure(crate::routes::gift_cards::config),
+        )
+        .await;
+
+        let id = issue_card!(&app, issuer.as_str());
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/adjustments", id))
+            .insert_header((AUTHORIZATION, admin.as_str()))
+            .set_json(json!({ "amount": { "amount": -6000, "currency": "USD" }, "reason": "Duplicate issue" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/adjustments", id))
+            .insert_header((AUTHORIZATION, admin.as_str()))
+            .set_json(json!({ "amount": { "amount": 250, "currency": "USD" }, "reason": "Goodwill credit" }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["balance"]["amount"], 5250);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/gift-cards/{}/ledger", id))
+            .insert_header((AUTHORIZATION, admin.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["ledger_balance"]["amount"], 5250);
+        assert_eq!(body["data"]["entries"].as_array().unwrap().len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn test_expired_cards_are_reactivated_with_a_fee() {
+        let repo = test_repo();
+        let config = test_config();
+        let issuer = issuer_auth(&repo, &config, "Alice").await;
+        let admin = admin_auth(&repo, &config).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(repo.clone())
+                .app_data(config)
+                .configure(crate::routes::gift_cards::config),
+        )
+        .await;
+
+        let id = issue_card!(&app, issuer.as_str());
+        let extend = |token: &str, body: Value| {
+            test::TestRequest::post()
+                .uri(&format!("/gift-cards/{}/extension", id))
+                .insert_header((AUTHORIZATION, token))
+                .set_json(body)
+                .to_request()
+        };
+        let in_days = |days: i64| (Utc::now() + Duration::days(days)).to_rfc3339();
+
+        let resp = test::call_service(&app, extend(&issuer, json!({ "expiration_date": in_days(60), "reason": "Asked nicely" }))).await;
+        assert_eq!(resp.status(), 403);
+        let resp = test::call_service(&app, extend(&admin, json!({ "expiration_date": in_days(60), "reason": "  " }))).await;
+        assert_eq!(resp.status(), 400);
+        let resp = test::call_service(&app, extend(&admin, json!({ "expiration_date": in_days(3000), "reason": "Forever" }))).await;
+        assert_eq!(resp.status(), 400);
+
+        let report = crate::expiry::sweep(repo.get_ref(), Utc::now() + Duration::days(31), 10).await.unwrap();
+        assert_eq!(report.expired, 1);
+
+        let req = extend(&admin, json!({
+            "expiration_date": in_days(60),
+            "reason": "Customer was travelling",
+            "fee": { "amount": 500, "currency": "USD" }
+        }));
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["message"], "Gift card reactivated");
+        assert_eq!(body["data"]["status"], "issued");
+        assert_eq!(body["data"]["balance"]["amount"], 4500);
+
+        let gift_card_id = Uuid::parse_str(&id).unwrap();
+        let kinds: Vec<_> = repo
+            .list_transactions(gift_card_id, 10, 0)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|txn| txn.kind)
+            .collect();
+        assert!(kinds.contains(&TransactionKind::Reactivation) && kinds.contains(&TransactionKind::Fee));
+        assert_eq!(repo.ledger_balance(gift_card_id).await.unwrap(), 4500);
+
+        // A second extension only moves the date
+        let body: Value = test::call_and_read_body_json(&app, extend(&admin, json!({ "expiration_date": in_days(90), "reason": "Goodwill" }))).await;
+        assert_eq!(body["message"], "Gift card extended");
+        assert_eq!(body["data"]["balance"]["amount"], 4500);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/gift-cards/{}/audit", id))
+            .insert_header((AUTHORIZATION, admin.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        let audits = body["data"].as_array().unwrap();
+        assert_eq!(audits.len(), 2);
+        assert_eq!(audits[0]["action"], "extension");
+        assert_eq!(audits[1]["action"], "reactivation");
+        assert_eq!(audits[1]["previous_status"], "expired");
+        assert_eq!(audits[1]["restored"]["amount"], 5000);
+        assert_eq!(audits[1]["fee"]["amount"], 500);
+        assert_eq!(audits[1]["reason"], "Customer was travelling");
+        let admin_id = repo.find_account_by_email("admin@admin.example").await.unwrap().unwrap().id;
+        assert_eq!(audits[1]["account_id"], admin_id.to_string());
+    }
+
+    #[actix_web::test]
+    async fn test_cards_suspended_at_expiry_come_back_suspended() {
+        let repo = test_repo();
+        let config = test_config();
+        let issuer = issuer_auth(&repo, &config, "Alice").await;
+        let admin = admin_auth(&repo, &config).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(repo.clone())
+                .app_data(config)
+                .configure(crate::routes::gift_cards::config),
+        )
+        .await;
+
+        let id = issue_card!(&app, issuer.as_str());
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/suspend", id))
+            .insert_header((AUTHORIZATION, admin.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["status"], "suspended");
+
+        let report = crate::expiry::sweep(repo.get_ref(), Utc::now() + Duration::days(31), 10).await.unwrap();
+        assert_eq!(report.expired, 1);
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/extension", id))
+            .insert_header((AUTHORIZATION, admin.as_str()))
+            .set_json(json!({ "expiration_date": (Utc::now() + Duration::days(60)).to_rfc3339(), "reason": "Customer was travelling" }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["message"], "Gift card reactivated");
+        assert_eq!(body["data"]["status"], "suspended");
+        assert_eq!(body["data"]["balance"]["amount"], 5000);
+
+        let gift_card_id = Uuid::parse_str(&id).unwrap();
+        let card = repo.find_card(gift_card_id).await.unwrap().unwrap();
+        assert_eq!(card.status_before_expiry, None);
+
+        // Lifting the suspension is still up to the issuer or support
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/resume", id))
+            .insert_header((AUTHORIZATION, admin.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["status"], "issued");
+    }
+
+    #[actix_web::test]
+    async fn test_holds_reserve_then_capture_or_void() {
+        let repo = test_repo();
+        let merchant_id = test_merchant(&repo, "Fuel").await;
+        let config = test_config();
+        let issuer = issuer_auth(&repo, &config, "Alice").await;
+        let merchant = merchant_auth(&repo, &config, merchant_id).await;
+        let recipient = recipient_auth(&repo, &config, "1234567890").await;
+        let (sms, sms_data) = test_sms();
+        let app = test::init_service(
+            App::new()
+                .app_data(repo)
+                .app_data(config)
+                .app_data(sms_data)
+                .configure(crate::routes::gift_cards::config),
+        )
+        .await;
+
+        let id = issue_card!(&app, issuer.as_str());
+
+        accept_card!(&app, sms, id, recipient.as_str());
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/holds", id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .set_json(json!({ "amount": { "amount": 3000, "currency": "USD" }, "merchant_id": merchant_id, "qr_code": signed_qr(&id) }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 201);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["data"]["status"], "active");
+        let hold_id = body["data"]["id"].as_str().unwrap().to_string();
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/gift-cards/{}/verify", signed_qr(&id)))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["balance"]["amount"], 5000);
+        assert_eq!(body["data"]["held"]["amount"], 3000);
+        assert_eq!(body["data"]["available"]["amount"], 2000);
+
+        // Held money cannot be spent or held again
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/use", id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .set_json(json!({ "gift_card_id": id, "amount": { "amount": 2500, "currency": "USD" }, "merchant_id": merchant_id, "qr_code": signed_qr(&id) }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["message"], "Insufficient balance");
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/holds/{}/capture", id, hold_id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .set_json(json!({ "amount": { "amount": 3500, "currency": "USD" } }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["message"], "Capture exceeds the held amount of 30.00 USD");
+
+        // Capturing less than the hold charges that amount and releases the rest
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/holds/{}/capture", id, hold_id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .set_json(json!({ "amount": { "amount": 2200, "currency": "USD" } }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["status"], "captured");
+        assert_eq!(body["data"]["captured_amount"]["amount"], 2200);
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/holds/{}/void", id, hold_id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["message"], "Hold is captured");
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/holds", id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .set_json(json!({ "amount": { "amount": 1000, "currency": "USD" }, "merchant_id": merchant_id, "qr_code": signed_qr(&id) }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        let hold_id = body["data"]["id"].as_str().unwrap().to_string();
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/holds/{}/void", id, hold_id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["status"], "voided");
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/gift-cards/{}/verify", signed_qr(&id)))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["balance"]["amount"], 2800);
+        assert_eq!(body["data"]["held"]["amount"], 0);
+        assert_eq!(body["data"]["available"]["amount"], 2800);
+    }
+
+    #[actix_web::test]
+    async fn test_verify_accepts_signed_qr_tokens() {
+        let repo = test_repo();
+        let merchant_id = test_merchant(&repo, "Kiosk").await;
+        let config = test_config();
+        let issuer = issuer_auth(&repo, &config, "Alice").await;
+        let merchant = merchant_auth(&repo, &config, merchant_id).await;
+        let keys = config.qr_signing_keys.clone();
+        let app = test::init_service(
+            App::new()
+                .app_data(repo)
+                .app_data(config)
+                .configure(crate::routes::gift_cards::config)
+                .configure(crate::routes::qr_keys::config),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/gift-cards")
+            .insert_header((AUTHORIZATION, issuer.as_str()))
+            .set_json(json!({
+                "recipient_name": "Bob",
+                "recipient_phone": "1234567890",
+                "balance": { "amount": 5000, "currency": "USD" },
+                "expiration_days": 30
+            }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        let id: Uuid = body["data"]["id"].as_str().unwrap().parse().unwrap();
+        assert!(body["data"]["qr_code"].is_null());
+
+        let verify = |scanned: String| {
+            test::TestRequest::get()
+                .uri(&format!("/gift-cards/{}/verify", scanned))
+                .insert_header((AUTHORIZATION, merchant.as_str()))
+                .to_request()
+        };
+        let token = keys.sign(id, Utc::now()).unwrap();
+        for scanned in [token.clone(), format!("{}{}", QR_PREFIX, token)] {
+            let body: Value = test::call_and_read_body_json(&app, verify(scanned)).await;
+            assert_eq!(body["data"]["id"], id.to_string());
+            assert_eq!(body["data"]["key_id"], keys.active().kid);
+        }
+
+        // A bare card ID is not a genuine code, as anyone can put one in a QR code
+        let resp = test::call_service(&app, verify(id.to_string())).await;
+        assert_eq!(resp.status(), 400);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["message"], "QR code is not a genuine gift card code");
+
+        // Nor is a payment without a scan
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/use", id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .set_json(json!({ "gift_card_id": id, "amount": { "amount": 100, "currency": "USD" }, "merchant_id": merchant_id }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["message"], "Scan the gift card's QR code, or pay with its short code");
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/holds", id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .set_json(json!({ "amount": { "amount": 100, "currency": "USD" }, "merchant_id": merchant_id, "qr_code": id }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["message"], "QR code is not a genuine gift card code");
+
+        let forger = QrKeyRing::from_spec(&format!("{}:{}", keys.active().kid, "Zm9yZ2VkLWtleS1mb3ItYS1mYWtlLWdpZnQtY2FyZCE=")).unwrap();
+        let resp = test::call_service(&app, verify(forger.sign(id, Utc::now()).unwrap())).await;
+        assert_eq!(resp.status(), 400);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["message"], "QR code is not a genuine gift card code");
+
+        // The public keys need no token
+        let req = test::TestRequest::get().uri("/qr-keys").to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["keys"][0]["kid"], keys.active().kid);
+        assert_eq!(body["data"]["keys"][0]["crv"], "Ed25519");
+        assert_eq!(body["data"]["keys"][0]["active"], true);
+    }
+
+    #[actix_web::test]
+    async fn test_live_qr_codes_rotate_and_replace_static_ones() {
+        let repo = test_repo();
+        let merchant_id = test_merchant(&repo, "Kiosk").await;
+        let config = test_config();
+        let issuer = issuer_auth(&repo, &config, "Alice").await;
+        let merchant = merchant_auth(&repo, &config, merchant_id).await;
+        let recipient = recipient_auth(&repo, &config, "1234567890").await;
+        let window_seconds = config.qr_live_window;
+        let (sms, sms_data) = test_sms();
+        let app = test::init_service(
+            App::new()
+                .app_data(repo.clone())
+                .app_data(config)
+                .app_data(sms_data)
+                .configure(crate::routes::gift_cards::config),
+        )
+        .await;
+
+        let id: Uuid = issue_card!(&app, issuer.as_str()).parse().unwrap();
+        accept_card!(&app, sms, id, recipient.as_str());
+
+        // Without live codes, a live code is not accepted
+        let forged = LiveQrCode::new(&generate_live_qr_secret(), id, live_qr_window(Utc::now(), window_seconds));
+        let req = test::TestRequest::get()
+            .uri(&format!("/gift-cards/{}/verify", forged))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["message"], "QR code is not a genuine gift card code");
+
+        let live_qr = |method: test::TestRequest, auth: &str| {
+            method.uri(&format!("/gift-cards/{}/live-qr", id)).insert_header((AUTHORIZATION, auth.to_string())).to_request()
+        };
+        let resp = test::call_service(&app, live_qr(test::TestRequest::get(), &recipient)).await;
+        assert_eq!(resp.status(), 404);
+        let resp = test::call_service(&app, live_qr(test::TestRequest::post(), &issuer)).await;
+        assert_eq!(resp.status(), 403);
+
+        let resp = test::call_service(&app, live_qr(test::TestRequest::post(), &recipient)).await;
+        assert_eq!(resp.status(), 200);
+        let body: Value = test::read_body_json(resp).await;
+        assert!(body["data"]["qr_code"].as_str().unwrap().starts_with("data:image/svg+xml;base64,"));
+        let window = body["data"]["window"].as_i64().unwrap();
+
+        let resp = test::call_service(&app, live_qr(test::TestRequest::get(), &recipient)).await;
+        assert_eq!(resp.headers().get(CACHE_CONTROL).unwrap(), "no-store");
+        let body: Value = test::read_body_json(resp).await;
+        let code = body["data"]["code"].as_str().unwrap().to_string();
+        assert!(body["data"]["window"].as_i64().unwrap() >= window);
+
+        let verify = |scanned: String| {
+            test::TestRequest::get()
+                .uri(&format!("/gift-cards/{}/verify", scanned))
+                .insert_header((AUTHORIZATION, merchant.as_str()))
+                .to_request()
+        };
+        let body: Value = test::call_and_read_body_json(&app, verify(code.clone())).await;
+        assert_eq!(body["data"]["id"], id.to_string());
+
+        // The static signed code no longer works
+        let token = test_config().qr_signing_keys.sign(id, Utc::now()).unwrap();
+        let body: Value = test::call_and_read_body_json(&app, verify(token)).await;
+        assert_eq!(body["message"], "This gift card only accepts its live QR code");
+
+        // The previous window is still accepted, older and future ones are not
+        let secret = repo.find_live_qr_secret(id).await.unwrap().unwrap().secret;
+        let current = live_qr_window(Utc::now(), window_seconds);
+        let at = |window: i64| LiveQrCode::new(&secret, id, window).to_string();
+        let body: Value = test::call_and_read_body_json(&app, verify(at(current - 1))).await;
+        assert_eq!(body["data"]["id"], id.to_string());
+        let body: Value = test::call_and_read_body_json(&app, verify(at(current - 2))).await;
+        assert_eq!(body["message"], "QR code has expired, scan the current one");
+        let body: Value = test::call_and_read_body_json(&app, verify(at(current + 2))).await;
+        assert_eq!(body["message"], "QR code is not a genuine gift card code");
+
+        let pay = |qr_code: Option<String>| {
+            test::TestRequest::post()
+                .uri(&format!("/gift-cards/{}/use", id))
+                .insert_header((AUTHORIZATION, merchant.as_str()))
+                .set_json(json!({
+                    "gift_card_id": id,
+                    "amount": { "amount": 1000, "currency": "USD" },
+                    "merchant_id": merchant_id,
+                    "qr_code": qr_code
+                }))
+                .to_request()
+        };
+        let body: Value = test::call_and_read_body_json(&app, pay(None)).await;
+        assert_eq!(body["message"], "This gift card only accepts its live QR code");
+        let body: Value = test::call_and_read_body_json(&app, pay(Some(at(current)))).await;
+        assert_eq!(body["data"]["balance"]["amount"], 4000);
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/holds", id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .set_json(json!({ "amount": { "amount": 1000, "currency": "USD" }, "merchant_id": merchant_id }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["message"], "This gift card only accepts its live QR code");
+    }
+
+    #[actix_web::test]
+    async fn test_qr_code_rendering_follows_the_query() {
+        let repo = test_repo();
+        let config = test_config();
+        let issuer = issuer_auth(&repo, &config, "Alice").await;
+        let app = test::init_service(
+            App::new()
+                .app_data(repo)
+                .app_data(config)
+                .configure(crate::routes::gift_cards::config),
+        )
+        .await;
+
+        let id = issue_card!(&app, issuer.as_str());
+
+        let qr_code = |query: &str| {
+            test::TestRequest::get()
+                .uri(&format!("/gift-cards/{}/qr-code?{}", id, query))
+                .insert_header((AUTHORIZATION, issuer.as_str()))
+                .to_request()
+        };
+
+        let body: Value = test::call_and_read_body_json(&app, qr_code("")).await;
+        assert!(body["data"].as_str().unwrap().starts_with("data:image/svg+xml;base64,"));
+
+        let resp = test::call_service(&app, qr_code("format=png&size=256&background=ffeecc&raw=true")).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers().get("content-type").unwrap(), "image/png");
+        let png = test::read_body(resp).await;
+        let image = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), (256, 256));
+        assert_eq!(image.get_pixel(0, 0).0, [0xff, 0xee, 0xcc, 0xff]);
+
+        let resp = test::call_service(&app, qr_code("logo=true")).await;
+        assert_eq!(resp.status(), 400);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["message"], "No logo is configured");
+
+        let resp = test::call_service(&app, qr_code("error_correction=x")).await;
+        assert_eq!(resp.status(), 400);
+    }
+
+    #[actix_web::test]
+    async fn test_vouchers_are_printable_without_live_qr_codes() {
+        let repo = test_repo();
+        let config = test_config();
+        let issuer = issuer_auth(&repo, &config, "Alice").await;
+        let merchant = merchant_auth(&repo, &config, test_merchant(&repo, "Coffee Shop").await).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(repo.clone())
+                .app_data(config)
+                .configure(crate::routes::gift_cards::config),
+        )
+        .await;
+
+        let id: Uuid = issue_card!(&app, issuer.as_str()).parse().unwrap();
+
+        let voucher = |auth: &str, query: &str| {
+            test::TestRequest::get()
+                .uri(&format!("/gift-cards/{}/voucher{}", id, query))
+                .insert_header((AUTHORIZATION, auth))
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, voucher(&issuer, "")).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers().get("content-type").unwrap(), "application/pdf");
+        assert_eq!(
+            resp.headers().get("content-disposition").unwrap().to_str().unwrap(),
+            format!("inline; filename=\"gift-card-{}.pdf\"", id)
+        );
+        let pdf = test::read_body(resp).await;
+        assert!(pdf.starts_with(b"%PDF-"));
+        assert!(String::from_utf8_lossy(&pdf).contains("(50.00 USD) Tj"));
+
+        // No font is configured for PNGs, and merchants cannot print cards
+        let resp = test::call_service(&app, voucher(&issuer, "?format=png")).await;
+        assert_eq!(resp.status(), 400);
+        let resp = test::call_service(&app, voucher(&merchant, "")).await;
+        assert_eq!(resp.status(), 403);
+
+        let secret = LiveQrSecret { gift_card_id: id, secret: generate_live_qr_secret(), created_at: Utc::now() };
+        repo.insert_live_qr_secret(&secret).await.unwrap();
+        let resp = test::call_service(&app, voucher(&issuer, "")).await;
+        assert_eq!(resp.status(), 409);
+    }
+
+    #[actix_web::test]
+    async fn test_short_codes_need_their_pin_and_lock_out() {
+        let repo = test_repo();
+        let merchant_id = test_merchant(&repo, "Cafe").await;
+        let config = test_config();
+        let issuer = issuer_auth(&repo, &config, "Alice").await;
+        let merchant = merchant_auth(&repo, &config, merchant_id).await;
+        let recipient = recipient_auth(&repo, &config, "1234567890").await;
+        let (sms, sms_data) = test_sms();
+        let app = test::init_service(
+            App::new()
+                .app_data(repo)
+                .app_data(config)
+                .app_data(sms_data)
+                .configure(crate::routes::gift_cards::config),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/gift-cards")
+            .insert_header((AUTHORIZATION, issuer.as_str()))
+            .set_json(json!({
+                "recipient_name": "Bob",
+                "recipient_phone": "1234567890",
+                "balance": { "amount": 5000, "currency": "USD" },
+                "expiration_days": 30,
+                "pin": "1234"
+            }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        let id = body["data"]["id"].as_str().unwrap().to_string();
+        let code = body["data"]["code"].as_str().unwrap().to_string();
+        assert_eq!(code.len(), 19);
+
+        let verify = |code: &str, pin: &str| {
+            test::TestRequest::post()
+                .uri(&format!("/gift-cards/by-code/{}/verify", code))
+                .insert_header((AUTHORIZATION, merchant.as_str()))
+                .set_json(json!({ "pin": pin }))
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, verify(&code, "9999")).await;
+        assert_eq!(resp.status(), 400);
+        let body: Value = test::call_and_read_body_json(&app, verify(&code.to_lowercase(), "1234")).await;
+        assert_eq!(body["data"]["id"], id.as_str());
+
+        accept_card!(&app, sms, id, recipient.as_str());
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/by-code/{}/use", code.replace('-', "")))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .set_json(json!({ "pin": "1234", "amount": { "amount": 1500, "currency": "USD" }, "merchant_id": merchant_id }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["balance"]["amount"], 3500);
+
+        // Five wrong PINs in a row lock the code, even against the right PIN
+        for _ in 0..5 {
+            test::call_service(&app, verify(&code, "0000")).await;
+        }
+        let resp = test::call_service(&app, verify(&code, "1234")).await;
+        assert_eq!(resp.status(), 429);
+    }
+
+    #[actix_web::test]
+    async fn test_refunds_restore_the_balance() {
+        let repo = test_repo();
+        let merchant_id = test_merchant(&repo, "Cafe").await;
+        let config = test_config();
+        let issuer = issuer_auth(&repo, &config, "Alice").await;
+        let merchant = merchant_auth(&repo, &config, merchant_id).await;
+        let recipient = recipient_auth(&repo, &config, "1234567890").await;
+        let admin = admin_auth(&repo, &config).await;
+        let (sms, sms_data) = test_sms();
+        let app = test::init_service(
+            App::new()
+                .app_data(repo)
+                .app_data(config)
+                .app_data(sms_data)
+                .configure(crate::routes::gift_cards::config),
+        )
+        .await;
+
+        let id = issue_card!(&app, issuer.as_str());
+
+        accept_card!(&app, sms, id, recipient.as_str());
+
+        // Spend the whole balance, depleting the card
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/use", id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .set_json(json!({ "gift_card_id": id, "amount": { "amount": 5000, "currency": "USD" }, "merchant_id": merchant_id, "qr_code": signed_qr(&id) }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["status"], "depleted");
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/gift-cards/{}/transactions", id))
+            .insert_header((AUTHORIZATION, issuer.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        let txn_id = body["data"][0]["id"].as_str().unwrap().to_string();
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/refunds", id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .set_json(json!({ "transaction_id": txn_id, "amount": { "amount": 2000, "currency": "USD" } }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["kind"], "refund");
+        assert_eq!(body["data"]["original_transaction_id"], txn_id.as_str());
+        let refund_id = body["data"]["id"].as_str().unwrap().to_string();
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/gift-cards/{}", id))
+            .insert_header((AUTHORIZATION, issuer.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["balance"]["amount"], 2000);
+        assert_eq!(body["data"]["status"], "accepted");
+
+        // Only 30.00 USD of the redemption is left to refund
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/refunds", id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .set_json(json!({ "transaction_id": txn_id, "amount": { "amount": 3500, "currency": "USD" } }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["message"], "Refund exceeds the refundable amount of 30.00 USD");
+
+        // Refunds themselves cannot be refunded
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/refunds", id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .set_json(json!({ "transaction_id": refund_id }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+
+        // Without an amount the rest of the redemption is refunded
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/refunds", id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .set_json(json!({ "transaction_id": txn_id }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["amount"]["amount"], 3000);
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/refunds", id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .set_json(json!({ "transaction_id": txn_id }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["message"], "Transaction has already been fully refunded");
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/gift-cards/{}/ledger", id))
+            .insert_header((AUTHORIZATION, admin.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["ledger_balance"]["amount"], 5000);
+        assert_eq!(body["data"]["balanced"], true);
+    }
+
+    #[actix_web::test]
+    async fn test_lifecycle_endpoints_enforce_transitions() {
+        let repo = test_repo();
+        let config = test_config();
+        let issuer = issuer_auth(&repo, &config, "Alice").await;
+        let recipient = recipient_auth(&repo, &config, "1234567890").await;
+        let (_, sms_data) = test_sms();
+        let app = test::init_service(
+            App::new()
+                .app_data(repo)
+                .app_data(config)
+                .app_data(sms_data)
+                .configure(crate::routes::gift_cards::config),
+        )
+        .await;
+
+        let id = issue_card!(&app, issuer.as_str());
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/suspend", id))
+            .insert_header((AUTHORIZATION, issuer.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["status"], "suspended");
+
+        // A card suspended before acceptance resumes as issued
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/resume", id))
+            .insert_header((AUTHORIZATION, issuer.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["status"], "issued");
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/cancel", id))
+            .insert_header((AUTHORIZATION, issuer.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["status"], "cancelled");
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/acceptance-code", id))
+            .insert_header((AUTHORIZATION, recipient.as_str()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 409);
+    }
+
+    #[actix_web::test]
+    async fn test_routes_enforce_roles() {
+        let repo = test_repo();
+        let merchant_id = test_merchant(&repo, "Cafe").await;
+        let config = test_config();
+        let issuer = issuer_auth(&repo, &config, "Alice").await;
+        let other_issuer = issuer_auth(&repo, &config, "Carol").await;
+        let merchant = merchant_auth(&repo, &config, merchant_id).await;
+        let recipient = recipient_auth(&repo, &config, "1234567890").await;
+        let other_recipient = recipient_auth(&repo, &config, "5555555555").await;
+        let app = test::init_service(
+            App::new()
+                .app_data(repo)
+                .app_data(config)
+                .configure(crate::routes::gift_cards::config),
+        )
+        .await;
+
+        let card = json!({
+            "recipient_name": "Bob",
+            "recipient_phone": "1234567890",
+            "balance": { "amount": 5000, "currency": "USD" },
+            "expiration_days": 30
+        });
+        let req = test::TestRequest::post().uri("/gift-cards").set_json(&card).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+
+        let req = test::TestRequest::post()
+            .uri("/gift-cards")
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .set_json(&card)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+
+        let req = test::TestRequest::post()
+            .uri("/gift-cards")
+            .insert_header((AUTHORIZATION, issuer.as_str()))
+            .set_json(&card)
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        let id = body["data"]["id"].as_str().unwrap().to_string();
+
+        // Only the card's own issuer manages it, and only its recipient accepts it
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/suspend", id))
+            .insert_header((AUTHORIZATION, other_issuer.as_str()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/accept", id))
+            .insert_header((AUTHORIZATION, other_recipient.as_str()))
+            .set_json(json!({ "gift_card_id": id, "code": "000000" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+
+        let req = test::TestRequest::get()
+            .uri("/gift-cards/by-recipient/1234567890")
+            .insert_header((AUTHORIZATION, other_recipient.as_str()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+
+        let req = test::TestRequest::get()
+            .uri("/gift-cards/by-recipient/1234567890")
+            .insert_header((AUTHORIZATION, recipient.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"].as_array().unwrap().len(), 1);
+
+        // Merchants see a card without the recipient's details or QR code
+        let req = test::TestRequest::get()
+            .uri(&format!("/gift-cards/{}", id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["balance"]["amount"], 5000);
+        assert!(body["data"].get("recipient_name").is_none());
+        assert!(body["data"].get("recipient_phone").is_none());
+        assert!(body["data"]["qr_code"].is_null());
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/gift-cards/{}", id))
+            .insert_header((AUTHORIZATION, recipient.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["recipient_name"], "Bob");
+        assert!(body["data"]["qr_code"].is_string());
+
+        // A merchant only pays itself, and the ledger is for admins
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/use", id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .set_json(json!({ "gift_card_id": id, "amount": { "amount": 100, "currency": "USD" }, "merchant_id": Uuid::new_v4() }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/gift-cards/{}/ledger", id))
+            .insert_header((AUTHORIZATION, issuer.as_str()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+    }
+
+    #[actix_web::test]
+    async fn test_unknown_gift_card_is_not_found() {
+        let repo = test_repo();
+        let config = test_config();
+        let issuer = issuer_auth(&repo, &config, "Alice").await;
+        let app = test::init_service(
+            App::new()
+                .app_data(repo)
+                .app_data(config)
+                .configure(crate::routes::gift_cards::config),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/gift-cards/{}", Uuid::new_v4()))
+            .insert_header((AUTHORIZATION, issuer.as_str()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+
+        let req = test::TestRequest::get()
+            .uri("/gift-cards/not-a-uuid")
+            .insert_header((AUTHORIZATION, issuer.as_str()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+    }
+}
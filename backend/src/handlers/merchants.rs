@@ -0,0 +1,346 @@
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use crate::middleware::auth::AuthenticatedUser;
+use crate::models::account::Role;
+use crate::models::merchant::{CreateMerchantDto, Merchant, UpdateMerchantDto};
+use crate::repository::GiftCardRepository;
+use crate::utils::error::AppError;
+use crate::utils::validation::validate_email;
+use super::{error_response, ApiResponse, PaginationParams};
+
+/// Register a new merchant
+pub async fn create_merchant(
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
+    merchant_dto: web::Json<CreateMerchantDto>,
+) -> HttpResponse {
+    let dto = merchant_dto.into_inner();
+    let result = async {
+        user.require(&[Role::Admin])?;
+        let name = check_name(&dto.name)?;
+        let contact_email = check_contact_email(dto.contact_email)?;
+        
+        let merchant = Merchant::new(name, contact_email, Utc::now());
+        repo.insert_merchant(&merchant).await?;
+        Ok::<_, AppError>(merchant)
+    }
+    .await;
+    
+    match result {
+        Ok(merchant) => HttpResponse::Created().json(ApiResponse {
+            success: true,
+            data: Some(merchant),
+            message: Some("Merchant created".to_string()),
+        }),
+        Err(e) => error_response(e),
+    }
+}
+
+/// List merchants by name
+pub async fn list_merchants(
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
+    query: web::Query<PaginationParams>,
+) -> HttpResponse {
+    let (limit, offset) = query.limit_offset();
+    
+    if let Err(e) = user.require(&[Role::Admin]) {
+        return error_response(e);
+    }
+    
+    match repo.list_merchants(limit, offset).await {
+        Ok(merchants) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(merchants),
+            message: None,
+        }),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Get merchant by ID
+pub async fn get_merchant(
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let result = async {
+        let merchant_id = parse_merchant_id(&path.into_inner())?;
+        user.require_merchant_or_admin(merchant_id)?;
+        fetch_merchant(repo.get_ref(), merchant_id).await
+    }
+    .await;
+    
+    match result {
+        Ok(merchant) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(merchant),
+            message: None,
+        }),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Change a merchant's details or reactivate it
+pub async fn update_merchant(
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    merchant_dto: web::Json<UpdateMerchantDto>,
+) -> HttpResponse {
+    let dto = merchant_dto.into_inner();
+    let result = async {
+        user.require(&[Role::Admin])?;
+        let merchant_id = parse_merchant_id(&path.into_inner())?;
+        let mut merchant = fetch_merchant(repo.get_ref(), merchant_id).await?;
+        
+        if let Some(name) = dto.name {
+            merchant.name = check_name(&name)?;
+        }
+        if dto.contact_email.is_some() {
+            merchant.contact_email = check_contact_email(dto.contact_email)?;
+        }
+        if let Some(active) = dto.active {
+            merchant.active = active;
+        }
+        merchant.updated_at = Utc::now();
+        
+        repo.update_merchant(&merchant).await?;
+        Ok::<_, AppError>(merchant)
+    }
+    .await;
+    
+    match result {
+        Ok(merchant) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(merchant),
+            message: Some("Merchant updated".to_string()),
+        }),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Deactivate a merchant; its transaction history is kept
+pub async fn deactivate_merchant(
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let result = async {
+        user.require(&[Role::Admin])?;
+        let merchant_id = parse_merchant_id(&path.into_inner())?;
+        let mut merchant = fetch_merchant(repo.get_ref(), merchant_id).await?;
+        
+        merchant.active = false;
+        merchant.updated_at = Utc::now();
+        
+        repo.update_merchant(&merchant).await?;
+        Ok::<_, AppError>(merchant)
+    }
+    .await;
+    
+    match result {
+        Ok(merchant) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(merchant),
+            message: Some("Merchant deactivated".to_string()),
+        }),
+        Err(e) => error_response(e),
+    }
+}
+
+/// List transactions taken by a merchant, newest first
+pub async fn list_merchant_transactions(
+    repo: web::Data<dyn GiftCardRepository>,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    query: web::Query<PaginationParams>,
+) -> HttpResponse {
+    let (limit, offset) = query.limit_offset();
+    let result = async {
+        let merchant_id = parse_merchant_id(&path.into_inner())?;
+        user.require_merchant_or_admin(merchant_id)?;
+        fetch_merchant(repo.get_ref(), merchant_id).await?;
+        repo.list_merchant_transactions(merchant_id, limit, offset).await
+    }
+    .await;
+    
+    match result {
+        Ok(txns) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            data: Some(txns),
+            message: None,
+        }),
+        Err(e) => error_response(e),
+    }
+}
+
+// Helper functions
+
+/// Fetch a merchant that may take payments, rejecting unknown or inactive ones
+pub(crate) async fn fetch_active_merchant(repo: &dyn GiftCardRepository, merchant_id: Uuid) -> Result<Merchant, AppError> {
+    let merchant = fetch_merchant(repo, merchant_id).await?;
+    if !merchant.active {
+        return Err(AppError::ValidationError(format!("Merchant {} is inactive", merchant.name)));
+    }
+    Ok(merchant)
+}
+
+/// Parse a merchant ID from the request path
+fn parse_merchant_id(raw: &str) -> Result<Uuid, AppError> {
+    Uuid::from_str(raw).map_err(|_| AppError::ValidationError("Invalid merchant ID".to_string()))
+}
+
+/// Fetch a merchant by ID, mapping a missing merchant to `NotFoundError`
+async fn fetch_merchant(repo: &dyn GiftCardRepository, merchant_id: Uuid) -> Result<Merchant, AppError> {
+    repo.find_merchant(merchant_id)
+        .await?
+        .ok_or_else(|| AppError::NotFoundError("Merchant not found".to_string()))
+}
+
+/// Trim a merchant name, rejecting empty or overlong names
+fn check_name(name: &str) -> Result<String, AppError> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > 100 {
+        return Err(AppError::ValidationError("Merchant name must be 1 to 100 characters".to_string()));
+    }
+    Ok(name.to_string())
+}
+
+/// Validate an optional contact email, treating a blank one as absent
+fn check_contact_email(email: Option<String>) -> Result<Option<String>, AppError> {
+    match email.map(|email| email.trim().to_string()) {
+        Some(email) if email.is_empty() => Ok(None),
+        Some(email) if !validate_email(&email) => {
+            Err(AppError::ValidationError("Invalid contact email".to_string()))
+        }
+        email => Ok(email),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::test_support::{
+        accept_card, admin_auth, issue_card, issuer_auth, merchant_auth, recipient_auth, signed_qr, test_config,
+        test_repo, test_sms,
+    };
+    use actix_web::http::header::AUTHORIZATION;
+    use actix_web::{test, App};
+    use serde_json::{json, Value};
+
+    #[actix_web::test]
+    async fn test_merchant_crud_and_history() {
+        let repo = test_repo();
+        let config = test_config();
+        let issuer = issuer_auth(&repo, &config, "Alice").await;
+        let recipient = recipient_auth(&repo, &config, "1234567890").await;
+        let admin = admin_auth(&repo, &config).await;
+        let (sms, sms_data) = test_sms();
+        let app = test::init_service(
+            App::new()
+                .app_data(repo.clone())
+                .app_data(config.clone())
+                .app_data(sms_data)
+                .configure(crate::routes::gift_cards::config)
+                .configure(crate::routes::merchants::config),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/merchants")
+            .insert_header((AUTHORIZATION, admin.as_str()))
+            .set_json(json!({ "name": "  ", "contact_email": null }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+
+        let req = test::TestRequest::post()
+            .uri("/merchants")
+            .insert_header((AUTHORIZATION, admin.as_str()))
+            .set_json(json!({ "name": "Corner Cafe", "contact_email": "billing@cafe.example" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 201);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["data"]["active"], true);
+        let merchant_id = body["data"]["id"].as_str().unwrap().to_string();
+        let merchant = merchant_auth(&repo, &config, merchant_id.parse().unwrap()).await;
+
+        // Only admins manage merchants
+        let req = test::TestRequest::get()
+            .uri("/merchants")
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403);
+
+        let req = test::TestRequest::patch()
+            .uri(&format!("/merchants/{}", merchant_id))
+            .insert_header((AUTHORIZATION, admin.as_str()))
+            .set_json(json!({ "name": "Corner Cafe & Bakery" }))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["name"], "Corner Cafe & Bakery");
+        assert_eq!(body["data"]["contact_email"], "billing@cafe.example");
+
+        let id = issue_card!(&app, issuer.as_str());
+
+        accept_card!(&app, sms, id, recipient.as_str());
+
+        let use_card = json!({
+            "gift_card_id": id,
+            "amount": { "amount": 1200, "currency": "USD" },
+            "merchant_id": merchant_id,
+            "qr_code": signed_qr(&id)
+        });
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/use", id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .set_json(&use_card)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/merchants/{}/transactions", merchant_id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"].as_array().unwrap().len(), 1);
+        assert_eq!(body["data"][0]["gift_card_id"], id.as_str());
+        assert_eq!(body["data"][0]["merchant"], "Corner Cafe & Bakery");
+
+        // A deactivated merchant keeps its history but cannot take payments
+        let req = test::TestRequest::delete()
+            .uri(&format!("/merchants/{}", merchant_id))
+            .insert_header((AUTHORIZATION, admin.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["active"], false);
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/gift-cards/{}/use", id))
+            .insert_header((AUTHORIZATION, merchant.as_str()))
+            .set_json(&use_card)
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["message"], "Merchant Corner Cafe & Bakery is inactive");
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/merchants/{}/transactions", merchant_id))
+            .insert_header((AUTHORIZATION, admin.as_str()))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"].as_array().unwrap().len(), 1);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/merchants/{}", uuid::Uuid::new_v4()))
+            .insert_header((AUTHORIZATION, admin.as_str()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 404);
+    }
+}
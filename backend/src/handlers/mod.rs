@@ -1 +1,211 @@
-pub mod gift_cards;
\ No newline at end of file
+use actix_web::{HttpResponse, ResponseError};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::error::AppError;
+
+pub mod auth;
+pub mod batches;
+pub mod exports;
+pub mod gift_cards;
+pub mod merchants;
+pub mod qr_keys;
+
+/// Envelope for every JSON response
+#[derive(Debug, Serialize)]
+pub(crate) struct ApiResponse<T> {
+    pub success: bool,
+    pub data: Option<T>,
+    pub message: Option<String>,
+}
+
+/// `page`/`per_page` query parameters for list endpoints
+#[derive(Debug, Deserialize)]
+pub struct PaginationParams {
+    page: Option<u32>,
+    per_page: Option<u32>,
+}
+
+impl PaginationParams {
+    /// Convert page/per_page into a SQL-style limit and offset
+    pub(crate) fn limit_offset(&self) -> (i64, i64) {
+        let page = self.page.unwrap_or(1).max(1);
+        let per_page = self.per_page.unwrap_or(10);
+        (per_page as i64, (page as i64 - 1) * per_page as i64)
+    }
+}
+
+/// Convert an `AppError` into an `ApiResponse` with the matching status code
+pub(crate) fn error_response(error: AppError) -> HttpResponse {
+    let message = match &error {
+        AppError::DatabaseError(e) => {
+            log::error!("Database error: {:?}", e);
+            "Database error".to_string()
+        }
+        AppError::InternalServerError(e) => {
+            log::error!("Internal server error: {}", e);
+            "Internal server error".to_string()
+        }
+        AppError::NotFoundError(message)
+        | AppError::ValidationError(message)
+        | AppError::UnauthorizedError(message)
+        | AppError::ForbiddenError(message)
+        | AppError::ConflictError(message)
+        | AppError::TooManyRequestsError(message) => message.clone(),
+        AppError::InvalidTransition(e) => e.to_string(),
+    };
+    
+    HttpResponse::build(error.status_code()).json(ApiResponse {
+        success: false,
+        data: None::<()>,
+        message: Some(message),
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod test_support {
+    use actix_web::web;
+    use async_trait::async_trait;
+    use chrono::Utc;
+    use std::sync::{Arc, Mutex};
+    use uuid::Uuid;
+
+    use crate::config::Config;
+    use crate::models::account::{Account, Role};
+    use crate::repository::{GiftCardRepository, InMemoryRepository};
+    use crate::sms::SmsSender;
+    use crate::utils::error::AppError;
+    use crate::utils::auth::{issue_token, TokenKind};
+
+    /// An empty in-memory repository
+    pub fn test_repo() -> web::Data<dyn GiftCardRepository> {
+        let repo: Arc<dyn GiftCardRepository> = Arc::new(InMemoryRepository::new());
+        web::Data::from(repo)
+    }
+
+    /// Development defaults, as used for signing test tokens
+    pub fn test_config() -> web::Data<Config> {
+        let config = Config::from_sources(None, &|key| (key == "DATABASE_URL").then(|| "memory://".to_string()));
+        web::Data::new(config.unwrap())
+    }
+
+    /// The signed token a scan of card `gift_card_id`'s QR code reads
+    pub fn signed_qr(gift_card_id: &str) -> String {
+        let gift_card_id = Uuid::parse_str(gift_card_id).unwrap();
+        test_config().qr_signing_keys.sign(gift_card_id, Utc::now()).unwrap()
+    }
+
+    /// Add an account with `role`, returning an `Authorization` header value for it
+    pub async fn account_auth(
+        repo: &web::Data<dyn GiftCardRepository>,
+        config: &Config,
+        name: &str,
+        role: Role,
+        merchant_id: Option<Uuid>,
+        phone: Option<&str>,
+    ) -> String {
+        let now = Utc::now();
+        let account = Account {
+            id: Uuid::new_v4(),
+            email: format!("{}@{}.example", name.to_lowercase().replace(' ', "-"), role),
+            name: name.to_string(),
+            role,
+            merchant_id,
+            phone: phone.map(str::to_string),
+            password_hash: String::new(),
+            created_at: now,
+            updated_at: now,
+        };
+        repo.insert_account(&account).await.unwrap();
+        format!("Bearer {}", issue_token(config, account.id, TokenKind::Access, now).unwrap())
+    }
+
+    /// An issuer account called `name`
+    pub async fn issuer_auth(repo: &web::Data<dyn GiftCardRepository>, config: &Config, name: &str) -> String {
+        account_auth(repo, config, name, Role::Issuer, None, None).await
+    }
+
+    /// An admin account
+    pub async fn admin_auth(repo: &web::Data<dyn GiftCardRepository>, config: &Config) -> String {
+        account_auth(repo, config, "Admin", Role::Admin, None, None).await
+    }
+
+    /// A merchant account acting for `merchant_id`
+    pub async fn merchant_auth(repo: &web::Data<dyn GiftCardRepository>, config: &Config, merchant_id: Uuid) -> String {
+        account_auth(repo, config, &merchant_id.to_string(), Role::Merchant, Some(merchant_id), None).await
+    }
+
+    /// A recipient account holding the cards sent to `phone`
+    pub async fn recipient_auth(repo: &web::Data<dyn GiftCardRepository>, config: &Config, phone: &str) -> String {
+        account_auth(repo, config, phone, Role::Recipient, None, Some(phone)).await
+    }
+
+    /// Keeps sent text messages so tests can read the codes in them
+    #[derive(Default)]
+    pub struct RecordingSmsSender {
+        pub messages: Mutex<Vec<(String, String)>>,
+    }
+
+    impl RecordingSmsSender {
+        /// The code in the last message sent
+        pub fn last_code(&self) -> String {
+            let messages = self.messages.lock().unwrap();
+            let (_, message) = messages.last().expect("no message was sent");
+            message.split_whitespace().next().unwrap().to_string()
+        }
+    }
+
+    #[async_trait]
+    impl SmsSender for RecordingSmsSender {
+        async fn send(&self, phone: &str, message: &str) -> Result<(), AppError> {
+            self.messages.lock().unwrap().push((phone.to_string(), message.to_string()));
+            Ok(())
+        }
+    }
+
+    /// A recording SMS sender, and the same sender as app data
+    pub fn test_sms() -> (Arc<RecordingSmsSender>, web::Data<dyn SmsSender>) {
+        let sms = Arc::new(RecordingSmsSender::default());
+        let data: Arc<dyn SmsSender> = sms.clone();
+        (sms, web::Data::from(data))
+    }
+
+    /// Request an acceptance code for card `$id` as `$auth`, then accept the
+    /// card with it, evaluating to the accept response
+    macro_rules! accept_card {
+        ($app:expr, $sms:expr, $id:expr, $auth:expr) => {{
+            let req = actix_web::test::TestRequest::post()
+                .uri(&format!("/gift-cards/{}/acceptance-code", $id))
+                .insert_header((actix_web::http::header::AUTHORIZATION, $auth))
+                .to_request();
+            let resp = actix_web::test::call_service($app, req).await;
+            assert_eq!(resp.status(), 201);
+
+            let req = actix_web::test::TestRequest::post()
+                .uri(&format!("/gift-cards/{}/accept", $id))
+                .insert_header((actix_web::http::header::AUTHORIZATION, $auth))
+                .set_json(serde_json::json!({ "gift_card_id": $id, "code": $sms.last_code() }))
+                .to_request();
+            actix_web::test::call_service($app, req).await
+        }};
+    }
+    pub(crate) use accept_card;
+
+    /// Issue a 50.00 USD card to Bob as `$auth`, evaluating to its ID
+    macro_rules! issue_card {
+        ($app:expr, $auth:expr) => {{
+            let req = actix_web::test::TestRequest::post()
+                .uri("/gift-cards")
+                .insert_header((actix_web::http::header::AUTHORIZATION, $auth))
+                .set_json(serde_json::json!({
+                    "recipient_name": "Bob",
+                    "recipient_phone": "1234567890",
+                    "balance": { "amount": 5000, "currency": "USD" },
+                    "expiration_days": 30
+                }))
+                .to_request();
+            let body: serde_json::Value = actix_web::test::call_and_read_body_json($app, req).await;
+            body["data"]["id"].as_str().unwrap().to_string()
+        }};
+    }
+    pub(crate) use issue_card;
+}
@@ -0,0 +1,15 @@
+use actix_web::{web, HttpResponse};
+
+use crate::config::Config;
+use super::ApiResponse;
+
+/// List the public keys QR codes are signed with
+///
+/// Public, so payment terminals can cache the keys and check QR codes offline.
+pub async fn list_qr_keys(config: web::Data<Config>) -> HttpResponse {
+    HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        data: Some(config.qr_signing_keys.public_keys()),
+        message: None,
+    })
+}
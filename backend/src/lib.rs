@@ -0,0 +1,11 @@
+pub mod models;
+pub mod routes;
+pub mod handlers;
+pub mod repository;
+pub mod utils;
+pub mod config;
+pub mod migrations;
+pub mod middleware;
+pub mod sms;
+pub mod expiry;
+pub mod voucher;
@@ -1,47 +1,193 @@
 use actix_cors::Cors;
 use actix_web::{middleware, web, App, HttpServer};
 use dotenv::dotenv;
-use sqlx::mysql::MySqlPoolOptions;
 use std::env;
+use std::time::Duration;
 
-mod models;
-mod routes;
-mod handlers;
-mod utils;
-mod config;
+use gift_card_backend::config::Config;
+use gift_card_backend::expiry;
+use gift_card_backend::handlers::auth::new_account;
+use gift_card_backend::handlers::batches;
+use gift_card_backend::middleware::idempotency::idempotency;
+use gift_card_backend::migrations::Migrator;
+use gift_card_backend::models::account::Role;
+use gift_card_backend::repository;
+use gift_card_backend::routes;
+use gift_card_backend::sms;
+use gift_card_backend::utils::error::AppError;
+
+const USAGE: &str = "Usage: gift-card-backend [serve | migrate [--dry-run] | migrate status | create-admin <email> <name>]";
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
 
-    // Database connection setup
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let db_pool = MySqlPoolOptions::new()
-        .max_connections(5)
-        .connect(&database_url)
-        .await
-        .expect("Failed to create database pool");
+    let config = match Config::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        [] | ["serve"] => serve(config).await,
+        ["migrate"] => migrate(&config, false).await,
+        ["migrate", "--dry-run"] | ["migrate", "status"] => migrate(&config, true).await,
+        ["create-admin", email, name] => create_admin(&config, email, name).await,
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Apply pending migrations, or only list them when `dry_run` is set
+async fn migrate(config: &Config, dry_run: bool) -> std::io::Result<()> {
+    let result: Result<(), AppError> = async {
+        let mut migrator = Migrator::connect(&config.database_url).await?;
+
+        if dry_run {
+            for applied in migrator.applied().await? {
+                println!("applied  {:>4}  {}  ({})", applied.version, applied.description, applied.applied_at);
+            }
+            for pending in migrator.pending().await? {
+                println!("pending  {:>4}  {}", pending.version, pending.description);
+            }
+            Ok(())
+        } else {
+            let applied = migrator.run().await?;
+            log::info!("Applied {} migration(s)", applied.len());
+            Ok(())
+        }
+    }
+    .await;
+
+    if let Err(e) = result {
+        log::error!("Migration failed: {}", e);
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Create an admin account, with the password read from `ADMIN_PASSWORD`
+///
+/// Admins create every other non-issuer account, so the first one is made here.
+async fn create_admin(config: &Config, email: &str, name: &str) -> std::io::Result<()> {
+    let result: Result<(), AppError> = async {
+        let password = env::var("ADMIN_PASSWORD")
+            .map_err(|_| AppError::ValidationError("ADMIN_PASSWORD must be set".to_string()))?;
+        let account = new_account(email.to_string(), name.to_string(), &password, Role::Admin, None, None)?;
+
+        let repo = repository::connect(&config.database_url, config.database_max_connections).await?;
+        repo.insert_account(&account).await?;
+        log::info!("Created admin account {} ({})", account.email, account.id);
+        Ok(())
+    }
+    .await;
+
+    if let Err(e) = result {
+        log::error!("Failed to create admin account: {}", e);
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Check or apply migrations, then run the HTTP server
+async fn serve(config: Config) -> std::io::Result<()> {
+    let schema_ready: Result<(), AppError> = async {
+        let mut migrator = Migrator::connect(&config.database_url).await?;
+        if config.database_auto_migrate {
+            migrator.run().await?;
+        }
+        migrator.check().await
+    }
+    .await;
+
+    if let Err(e) = schema_ready {
+        log::error!("Refusing to start: {}", e);
+        log::error!("Run `gift-card-backend migrate` or set DATABASE_AUTO_MIGRATE=true");
+        std::process::exit(1);
+    }
+
+    // Storage setup, backend chosen from the DATABASE_URL scheme
+    let repo = match repository::connect(&config.database_url, config.database_max_connections).await {
+        Ok(repo) => repo,
+        Err(e) => {
+            log::error!("Failed to connect to the gift card repository: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let repo: web::Data<dyn repository::GiftCardRepository> = web::Data::from(repo);
+
+    // Delivery of acceptance codes, chosen from SMS_SENDER
+    let sms = match sms::connect(&config.sms_sender) {
+        Ok(sms) => sms,
+        Err(e) => {
+            log::error!("Failed to set up the SMS sender: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let sms: web::Data<dyn sms::SmsSender> = web::Data::from(sms);
+
+    // Expire cards past their expiration date and book their breakage
+    if config.expiry_sweep_interval > 0 {
+        expiry::spawn(repo.clone(), Duration::from_secs(config.expiry_sweep_interval), config.expiry_sweep_batch);
+    }
+
+    log::info!(
+        "Starting server at http://{}:{} ({:?} profile)",
+        config.server_host,
+        config.server_port,
+        config.profile
+    );
 
-    log::info!("Starting server at http://localhost:8080");
+    let bind_address = (config.server_host.clone(), config.server_port);
+    let app_config = web::Data::new(config);
 
     HttpServer::new(move || {
-        let cors = Cors::default()
-            .allow_any_origin()
+        let mut cors = Cors::default()
             .allow_any_method()
             .allow_any_header()
             .max_age(3600);
+        if app_config.allows_any_origin() {
+            cors = cors.allow_any_origin();
+        } else {
+            for origin in &app_config.cors_allowed_origins {
+                cors = cors.allowed_origin(origin);
+            }
+        }
 
         App::new()
             .wrap(middleware::Logger::default())
             .wrap(cors)
-            .app_data(web::Data::new(db_pool.clone()))
+            .app_data(app_config.clone())
+            .app_data(repo.clone())
+            .app_data(sms.clone())
+            // Batch uploads are the largest bodies, and Idempotency-Key buffers them too
+            .app_data(web::PayloadConfig::new(batches::MAX_BODY_BYTES))
             .service(
                 web::scope("/api")
-                    .configure(routes::gift_cards::config)
+                    // Account responses carry tokens, which are never stored for replay
+                    .configure(routes::auth::config)
+                    .service(
+                        web::scope("")
+                            .wrap(middleware::from_fn(idempotency))
+                            .configure(routes::batches::config)
+                            .configure(routes::exports::config)
+                            .configure(routes::gift_cards::config)
+                            .configure(routes::merchants::config)
+                            .configure(routes::qr_keys::config)
+                    )
             )
     })
-    .bind(("0.0.0.0", 8080))?
+    .bind(bind_address)?
     .run()
     .await
-}
\ No newline at end of file
+}
@@ -0,0 +1,157 @@
+use actix_web::dev::Payload;
+use actix_web::error::InternalError;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{web, Error, FromRequest, HttpRequest};
+use std::future::Future;
+use std::pin::Pin;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::handlers::error_response;
+use crate::models::account::Role;
+use crate::models::gift_card::GiftCard;
+use crate::repository::GiftCardRepository;
+use crate::utils::auth::{decode_token, TokenKind};
+use crate::utils::error::AppError;
+
+/// The account a request was made by, taken from its bearer token
+///
+/// Add as a handler argument to require a valid access token in the
+/// `Authorization: Bearer <token>` header; requests without one are rejected
+/// with `401 Unauthorized` before the handler runs. The account is loaded on
+/// every request, so role changes and deleted accounts take effect at once.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub id: Uuid,
+    pub role: Role,
+    pub merchant_id: Option<Uuid>,  // Set for merchant accounts
+    pub phone: Option<String>,      // Set for recipient accounts
+}
+
+impl AuthenticatedUser {
+    /// Allow only the given roles
+    pub fn require(&self, roles: &[Role]) -> Result<(), AppError> {
+        if roles.contains(&self.role) {
+            Ok(())
+        } else {
+            Err(forbidden())
+        }
+    }
+
+    /// Whether this is the card's issuer or recipient, or an admin
+    pub fn owns_card(&self, card: &GiftCard) -> bool {
+        match self.role {
+            Role::Admin => true,
+            Role::Issuer => card.issuer_id == Some(self.id),
+            Role::Recipient => self.phone.as_deref() == Some(card.recipient_phone.as_str()),
+            Role::Merchant => false,
+        }
+    }
+
+    /// Allow only the card's issuer or recipient, or an admin
+    pub fn require_card_owner(&self, card: &GiftCard) -> Result<(), AppError> {
+        if self.owns_card(card) {
+            Ok(())
+        } else {
+            Err(forbidden())
+        }
+    }
+
+    /// Allow only the card's issuer, or an admin
+    pub fn require_card_issuer(&self, card: &GiftCard) -> Result<(), AppError> {
+        self.require(&[Role::Admin, Role::Issuer])?;
+        self.require_card_owner(card)
+    }
+
+    /// Allow only the card's recipient
+    pub fn require_card_recipient(&self, card: &GiftCard) -> Result<(), AppError> {
+        self.require(&[Role::Recipient])?;
+        self.require_card_owner(card)
+    }
+
+    /// Allow only the recipient account for `phone`, or an admin
+    pub fn require_recipient_or_admin(&self, phone: &str) -> Result<(), AppError> {
+        match self.role {
+            Role::Admin => Ok(()),
+            Role::Recipient if self.phone.as_deref() == Some(phone) => Ok(()),
+            _ => Err(forbidden()),
+        }
+    }
+
+    /// Allow only the account `account_id`, or an admin
+    pub fn require_account_or_admin(&self, account_id: Uuid) -> Result<(), AppError> {
+        if self.role == Role::Admin || self.id == account_id {
+            Ok(())
+        } else {
+            Err(forbidden())
+        }
+    }
+
+    /// Allow only a merchant account acting for `merchant_id`
+    pub fn require_merchant(&self, merchant_id: Uuid) -> Result<(), AppError> {
+        if self.role == Role::Merchant && self.merchant_id == Some(merchant_id) {
+            Ok(())
+        } else {
+            Err(forbidden())
+        }
+    }
+
+    /// Allow only a merchant account acting for `merchant_id`, or an admin
+    pub fn require_merchant_or_admin(&self, merchant_id: Uuid) -> Result<(), AppError> {
+        if self.role == Role::Admin {
+            Ok(())
+        } else {
+            self.require_merchant(merchant_id)
+        }
+    }
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            authenticate(&req)
+                .await
+                .map_err(|e| InternalError::from_response(e.to_string(), error_response(e)).into())
+        })
+    }
+}
+
+fn forbidden() -> AppError {
+    AppError::ForbiddenError("You are not allowed to do this".to_string())
+}
+
+/// Read and verify the bearer token on `req`, then load its account
+async fn authenticate(req: &HttpRequest) -> Result<AuthenticatedUser, AppError> {
+    let config = req
+        .app_data::<web::Data<Config>>()
+        .ok_or_else(|| AppError::InternalServerError("No configuration registered for authentication".to_string()))?;
+    let repo = req
+        .app_data::<web::Data<dyn GiftCardRepository>>()
+        .ok_or_else(|| AppError::InternalServerError("No repository registered for authentication".to_string()))?;
+
+    let token = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| AppError::UnauthorizedError("A bearer token is required".to_string()))?;
+
+    let claims = decode_token(config, token, TokenKind::Access)?;
+    let account = repo
+        .find_account(claims.sub)
+        .await?
+        .ok_or_else(|| AppError::UnauthorizedError("Invalid or expired token".to_string()))?;
+
+    Ok(AuthenticatedUser {
+        id: account.id,
+        role: account.role,
+        merchant_id: account.merchant_id,
+        phone: account.phone,
+    })
+}
@@ -0,0 +1,281 @@
+use actix_web::body::{self, BoxBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{ContentType, HeaderValue};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, FromRequest, HttpRequest, HttpResponse};
+use chrono::{Duration, Utc};
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+use crate::handlers::error_response;
+use crate::middleware::auth::AuthenticatedUser;
+use crate::repository::GiftCardRepository;
+use crate::utils::error::AppError;
+
+/// Request header carrying the client's idempotency key
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Response header set when a stored response is replayed
+pub const REPLAYED_HEADER: &str = "Idempotent-Replayed";
+
+/// Replay window used when no [`Config`] is registered, in seconds
+const DEFAULT_TTL: i64 = 86400;
+
+const MAX_KEY_LENGTH: usize = 255;
+
+/// Make mutating requests safe to retry with an `Idempotency-Key` header
+///
+/// Register with `actix_web::middleware::from_fn`. Keys are scoped to the
+/// signed-in account, so clients cannot see each other's responses. The
+/// first request with a key stores a fingerprint of its caller, method, path
+/// and body together with the response; a retry with the same key and body
+/// gets the stored response back, and reusing the key for a different
+/// request is a conflict. Server errors are not stored, so those requests
+/// can be retried. Requests without the header are passed through unchanged.
+/// Responses carrying credentials must not be stored, so do not wrap the
+/// account routes.
+pub async fn idempotency(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let mutating = matches!(*req.method(), Method::POST | Method::PUT | Method::PATCH | Method::DELETE);
+    let header = req.headers().get(IDEMPOTENCY_KEY_HEADER).cloned();
+    let header = match header {
+        Some(header) if mutating => header,
+        _ => return Ok(next.call(req).await?.map_into_boxed_body()),
+    };
+
+    let key = match parse_key(&header) {
+        Ok(key) => key,
+        Err(e) => return Ok(req.into_response(error_response(e))),
+    };
+    let repo = match req.app_data::<web::Data<dyn GiftCardRepository>>() {
+        Some(repo) => repo.clone(),
+        None => {
+            let e = AppError::InternalServerError("No repository registered for idempotency keys".to_string());
+            return Ok(req.into_response(error_response(e)));
+        }
+    };
+    let ttl = req
+        .app_data::<web::Data<Config>>()
+        .map(|config| config.idempotency_key_ttl)
+        .unwrap_or(DEFAULT_TTL);
+
+    // Buffer the body so it can be fingerprinted and still reach the handler
+    let (http_req, mut payload) = req.into_parts();
+    let principal = Option::<AuthenticatedUser>::from_request(&http_req, &mut payload)
+        .await?
+        .map(|user| user.id.to_string())
+        .unwrap_or_default();
+    let key = scoped_key(&principal, &key);
+    let body = web::Bytes::from_request(&http_req, &mut payload).await?;
+    let fingerprint = fingerprint(&principal, &http_req, &body);
+    let req = ServiceRequest::from_parts(http_req, Payload::from(body));
+
+    let now = Utc::now();
+    let existing = match repo.claim_idempotency_key(&key, &fingerprint, now, now - Duration::seconds(ttl)).await {
+        Ok(existing) => existing,
+        Err(e) => return Ok(req.into_response(error_response(e))),
+    };
+
+    if let Some(record) = existing {
+        let response = if record.fingerprint != fingerprint {
+            error_response(AppError::ConflictError(
+                "Idempotency-Key was already used for a different request".to_string(),
+            ))
+        } else if let (Some(status), Some(body)) = (record.response_status, record.response_body) {
+            replay(status, body)
+        } else {
+            error_response(AppError::ConflictError(
+                "A request with this Idempotency-Key is still being processed".to_string(),
+            ))
+        };
+        return Ok(req.into_response(response));
+    }
+
+    let res = match next.call(req).await {
+        Ok(res) => res,
+        Err(e) => {
+            release(repo.get_ref(), &key).await;
+            return Err(e);
+        }
+    };
+
+    if res.status().is_server_error() {
+        release(repo.get_ref(), &key).await;
+        return Ok(res.map_into_boxed_body());
+    }
+
+    // Capture the body to store it, then hand the same bytes to the client
+    let (http_req, res) = res.into_parts();
+    let (res, body) = res.into_parts();
+    let bytes = match body::to_bytes(body).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            release(repo.get_ref(), &key).await;
+            let e: Box<dyn std::error::Error> = e.into();
+            return Err(actix_web::error::ErrorInternalServerError(e.to_string()));
+        }
+    };
+
+    match std::str::from_utf8(&bytes) {
+        Ok(text) => {
+            if let Err(e) = repo.complete_idempotency_key(&key, res.status().as_u16(), text).await {
+                log::error!("Failed to store response for idempotency key {}: {}", key, e);
+            }
+        }
+        Err(_) => release(repo.get_ref(), &key).await,
+    }
+
+    Ok(ServiceResponse::new(http_req, res.set_body(bytes).map_into_boxed_body()))
+}
+
+/// Validate the raw header value
+fn parse_key(header: &HeaderValue) -> Result<String, AppError> {
+    let key = header
+        .to_str()
+        .map_err(|_| AppError::ValidationError("Idempotency-Key must be printable ASCII".to_string()))?
+        .trim();
+
+    if key.is_empty() || key.len() > MAX_KEY_LENGTH || !key.chars().all(|c| c.is_ascii_graphic()) {
+        return Err(AppError::ValidationError(format!(
+            "Idempotency-Key must be 1 to {} printable characters without spaces",
+            MAX_KEY_LENGTH
+        )));
+    }
+
+    Ok(key.to_string())
+}
+
+/// The key a client's key is stored under: SHA-256 over the ID of the
+/// signed-in account, empty for anonymous callers, and the client's key
+fn scoped_key(principal: &str, key: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(principal);
+    hasher.update(b"\n");
+    hasher.update(key);
+
+    hex_digest(hasher)
+}
+
+/// SHA-256 over the caller, method, path with query string, and body
+fn fingerprint(principal: &str, req: &HttpRequest, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(principal);
+    hasher.update(b"\n");
+    hasher.update(req.method().as_str());
+    hasher.update(b" ");
+    hasher.update(req.uri().to_string());
+    hasher.update(b"\n");
+    hasher.update(body);
+
+    hex_digest(hasher)
+}
+
+fn hex_digest(hasher: Sha256) -> String {
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Rebuild a stored response; every mutating endpoint responds with JSON
+fn replay(status: u16, body: String) -> HttpResponse {
+    HttpResponse::build(StatusCode::from_u16(status).unwrap_or(StatusCode::OK))
+        .insert_header(ContentType::json())
+        .insert_header((REPLAYED_HEADER, "true"))
+        .body(body)
+}
+
+/// Free a claimed key after a failure; errors are only logged
+async fn release(repo: &dyn GiftCardRepository, key: &str) {
+    if let Err(e) = repo.release_idempotency_key(key).await {
+        log::error!("Failed to release idempotency key {}: {}", key, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::test_support::{issuer_auth, test_config, test_repo};
+    use actix_web::http::header::AUTHORIZATION;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, App};
+    use serde_json::{json, Value};
+
+    fn create_request(auth: &str, key: &str, balance: i64) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri("/gift-cards")
+            .insert_header((AUTHORIZATION, auth))
+            .insert_header((IDEMPOTENCY_KEY_HEADER, key))
+            .set_json(json!({
+                "recipient_name": "Bob",
+                "recipient_phone": "1234567890",
+                "balance": { "amount": balance, "currency": "USD" },
+                "expiration_days": 30
+            }))
+    }
+
+    #[actix_web::test]
+    async fn test_retries_replay_the_first_response() {
+        let repo = test_repo();
+        let config = test_config();
+        let auth = issuer_auth(&repo, &config, "Alice").await;
+        let app = test::init_service(
+            App::new()
+                .app_data(repo.clone())
+                .app_data(config)
+                .wrap(from_fn(idempotency))
+                .configure(crate::routes::gift_cards::config),
+        )
+        .await;
+
+        let first: Value = test::call_and_read_body_json(&app, create_request(&auth, "order-1", 5000).to_request()).await;
+
+        let resp = test::call_service(&app, create_request(&auth, "order-1", 5000).to_request()).await;
+        assert_eq!(resp.status(), 201);
+        assert_eq!(resp.headers().get(REPLAYED_HEADER).unwrap(), "true");
+        let retry: Value = test::read_body_json(resp).await;
+        assert_eq!(retry["data"]["id"], first["data"]["id"]);
+
+        // Only one card was issued
+        let cards = repo.list_cards_by_recipient("1234567890", 10, 0).await.unwrap();
+        assert_eq!(cards.len(), 1);
+
+        let resp = test::call_service(&app, create_request(&auth, "order-1", 7000).to_request()).await;
+        assert_eq!(resp.status(), 409);
+
+        let resp = test::call_service(&app, create_request(&auth, "order-2", 7000).to_request()).await;
+        assert_eq!(resp.status(), 201);
+
+        let resp = test::call_service(&app, create_request(&auth, "has space", 7000).to_request()).await;
+        assert_eq!(resp.status(), 400);
+    }
+
+    #[actix_web::test]
+    async fn test_keys_are_scoped_to_the_caller() {
+        let repo = test_repo();
+        let config = test_config();
+        let alice = issuer_auth(&repo, &config, "Alice").await;
+        let carol = issuer_auth(&repo, &config, "Carol").await;
+        let app = test::init_service(
+            App::new()
+                .app_data(repo.clone())
+                .app_data(config)
+                .wrap(from_fn(idempotency))
+                .configure(crate::routes::gift_cards::config),
+        )
+        .await;
+
+        let first: Value = test::call_and_read_body_json(&app, create_request(&alice, "order-1", 5000).to_request()).await;
+
+        // Another account reusing the key gets its own card, not Alice's response
+        let resp = test::call_service(&app, create_request(&carol, "order-1", 5000).to_request()).await;
+        assert_eq!(resp.status(), 201);
+        assert!(resp.headers().get(REPLAYED_HEADER).is_none());
+        let other: Value = test::read_body_json(resp).await;
+        assert_ne!(other["data"]["id"], first["data"]["id"]);
+        assert_eq!(other["data"]["issuer_name"], "Carol");
+
+        let cards = repo.list_cards_by_recipient("1234567890", 10, 0).await.unwrap();
+        assert_eq!(cards.len(), 2);
+    }
+}
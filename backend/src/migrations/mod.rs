@@ -0,0 +1,500 @@
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::mysql::{MySqlConnectOptions, MySqlConnection};
+use sqlx::postgres::{PgConnectOptions, PgConnection};
+use sqlx::{ConnectOptions, Connection, Executor, Row};
+use std::str::FromStr;
+
+use crate::repository::Backend;
+use crate::utils::error::AppError;
+
+/// A schema migration embedded in the binary
+#[derive(Debug)]
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+impl Migration {
+    /// SHA-256 of the migration SQL, used to detect edited migrations
+    pub fn checksum(&self) -> String {
+        Sha256::digest(self.sql.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+/// A migration recorded in the `schema_migrations` table
+#[derive(Debug)]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub description: String,
+    pub checksum: String,
+    pub applied_at: DateTime<Utc>,
+}
+
+/// Migrations for PostgreSQL, in version order
+pub static POSTGRES_MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "init",
+        sql: include_str!("../../migrations/postgres/0001_init.sql"),
+    },
+    Migration {
+        version: 2,
+        description: "card status",
+        sql: include_str!("../../migrations/postgres/0002_card_status.sql"),
+    },
+    Migration {
+        version: 3,
+        description: "ledger",
+        sql: include_str!("../../migrations/postgres/0003_ledger.sql"),
+    },
+    Migration {
+        version: 4,
+        description: "money",
+        sql: include_str!("../../migrations/postgres/0004_money.sql"),
+    },
+    Migration {
+        version: 5,
+        description: "idempotency keys",
+        sql: include_str!("../../migrations/postgres/0005_idempotency_keys.sql"),
+    },
+    Migration {
+        version: 6,
+        description: "refunds",
+        sql: include_str!("../../migrations/postgres/0006_refunds.sql"),
+    },
+    Migration {
+        version: 7,
+        description: "holds",
+        sql: include_str!("../../migrations/postgres/0007_holds.sql"),
+    },
+    Migration {
+        version: 8,
+        description: "merchants",
+        sql: include_str!("../../migrations/postgres/0008_merchants.sql"),
+    },
+    Migration {
+        version: 9,
+        description: "issuer accounts",
+        sql: include_str!("../../migrations/postgres/0009_issuer_accounts.sql"),
+    },
+    Migration {
+        version: 10,
+        description: "account roles",
+        sql: include_str!("../../migrations/postgres/0010_account_roles.sql"),
+    },
+    Migration {
+        version: 11,
+        description: "acceptance codes",
+        sql: include_str!("../../migrations/postgres/0011_acceptance_codes.sql"),
+    },
+    Migration {
+        version: 12,
+        description: "live qr secrets",
+        sql: include_str!("../../migrations/postgres/0012_live_qr_secrets.sql"),
+    },
+    Migration {
+        version: 13,
+        description: "card codes",
+        sql: include_str!("../../migrations/postgres/0013_card_codes.sql"),
+    },
+    Migration {
+        version: 14,
+        description: "issuance batches",
+        sql: include_str!("../../migrations/postgres/0014_issuance_batches.sql"),
+    },
+    Migration {
+        version: 15,
+        description: "export indexes",
+        sql: include_str!("../../migrations/postgres/0015_export_indexes.sql"),
+    },
+    Migration {
+        version: 16,
+        description: "card expiry",
+        sql: include_str!("../../migrations/postgres/0016_card_expiry.sql"),
+    },
+    Migration {
+        version: 17,
+        description: "card audit",
+        sql: include_str!("../../migrations/postgres/0017_card_audit.sql"),
+    },
+    Migration {
+        version: 18,
+        description: "card transfers",
+        sql: include_str!("../../migrations/postgres/0018_card_transfers.sql"),
+    },
+    Migration {
+        version: 19,
+        description: "card replacement",
+        sql: include_str!("../../migrations/postgres/0019_card_replacement.sql"),
+    },
+    Migration {
+        version: 20,
+        description: "card top-ups",
+        sql: include_str!("../../migrations/postgres/0020_card_top_ups.sql"),
+    },
+    Migration {
+        version: 21,
+        description: "status before expiry",
+        sql: include_str!("../../migrations/postgres/0021_status_before_expiry.sql"),
+    },
+    Migration {
+        version: 22,
+        description: "first issued at",
+        sql: include_str!("../../migrations/postgres/0022_first_issued_at.sql"),
+    },
+];
+
+/// Migrations for MySQL, in version order
+pub static MYSQL_MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "init",
+        sql: include_str!("../../migrations/mysql/0001_init.sql"),
+    },
+    Migration {
+        version: 2,
+        description: "card status",
+        sql: include_str!("../../migrations/mysql/0002_card_status.sql"),
+    },
+    Migration {
+        version: 3,
+        description: "ledger",
+        sql: include_str!("../../migrations/mysql/0003_ledger.sql"),
+    },
+    Migration {
+        version: 4,
+        description: "money",
+        sql: include_str!("../../migrations/mysql/0004_money.sql"),
+    },
+    Migration {
+        version: 5,
+        description: "idempotency keys",
+        sql: include_str!("../../migrations/mysql/0005_idempotency_keys.sql"),
+    },
+    Migration {
+        version: 6,
+        description: "refunds",
+        sql: include_str!("../../migrations/mysql/0006_refunds.sql"),
+    },
+    Migration {
+        version: 7,
+        description: "holds",
+        sql: include_str!("../../migrations/mysql/0007_holds.sql"),
+    },
+    Migration {
+        version: 8,
+        description: "merchants",
+        sql: include_str!("../../migrations/mysql/0008_merchants.sql"),
+    },
+    Migration {
+        version: 9,
+        description: "issuer accounts",
+        sql: include_str!("../../migrations/mysql/0009_issuer_accounts.sql"),
+    },
+    Migration {
+        version: 10,
+        description: "account roles",
+        sql: include_str!("../../migrations/mysql/0010_account_roles.sql"),
+    },
+    Migration {
+        version: 11,
+        description: "acceptance codes",
+        sql: include_str!("../../migrations/mysql/0011_acceptance_codes.sql"),
+    },
+    Migration {
+        version: 12,
+        description: "live qr secrets",
+        sql: include_str!("../../migrations/mysql/0012_live_qr_secrets.sql"),
+    },
+    Migration {
+        version: 13,
+        description: "card codes",
+        sql: include_str!("../../migrations/mysql/0013_card_codes.sql"),
+    },
+    Migration {
+        version: 14,
+        description: "issuance batches",
+        sql: include_str!("../../migrations/mysql/0014_issuance_batches.sql"),
+    },
+    Migration {
+        version: 15,
+        description: "export indexes",
+        sql: include_str!("../../migrations/mysql/0015_export_indexes.sql"),
+    },
+    Migration {
+        version: 16,
+        description: "card expiry",
+        sql: include_str!("../../migrations/mysql/0016_card_expiry.sql"),
+    },
+    Migration {
+        version: 17,
+        description: "card audit",
+        sql: include_str!("../../migrations/mysql/0017_card_audit.sql"),
+    },
+    Migration {
+        version: 18,
+        description: "card transfers",
+        sql: include_str!("../../migrations/mysql/0018_card_transfers.sql"),
+    },
+    Migration {
+        version: 19,
+        description: "card replacement",
+        sql: include_str!("../../migrations/mysql/0019_card_replacement.sql"),
+    },
+    Migration {
+        version: 20,
+        description: "card top-ups",
+        sql: include_str!("../../migrations/mysql/0020_card_top_ups.sql"),
+    },
+    Migration {
+        version: 21,
+        description: "status before expiry",
+        sql: include_str!("../../migrations/mysql/0021_status_before_expiry.sql"),
+    },
+    Migration {
+        version: 22,
+        description: "first issued at",
+        sql: include_str!("../../migrations/mysql/0022_first_issued_at.sql"),
+    },
+];
+
+/// Embedded migrations for a backend; the in-memory store has none
+pub fn migrations_for(backend: Backend) -> &'static [Migration] {
+    match backend {
+        Backend::Postgres => POSTGRES_MIGRATIONS,
+        Backend::MySql => MYSQL_MIGRATIONS,
+        Backend::Memory => &[],
+    }
+}
+
+const CREATE_POSTGRES_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS schema_migrations (
+    version BIGINT PRIMARY KEY,
+    description VARCHAR(255) NOT NULL,
+    checksum CHAR(64) NOT NULL,
+    applied_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+)
+"#;
+
+const CREATE_MYSQL_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS schema_migrations (
+    version BIGINT PRIMARY KEY,
+    description VARCHAR(255) NOT NULL,
+    checksum CHAR(64) NOT NULL,
+    applied_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+)
+"#;
+
+enum MigrationConnection {
+    Postgres(PgConnection),
+    MySql(MySqlConnection),
+}
+
+/// Applies embedded migrations and tracks them in `schema_migrations`
+pub struct Migrator {
+    connection: Option<MigrationConnection>,
+    migrations: &'static [Migration],
+}
+
+impl Migrator {
+    /// Open a dedicated connection for running migrations
+    pub async fn connect(database_url: &str) -> Result<Self, AppError> {
+        let backend = Backend::from_url(database_url)
+            .ok_or_else(|| AppError::ValidationError("Unsupported DATABASE_URL scheme".to_string()))?;
+
+        let connection = match backend {
+            Backend::Postgres => Some(MigrationConnection::Postgres(
+                PgConnectOptions::from_str(database_url)?
+                    .log_statements(log::LevelFilter::Debug)
+                    .connect()
+                    .await?,
+            )),
+            Backend::MySql => Some(MigrationConnection::MySql(
+                MySqlConnectOptions::from_str(database_url)?
+                    .log_statements(log::LevelFilter::Debug)
+                    .connect()
+                    .await?,
+            )),
+            Backend::Memory => None,
+        };
+
+        Ok(Self {
+            connection,
+            migrations: migrations_for(backend),
+        })
+    }
+
+    /// Migrations recorded as applied, creating the tracking table if needed
+    pub async fn applied(&mut self) -> Result<Vec<AppliedMigration>, AppError> {
+        let select = "SELECT version, description, checksum, applied_at FROM schema_migrations ORDER BY version";
+
+        let applied = match &mut self.connection {
+            None => Vec::new(),
+            Some(MigrationConnection::Postgres(conn)) => {
+                conn.execute(CREATE_POSTGRES_TABLE).await?;
+                sqlx::query(select)
+                    .fetch_all(&mut *conn)
+                    .await?
+                    .iter()
+                    .map(|row| {
+                        Ok(AppliedMigration {
+                            version: row.try_get("version")?,
+                            description: row.try_get("description")?,
+                            checksum: row.try_get("checksum")?,
+                            applied_at: row.try_get("applied_at")?,
+                        })
+                    })
+                    .collect::<Result<_, sqlx::Error>>()?
+            }
+            Some(MigrationConnection::MySql(conn)) => {
+                conn.execute(CREATE_MYSQL_TABLE).await?;
+                sqlx::query(select)
+                    .fetch_all(&mut *conn)
+                    .await?
+                    .iter()
+                    .map(|row| {
+                        Ok(AppliedMigration {
+                            version: row.try_get("version")?,
+                            description: row.try_get("description")?,
+                            checksum: row.try_get("checksum")?,
+                            applied_at: row.try_get("applied_at")?,
+                        })
+                    })
+                    .collect::<Result<_, sqlx::Error>>()?
+            }
+        };
+
+        Ok(applied)
+    }
+
+    /// Embedded migrations not yet applied to the database
+    pub async fn pending(&mut self) -> Result<Vec<&'static Migration>, AppError> {
+        let applied = self.applied().await?;
+
+        Ok(self
+            .migrations
+            .iter()
+            .filter(|m| !applied.iter().any(|a| a.version == m.version))
+            .collect())
+    }
+
+    /// Apply every pending migration in order, returning the ones applied
+    ///
+    /// On PostgreSQL each migration runs in its own transaction. MySQL
+    /// commits DDL implicitly, so a failed migration may leave partial
+    /// changes behind and is not recorded.
+    pub async fn run(&mut self) -> Result<Vec<&'static Migration>, AppError> {
+        let pending = self.pending().await?;
+
+        for migration in &pending {
+            log::info!("Applying migration {} ({})", migration.version, migration.description);
+
+            match &mut self.connection {
+                None => {}
+                Some(MigrationConnection::Postgres(conn)) => {
+                    let mut tx = conn.begin().await?;
+                    tx.execute(migration.sql).await?;
+                    sqlx::query("INSERT INTO schema_migrations (version, description, checksum) VALUES ($1, $2, $3)")
+                        .bind(migration.version)
+                        .bind(migration.description)
+                        .bind(migration.checksum())
+                        .execute(&mut tx)
+                        .await?;
+                    tx.commit().await?;
+                }
+                Some(MigrationConnection::MySql(conn)) => {
+                    conn.execute(migration.sql).await?;
+                    sqlx::query("INSERT INTO schema_migrations (version, description, checksum) VALUES (?, ?, ?)")
+                        .bind(migration.version)
+                        .bind(migration.description)
+                        .bind(migration.checksum())
+                        .execute(&mut *conn)
+                        .await?;
+                }
+            }
+        }
+
+        Ok(pending)
+    }
+
+    /// Refuse to continue if the database schema does not match the code
+    pub async fn check(&mut self) -> Result<(), AppError> {
+        let applied = self.applied().await?;
+        check_applied(self.migrations, &applied)
+    }
+}
+
+/// Compare recorded migrations against the embedded ones
+fn check_applied(migrations: &[Migration], applied: &[AppliedMigration]) -> Result<(), AppError> {
+    for record in applied {
+        match migrations.iter().find(|m| m.version == record.version) {
+            Some(migration) if migration.checksum() != record.checksum => {
+                return Err(AppError::InternalServerError(format!(
+                    "Migration {} ({}) was modified after it was applied",
+                    record.version, record.description
+                )));
+            }
+            Some(_) => {}
+            None => log::warn!(
+                "Database has migration {} ({}) unknown to this build",
+                record.version,
+                record.description
+            ),
+        }
+    }
+
+    let pending: Vec<String> = migrations
+        .iter()
+        .filter(|m| !applied.iter().any(|a| a.version == m.version))
+        .map(|m| format!("{} ({})", m.version, m.description))
+        .collect();
+
+    if !pending.is_empty() {
+        return Err(AppError::InternalServerError(format!(
+            "Database schema is out of date, pending migrations: {}",
+            pending.join(", ")
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn applied(migration: &Migration) -> AppliedMigration {
+        AppliedMigration {
+            version: migration.version,
+            description: migration.description.to_string(),
+            checksum: migration.checksum(),
+            applied_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_dialects_have_matching_versions() {
+        let postgres: Vec<i64> = POSTGRES_MIGRATIONS.iter().map(|m| m.version).collect();
+        let mysql: Vec<i64> = MYSQL_MIGRATIONS.iter().map(|m| m.version).collect();
+
+        assert_eq!(postgres, mysql);
+        assert!(postgres.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_check_applied() {
+        assert!(check_applied(POSTGRES_MIGRATIONS, &[]).is_err());
+
+        let all: Vec<AppliedMigration> = POSTGRES_MIGRATIONS.iter().map(applied).collect();
+        assert!(check_applied(POSTGRES_MIGRATIONS, &all).is_ok());
+
+        let mut edited: Vec<AppliedMigration> = POSTGRES_MIGRATIONS.iter().map(applied).collect();
+        edited[0].checksum = "0".repeat(64);
+        assert!(check_applied(POSTGRES_MIGRATIONS, &edited).is_err());
+    }
+}
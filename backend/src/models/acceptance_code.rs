@@ -0,0 +1,31 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+/// A one-time passcode sent to a card's recipient phone to confirm acceptance
+#[derive(Debug, Clone)]
+pub struct AcceptanceCode {
+    pub id: Uuid,
+    pub gift_card_id: Uuid,
+    pub code_hash: String,             // Argon2 PHC string; the code itself is never stored
+    pub attempts: i32,                 // Confirmations tried with this code, right or wrong
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>, // Set once the code has accepted the card
+    pub created_at: DateTime<Utc>,
+}
+
+impl AcceptanceCode {
+    /// Whether the code can still be used to accept the card
+    pub fn is_pending(&self, now: DateTime<Utc>) -> bool {
+        self.used_at.is_none() && self.expires_at > now
+    }
+}
+
+/// DTO returned once an acceptance code has been sent
+#[derive(Debug, Serialize)]
+pub struct AcceptanceCodeSentDto {
+    pub gift_card_id: Uuid,
+    pub sent_to: String,               // Recipient phone with all but the last four digits masked
+    pub expires_at: DateTime<Utc>,
+    pub max_attempts: i32,
+}
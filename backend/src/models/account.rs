@@ -0,0 +1,111 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// What an account is allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Admin,      // Operates the system; every route
+    Issuer,     // Issues cards and manages the ones it issued
+    Merchant,   // Takes payments for one merchant
+    Recipient,  // Holds the cards sent to one phone number
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [Role::Admin, Role::Issuer, Role::Merchant, Role::Recipient];
+
+    /// Name used in the API and the `role` column
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Issuer => "issuer",
+            Role::Merchant => "merchant",
+            Role::Recipient => "recipient",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Role::ALL
+            .into_iter()
+            .find(|role| role.as_str() == s)
+            .ok_or_else(|| format!("unknown role: {}", s))
+    }
+}
+
+impl_sql_text!(Role);
+
+/// An account that signs in to the API
+#[derive(Debug, Clone, Serialize)]
+pub struct Account {
+    pub id: Uuid,
+    pub email: String,                 // Stored lower-cased; unique
+    pub name: String,                  // Shown on the cards an issuer account issues
+    pub role: Role,
+    pub merchant_id: Option<Uuid>,     // Merchant a merchant account acts for
+    pub phone: Option<String>,         // Phone number a recipient account holds cards for
+    #[serde(skip_serializing)]
+    pub password_hash: String,         // Argon2 PHC string
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// DTO for registering an issuer account
+#[derive(Debug, Deserialize)]
+pub struct RegisterIssuerDto {
+    pub email: String,
+    pub name: String,
+    pub password: String,
+}
+
+/// DTO for an admin creating an account of any role
+#[derive(Debug, Deserialize)]
+pub struct CreateAccountDto {
+    pub email: String,
+    pub name: String,
+    pub password: String,
+    pub role: Role,
+    pub merchant_id: Option<Uuid>,     // Required for merchant accounts
+    pub phone: Option<String>,         // Required for recipient accounts
+}
+
+/// DTO for signing in with an email and password
+#[derive(Debug, Deserialize)]
+pub struct LoginDto {
+    pub email: String,
+    pub password: String,
+}
+
+/// DTO for exchanging a refresh token for a new pair of tokens
+#[derive(Debug, Deserialize)]
+pub struct RefreshTokenDto {
+    pub refresh_token: String,
+}
+
+/// Tokens returned by registration, login and refresh
+#[derive(Debug, Serialize)]
+pub struct AuthTokensDto {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: &'static str,      // Always "Bearer"
+    pub expires_in: i64,               // Access token lifetime, in seconds
+}
+
+/// An account together with freshly issued tokens, returned by registration and login
+#[derive(Debug, Serialize)]
+pub struct AccountSessionDto {
+    pub account: Account,
+    pub tokens: AuthTokensDto,
+}
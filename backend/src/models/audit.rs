@@ -0,0 +1,66 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use super::card_status::CardStatus;
+use super::money::Money;
+
+/// Change support staff made to a card by hand
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditAction {
+    Extension,     // Expiration date moved later
+    Reactivation,  // Expired card made usable again, with a later expiration date
+    Replacement,   // Card killed and its balance moved to a successor, e.g. after its QR code leaked
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 3] = [AuditAction::Extension, AuditAction::Reactivation, AuditAction::Replacement];
+
+    /// Name used in the API and the `action` column
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuditAction::Extension => "extension",
+            AuditAction::Reactivation => "reactivation",
+            AuditAction::Replacement => "replacement",
+        }
+    }
+}
+
+impl fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AuditAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        AuditAction::ALL
+            .into_iter()
+            .find(|action| action.as_str() == s)
+            .ok_or_else(|| format!("unknown audit action: {}", s))
+    }
+}
+
+impl_sql_text!(AuditAction);
+
+/// Who changed a card by hand, why, and what changed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CardAudit {
+    pub id: Uuid,
+    pub gift_card_id: Uuid,
+    pub account_id: Uuid,              // Account that made the change
+    pub action: AuditAction,
+    pub reason: String,
+    pub previous_status: CardStatus,
+    pub status: CardStatus,
+    pub previous_expiration_date: DateTime<Utc>,
+    pub expiration_date: DateTime<Utc>,
+    pub restored: Option<Money>,       // Balance given back from expiry, on reactivation
+    pub fee: Option<Money>,            // Charged to the card
+    pub created_at: DateTime<Utc>,
+}
@@ -0,0 +1,151 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use super::gift_card::CreateGiftCardDto;
+use super::money::{Currency, Money};
+
+/// How a batch treats rows that cannot be issued
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchMode {
+    #[default]
+    AllOrNothing,  // Any invalid row rejects the batch, and the cards are written together
+    BestEffort,    // Valid rows are issued one by one and the rest are reported
+}
+
+impl BatchMode {
+    pub const ALL: [BatchMode; 2] = [BatchMode::AllOrNothing, BatchMode::BestEffort];
+
+    /// Name used in the API and the `mode` column
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BatchMode::AllOrNothing => "all_or_nothing",
+            BatchMode::BestEffort => "best_effort",
+        }
+    }
+}
+
+impl fmt::Display for BatchMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for BatchMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BatchMode::ALL
+            .into_iter()
+            .find(|mode| mode.as_str() == s)
+            .ok_or_else(|| format!("unknown batch mode: {}", s))
+    }
+}
+
+impl_sql_text!(BatchMode);
+
+/// Progress of an issuance batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchStatus {
+    Pending,    // Accepted, waiting to be processed in the background
+    Running,    // Cards are being issued
+    Completed,  // Every row was processed; see the results for failed rows
+    Failed,     // Nothing was issued, because of invalid rows or an error
+}
+
+impl BatchStatus {
+    pub const ALL: [BatchStatus; 4] = [
+        BatchStatus::Pending,
+        BatchStatus::Running,
+        BatchStatus::Completed,
+        BatchStatus::Failed,
+    ];
+
+    /// Name used in the API and the `status` column
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BatchStatus::Pending => "pending",
+            BatchStatus::Running => "running",
+            BatchStatus::Completed => "completed",
+            BatchStatus::Failed => "failed",
+        }
+    }
+}
+
+impl fmt::Display for BatchStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for BatchStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BatchStatus::ALL
+            .into_iter()
+            .find(|status| status.as_str() == s)
+            .ok_or_else(|| format!("unknown batch status: {}", s))
+    }
+}
+
+impl_sql_text!(BatchStatus);
+
+/// Outcome of one row of a batch
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BatchRowResult {
+    pub row: usize,                    // Position in the batch, from 1; CSV header lines are not counted
+    pub gift_card_id: Option<Uuid>,    // Set once the row's card is issued
+    pub code: Option<String>,          // The issued card's short code, in groups of four
+    pub errors: Vec<String>,           // Why the row was not issued
+}
+
+/// A request to issue many gift cards at once, and what came of each row
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssuanceBatch {
+    pub id: Uuid,
+    pub issuer_id: Uuid,               // Account the cards are issued by
+    pub mode: BatchMode,
+    pub status: BatchStatus,
+    pub total_rows: i32,
+    pub issued: i32,                   // Rows whose card was issued
+    pub failed: i32,                   // Rows that were invalid or could not be issued
+    pub results: Vec<BatchRowResult>,  // One per row, in order
+    pub error: Option<String>,         // Why a batch failed as a whole
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/// Query parameters for submitting a batch
+#[derive(Debug, Default, Deserialize)]
+pub struct BatchQuery {
+    pub mode: Option<BatchMode>,       // all_or_nothing (default) or best_effort
+}
+
+/// A CSV row, with the balance split into `amount` and `currency` columns
+#[derive(Debug, Deserialize)]
+pub struct BatchCsvRow {
+    pub recipient_name: String,
+    pub recipient_phone: String,
+    pub amount: i64,                   // In minor units, as in the JSON API
+    pub currency: Currency,
+    pub expiration_days: i32,
+    #[serde(default)]
+    pub pin: Option<String>,           // Empty or left out for no PIN
+}
+
+impl From<BatchCsvRow> for CreateGiftCardDto {
+    fn from(row: BatchCsvRow) -> Self {
+        Self {
+            recipient_name: row.recipient_name,
+            recipient_phone: row.recipient_phone,
+            balance: Money::new(row.amount, row.currency),
+            expiration_days: row.expiration_days,
+            pin: row.pin.filter(|pin| !pin.is_empty()),
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::utils::short_code::generate_short_code;
+use super::money::Money;
+
+/// A card's short code, typed in by cashiers when its QR code will not scan,
+/// and the optional PIN that must come with it
+#[derive(Debug, Clone)]
+pub struct CardCode {
+    pub gift_card_id: Uuid,
+    pub code: String,                  // 16 characters in canonical form, unique across cards
+    pub pin_hash: Option<String>,      // Argon2 PHC string; unset when the card has no PIN
+    pub pin_attempts: i32,             // Wrong PINs since the last right one or lockout
+    pub locked_until: Option<DateTime<Utc>>, // PINs are refused until then after too many wrong ones
+    pub created_at: DateTime<Utc>,
+}
+
+impl CardCode {
+    /// A fresh random code for a card
+    pub fn new(gift_card_id: Uuid, pin_hash: Option<String>, now: DateTime<Utc>) -> Self {
+        Self {
+            gift_card_id,
+            code: generate_short_code(),
+            pin_hash,
+            pin_attempts: 0,
+            locked_until: None,
+            created_at: now,
+        }
+    }
+}
+
+/// DTO for setting, changing or removing a card's PIN
+#[derive(Debug, Deserialize)]
+pub struct SetPinDto {
+    pub pin: Option<String>,           // 4 to 8 digits; null removes the PIN
+}
+
+/// DTO for verifying a gift card by its short code
+#[derive(Debug, Deserialize)]
+pub struct VerifyByCodeDto {
+    pub pin: Option<String>,           // Required if the card has a PIN
+}
+
+/// DTO for paying with a gift card by its short code
+#[derive(Debug, Deserialize)]
+pub struct UseByCodeDto {
+    pub pin: Option<String>,           // Required if the card has a PIN
+    pub amount: Money,                 // Must be in the card's currency
+    pub merchant_id: Uuid,             // Merchant taking the payment
+}
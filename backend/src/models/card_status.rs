@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Lifecycle state of a gift card
+///
+/// All legal moves between states are listed in [`CardStatus::can_transition_to`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CardStatus {
+    Issued,     // Created, waiting for the recipient to accept
+    Accepted,   // Accepted by the recipient and usable for payment
+    Depleted,   // Balance fully spent
+    Expired,    // Past its expiration date
+    Suspended,  // Temporarily blocked, e.g. while fraud is investigated
+    Cancelled,  // Permanently voided by the issuer or support
+    Replaced,   // Superseded by a replacement card
+}
+
+/// Attempted move between two states that the lifecycle does not allow
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidTransition {
+    pub from: CardStatus,
+    pub to: CardStatus,
+}
+
+impl fmt::Display for InvalidTransition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Gift card is {} and cannot become {}", self.from, self.to)
+    }
+}
+
+impl std::error::Error for InvalidTransition {}
+
+impl CardStatus {
+    pub const ALL: [CardStatus; 7] = [
+        CardStatus::Issued,
+        CardStatus::Accepted,
+        CardStatus::Depleted,
+        CardStatus::Expired,
+        CardStatus::Suspended,
+        CardStatus::Cancelled,
+        CardStatus::Replaced,
+    ];
+
+    /// Name used in the API and the `status` column
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CardStatus::Issued => "issued",
+            CardStatus::Accepted => "accepted",
+            CardStatus::Depleted => "depleted",
+            CardStatus::Expired => "expired",
+            CardStatus::Suspended => "suspended",
+            CardStatus::Cancelled => "cancelled",
+            CardStatus::Replaced => "replaced",
+        }
+    }
+
+    /// The lifecycle transition table
+    pub fn can_transition_to(self, next: CardStatus) -> bool {
+        use CardStatus::*;
+
+        matches!(
+            (self, next),
+            (Issued, Accepted | Expired | Suspended | Cancelled | Replaced)
+                | (Accepted, Depleted | Expired | Suspended | Cancelled | Replaced)
+                | (Depleted, Accepted | Cancelled)
+                | (Expired, Issued | Accepted | Cancelled)
+                | (Suspended, Issued | Accepted | Expired | Cancelled | Replaced)
+        )
+    }
+
+    /// Validate a move to `next`, returning the new state
+    pub fn transition_to(self, next: CardStatus) -> Result<CardStatus, InvalidTransition> {
+        if self.can_transition_to(next) {
+            Ok(next)
+        } else {
+            Err(InvalidTransition { from: self, to: next })
+        }
+    }
+
+    /// States a card can expire from
+    pub fn expirable() -> impl Iterator<Item = CardStatus> {
+        CardStatus::ALL.into_iter().filter(|status| status.can_transition_to(CardStatus::Expired))
+    }
+
+    /// Whether the card can no longer change state
+    pub fn is_terminal(self) -> bool {
+        matches!(self, CardStatus::Cancelled | CardStatus::Replaced)
+    }
+}
+
+impl fmt::Display for CardStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for CardStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CardStatus::ALL
+            .into_iter()
+            .find(|status| status.as_str() == s)
+            .ok_or_else(|| format!("unknown gift card status: {}", s))
+    }
+}
+
+// Stored as its lower-case name in a VARCHAR column on every backend
+impl_sql_text!(CardStatus);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trips_through_str() {
+        for status in CardStatus::ALL {
+            assert_eq!(status.as_str().parse::<CardStatus>(), Ok(status));
+        }
+        assert!("active".parse::<CardStatus>().is_err());
+    }
+
+    #[test]
+    fn test_transitions() {
+        assert_eq!(CardStatus::Issued.transition_to(CardStatus::Accepted), Ok(CardStatus::Accepted));
+        assert!(CardStatus::Accepted.can_transition_to(CardStatus::Depleted));
+        assert!(!CardStatus::Issued.can_transition_to(CardStatus::Depleted));
+        assert!(!CardStatus::Accepted.can_transition_to(CardStatus::Accepted));
+        assert_eq!(
+            CardStatus::Cancelled.transition_to(CardStatus::Accepted),
+            Err(InvalidTransition { from: CardStatus::Cancelled, to: CardStatus::Accepted })
+        );
+    }
+
+    #[test]
+    fn test_terminal_states_have_no_exits() {
+        for from in CardStatus::ALL.into_iter().filter(|s| s.is_terminal()) {
+            assert!(CardStatus::ALL.iter().all(|to| !from.can_transition_to(*to)));
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::card_status::CardStatus;
+use super::gift_card::{GiftCard, GiftCardTransaction, TransactionKind};
+use crate::utils::validation::format_money;
+
+/// File format an export is streamed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,     // A header line, then one line per record
+    Ndjson,  // One JSON object per line
+}
+
+impl ExportFormat {
+    /// MIME type of files in this format
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    /// File name extension for this format
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+/// Query parameters for an export
+#[derive(Debug, Default, Deserialize)]
+pub struct ExportQuery {
+    pub format: Option<ExportFormat>,   // csv (default) or ndjson
+    pub columns: Option<String>,        // Comma separated column names; all of them when unset
+    pub from: Option<DateTime<Utc>>,    // Created or made at or after this time
+    pub to: Option<DateTime<Utc>>,      // Created or made before this time
+    pub status: Option<CardStatus>,     // Gift cards only
+    pub kind: Option<TransactionKind>,  // Transactions only
+    pub issuer_id: Option<Uuid>,        // Cards issued by this account, or transactions on them
+    pub merchant_id: Option<Uuid>,      // Transactions only
+}
+
+/// Which records an export includes
+#[derive(Debug, Clone, Default)]
+pub struct ExportFilter {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub status: Option<CardStatus>,
+    pub kind: Option<TransactionKind>,
+    pub issuer_id: Option<Uuid>,
+    pub merchant_id: Option<Uuid>,
+}
+
+/// A kind of record that can be exported, and the columns it has
+pub trait ExportRecord {
+    /// Every column, in their default order
+    const COLUMNS: &'static [&'static str];
+
+    /// Where the record sorts in an export: its timestamp, then its ID
+    fn cursor(&self) -> (DateTime<Utc>, Uuid);
+
+    /// The value of `column`, which is one of `COLUMNS`; money is formatted
+    /// with `format_money` and times as RFC 3339
+    fn value(&self, column: &str) -> Value;
+}
+
+impl ExportRecord for GiftCard {
+    const COLUMNS: &'static [&'static str] = &[
+        "id",
+        "issuer_id",
+        "issuer_name",
+        "recipient_name",
+        "recipient_phone",
+        "balance",
+        "initial_balance",
+        "total_loaded",
+        "currency",
+        "status",
+        "expiration_date",
+        "accepted_at",
+        "replaces_id",
+        "created_at",
+        "updated_at",
+    ];
+
+    fn cursor(&self) -> (DateTime<Utc>, Uuid) {
+        (self.created_at, self.id)
+    }
+
+    fn value(&self, column: &str) -> Value {
+        match column {
+            "id" => Value::from(self.id.to_string()),
+            "issuer_id" => self.issuer_id.map(|id| Value::from(id.to_string())).unwrap_or(Value::Null),
+            "issuer_name" => Value::from(self.issuer_name.as_str()),
+            "recipient_name" => Value::from(self.recipient_name.as_str()),
+            "recipient_phone" => Value::from(self.recipient_phone.as_str()),
+            "balance" => Value::from(format_money(self.balance)),
+            "initial_balance" => Value::from(format_money(self.initial_balance)),
+            "total_loaded" => Value::from(format_money(self.total_loaded)),
+            "currency" => Value::from(self.currency().as_str()),
+            "status" => Value::from(self.status.as_str()),
+            "expiration_date" => timestamp(self.expiration_date),
+            "accepted_at" => self.accepted_at.map(timestamp).unwrap_or(Value::Null),
+            "replaces_id" => self.replaces_id.map(|id| Value::from(id.to_string())).unwrap_or(Value::Null),
+            "created_at" => timestamp(self.created_at),
+            "updated_at" => timestamp(self.updated_at),
+            _ => Value::Null,
+        }
+    }
+}
+
+impl ExportRecord for GiftCardTransaction {
+    const COLUMNS: &'static [&'static str] = &[
+        "id",
+        "gift_card_id",
+        "kind",
+        "amount",
+        "currency",
+        "merchant_id",
+        "merchant",
+        "original_transaction_id",
+        "transaction_date",
+    ];
+
+    fn cursor(&self) -> (DateTime<Utc>, Uuid) {
+        (self.transaction_date, self.id)
+    }
+
+    fn value(&self, column: &str) -> Value {
+        match column {
+            "id" => Value::from(self.id.to_string()),
+            "gift_card_id" => Value::from(self.gift_card_id.to_string()),
+            "kind" => Value::from(self.kind.as_str()),
+            "amount" => Value::from(format_money(self.amount)),
+            "currency" => Value::from(self.amount.currency().as_str()),
+            "merchant_id" => self.merchant_id.map(|id| Value::from(id.to_string())).unwrap_or(Value::Null),
+            "merchant" => self.merchant.as_deref().map(Value::from).unwrap_or(Value::Null),
+            "original_transaction_id" => {
+                self.original_transaction_id.map(|id| Value::from(id.to_string())).unwrap_or(Value::Null)
+            }
+            "transaction_date" => timestamp(self.transaction_date),
+            _ => Value::Null,
+        }
+    }
+}
+
+fn timestamp(time: DateTime<Utc>) -> Value {
+    Value::from(time.to_rfc3339_opts(SecondsFormat::Millis, true))
+}
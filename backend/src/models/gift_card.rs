@@ -1,61 +1,187 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use std::fmt;
+use std::str::FromStr;
 use uuid::Uuid;
 
+use super::account::Account;
+use super::card_status::{CardStatus, InvalidTransition};
+use super::merchant::Merchant;
+use super::money::{Currency, Money, MoneyError};
+
 /// Represents a gift card in the database
-#[derive(Debug, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GiftCard {
     pub id: Uuid,
+    pub issuer_id: Option<Uuid>,       // Issuer account; unset on cards issued before accounts existed
     pub issuer_name: String,          // Name of the person who issued the gift card
     pub recipient_name: String,        // Name of the recipient
     pub recipient_phone: String,       // Phone number of the recipient
-    pub balance: i32,                  // Balance in cents (e.g., 5000 = $50.00)
-    pub initial_balance: i32,          // Original balance in cents
+    pub balance: Money,                // Remaining balance, in the card's currency
+    pub initial_balance: Money,        // Original balance
+    pub total_loaded: Money,           // Initial balance plus every top-up since
+    pub held: Money,                   // Reserved by open holds; derived from them, not stored
     pub expiration_date: DateTime<Utc>, // Expiration date
-    pub is_accepted: bool,             // Whether the recipient has accepted the gift card
-    pub is_active: bool,               // Whether the gift card is active
+    pub status: CardStatus,            // Lifecycle state
+    pub status_before_expiry: Option<CardStatus>, // State the card was in when it expired; cleared on reactivation
+    pub accepted_at: Option<DateTime<Utc>>, // When the recipient accepted the gift card
+    pub replaces_id: Option<Uuid>,     // Card this one replaced, if it is a replacement
+    pub first_issued_at: DateTime<Utc>, // When the first card in its line was issued; kept by transfers and replacements
     pub created_at: DateTime<Utc>,     // When the gift card was created
     pub updated_at: DateTime<Utc>,     // When the gift card was last updated
 }
 
+impl GiftCard {
+    /// A new card issued by `issuer` as described by `dto`
+    pub fn issue(issuer: &Account, dto: &CreateGiftCardDto, now: DateTime<Utc>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            issuer_id: Some(issuer.id),
+            issuer_name: issuer.name.clone(),
+            recipient_name: dto.recipient_name.clone(),
+            recipient_phone: dto.recipient_phone.clone(),
+            balance: dto.balance,
+            initial_balance: dto.balance,
+            total_loaded: dto.balance,
+            held: Money::zero(dto.balance.currency()),
+            expiration_date: now + Duration::days(dto.expiration_days as i64),
+            status: CardStatus::Issued,
+            status_before_expiry: None,
+            accepted_at: None,
+            replaces_id: None,
+            first_issued_at: now,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// A new card holding `amount` moved off this one, for another recipient
+    ///
+    /// It keeps this card's issuer, expiration date and original issue date, and
+    /// its recipient must accept it before it can be spent.
+    pub fn regift(&self, recipient_name: &str, recipient_phone: &str, amount: Money, now: DateTime<Utc>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            issuer_id: self.issuer_id,
+            issuer_name: self.issuer_name.clone(),
+            recipient_name: recipient_name.to_string(),
+            recipient_phone: recipient_phone.to_string(),
+            balance: amount,
+            initial_balance: amount,
+            total_loaded: amount,
+            held: Money::zero(amount.currency()),
+            expiration_date: self.expiration_date,
+            status: CardStatus::Issued,
+            status_before_expiry: None,
+            accepted_at: None,
+            replaces_id: None,
+            first_issued_at: self.first_issued_at,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// A card to take over from this one, e.g. after its QR code leaked
+    ///
+    /// It carries this card's recipient, balance, expiration date, issuer and
+    /// status, so it is already accepted if this card was and stays suspended
+    /// if this card was.
+    pub fn successor(&self, now: DateTime<Utc>) -> Self {
+        Self {
+            status: self.status,
+            accepted_at: self.accepted_at,
+            replaces_id: Some(self.id),
+            ..self.regift(&self.recipient_name, &self.recipient_phone, self.balance, now)
+        }
+    }
+
+    /// The single currency the card is denominated in
+    pub fn currency(&self) -> Currency {
+        self.initial_balance.currency()
+    }
+
+    /// Balance that is not reserved by holds
+    pub fn available(&self) -> Result<Money, MoneyError> {
+        self.balance.checked_sub(self.held)
+    }
+
+    /// Move the card to `next`, enforcing the lifecycle transition table
+    ///
+    /// A suspended card can only resume to `Accepted` if it had been accepted
+    /// before, and to `Issued` if it had not.
+    pub fn transition(&mut self, next: CardStatus, now: DateTime<Utc>) -> Result<(), InvalidTransition> {
+        let resuming = self.status == CardStatus::Suspended
+            && matches!(next, CardStatus::Issued | CardStatus::Accepted);
+        if resuming && (next == CardStatus::Accepted) != self.accepted_at.is_some() {
+            return Err(InvalidTransition { from: self.status, to: next });
+        }
+
+        self.status = self.status.transition_to(next)?;
+        if next == CardStatus::Accepted && self.accepted_at.is_none() {
+            self.accepted_at = Some(now);
+        }
+        self.updated_at = now;
+        Ok(())
+    }
+
+    /// Status to return to when a suspended card is resumed
+    pub fn resume_status(&self) -> CardStatus {
+        if self.accepted_at.is_some() {
+            CardStatus::Accepted
+        } else {
+            CardStatus::Issued
+        }
+    }
+}
+
 /// DTO for creating a new gift card
+///
+/// The issuer is the authenticated account, not a field of the request.
 #[derive(Debug, Deserialize)]
 pub struct CreateGiftCardDto {
-    pub issuer_name: String,
     pub recipient_name: String,
     pub recipient_phone: String,
-    pub balance: i32,                  // Balance in cents
+    pub balance: Money,
     pub expiration_days: i32,          // Days until expiration from creation date
+    pub pin: Option<String>,           // 4 to 8 digits asked for with the card's short code
 }
 
 /// DTO for accepting a gift card
 #[derive(Debug, Deserialize)]
 pub struct AcceptGiftCardDto {
     pub gift_card_id: Uuid,
-    pub recipient_phone: String,       // For verification purposes
+    pub code: String,                  // One-time code sent to the recipient phone
 }
 
 /// DTO for using a gift card for payment
 #[derive(Debug, Deserialize)]
 pub struct UseGiftCardDto {
     pub gift_card_id: Uuid,
-    pub amount: i32,                   // Amount to use in cents
+    pub amount: Money,                 // Must be in the card's currency
+    pub merchant_id: Uuid,             // Merchant taking the payment
+    pub qr_code: Option<String>,       // Scanned QR text, required: a signed token, or a live code if live QR codes are on
 }
 
 /// DTO for gift card response with QR data
 #[derive(Debug, Serialize)]
 pub struct GiftCardResponseDto {
     pub id: Uuid,
+    pub issuer_id: Option<Uuid>,
     pub issuer_name: String,
-    pub recipient_name: String,
-    pub recipient_phone: String,
-    pub balance: i32,                  // Balance in cents
-    pub initial_balance: i32,          // Original balance in cents
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recipient_name: Option<String>,  // Left out for merchants
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recipient_phone: Option<String>,  // Left out for merchants
+    pub balance: Money,
+    pub initial_balance: Money,
+    pub total_loaded: Money,
     pub expiration_date: DateTime<Utc>,
-    pub is_accepted: bool,
-    pub is_active: bool,
+    pub status: CardStatus,
+    pub accepted_at: Option<DateTime<Utc>>,
+    pub replaces_id: Option<Uuid>,
     pub qr_code: Option<String>,       // Base64 encoded QR code image
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,          // Short code in groups of four; shown to the card's owners
     pub created_at: DateTime<Utc>,
 }
 
@@ -63,26 +189,211 @@ pub struct GiftCardResponseDto {
 #[derive(Debug, Serialize)]
 pub struct GiftCardVerificationDto {
     pub id: Uuid,
-    pub balance: i32,
-    pub is_active: bool,
-    pub is_accepted: bool,
+    pub balance: Money,
+    pub available: Money,              // Balance minus open holds
+    pub held: Money,
+    pub status: CardStatus,
     pub expiration_date: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,        // Key that signed the scanned QR token; unset when verified by ID
 }
 
+/// What a transaction did to the card's balance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionKind {
+    Redemption,    // Spent at a merchant
+    Refund,        // Returned to the card from an earlier redemption
+    Expiry,        // Unspent balance written off when the card expired
+    Reactivation,  // Balance written off at expiry, restored when the card was reactivated
+    Fee,           // Charged by support staff, e.g. for reactivating the card
+    #[serde(rename = "transfer_out")]
+    TransferOut,   // Moved to a new card for another recipient
+    #[serde(rename = "transfer_in")]
+    TransferIn,    // Arrived from the card it was transferred off
+    Load,          // Added to the card by a top-up
+}
+
+impl TransactionKind {
+    pub const ALL: [TransactionKind; 8] = [
+        TransactionKind::Redemption,
+        TransactionKind::Refund,
+        TransactionKind::Expiry,
+        TransactionKind::Reactivation,
+        TransactionKind::Fee,
+        TransactionKind::TransferOut,
+        TransactionKind::TransferIn,
+        TransactionKind::Load,
+    ];
+
+    /// Name used in the API and the `kind` column
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TransactionKind::Redemption => "redemption",
+            TransactionKind::Refund => "refund",
+            TransactionKind::Expiry => "expiry",
+            TransactionKind::Reactivation => "reactivation",
+            TransactionKind::Fee => "fee",
+            TransactionKind::TransferOut => "transfer_out",
+            TransactionKind::TransferIn => "transfer_in",
+            TransactionKind::Load => "load",
+        }
+    }
+}
+
+impl fmt::Display for TransactionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for TransactionKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TransactionKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| format!("unknown transaction kind: {}", s))
+    }
+}
+
+impl_sql_text!(TransactionKind);
+
 /// Transaction record for gift card usage
-#[derive(Debug, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GiftCardTransaction {
     pub id: Uuid,
     pub gift_card_id: Uuid,
-    pub amount: i32,                   // Amount used in cents
-    pub merchant: String,              // Where the transaction occurred
+    pub kind: TransactionKind,
+    pub amount: Money,                 // Amount moved on or off the card, always positive
+    pub merchant_id: Option<Uuid>,     // Merchant that took the payment; unset unless a redemption or refund
+    pub merchant: Option<String>,      // Merchant name when the transaction occurred
+    pub original_transaction_id: Option<Uuid>, // Redemption a refund reverses, or transfer a receipt came from
     pub transaction_date: DateTime<Utc>,
 }
 
+impl GiftCardTransaction {
+    /// A payment of `amount` at `merchant`
+    pub fn redemption(gift_card_id: Uuid, amount: Money, merchant: &Merchant, now: DateTime<Utc>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            gift_card_id,
+            kind: TransactionKind::Redemption,
+            amount,
+            merchant_id: Some(merchant.id),
+            merchant: Some(merchant.name.clone()),
+            original_transaction_id: None,
+            transaction_date: now,
+        }
+    }
+
+    /// Return `amount` of this redemption to the card
+    pub fn refund(&self, amount: Money, now: DateTime<Utc>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            gift_card_id: self.gift_card_id,
+            kind: TransactionKind::Refund,
+            amount,
+            merchant_id: self.merchant_id,
+            merchant: self.merchant.clone(),
+            original_transaction_id: Some(self.id),
+            transaction_date: now,
+        }
+    }
+
+    /// The `amount` left on a card when it expired, which it loses
+    pub fn expiry(gift_card_id: Uuid, amount: Money, now: DateTime<Utc>) -> Self {
+        Self::without_merchant(gift_card_id, TransactionKind::Expiry, amount, now)
+    }
+
+    /// The `amount` an expired card lost, given back as it is reactivated
+    pub fn reactivation(gift_card_id: Uuid, amount: Money, now: DateTime<Utc>) -> Self {
+        Self::without_merchant(gift_card_id, TransactionKind::Reactivation, amount, now)
+    }
+
+    /// A fee of `amount` charged to the card
+    pub fn fee(gift_card_id: Uuid, amount: Money, now: DateTime<Utc>) -> Self {
+        Self::without_merchant(gift_card_id, TransactionKind::Fee, amount, now)
+    }
+
+    /// The `amount` a top-up added to the card
+    pub fn load(gift_card_id: Uuid, amount: Money, now: DateTime<Utc>) -> Self {
+        Self::without_merchant(gift_card_id, TransactionKind::Load, amount, now)
+    }
+
+    /// The `amount` moved off a card to a new one
+    pub fn transfer_out(gift_card_id: Uuid, amount: Money, now: DateTime<Utc>) -> Self {
+        Self::without_merchant(gift_card_id, TransactionKind::TransferOut, amount, now)
+    }
+
+    /// This transfer arriving on the new card `gift_card_id`
+    pub fn transfer_in(&self, gift_card_id: Uuid) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            gift_card_id,
+            kind: TransactionKind::TransferIn,
+            amount: self.amount,
+            merchant_id: None,
+            merchant: None,
+            original_transaction_id: Some(self.id),
+            transaction_date: self.transaction_date,
+        }
+    }
+
+    fn without_merchant(gift_card_id: Uuid, kind: TransactionKind, amount: Money, now: DateTime<Utc>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            gift_card_id,
+            kind,
+            amount,
+            merchant_id: None,
+            merchant: None,
+            original_transaction_id: None,
+            transaction_date: now,
+        }
+    }
+}
+
 /// DTO for creating a transaction
 #[derive(Debug, Deserialize)]
 pub struct CreateTransactionDto {
     pub gift_card_id: Uuid,
-    pub amount: i32,
-    pub merchant: String,
+    pub amount: Money,
+    pub merchant_id: Uuid,
+}
+
+/// DTO for extending a card's expiration date, reactivating it if it has expired
+#[derive(Debug, Deserialize)]
+pub struct ExtendGiftCardDto {
+    pub expiration_date: DateTime<Utc>,  // New expiration date; must be later than the current one
+    pub reason: String,                  // Why, for the audit log
+    pub fee: Option<Money>,              // Charged to the card, after any restored balance
+}
+
+/// DTO for moving a card's balance to a new card for someone else
+#[derive(Debug, Deserialize)]
+pub struct TransferGiftCardDto {
+    pub recipient_name: String,
+    pub recipient_phone: String,
+    pub amount: Option<Money>,           // Defaults to the card's whole available balance
+}
+
+/// DTO for adding value to a card
+#[derive(Debug, Deserialize)]
+pub struct TopUpGiftCardDto {
+    pub amount: Money,                   // Must be in the card's currency
+}
+
+/// DTO for replacing a card whose code or QR has leaked
+#[derive(Debug, Deserialize)]
+pub struct ReplaceGiftCardDto {
+    pub reason: String,                  // Why, for the audit log
+}
+
+/// DTO for refunding a redemption back to the card
+#[derive(Debug, Deserialize)]
+pub struct RefundGiftCardDto {
+    pub transaction_id: Uuid,          // Redemption being refunded
+    pub amount: Option<Money>,         // Defaults to everything not yet refunded
 }
\ No newline at end of file
@@ -0,0 +1,135 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use super::money::Money;
+
+/// State of an authorization hold
+///
+/// A hold past its expiry is stored as `Active` until it is next written,
+/// but no longer counts against the card; see [`Hold::is_open`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HoldStatus {
+    Active,    // Reserving part of the card's balance
+    Captured,  // Settled into a redemption
+    Voided,    // Released without being charged
+    Expired,   // Lapsed without being captured
+}
+
+impl HoldStatus {
+    pub const ALL: [HoldStatus; 4] = [
+        HoldStatus::Active,
+        HoldStatus::Captured,
+        HoldStatus::Voided,
+        HoldStatus::Expired,
+    ];
+
+    /// Name used in the API and the `status` column
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HoldStatus::Active => "active",
+            HoldStatus::Captured => "captured",
+            HoldStatus::Voided => "voided",
+            HoldStatus::Expired => "expired",
+        }
+    }
+}
+
+impl fmt::Display for HoldStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for HoldStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        HoldStatus::ALL
+            .into_iter()
+            .find(|status| status.as_str() == s)
+            .ok_or_else(|| format!("unknown hold status: {}", s))
+    }
+}
+
+impl_sql_text!(HoldStatus);
+
+/// An amount reserved on a gift card, to be captured or voided later
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hold {
+    pub id: Uuid,
+    pub gift_card_id: Uuid,
+    pub amount: Money,                   // Amount reserved
+    pub captured_amount: Option<Money>,  // Amount charged when captured
+    pub merchant_id: Uuid,               // Merchant that placed the hold
+    pub merchant: String,                // Merchant name when the hold was placed
+    pub status: HoldStatus,
+    pub transaction_id: Option<Uuid>,    // Redemption created by the capture
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Hold {
+    /// Whether the hold still reserves its amount at `now`
+    pub fn is_open(&self, now: DateTime<Utc>) -> bool {
+        self.status == HoldStatus::Active && self.expires_at > now
+    }
+
+    /// The hold as seen at `now`, with a lapsed active hold shown as expired
+    pub fn as_of(mut self, now: DateTime<Utc>) -> Self {
+        if self.status == HoldStatus::Active && self.expires_at <= now {
+            self.status = HoldStatus::Expired;
+        }
+        self
+    }
+}
+
+/// DTO for placing a hold on a gift card
+#[derive(Debug, Deserialize)]
+pub struct AuthorizeHoldDto {
+    pub amount: Money,                 // Must be in the card's currency
+    pub merchant_id: Uuid,             // Merchant placing the hold
+    pub qr_code: Option<String>,       // Scanned QR text, required: a signed token, or a live code if live QR codes are on
+}
+
+/// DTO for capturing a hold
+#[derive(Debug, Deserialize)]
+pub struct CaptureHoldDto {
+    pub amount: Option<Money>,         // Final amount, defaults to the whole hold
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::money::Currency;
+    use chrono::Duration;
+
+    #[test]
+    fn test_active_hold_lapses_at_expiry() {
+        let now = Utc::now();
+        let hold = Hold {
+            id: Uuid::new_v4(),
+            gift_card_id: Uuid::new_v4(),
+            amount: Money::new(2000, Currency::USD),
+            captured_amount: None,
+            merchant_id: Uuid::new_v4(),
+            merchant: "Fuel".to_string(),
+            status: HoldStatus::Active,
+            transaction_id: None,
+            expires_at: now + Duration::hours(1),
+            created_at: now,
+            updated_at: now,
+        };
+
+        assert!(hold.is_open(now));
+        assert_eq!(hold.clone().as_of(now).status, HoldStatus::Active);
+
+        let later = now + Duration::hours(2);
+        assert!(!hold.is_open(later));
+        assert_eq!(hold.as_of(later).status, HoldStatus::Expired);
+    }
+}
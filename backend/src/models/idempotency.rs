@@ -0,0 +1,21 @@
+use chrono::{DateTime, Utc};
+
+/// A request stored under an `Idempotency-Key` header
+///
+/// The response fields stay empty while the first request with the key is
+/// still being processed.
+#[derive(Debug, Clone)]
+pub struct IdempotencyRecord {
+    pub key: String,
+    pub fingerprint: String,              // SHA-256 of the caller, method, path and body
+    pub response_status: Option<u16>,
+    pub response_body: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl IdempotencyRecord {
+    /// Whether the original request has finished and its response can be replayed
+    pub fn is_complete(&self) -> bool {
+        self.response_status.is_some()
+    }
+}
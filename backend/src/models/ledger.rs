@@ -0,0 +1,407 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use super::gift_card::{GiftCard, GiftCardTransaction};
+use super::money::{Currency, Money};
+
+/// Account a journal line posts to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LedgerAccount {
+    CardLiability,       // Value owed to a card holder, tracked per card
+    IssuerFunding,       // Money received from issuers for the cards they bought
+    MerchantSettlement,  // Owed to merchants for redemptions
+    BreakageIncome,      // Value recognised as income when cards expire unspent
+    Adjustments,         // Offset for manual corrections
+    FeeIncome,           // Fees charged to cards by support staff
+}
+
+impl LedgerAccount {
+    pub const ALL: [LedgerAccount; 6] = [
+        LedgerAccount::CardLiability,
+        LedgerAccount::IssuerFunding,
+        LedgerAccount::MerchantSettlement,
+        LedgerAccount::BreakageIncome,
+        LedgerAccount::Adjustments,
+        LedgerAccount::FeeIncome,
+    ];
+
+    /// Name used in the API and the `account` column
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LedgerAccount::CardLiability => "card_liability",
+            LedgerAccount::IssuerFunding => "issuer_funding",
+            LedgerAccount::MerchantSettlement => "merchant_settlement",
+            LedgerAccount::BreakageIncome => "breakage_income",
+            LedgerAccount::Adjustments => "adjustments",
+            LedgerAccount::FeeIncome => "fee_income",
+        }
+    }
+}
+
+impl fmt::Display for LedgerAccount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for LedgerAccount {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        LedgerAccount::ALL
+            .into_iter()
+            .find(|account| account.as_str() == s)
+            .ok_or_else(|| format!("unknown ledger account: {}", s))
+    }
+}
+
+impl_sql_text!(LedgerAccount);
+
+/// Business event a journal entry records
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    Issuance,
+    Redemption,
+    Refund,
+    Breakage,
+    Adjustment,
+    Reactivation,
+    Fee,
+    Transfer,
+    Replacement,
+    Load,
+}
+
+impl EntryKind {
+    pub const ALL: [EntryKind; 10] = [
+        EntryKind::Issuance,
+        EntryKind::Redemption,
+        EntryKind::Refund,
+        EntryKind::Breakage,
+        EntryKind::Adjustment,
+        EntryKind::Reactivation,
+        EntryKind::Fee,
+        EntryKind::Transfer,
+        EntryKind::Replacement,
+        EntryKind::Load,
+    ];
+
+    /// Name used in the API and the `kind` column
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EntryKind::Issuance => "issuance",
+            EntryKind::Redemption => "redemption",
+            EntryKind::Refund => "refund",
+            EntryKind::Breakage => "breakage",
+            EntryKind::Adjustment => "adjustment",
+            EntryKind::Reactivation => "reactivation",
+            EntryKind::Fee => "fee",
+            EntryKind::Transfer => "transfer",
+            EntryKind::Replacement => "replacement",
+            EntryKind::Load => "load",
+        }
+    }
+}
+
+impl fmt::Display for EntryKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for EntryKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        EntryKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| format!("unknown journal entry kind: {}", s))
+    }
+}
+
+impl_sql_text!(EntryKind);
+
+/// One posting of a journal entry
+///
+/// Positive amounts are debits and negative amounts are credits, so the
+/// lines of a balanced entry sum to zero.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalLine {
+    pub account: LedgerAccount,
+    pub gift_card_id: Option<Uuid>,  // Set only on card liability lines
+    pub amount: i64,                 // Minor units of the entry's currency, debit positive
+}
+
+impl JournalLine {
+    fn card(gift_card_id: Uuid, amount: i64) -> Self {
+        Self { account: LedgerAccount::CardLiability, gift_card_id: Some(gift_card_id), amount }
+    }
+
+    fn account(account: LedgerAccount, amount: i64) -> Self {
+        Self { account, gift_card_id: None, amount }
+    }
+}
+
+/// An append-only, balanced group of ledger postings
+///
+/// A card's balance is the credit balance of its card liability lines
+/// across all entries; repositories refuse writes that break this.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub id: Uuid,
+    pub kind: EntryKind,
+    pub description: String,
+    pub currency: Currency,            // Currency of every line in the entry
+    pub transaction_id: Option<Uuid>,  // Transaction record this entry backs, if any
+    pub created_at: DateTime<Utc>,
+    pub lines: Vec<JournalLine>,
+}
+
+impl JournalEntry {
+    fn new(
+        kind: EntryKind,
+        description: impl Into<String>,
+        currency: Currency,
+        transaction_id: Option<Uuid>,
+        created_at: DateTime<Utc>,
+        lines: Vec<JournalLine>,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            kind,
+            description: description.into(),
+            currency,
+            transaction_id,
+            created_at,
+            lines,
+        }
+    }
+
+    /// Issuer funds a new card: debit issuer funding, credit the card
+    pub fn issuance(card: &GiftCard) -> Self {
+        Self::new(
+            EntryKind::Issuance,
+            format!("Issued by {}", card.issuer_name),
+            card.currency(),
+            None,
+            card.created_at,
+            vec![
+                JournalLine::account(LedgerAccount::IssuerFunding, card.initial_balance.amount()),
+                JournalLine::card(card.id, -card.initial_balance.amount()),
+            ],
+        )
+    }
+
+    /// Card is topped up: debit issuer funding, credit the card
+    pub fn load(txn: &GiftCardTransaction) -> Self {
+        Self::new(
+            EntryKind::Load,
+            "Topped up",
+            txn.amount.currency(),
+            Some(txn.id),
+            txn.transaction_date,
+            vec![
+                JournalLine::account(LedgerAccount::IssuerFunding, txn.amount.amount()),
+                JournalLine::card(txn.gift_card_id, -txn.amount.amount()),
+            ],
+        )
+    }
+
+    /// Card is spent at a merchant: debit the card, credit merchant settlement
+    pub fn redemption(txn: &GiftCardTransaction) -> Self {
+        Self::new(
+            EntryKind::Redemption,
+            format!("Redeemed at {}", txn.merchant.as_deref().unwrap_or_default()),
+            txn.amount.currency(),
+            Some(txn.id),
+            txn.transaction_date,
+            vec![
+                JournalLine::card(txn.gift_card_id, txn.amount.amount()),
+                JournalLine::account(LedgerAccount::MerchantSettlement, -txn.amount.amount()),
+            ],
+        )
+    }
+
+    /// Reverse part of a redemption: debit merchant settlement, credit the card
+    pub fn refund(txn: &GiftCardTransaction) -> Self {
+        Self::new(
+            EntryKind::Refund,
+            format!("Refunded by {}", txn.merchant.as_deref().unwrap_or_default()),
+            txn.amount.currency(),
+            Some(txn.id),
+            txn.transaction_date,
+            vec![
+                JournalLine::account(LedgerAccount::MerchantSettlement, txn.amount.amount()),
+                JournalLine::card(txn.gift_card_id, -txn.amount.amount()),
+            ],
+        )
+    }
+
+    /// Unspent value of an expired card becomes income
+    pub fn breakage(gift_card_id: Uuid, amount: Money, now: DateTime<Utc>) -> Self {
+        Self::new(
+            EntryKind::Breakage,
+            "Expired with unspent balance",
+            amount.currency(),
+            None,
+            now,
+            vec![
+                JournalLine::card(gift_card_id, amount.amount()),
+                JournalLine::account(LedgerAccount::BreakageIncome, -amount.amount()),
+            ],
+        )
+    }
+
+    /// Expired card is reactivated: debit breakage income, credit the card
+    pub fn reactivation(txn: &GiftCardTransaction, reason: &str) -> Self {
+        Self::new(
+            EntryKind::Reactivation,
+            reason,
+            txn.amount.currency(),
+            Some(txn.id),
+            txn.transaction_date,
+            vec![
+                JournalLine::account(LedgerAccount::BreakageIncome, txn.amount.amount()),
+                JournalLine::card(txn.gift_card_id, -txn.amount.amount()),
+            ],
+        )
+    }
+
+    /// Fee is charged to a card: debit the card, credit fee income
+    pub fn fee(txn: &GiftCardTransaction, reason: &str) -> Self {
+        Self::new(
+            EntryKind::Fee,
+            reason,
+            txn.amount.currency(),
+            Some(txn.id),
+            txn.transaction_date,
+            vec![
+                JournalLine::card(txn.gift_card_id, txn.amount.amount()),
+                JournalLine::account(LedgerAccount::FeeIncome, -txn.amount.amount()),
+            ],
+        )
+    }
+
+    /// Balance moves to a new card: debit the card it leaves, credit the new card
+    pub fn transfer(txn: &GiftCardTransaction, to_gift_card_id: Uuid) -> Self {
+        Self::new(
+            EntryKind::Transfer,
+            "Transferred to a new card",
+            txn.amount.currency(),
+            Some(txn.id),
+            txn.transaction_date,
+            vec![
+                JournalLine::card(txn.gift_card_id, txn.amount.amount()),
+                JournalLine::card(to_gift_card_id, -txn.amount.amount()),
+            ],
+        )
+    }
+
+    /// Balance carried over to a replacement card: debit the replaced card,
+    /// credit its successor
+    pub fn replacement(successor: &GiftCard) -> Self {
+        let replaced_id = successor.replaces_id.unwrap_or_default();
+        Self::new(
+            EntryKind::Replacement,
+            format!("Replaced by {}", successor.id),
+            successor.currency(),
+            None,
+            successor.created_at,
+            vec![
+                JournalLine::card(replaced_id, successor.balance.amount()),
+                JournalLine::card(successor.id, -successor.balance.amount()),
+            ],
+        )
+    }
+
+    /// Manual correction; a positive `amount` adds to the card's balance
+    pub fn adjustment(gift_card_id: Uuid, amount: Money, reason: &str, now: DateTime<Utc>) -> Self {
+        Self::new(
+            EntryKind::Adjustment,
+            reason,
+            amount.currency(),
+            None,
+            now,
+            vec![
+                JournalLine::account(LedgerAccount::Adjustments, amount.amount()),
+                JournalLine::card(gift_card_id, -amount.amount()),
+            ],
+        )
+    }
+
+    /// Whether the entry has non-zero lines that sum to zero
+    pub fn is_balanced(&self) -> bool {
+        !self.lines.is_empty()
+            && self.lines.iter().all(|line| line.amount != 0)
+            && self.lines.iter().try_fold(0i64, |sum, line| sum.checked_add(line.amount)) == Some(0)
+    }
+
+    /// Change this entry makes to a card's balance
+    pub fn card_delta(&self, gift_card_id: Uuid) -> i64 {
+        -self
+            .lines
+            .iter()
+            .filter(|line| line.account == LedgerAccount::CardLiability && line.gift_card_id == Some(gift_card_id))
+            .map(|line| line.amount)
+            .sum::<i64>()
+    }
+}
+
+/// A card's ledger, compared against its stored balance
+#[derive(Debug, Serialize)]
+pub struct CardLedgerDto {
+    pub gift_card_id: Uuid,
+    pub balance: Money,            // Balance stored on the card
+    pub ledger_balance: Money,     // Balance derived from the ledger
+    pub balanced: bool,            // Whether the two agree
+    pub entries: Vec<JournalEntry>,
+}
+
+/// DTO for a manual balance adjustment
+#[derive(Debug, Deserialize)]
+pub struct AdjustBalanceDto {
+    pub amount: Money,                 // Amount to add, negative to remove
+    pub reason: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::merchant::Merchant;
+
+    #[test]
+    fn test_entries_are_balanced() {
+        let card_id = Uuid::new_v4();
+        let now = Utc::now();
+        let usd = |amount| Money::new(amount, Currency::USD);
+        let txn = GiftCardTransaction::redemption(card_id, usd(1500), &Merchant::new("Cafe", None, now), now);
+
+        let entries = [
+            JournalEntry::redemption(&txn),
+            JournalEntry::refund(&txn.refund(usd(500), now)),
+            JournalEntry::breakage(card_id, usd(200), now),
+            JournalEntry::adjustment(card_id, usd(-300), "Duplicate load", now),
+        ];
+        assert!(entries.iter().all(JournalEntry::is_balanced));
+
+        let deltas: Vec<i64> = entries.iter().map(|entry| entry.card_delta(card_id)).collect();
+        assert_eq!(deltas, vec![-1500, 500, -200, -300]);
+        assert_eq!(entries[0].card_delta(Uuid::new_v4()), 0);
+    }
+
+    #[test]
+    fn test_unbalanced_entry_is_detected() {
+        let mut entry = JournalEntry::breakage(Uuid::new_v4(), Money::new(200, Currency::USD), Utc::now());
+        entry.lines[1].amount = -150;
+        assert!(!entry.is_balanced());
+
+        entry.lines.clear();
+        assert!(!entry.is_balanced());
+    }
+}
@@ -0,0 +1,25 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+/// The secret a card's live QR codes are derived from
+///
+/// Cards get one when their recipient turns live QR codes on, and from then on
+/// only accept a code from the current or previous time window.
+#[derive(Debug, Clone)]
+pub struct LiveQrSecret {
+    pub gift_card_id: Uuid,
+    pub secret: String,                // 64 hex characters, used as an HMAC-SHA256 key
+    pub created_at: DateTime<Utc>,
+}
+
+/// DTO for the live QR code a recipient's device currently shows
+#[derive(Debug, Serialize)]
+pub struct LiveQrCodeDto {
+    pub gift_card_id: Uuid,
+    pub code: String,                  // Text encoded in the QR code
+    pub qr_code: String,               // Base64 encoded QR code image
+    pub window: i64,                   // Time window the code belongs to
+    pub refresh_at: DateTime<Utc>,     // When the next code is issued; poll again then
+    pub expires_at: DateTime<Utc>,     // When this code stops being accepted
+}
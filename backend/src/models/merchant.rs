@@ -0,0 +1,43 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A business that accepts gift cards as payment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Merchant {
+    pub id: Uuid,
+    pub name: String,
+    pub contact_email: Option<String>,
+    pub active: bool,                  // Inactive merchants keep their history but cannot take payments
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Merchant {
+    /// A new, active merchant
+    pub fn new(name: impl Into<String>, contact_email: Option<String>, now: DateTime<Utc>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            name: name.into(),
+            contact_email,
+            active: true,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+/// DTO for registering a merchant
+#[derive(Debug, Deserialize)]
+pub struct CreateMerchantDto {
+    pub name: String,
+    pub contact_email: Option<String>,
+}
+
+/// DTO for changing a merchant; omitted fields are left as they are
+#[derive(Debug, Deserialize)]
+pub struct UpdateMerchantDto {
+    pub name: Option<String>,
+    pub contact_email: Option<String>,
+    pub active: Option<bool>,
+}
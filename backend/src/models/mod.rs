@@ -1,3 +1,76 @@
+/// Store an enum as its `as_str()` name in a text column on every backend
+///
+/// The type must implement `FromStr` with an error convertible into a boxed
+/// error, which is used when decoding.
+macro_rules! impl_sql_text {
+    ($ty:ty) => {
+        impl<DB: sqlx::Database> sqlx::Type<DB> for $ty
+        where
+            str: sqlx::Type<DB>,
+        {
+            fn type_info() -> DB::TypeInfo {
+                <str as sqlx::Type<DB>>::type_info()
+            }
+
+            fn compatible(ty: &DB::TypeInfo) -> bool {
+                <str as sqlx::Type<DB>>::compatible(ty)
+            }
+        }
+
+        impl<'q, DB: sqlx::Database> sqlx::Encode<'q, DB> for $ty
+        where
+            &'q str: sqlx::Encode<'q, DB>,
+        {
+            fn encode_by_ref(
+                &self,
+                buf: &mut <DB as sqlx::database::HasArguments<'q>>::ArgumentBuffer,
+            ) -> sqlx::encode::IsNull {
+                <&str as sqlx::Encode<DB>>::encode(self.as_str(), buf)
+            }
+        }
+
+        impl<'r, DB: sqlx::Database> sqlx::Decode<'r, DB> for $ty
+        where
+            &'r str: sqlx::Decode<'r, DB>,
+        {
+            fn decode(
+                value: <DB as sqlx::database::HasValueRef<'r>>::ValueRef,
+            ) -> Result<Self, sqlx::error::BoxDynError> {
+                let text = <&str as sqlx::Decode<DB>>::decode(value)?;
+                Ok(text.parse()?)
+            }
+        }
+    };
+}
+
+pub mod acceptance_code;
+pub mod account;
+pub mod audit;
+pub mod batch;
+pub mod card_code;
+pub mod card_status;
+pub mod export;
 pub mod gift_card;
+pub mod hold;
+pub mod idempotency;
+pub mod ledger;
+pub mod live_qr;
+pub mod merchant;
+pub mod money;
+pub mod qr_key;
 
-pub use gift_card::*;
\ No newline at end of file
+pub use acceptance_code::*;
+pub use account::*;
+pub use audit::*;
+pub use batch::*;
+pub use card_code::*;
+pub use card_status::*;
+pub use export::*;
+pub use gift_card::*;
+pub use hold::*;
+pub use idempotency::*;
+pub use ledger::*;
+pub use live_qr::*;
+pub use merchant::*;
+pub use money::*;
+pub use qr_key::*;
@@ -0,0 +1,249 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// Active ISO-4217 currency codes and their number of minor units
+static CURRENCIES: &[(&str, u8)] = &[
+    ("AED", 2), ("AFN", 2), ("ALL", 2), ("AMD", 2), ("ANG", 2), ("AOA", 2), ("ARS", 2), ("AUD", 2),
+    ("AWG", 2), ("AZN", 2), ("BAM", 2), ("BBD", 2), ("BDT", 2), ("BGN", 2), ("BHD", 3), ("BIF", 0),
+    ("BMD", 2), ("BND", 2), ("BOB", 2), ("BRL", 2), ("BSD", 2), ("BTN", 2), ("BWP", 2), ("BYN", 2),
+    ("BZD", 2), ("CAD", 2), ("CDF", 2), ("CHF", 2), ("CLF", 4), ("CLP", 0), ("CNY", 2), ("COP", 2),
+    ("CRC", 2), ("CUP", 2), ("CVE", 2), ("CZK", 2), ("DJF", 0), ("DKK", 2), ("DOP", 2), ("DZD", 2),
+    ("EGP", 2), ("ERN", 2), ("ETB", 2), ("EUR", 2), ("FJD", 2), ("FKP", 2), ("GBP", 2), ("GEL", 2),
+    ("GHS", 2), ("GIP", 2), ("GMD", 2), ("GNF", 0), ("GTQ", 2), ("GYD", 2), ("HKD", 2), ("HNL", 2),
+    ("HTG", 2), ("HUF", 2), ("IDR", 2), ("ILS", 2), ("INR", 2), ("IQD", 3), ("IRR", 2), ("ISK", 0),
+    ("JMD", 2), ("JOD", 3), ("JPY", 0), ("KES", 2), ("KGS", 2), ("KHR", 2), ("KMF", 0), ("KPW", 2),
+    ("KRW", 0), ("KWD", 3), ("KYD", 2), ("KZT", 2), ("LAK", 2), ("LBP", 2), ("LKR", 2), ("LRD", 2),
+    ("LSL", 2), ("LYD", 3), ("MAD", 2), ("MDL", 2), ("MGA", 2), ("MKD", 2), ("MMK", 2), ("MNT", 2),
+    ("MOP", 2), ("MRU", 2), ("MUR", 2), ("MVR", 2), ("MWK", 2), ("MXN", 2), ("MYR", 2), ("MZN", 2),
+    ("NAD", 2), ("NGN", 2), ("NIO", 2), ("NOK", 2), ("NPR", 2), ("NZD", 2), ("OMR", 3), ("PAB", 2),
+    ("PEN", 2), ("PGK", 2), ("PHP", 2), ("PKR", 2), ("PLN", 2), ("PYG", 0), ("QAR", 2), ("RON", 2),
+    ("RSD", 2), ("RUB", 2), ("RWF", 0), ("SAR", 2), ("SBD", 2), ("SCR", 2), ("SDG", 2), ("SEK", 2),
+    ("SGD", 2), ("SHP", 2), ("SLE", 2), ("SOS", 2), ("SRD", 2), ("SSP", 2), ("STN", 2), ("SVC", 2),
+    ("SYP", 2), ("SZL", 2), ("THB", 2), ("TJS", 2), ("TMT", 2), ("TND", 3), ("TOP", 2), ("TRY", 2),
+    ("TTD", 2), ("TWD", 2), ("TZS", 2), ("UAH", 2), ("UGX", 0), ("USD", 2), ("UYU", 2), ("UZS", 2),
+    ("VES", 2), ("VND", 0), ("VUV", 0), ("WST", 2), ("XAF", 0), ("XCD", 2), ("XCG", 2), ("XOF", 0),
+    ("XPF", 0), ("YER", 2), ("ZAR", 2), ("ZMW", 2), ("ZWG", 2),
+];
+
+/// An ISO-4217 currency
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Currency {
+    code: &'static str,
+    minor_units: u8,
+}
+
+impl Currency {
+    pub const USD: Currency = Currency { code: "USD", minor_units: 2 };
+
+    /// Three-letter currency code, e.g. `USD`
+    pub fn as_str(self) -> &'static str {
+        self.code
+    }
+
+    /// Number of decimal places in the currency's minor unit (2 for cents)
+    pub fn minor_units(self) -> u8 {
+        self.minor_units
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code)
+    }
+}
+
+impl FromStr for Currency {
+    type Err = MoneyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CURRENCIES
+            .iter()
+            .find(|(code, _)| *code == s)
+            .map(|&(code, minor_units)| Currency { code, minor_units })
+            .ok_or_else(|| MoneyError::UnknownCurrency(s.to_string()))
+    }
+}
+
+impl Serialize for Currency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code)
+    }
+}
+
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = String::deserialize(deserializer)?;
+        code.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+// Stored as its code in a CHAR(3) column on every backend
+impl_sql_text!(Currency);
+
+/// Error from constructing or combining [`Money`] values
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MoneyError {
+    UnknownCurrency(String),
+    CurrencyMismatch { expected: Currency, found: Currency },
+    Overflow,
+}
+
+impl fmt::Display for MoneyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoneyError::UnknownCurrency(code) => write!(f, "Unknown currency: {}", code),
+            MoneyError::CurrencyMismatch { expected, found } => {
+                write!(f, "Currency mismatch: expected {}, got {}", expected, found)
+            }
+            MoneyError::Overflow => write!(f, "Amount is out of range"),
+        }
+    }
+}
+
+impl std::error::Error for MoneyError {}
+
+/// An amount of money in the minor unit of its currency
+///
+/// Serializes as `{"amount": 5000, "currency": "USD"}`. Arithmetic is
+/// checked and only allowed between amounts of the same currency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Money {
+    amount: i64,
+    currency: Currency,
+}
+
+impl Money {
+    pub fn new(amount: i64, currency: Currency) -> Self {
+        Self { amount, currency }
+    }
+
+    pub fn zero(currency: Currency) -> Self {
+        Self::new(0, currency)
+    }
+
+    /// `units` whole units of `currency`, e.g. dollars, saturating on overflow
+    pub fn whole(units: i64, currency: Currency) -> Self {
+        Self::new(units.saturating_mul(10i64.pow(currency.minor_units() as u32)), currency)
+    }
+
+    /// Amount in minor units, e.g. cents
+    pub fn amount(self) -> i64 {
+        self.amount
+    }
+
+    pub fn currency(self) -> Currency {
+        self.currency
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.amount == 0
+    }
+
+    pub fn is_positive(self) -> bool {
+        self.amount > 0
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.amount < 0
+    }
+
+    /// Fail unless `other` is in the same currency
+    pub fn same_currency(self, other: Money) -> Result<(), MoneyError> {
+        if self.currency == other.currency {
+            Ok(())
+        } else {
+            Err(MoneyError::CurrencyMismatch { expected: self.currency, found: other.currency })
+        }
+    }
+
+    pub fn checked_add(self, other: Money) -> Result<Money, MoneyError> {
+        self.same_currency(other)?;
+        let amount = self.amount.checked_add(other.amount).ok_or(MoneyError::Overflow)?;
+        Ok(Money::new(amount, self.currency))
+    }
+
+    pub fn checked_sub(self, other: Money) -> Result<Money, MoneyError> {
+        self.same_currency(other)?;
+        let amount = self.amount.checked_sub(other.amount).ok_or(MoneyError::Overflow)?;
+        Ok(Money::new(amount, self.currency))
+    }
+
+    pub fn checked_neg(self) -> Result<Money, MoneyError> {
+        let amount = self.amount.checked_neg().ok_or(MoneyError::Overflow)?;
+        Ok(Money::new(amount, self.currency))
+    }
+}
+
+impl fmt::Display for Money {
+    /// Decimal amount followed by the currency code, e.g. `12.34 USD`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.amount < 0 { "-" } else { "" };
+        let digits = self.currency.minor_units as u32;
+        let units = self.amount.unsigned_abs();
+
+        if digits == 0 {
+            write!(f, "{}{} {}", sign, units, self.currency)
+        } else {
+            let scale = 10u64.pow(digits);
+            write!(
+                f,
+                "{}{}.{:0width$} {}",
+                sign,
+                units / scale,
+                units % scale,
+                self.currency,
+                width = digits as usize
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eur() -> Currency {
+        "EUR".parse().unwrap()
+    }
+
+    #[test]
+    fn test_parses_known_currencies() {
+        assert_eq!("USD".parse::<Currency>(), Ok(Currency::USD));
+        assert_eq!("KRW".parse::<Currency>().unwrap().minor_units(), 0);
+        assert_eq!("KWD".parse::<Currency>().unwrap().minor_units(), 3);
+        assert!("usd".parse::<Currency>().is_err());
+        assert!("XYZ".parse::<Currency>().is_err());
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let ten = Money::new(1000, Currency::USD);
+        assert_eq!(ten.checked_sub(Money::new(250, Currency::USD)), Ok(Money::new(750, Currency::USD)));
+        assert_eq!(
+            ten.checked_add(Money::new(1, eur())),
+            Err(MoneyError::CurrencyMismatch { expected: Currency::USD, found: eur() })
+        );
+        assert_eq!(
+            Money::new(i64::MAX, Currency::USD).checked_add(Money::new(1, Currency::USD)),
+            Err(MoneyError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_display_uses_minor_units() {
+        assert_eq!(Money::new(1234, Currency::USD).to_string(), "12.34 USD");
+        assert_eq!(Money::new(-5, Currency::USD).to_string(), "-0.05 USD");
+        assert_eq!(Money::new(5000, "KRW".parse().unwrap()).to_string(), "5000 KRW");
+        assert_eq!(Money::new(1500, "KWD".parse().unwrap()).to_string(), "1.500 KWD");
+    }
+
+    #[test]
+    fn test_serializes_as_amount_and_currency() {
+        let json = serde_json::to_value(Money::new(5000, Currency::USD)).unwrap();
+        assert_eq!(json, serde_json::json!({ "amount": 5000, "currency": "USD" }));
+
+        let parsed: Result<Money, _> = serde_json::from_value(serde_json::json!({ "amount": 1, "currency": "ABC" }));
+        assert!(parsed.is_err());
+    }
+}
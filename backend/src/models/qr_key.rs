@@ -0,0 +1,20 @@
+use serde::Serialize;
+
+/// Public half of a QR signing key, as a JSON Web Key (RFC 8037)
+#[derive(Debug, Serialize)]
+pub struct QrPublicKeyDto {
+    pub kty: &'static str,             // Always "OKP"
+    pub crv: &'static str,             // Always "Ed25519"
+    pub alg: &'static str,             // Always "EdDSA"
+    #[serde(rename = "use")]
+    pub key_use: &'static str,         // Always "sig"
+    pub kid: String,
+    pub x: String,                     // Public key, base64url without padding
+    pub active: bool,                  // Whether new QR codes are signed with this key
+}
+
+/// Every key a QR code may be signed with, for verifying codes offline
+#[derive(Debug, Serialize)]
+pub struct QrKeySetDto {
+    pub keys: Vec<QrPublicKeyDto>,
+}
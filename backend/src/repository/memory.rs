@@ -0,0 +1,727 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+use super::{
+    check_available, check_entries, check_ledger_balance, check_refund, CardUpdate, CardUpdateFn,
+    GiftCardRepository,
+};
+use crate::models::acceptance_code::AcceptanceCode;
+use crate::models::account::Account;
+use crate::models::audit::CardAudit;
+use crate::models::batch::IssuanceBatch;
+use crate::models::export::{ExportFilter, ExportRecord};
+use crate::models::gift_card::{GiftCard, GiftCardTransaction, TransactionKind};
+use crate::models::hold::{Hold, HoldStatus};
+use crate::models::idempotency::IdempotencyRecord;
+use crate::models::ledger::JournalEntry;
+use crate::models::card_code::CardCode;
+use crate::models::card_status::CardStatus;
+use crate::models::live_qr::LiveQrSecret;
+use crate::models::merchant::Merchant;
+use crate::models::money::Money;
+use crate::utils::error::AppError;
+
+#[derive(Default)]
+struct State {
+    cards: HashMap<Uuid, GiftCard>,
+    transactions: Vec<GiftCardTransaction>,
+    entries: Vec<JournalEntry>,
+    holds: HashMap<Uuid, Hold>,
+    merchants: HashMap<Uuid, Merchant>,
+    accounts: HashMap<Uuid, Account>,
+    acceptance_codes: HashMap<Uuid, AcceptanceCode>,
+    live_qr_secrets: HashMap<Uuid, LiveQrSecret>,
+    card_codes: HashMap<Uuid, CardCode>,
+    batches: HashMap<Uuid, IssuanceBatch>,
+    audits: Vec<CardAudit>,
+    idempotency_keys: HashMap<String, IdempotencyRecord>,
+}
+
+impl State {
+    fn ledger_balance(&self, gift_card_id: Uuid) -> i64 {
+        self.entries.iter().map(|entry| entry.card_delta(gift_card_id)).sum()
+    }
+
+    fn find_transaction(&self, id: Uuid) -> Option<&GiftCardTransaction> {
+        self.transactions.iter().find(|txn| txn.id == id)
+    }
+
+    /// Sum of the open holds on a card, in minor units
+    fn held_amount(&self, gift_card_id: Uuid, now: DateTime<Utc>) -> i64 {
+        self.holds
+            .values()
+            .filter(|hold| hold.gift_card_id == gift_card_id && hold.is_open(now))
+            .map(|hold| hold.amount.amount())
+            .sum()
+    }
+
+    /// A stored card with `held` filled in
+    fn card(&self, id: Uuid) -> Option<GiftCard> {
+        let mut card = self.cards.get(&id)?.clone();
+        card.held = Money::new(self.held_amount(id, Utc::now()), card.currency());
+        Some(card)
+    }
+}
+
+/// Sum of the refunds in `transactions` against a redemption
+fn refunded_amount<'a>(transactions: impl IntoIterator<Item = &'a GiftCardTransaction>, transaction_id: Uuid) -> i64 {
+    transactions
+        .into_iter()
+        .filter(|txn| txn.kind == TransactionKind::Refund && txn.original_transaction_id == Some(transaction_id))
+        .map(|txn| txn.amount.amount())
+        .sum()
+}
+
+/// Whether `time` falls in the export date range of `filter`
+fn in_range(filter: &ExportFilter, time: DateTime<Utc>) -> bool {
+    filter.from.is_none_or(|from| time >= from) && filter.to.is_none_or(|to| time < to)
+}
+
+/// In-process repository used for tests and local development
+///
+/// All data lives behind a single mutex, which also makes
+/// [`GiftCardRepository::update_cards`] trivially atomic.
+#[derive(Default)]
+pub struct InMemoryRepository {
+    state: Mutex<State>,
+}
+
+impl InMemoryRepository {
+    /// Create an empty repository
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, State>, AppError> {
+        self.state
+            .lock()
+            .map_err(|_| AppError::InternalServerError("In-memory store poisoned".to_string()))
+    }
+}
+
+/// Apply `limit`/`offset` pagination to an already sorted list
+fn paginate<T>(items: Vec<T>, limit: i64, offset: i64) -> Vec<T> {
+    items
+        .into_iter()
+        .skip(offset.max(0) as usize)
+        .take(limit.max(0) as usize)
+        .collect()
+}
+
+#[async_trait]
+impl GiftCardRepository for InMemoryRepository {
+    async fn find_card(&self, id: Uuid) -> Result<Option<GiftCard>, AppError> {
+        Ok(self.lock()?.card(id))
+    }
+
+    async fn list_cards_by_recipient(
+        &self,
+        recipient_phone: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<GiftCard>, AppError> {
+        let state = self.lock()?;
+
+        let mut cards: Vec<GiftCard> = state
+            .cards
+            .values()
+            .filter(|card| card.recipient_phone == recipient_phone)
+            .filter_map(|card| state.card(card.id))
+            .collect();
+        cards.sort_by_key(|card| Reverse(card.created_at));
+
+        Ok(paginate(cards, limit, offset))
+    }
+
+    async fn list_transactions(
+        &self,
+        gift_card_id: Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<GiftCardTransaction>, AppError> {
+        let state = self.lock()?;
+
+        let mut transactions: Vec<GiftCardTransaction> = state
+            .transactions
+            .iter()
+            .filter(|txn| txn.gift_card_id == gift_card_id)
+            .cloned()
+            .collect();
+        transactions.sort_by_key(|txn| Reverse(txn.transaction_date));
+
+        Ok(paginate(transactions, limit, offset))
+    }
+
+    async fn find_transaction(&self, id: Uuid) -> Result<Option<GiftCardTransaction>, AppError> {
+        Ok(self.lock()?.find_transaction(id).cloned())
+    }
+
+    async fn find_hold(&self, id: Uuid) -> Result<Option<Hold>, AppError> {
+        Ok(self.lock()?.holds.get(&id).cloned())
+    }
+
+    async fn refunded_amount(&self, transaction_id: Uuid) -> Result<i64, AppError> {
+        Ok(refunded_amount(&self.lock()?.transactions, transaction_id))
+    }
+
+    async fn loaded_amount(&self, gift_card_id: Uuid, since: DateTime<Utc>) -> Result<i64, AppError> {
+        let loaded = self
+            .lock()?
+            .transactions
+            .iter()
+            .filter(|txn| txn.gift_card_id == gift_card_id && txn.kind == TransactionKind::Load)
+            .filter(|txn| txn.transaction_date >= since)
+            .map(|txn| txn.amount.amount())
+            .sum();
+        Ok(loaded)
+    }
+
+    async fn list_journal_entries(
+        &self,
+        gift_card_id: Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<JournalEntry>, AppError> {
+        let state = self.lock()?;
+
+        let mut entries: Vec<JournalEntry> = state
+            .entries
+            .iter()
+            .filter(|entry| entry.lines.iter().any(|line| line.gift_card_id == Some(gift_card_id)))
+            .cloned()
+            .collect();
+        entries.sort_by_key(|entry| Reverse(entry.created_at));
+
+        Ok(paginate(entries, limit, offset))
+    }
+
+    async fn ledger_balance(&self, gift_card_id: Uuid) -> Result<i64, AppError> {
+        Ok(self.lock()?.ledger_balance(gift_card_id))
+    }
+
+    async fn insert_account(&self, account: &Account) -> Result<(), AppError> {
+        let mut state = self.lock()?;
+        if state.accounts.values().any(|existing| existing.email == account.email) {
+            return Err(AppError::ConflictError("An account with this email already exists".to_string()));
+        }
+        state.accounts.insert(account.id, account.clone());
+        Ok(())
+    }
+
+    async fn find_account(&self, id: Uuid) -> Result<Option<Account>, AppError> {
+        Ok(self.lock()?.accounts.get(&id).cloned())
+    }
+
+    async fn find_account_by_email(&self, email: &str) -> Result<Option<Account>, AppError> {
+        Ok(self.lock()?.accounts.values().find(|account| account.email == email).cloned())
+    }
+
+    async fn insert_acceptance_code(&self, code: &AcceptanceCode) -> Result<(), AppError> {
+        self.lock()?.acceptance_codes.insert(code.id, code.clone());
+        Ok(())
+    }
+
+    async fn find_latest_acceptance_code(&self, gift_card_id: Uuid) -> Result<Option<AcceptanceCode>, AppError> {
+        Ok(self
+            .lock()?
+            .acceptance_codes
+            .values()
+            .filter(|code| code.gift_card_id == gift_card_id)
+            .max_by_key(|code| code.created_at)
+            .cloned())
+    }
+
+    async fn claim_acceptance_attempt(&self, id: Uuid, max_attempts: i32) -> Result<bool, AppError> {
+        match self.lock()?.acceptance_codes.get_mut(&id) {
+            Some(code) if code.used_at.is_none() && code.attempts < max_attempts => {
+                code.attempts += 1;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn use_acceptance_code(&self, id: Uuid, now: DateTime<Utc>) -> Result<bool, AppError> {
+        match self.lock()?.acceptance_codes.get_mut(&id) {
+            Some(code) if code.used_at.is_none() => {
+                code.used_at = Some(now);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn insert_live_qr_secret(&self, secret: &LiveQrSecret) -> Result<LiveQrSecret, AppError> {
+        Ok(self
+            .lock()?
+            .live_qr_secrets
+            .entry(secret.gift_card_id)
+            .or_insert_with(|| secret.clone())
+            .clone())
+    }
+
+    async fn find_live_qr_secret(&self, gift_card_id: Uuid) -> Result<Option<LiveQrSecret>, AppError> {
+        Ok(self.lock()?.live_qr_secrets.get(&gift_card_id).cloned())
+    }
+
+    async fn insert_card_code(&self, code: &CardCode) -> Result<CardCode, AppError> {
+        let mut state = self.lock()?;
+        if let Some(existing) = state.card_codes.get(&code.gift_card_id) {
+            return Ok(existing.clone());
+        }
+        if state.card_codes.values().any(|other| other.code == code.code) {
+            return Err(AppError::ConflictError("Short code is already taken".to_string()));
+        }
+        state.card_codes.insert(code.gift_card_id, code.clone());
+        Ok(code.clone())
+    }
+
+    async fn find_card_code(&self, gift_card_id: Uuid) -> Result<Option<CardCode>, AppError> {
+        Ok(self.lock()?.card_codes.get(&gift_card_id).cloned())
+    }
+
+    async fn find_card_code_by_code(&self, code: &str) -> Result<Option<CardCode>, AppError> {
+        Ok(self.lock()?.card_codes.values().find(|card_code| card_code.code == code).cloned())
+    }
+
+    async fn set_card_pin(&self, gift_card_id: Uuid, pin_hash: Option<&str>) -> Result<(), AppError> {
+        let mut state = self.lock()?;
+        let card_code = state
+            .card_codes
+            .get_mut(&gift_card_id)
+            .ok_or_else(|| AppError::NotFoundError("Card code not found".to_string()))?;
+        card_code.pin_hash = pin_hash.map(str::to_string);
+        card_code.pin_attempts = 0;
+        card_code.locked_until = None;
+        Ok(())
+    }
+
+    async fn claim_pin_attempt(
+        &self,
+        gift_card_id: Uuid,
+        max_attempts: i32,
+        now: DateTime<Utc>,
+        lock_until: DateTime<Utc>,
+    ) -> Result<bool, AppError> {
+        match self.lock()?.card_codes.get_mut(&gift_card_id) {
+            Some(card_code) if card_code.locked_until.is_none_or(|until| until <= now) => {
+                if card_code.pin_attempts + 1 >= max_attempts {
+                    card_code.pin_attempts = 0;
+                    card_code.locked_until = Some(lock_until);
+                } else {
+                    card_code.pin_attempts += 1;
+                    card_code.locked_until = None;
+                }
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn reset_pin_attempts(&self, gift_card_id: Uuid) -> Result<(), AppError> {
+        if let Some(card_code) = self.lock()?.card_codes.get_mut(&gift_card_id) {
+            card_code.pin_attempts = 0;
+            card_code.locked_until = None;
+        }
+        Ok(())
+    }
+
+    async fn insert_batch(&self, batch: &IssuanceBatch) -> Result<(), AppError> {
+        self.lock()?.batches.insert(batch.id, batch.clone());
+        Ok(())
+    }
+
+    async fn update_batch(&self, batch: &IssuanceBatch) -> Result<(), AppError> {
+        match self.lock()?.batches.get_mut(&batch.id) {
+            Some(stored) => {
+                *stored = batch.clone();
+                Ok(())
+            }
+            None => Err(AppError::NotFoundError("Batch not found".to_string())),
+        }
+    }
+
+    async fn find_batch(&self, id: Uuid) -> Result<Option<IssuanceBatch>, AppError> {
+        Ok(self.lock()?.batches.get(&id).cloned())
+    }
+
+    async fn list_card_audits(&self, gift_card_id: Uuid, limit: i64, offset: i64) -> Result<Vec<CardAudit>, AppError> {
+        let state = self.lock()?;
+
+        let mut audits: Vec<CardAudit> =
+            state.audits.iter().filter(|audit| audit.gift_card_id == gift_card_id).cloned().collect();
+        audits.sort_by_key(|audit| Reverse(audit.created_at));
+
+        Ok(paginate(audits, limit, offset))
+    }
+
+    async fn export_cards(
+        &self,
+        filter: &ExportFilter,
+        after: Option<(DateTime<Utc>, Uuid)>,
+        limit: i64,
+    ) -> Result<Vec<GiftCard>, AppError> {
+        let state = self.lock()?;
+
+        let mut cards: Vec<GiftCard> = state
+            .cards
+            .values()
+            .filter(|card| in_range(filter, card.created_at))
+            .filter(|card| filter.status.is_none_or(|status| card.status == status))
+            .filter(|card| filter.issuer_id.is_none_or(|issuer_id| card.issuer_id == Some(issuer_id)))
+            .filter(|card| after.is_none_or(|after| card.cursor() > after))
+            .cloned()
+            .collect();
+        cards.sort_by_key(|card| card.cursor());
+
+        Ok(paginate(cards, limit, 0))
+    }
+
+    async fn export_transactions(
+        &self,
+        filter: &ExportFilter,
+        after: Option<(DateTime<Utc>, Uuid)>,
+        limit: i64,
+    ) -> Result<Vec<GiftCardTransaction>, AppError> {
+        let state = self.lock()?;
+
+        let mut transactions: Vec<GiftCardTransaction> = state
+            .transactions
+            .iter()
+            .filter(|txn| in_range(filter, txn.transaction_date))
+            .filter(|txn| filter.kind.is_none_or(|kind| txn.kind == kind))
+            .filter(|txn| {
+                filter.issuer_id.is_none_or(|issuer_id| {
+                    state.cards.get(&txn.gift_card_id).is_some_and(|card| card.issuer_id == Some(issuer_id))
+                })
+            })
+            .filter(|txn| filter.merchant_id.is_none_or(|merchant_id| txn.merchant_id == Some(merchant_id)))
+            .filter(|txn| after.is_none_or(|after| txn.cursor() > after))
+            .cloned()
+            .collect();
+        transactions.sort_by_key(|txn| txn.cursor());
+
+        Ok(paginate(transactions, limit, 0))
+    }
+
+    async fn find_expiring_cards(
+        &self,
+        now: DateTime<Utc>,
+        after: Option<(DateTime<Utc>, Uuid)>,
+        limit: i64,
+    ) -> Result<Vec<GiftCard>, AppError> {
+        let state = self.lock()?;
+
+        let cursor = |card: &GiftCard| (card.expiration_date, card.id);
+        let mut cards: Vec<GiftCard> = state
+            .cards
+            .values()
+            .filter(|card| card.expiration_date <= now && card.status.can_transition_to(CardStatus::Expired))
+            .filter(|card| after.is_none_or(|after| cursor(card) > after))
+            .cloned()
+            .collect();
+        cards.sort_by_key(cursor);
+
+        Ok(paginate(cards, limit, 0))
+    }
+
+    async fn find_open_holds(&self, gift_card_id: Uuid, now: DateTime<Utc>) -> Result<Vec<Hold>, AppError> {
+        let state = self.lock()?;
+
+        let mut holds: Vec<Hold> = state
+            .holds
+            .values()
+            .filter(|hold| hold.gift_card_id == gift_card_id && hold.is_open(now))
+            .cloned()
+            .collect();
+        holds.sort_by_key(|hold| (hold.created_at, hold.id));
+        Ok(holds)
+    }
+
+    async fn insert_merchant(&self, merchant: &Merchant) -> Result<(), AppError> {
+        self.lock()?.merchants.insert(merchant.id, merchant.clone());
+        Ok(())
+    }
+
+    async fn find_merchant(&self, id: Uuid) -> Result<Option<Merchant>, AppError> {
+        Ok(self.lock()?.merchants.get(&id).cloned())
+    }
+
+    async fn list_merchants(&self, limit: i64, offset: i64) -> Result<Vec<Merchant>, AppError> {
+        let mut merchants: Vec<Merchant> = self.lock()?.merchants.values().cloned().collect();
+        merchants.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+
+        Ok(paginate(merchants, limit, offset))
+    }
+
+    async fn update_merchant(&self, merchant: &Merchant) -> Result<(), AppError> {
+        match self.lock()?.merchants.get_mut(&merchant.id) {
+            Some(stored) => {
+                *stored = merchant.clone();
+                Ok(())
+            }
+            None => Err(AppError::NotFoundError("Merchant not found".to_string())),
+        }
+    }
+
+    async fn list_merchant_transactions(
+        &self,
+        merchant_id: Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<GiftCardTransaction>, AppError> {
+        let state = self.lock()?;
+
+        let mut transactions: Vec<GiftCardTransaction> = state
+            .transactions
+            .iter()
+            .filter(|txn| txn.merchant_id == Some(merchant_id))
+            .cloned()
+            .collect();
+        transactions.sort_by_key(|txn| Reverse(txn.transaction_date));
+
+        Ok(paginate(transactions, limit, offset))
+    }
+
+    async fn update_cards(&self, ids: &[Uuid], update: CardUpdateFn) -> Result<CardUpdate, AppError> {
+        let mut state = self.lock()?;
+
+        let cards = ids
+            .iter()
+            .map(|id| {
+                state
+                    .card(*id)
+                    .ok_or_else(|| AppError::NotFoundError("Gift card not found".to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let changes = update(cards)?;
+
+        // Validate everything before touching the state, so failures write nothing
+        check_entries(&changes)?;
+        for card in &changes.cards {
+            if !ids.contains(&card.id) && state.cards.contains_key(&card.id) {
+                return Err(AppError::ValidationError("Gift card already exists".to_string()));
+            }
+            let pending: i64 = changes.entries.iter().map(|entry| entry.card_delta(card.id)).sum();
+            check_ledger_balance(card, state.ledger_balance(card.id) + pending)?;
+        }
+        for txn in changes.transactions.iter().filter(|txn| txn.kind == TransactionKind::Refund) {
+            let original_id = txn.original_transaction_id.unwrap_or_default();
+            let total = refunded_amount(state.transactions.iter().chain(&changes.transactions), original_id);
+            check_refund(txn, state.find_transaction(original_id), total)?;
+        }
+        for hold in changes.holds.iter().filter(|hold| hold.status != HoldStatus::Active) {
+            if state.holds.get(&hold.id).map(|stored| stored.status) != Some(HoldStatus::Active) {
+                return Err(AppError::ConflictError("Hold is no longer active".to_string()));
+            }
+        }
+        // Holds as they will be stored once the changes are applied
+        let now = Utc::now();
+        let holds: Vec<&Hold> = state
+            .holds
+            .values()
+            .filter(|stored| changes.holds.iter().all(|hold| hold.id != stored.id))
+            .chain(&changes.holds)
+            .collect();
+        for card in &changes.cards {
+            let held: i64 = holds
+                .iter()
+                .filter(|hold| hold.gift_card_id == card.id && hold.is_open(now))
+                .map(|hold| hold.amount.amount())
+                .sum();
+            check_available(card, held)?;
+        }
+
+        for card in &changes.cards {
+            state.cards.insert(card.id, card.clone());
+        }
+        state.transactions.extend(changes.transactions.iter().cloned());
+        state.entries.extend(changes.entries.iter().cloned());
+        for hold in &changes.holds {
+            state.holds.insert(hold.id, hold.clone());
+        }
+        state.audits.extend(changes.audits.iter().cloned());
+
+        Ok(changes)
+    }
+
+    async fn claim_idempotency_key(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        now: DateTime<Utc>,
+        stale_before: DateTime<Utc>,
+    ) -> Result<Option<IdempotencyRecord>, AppError> {
+        let mut state = self.lock()?;
+
+        if let Some(record) = state.idempotency_keys.get(key) {
+            if record.created_at >= stale_before {
+                return Ok(Some(record.clone()));
+            }
+        }
+
+        let record = IdempotencyRecord {
+            key: key.to_string(),
+            fingerprint: fingerprint.to_string(),
+            response_status: None,
+            response_body: None,
+            created_at: now,
+        };
+        state.idempotency_keys.insert(key.to_string(), record);
+        Ok(None)
+    }
+
+    async fn complete_idempotency_key(&self, key: &str, status: u16, body: &str) -> Result<(), AppError> {
+        if let Some(record) = self.lock()?.idempotency_keys.get_mut(key) {
+            record.response_status = Some(status);
+            record.response_body = Some(body.to_string());
+        }
+        Ok(())
+    }
+
+    async fn release_idempotency_key(&self, key: &str) -> Result<(), AppError> {
+        let mut state = self.lock()?;
+        if state.idempotency_keys.get(key).is_some_and(|record| !record.is_complete()) {
+            state.idempotency_keys.remove(key);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::card_status::CardStatus;
+    use crate::models::money::Currency;
+    use chrono::{Duration, Utc};
+
+    fn usd(cents: i64) -> Money {
+        Money::new(cents, Currency::USD)
+    }
+
+    fn sample_card(phone: &str) -> GiftCard {
+        let now = Utc::now();
+        GiftCard {
+            id: Uuid::new_v4(),
+            issuer_id: None,
+            issuer_name: "Alice".to_string(),
+            recipient_name: "Bob".to_string(),
+            recipient_phone: phone.to_string(),
+            balance: usd(5000),
+            initial_balance: usd(5000),
+            total_loaded: usd(5000),
+            held: usd(0),
+            expiration_date: now + Duration::days(30),
+            status: CardStatus::Issued,
+            status_before_expiry: None,
+            accepted_at: None,
+            replaces_id: None,
+            first_issued_at: now,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    async fn issue(repo: &InMemoryRepository, card: &GiftCard) -> Result<(), AppError> {
+        repo.insert_card(card, &JournalEntry::issuance(card)).await
+    }
+
+    #[actix_web::test]
+    async fn test_insert_and_find() {
+        let repo = InMemoryRepository::new();
+        let card = sample_card("1234567890");
+
+        issue(&repo, &card).await.unwrap();
+
+        let found = repo.find_card(card.id).await.unwrap().unwrap();
+        assert_eq!(found.recipient_name, "Bob");
+        assert!(repo.find_card(Uuid::new_v4()).await.unwrap().is_none());
+        assert!(issue(&repo, &card).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_update_cards_is_all_or_nothing() {
+        let repo = InMemoryRepository::new();
+        let card = sample_card("1234567890");
+        issue(&repo, &card).await.unwrap();
+
+        let result = repo
+            .update_cards(
+                &[card.id],
+                Box::new(|mut cards| {
+                    cards[0].balance = usd(0);
+                    Err(AppError::ValidationError("rejected".to_string()))
+                }),
+            )
+            .await;
+        assert!(result.is_err());
+        assert_eq!(repo.find_card(card.id).await.unwrap().unwrap().balance, usd(5000));
+
+        let card_id = card.id;
+        repo.update_cards(
+            &[card.id],
+            Box::new(move |mut cards| {
+                let mut card = cards.remove(0);
+                card.balance = usd(3500);
+                let merchant = Merchant::new("Cafe", None, Utc::now());
+                let txn = GiftCardTransaction::redemption(card_id, usd(1500), &merchant, Utc::now());
+                let entry = JournalEntry::redemption(&txn);
+                Ok(CardUpdate {
+                    cards: vec![card],
+                    transactions: vec![txn],
+                    entries: vec![entry],
+                    ..Default::default()
+                })
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(repo.find_card(card.id).await.unwrap().unwrap().balance, usd(3500));
+        assert_eq!(repo.list_transactions(card.id, 10, 0).await.unwrap().len(), 1);
+        assert_eq!(repo.list_journal_entries(card.id, 10, 0).await.unwrap().len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn test_balance_changes_require_ledger_entries() {
+        let repo = InMemoryRepository::new();
+        let card = sample_card("1234567890");
+        issue(&repo, &card).await.unwrap();
+
+        let result = repo
+            .update_cards(
+                &[card.id],
+                Box::new(|mut cards| {
+                    cards[0].balance = usd(6000);
+                    Ok(CardUpdate { cards, ..Default::default() })
+                }),
+            )
+            .await;
+        assert!(result.is_err());
+        assert_eq!(repo.ledger_balance(card.id).await.unwrap(), 5000);
+
+        // An issuance entry that disagrees with the card is refused as well
+        let mut unfunded = sample_card("1234567890");
+        let entry = JournalEntry::issuance(&unfunded);
+        unfunded.balance = usd(9000);
+        assert!(repo.insert_card(&unfunded, &entry).await.is_err());
+        assert!(repo.find_card(unfunded.id).await.unwrap().is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_list_by_recipient_paginates() {
+        let repo = InMemoryRepository::new();
+        for _ in 0..3 {
+            issue(&repo, &sample_card("1234567890")).await.unwrap();
+        }
+        issue(&repo, &sample_card("5555555555")).await.unwrap();
+
+        assert_eq!(repo.list_cards_by_recipient("1234567890", 10, 0).await.unwrap().len(), 3);
+        assert_eq!(repo.list_cards_by_recipient("1234567890", 2, 2).await.unwrap().len(), 1);
+    }
+}
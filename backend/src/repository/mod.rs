@@ -0,0 +1,415 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::models::gift_card::{GiftCard, GiftCardTransaction, TransactionKind};
+use crate::models::audit::CardAudit;
+use crate::models::hold::Hold;
+use crate::models::idempotency::IdempotencyRecord;
+use crate::models::acceptance_code::AcceptanceCode;
+use crate::models::account::Account;
+use crate::models::batch::{BatchRowResult, IssuanceBatch};
+use crate::models::card_code::CardCode;
+use crate::models::export::ExportFilter;
+use crate::models::ledger::{JournalEntry, JournalLine};
+use crate::models::live_qr::LiveQrSecret;
+use crate::models::merchant::Merchant;
+use crate::models::money::Money;
+use crate::utils::error::AppError;
+
+pub mod memory;
+pub mod mysql;
+pub mod postgres;
+
+pub use memory::InMemoryRepository;
+pub use mysql::MySqlRepository;
+pub use postgres::PostgresRepository;
+
+/// Writes produced by a [`CardUpdateFn`], persisted atomically
+#[derive(Debug, Default)]
+pub struct CardUpdate {
+    /// Cards to persist; locked cards are updated, any other card is inserted
+    pub cards: Vec<GiftCard>,
+    /// Transaction records to append
+    pub transactions: Vec<GiftCardTransaction>,
+    /// Journal entries to append; every card written must match its ledger afterwards
+    pub entries: Vec<JournalEntry>,
+    /// Holds to persist; active holds are inserted, any other hold is updated
+    /// and must still be active in storage
+    pub holds: Vec<Hold>,
+    /// Audit records to append for changes staff made by hand
+    pub audits: Vec<CardAudit>,
+}
+
+/// Business logic run against locked cards, in the order their IDs were requested
+pub type CardUpdateFn = Box<dyn FnOnce(Vec<GiftCard>) -> Result<CardUpdate, AppError> + Send>;
+
+/// Storage for gift cards, their transactions and the ledger
+#[async_trait]
+pub trait GiftCardRepository: Send + Sync {
+    /// Insert a newly issued gift card together with its issuance entry
+    async fn insert_card(&self, card: &GiftCard, issuance: &JournalEntry) -> Result<(), AppError> {
+        let changes = CardUpdate {
+            cards: vec![card.clone()],
+            entries: vec![issuance.clone()],
+            ..Default::default()
+        };
+        self.update_cards(&[], Box::new(move |_| Ok(changes))).await?;
+        Ok(())
+    }
+
+    /// Fetch a gift card by ID, with `held` set from its open holds
+    async fn find_card(&self, id: Uuid) -> Result<Option<GiftCard>, AppError>;
+
+    /// List gift cards for a recipient phone, newest first
+    async fn list_cards_by_recipient(
+        &self,
+        recipient_phone: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<GiftCard>, AppError>;
+
+    /// List transactions for a gift card, newest first
+    async fn list_transactions(
+        &self,
+        gift_card_id: Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<GiftCardTransaction>, AppError>;
+
+    /// Fetch a transaction by ID
+    async fn find_transaction(&self, id: Uuid) -> Result<Option<GiftCardTransaction>, AppError>;
+
+    /// Fetch a hold by ID
+    async fn find_hold(&self, id: Uuid) -> Result<Option<Hold>, AppError>;
+
+    /// Total refunded so far against a redemption, in minor units of its currency
+    async fn refunded_amount(&self, transaction_id: Uuid) -> Result<i64, AppError>;
+
+    /// Total loaded onto a gift card by top-ups since `since`, in minor units of its currency
+    async fn loaded_amount(&self, gift_card_id: Uuid, since: DateTime<Utc>) -> Result<i64, AppError>;
+
+    /// List journal entries touching a gift card, newest first
+    async fn list_journal_entries(
+        &self,
+        gift_card_id: Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<JournalEntry>, AppError>;
+
+    /// Balance of a gift card according to the ledger, in minor units of its currency
+    async fn ledger_balance(&self, gift_card_id: Uuid) -> Result<i64, AppError>;
+
+    /// Insert a new account, rejecting an email that is already registered
+    async fn insert_account(&self, account: &Account) -> Result<(), AppError>;
+
+    /// Fetch an account by ID
+    async fn find_account(&self, id: Uuid) -> Result<Option<Account>, AppError>;
+
+    /// Fetch an account by its lower-cased email
+    async fn find_account_by_email(&self, email: &str) -> Result<Option<Account>, AppError>;
+
+    /// Insert a newly sent acceptance code
+    async fn insert_acceptance_code(&self, code: &AcceptanceCode) -> Result<(), AppError>;
+
+    /// Fetch the acceptance code most recently sent for a gift card
+    async fn find_latest_acceptance_code(&self, gift_card_id: Uuid) -> Result<Option<AcceptanceCode>, AppError>;
+
+    /// Count one confirmation attempt against an unused code, returning
+    /// `false` without counting it once `max_attempts` have been made
+    async fn claim_acceptance_attempt(&self, id: Uuid, max_attempts: i32) -> Result<bool, AppError>;
+
+    /// Mark a code used, returning `false` if it already was
+    async fn use_acceptance_code(&self, id: Uuid, now: DateTime<Utc>) -> Result<bool, AppError>;
+
+    /// Store a card's live QR secret unless it already has one, returning
+    /// the secret the card ends up with
+    async fn insert_live_qr_secret(&self, secret: &LiveQrSecret) -> Result<LiveQrSecret, AppError>;
+
+    /// Fetch a card's live QR secret, if live codes are on for it
+    async fn find_live_qr_secret(&self, gift_card_id: Uuid) -> Result<Option<LiveQrSecret>, AppError>;
+
+    /// Store a card's short code unless it already has one, returning the code
+    /// the card ends up with
+    async fn insert_card_code(&self, code: &CardCode) -> Result<CardCode, AppError>;
+
+    /// Fetch a card's short code
+    async fn find_card_code(&self, gift_card_id: Uuid) -> Result<Option<CardCode>, AppError>;
+
+    /// Fetch the short code record for a canonical short code
+    async fn find_card_code_by_code(&self, code: &str) -> Result<Option<CardCode>, AppError>;
+
+    /// Set or remove a card's PIN, clearing any count of wrong PINs and lockout;
+    /// fails with `NotFoundError` if the card has no short code
+    async fn set_card_pin(&self, gift_card_id: Uuid, pin_hash: Option<&str>) -> Result<(), AppError>;
+
+    /// Count one PIN attempt against a card that is not locked out at `now`,
+    /// returning `false` without counting it if it is. The attempt that
+    /// reaches `max_attempts` locks the card out until `lock_until` and starts
+    /// the count again.
+    async fn claim_pin_attempt(
+        &self,
+        gift_card_id: Uuid,
+        max_attempts: i32,
+        now: DateTime<Utc>,
+        lock_until: DateTime<Utc>,
+    ) -> Result<bool, AppError>;
+
+    /// Forget wrong PINs and any lockout after the right PIN was given
+    async fn reset_pin_attempts(&self, gift_card_id: Uuid) -> Result<(), AppError>;
+
+    /// Insert a new issuance batch
+    async fn insert_batch(&self, batch: &IssuanceBatch) -> Result<(), AppError>;
+
+    /// Save a batch's progress: its status, counts, results, error and completion time
+    async fn update_batch(&self, batch: &IssuanceBatch) -> Result<(), AppError>;
+
+    /// Fetch an issuance batch by ID
+    async fn find_batch(&self, id: Uuid) -> Result<Option<IssuanceBatch>, AppError>;
+
+    /// List the audit records for a gift card, newest first
+    async fn list_card_audits(&self, gift_card_id: Uuid, limit: i64, offset: i64) -> Result<Vec<CardAudit>, AppError>;
+
+    /// A page of up to `limit` gift cards matching `filter`, in creation order
+    /// starting after the card at `after`, with `held` left at zero
+    async fn export_cards(
+        &self,
+        filter: &ExportFilter,
+        after: Option<(DateTime<Utc>, Uuid)>,
+        limit: i64,
+    ) -> Result<Vec<GiftCard>, AppError>;
+
+    /// A page of up to `limit` transactions matching `filter`, in date order
+    /// starting after the transaction at `after`
+    async fn export_transactions(
+        &self,
+        filter: &ExportFilter,
+        after: Option<(DateTime<Utc>, Uuid)>,
+        limit: i64,
+    ) -> Result<Vec<GiftCardTransaction>, AppError>;
+
+    /// A page of up to `limit` cards that are past their expiration date at
+    /// `now` but can still expire, in expiration order starting after the
+    /// card at `after`, with `held` left at zero
+    async fn find_expiring_cards(
+        &self,
+        now: DateTime<Utc>,
+        after: Option<(DateTime<Utc>, Uuid)>,
+        limit: i64,
+    ) -> Result<Vec<GiftCard>, AppError>;
+
+    /// Holds on a card that still reserve part of its balance at `now`
+    async fn find_open_holds(&self, gift_card_id: Uuid, now: DateTime<Utc>) -> Result<Vec<Hold>, AppError>;
+
+    /// Insert a new merchant
+    async fn insert_merchant(&self, merchant: &Merchant) -> Result<(), AppError>;
+
+    /// Fetch a merchant by ID
+    async fn find_merchant(&self, id: Uuid) -> Result<Option<Merchant>, AppError>;
+
+    /// List merchants ordered by name
+    async fn list_merchants(&self, limit: i64, offset: i64) -> Result<Vec<Merchant>, AppError>;
+
+    /// Save changes to an existing merchant
+    async fn update_merchant(&self, merchant: &Merchant) -> Result<(), AppError>;
+
+    /// List transactions taken by a merchant, newest first
+    async fn list_merchant_transactions(
+        &self,
+        merchant_id: Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<GiftCardTransaction>, AppError>;
+
+    /// Lock the given cards, run `update` on them and persist its result in a
+    /// single transaction. Nothing is written if `update` returns an error,
+    /// if a journal entry is unbalanced, if a written card's balance does
+    /// not match its ledger, if a refund exceeds what is left of its
+    /// redemption, or if a card's balance falls below its open holds.
+    async fn update_cards(&self, ids: &[Uuid], update: CardUpdateFn) -> Result<CardUpdate, AppError>;
+
+    /// Reserve an idempotency key for a new request, or return the record
+    /// already stored under it. Records created before `stale_before` are
+    /// replaced as if the key were unused.
+    async fn claim_idempotency_key(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        now: DateTime<Utc>,
+        stale_before: DateTime<Utc>,
+    ) -> Result<Option<IdempotencyRecord>, AppError>;
+
+    /// Store the response for a claimed key so duplicates can replay it
+    async fn complete_idempotency_key(&self, key: &str, status: u16, body: &str) -> Result<(), AppError>;
+
+    /// Drop a claimed key that has no stored response, so the request can be retried
+    async fn release_idempotency_key(&self, key: &str) -> Result<(), AppError>;
+}
+
+/// Storage backend selected from the scheme of a database URL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    MySql,
+    Postgres,
+    Memory,
+}
+
+impl Backend {
+    /// Pick the backend for a database URL
+    pub fn from_url(database_url: &str) -> Option<Self> {
+        let scheme = database_url.split(':').next()?;
+
+        match scheme {
+            "mysql" | "mariadb" => Some(Backend::MySql),
+            "postgres" | "postgresql" => Some(Backend::Postgres),
+            "memory" => Some(Backend::Memory),
+            _ => None,
+        }
+    }
+}
+
+/// Connect to the repository named by `database_url`
+///
+/// `mysql://` and `postgres://` URLs open a connection pool, `memory://`
+/// starts an empty in-process store.
+pub async fn connect(
+    database_url: &str,
+    max_connections: u32,
+) -> Result<Arc<dyn GiftCardRepository>, AppError> {
+    match Backend::from_url(database_url) {
+        Some(Backend::MySql) => Ok(Arc::new(MySqlRepository::connect(database_url, max_connections).await?)),
+        Some(Backend::Postgres) => Ok(Arc::new(PostgresRepository::connect(database_url, max_connections).await?)),
+        Some(Backend::Memory) => Ok(Arc::new(InMemoryRepository::new())),
+        None => Err(AppError::ValidationError(format!(
+            "Unsupported DATABASE_URL scheme: {}",
+            database_url.split(':').next().unwrap_or_default()
+        ))),
+    }
+}
+
+/// Encode a batch's row results for its `results` column
+pub(crate) fn encode_batch_results(results: &[BatchRowResult]) -> Result<String, AppError> {
+    serde_json::to_string(results)
+        .map_err(|e| AppError::InternalServerError(format!("Failed to encode batch results: {}", e)))
+}
+
+/// Decode a batch's `results` column
+pub(crate) fn decode_batch_results(text: &str) -> Result<Vec<BatchRowResult>, sqlx::Error> {
+    serde_json::from_str(text).map_err(|e| sqlx::Error::Decode(Box::new(e)))
+}
+
+/// Order in which cards are locked, so concurrent updates cannot deadlock
+pub(crate) fn lock_order(ids: &[Uuid]) -> Vec<Uuid> {
+    let mut ordered = ids.to_vec();
+    ordered.sort();
+    ordered.dedup();
+    ordered
+}
+
+/// Reject journal entries that are unbalanced, post to a card that is not
+/// being written, or use a currency other than the card's
+pub(crate) fn check_entries(changes: &CardUpdate) -> Result<(), AppError> {
+    for entry in &changes.entries {
+        if !entry.is_balanced() {
+            return Err(AppError::InternalServerError(format!(
+                "Journal entry {} ({}) is not balanced",
+                entry.id, entry.kind
+            )));
+        }
+
+        for card_id in entry.lines.iter().filter_map(|line| line.gift_card_id) {
+            let card = changes.cards.iter().find(|card| card.id == card_id).ok_or_else(|| {
+                AppError::InternalServerError(format!(
+                    "Journal entry {} posts to gift card {} without updating it",
+                    entry.id, card_id
+                ))
+            })?;
+            if card.currency() != entry.currency {
+                return Err(AppError::InternalServerError(format!(
+                    "Journal entry {} is in {} but gift card {} is in {}",
+                    entry.id,
+                    entry.currency,
+                    card.id,
+                    card.currency()
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Compare a written card against the balance its ledger lines add up to
+pub(crate) fn check_ledger_balance(card: &GiftCard, ledger_balance: i64) -> Result<(), AppError> {
+    if card.balance.currency() == card.currency() && card.balance.amount() == ledger_balance {
+        Ok(())
+    } else {
+        Err(AppError::InternalServerError(format!(
+            "Gift card {} balance {} does not match its ledger balance {}",
+            card.id, card.balance, ledger_balance
+        )))
+    }
+}
+
+/// Reject a write that leaves a card with less balance than its open holds reserve
+pub(crate) fn check_available(card: &GiftCard, held: i64) -> Result<(), AppError> {
+    if card.balance.amount() >= held {
+        Ok(())
+    } else {
+        Err(AppError::ValidationError("Balance would fall below the amount on hold".to_string()))
+    }
+}
+
+/// Reject a refund that does not reverse a redemption on the same card, or
+/// that takes the total refunded (this refund included) above the redemption
+pub(crate) fn check_refund(
+    refund: &GiftCardTransaction,
+    original: Option<&GiftCardTransaction>,
+    total_refunded: i64,
+) -> Result<(), AppError> {
+    let original = original.ok_or_else(|| AppError::NotFoundError("Transaction not found".to_string()))?;
+    if original.kind != TransactionKind::Redemption || original.gift_card_id != refund.gift_card_id {
+        return Err(AppError::ValidationError(
+            "Only redemptions on this gift card can be refunded".to_string(),
+        ));
+    }
+    original.amount.same_currency(refund.amount)?;
+
+    if total_refunded > original.amount.amount() {
+        let refundable = original.amount.amount() - (total_refunded - refund.amount.amount());
+        return Err(AppError::ValidationError(format!(
+            "Refund exceeds the refundable amount of {}",
+            Money::new(refundable.max(0), original.amount.currency())
+        )));
+    }
+    Ok(())
+}
+
+/// Fold joined entry/line rows, ordered by entry, into entries with their lines
+pub(crate) fn group_entries(rows: Vec<(JournalEntry, JournalLine)>) -> Vec<JournalEntry> {
+    let mut entries: Vec<JournalEntry> = Vec::new();
+    for (entry, line) in rows {
+        match entries.last_mut() {
+            Some(last) if last.id == entry.id => last.lines.push(line),
+            _ => entries.push(JournalEntry { lines: vec![line], ..entry }),
+        }
+    }
+    entries
+}
+
+#[cfg(test)]
+mod sql_tests;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backend_from_url() {
+        assert_eq!(Backend::from_url("mysql://root@localhost/db"), Some(Backend::MySql));
+        assert_eq!(Backend::from_url("postgres://localhost/db"), Some(Backend::Postgres));
+        assert_eq!(Backend::from_url("postgresql://localhost/db"), Some(Backend::Postgres));
+        assert_eq!(Backend::from_url("memory://"), Some(Backend::Memory));
+        assert_eq!(Backend::from_url("sqlite://db.sqlite"), None);
+    }
+}
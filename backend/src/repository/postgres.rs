@@ -0,0 +1,1243 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::postgres::{PgConnectOptions, PgPool, PgPoolOptions, PgRow};
+use sqlx::{ConnectOptions, PgExecutor, Postgres, QueryBuilder, Row, Transaction};
+use std::str::FromStr;
+use uuid::Uuid;
+
+use super::{
+    check_available, check_entries, check_ledger_balance, check_refund, decode_batch_results,
+    encode_batch_results, group_entries, lock_order, CardUpdate, CardUpdateFn, GiftCardRepository,
+};
+use crate::models::acceptance_code::AcceptanceCode;
+use crate::models::account::Account;
+use crate::models::audit::CardAudit;
+use crate::models::batch::IssuanceBatch;
+use crate::models::export::ExportFilter;
+use crate::models::gift_card::{GiftCard, GiftCardTransaction, TransactionKind};
+use crate::models::hold::{Hold, HoldStatus};
+use crate::models::idempotency::IdempotencyRecord;
+use crate::models::ledger::{JournalEntry, JournalLine, LedgerAccount};
+use crate::models::card_code::CardCode;
+use crate::models::card_status::CardStatus;
+use crate::models::live_qr::LiveQrSecret;
+use crate::models::merchant::Merchant;
+use crate::models::money::{Currency, Money};
+use crate::utils::error::AppError;
+
+const CARD_COLUMNS: &str = "id, issuer_id, issuer_name, recipient_name, recipient_phone, balance, initial_balance, \
+     total_loaded, currency, expiration_date, status, status_before_expiry, accepted_at, replaces_id, \
+     first_issued_at, created_at, updated_at";
+
+const HOLD_COLUMNS: &str = "id, gift_card_id, amount, captured_amount, currency, merchant_id, merchant, \
+     status, transaction_id, expires_at, created_at, updated_at";
+
+const ACCOUNT_COLUMNS: &str =
+    "id, email, name, role, merchant_id, phone, password_hash, created_at, updated_at";
+
+const ACCEPTANCE_CODE_COLUMNS: &str = "id, gift_card_id, code_hash, attempts, expires_at, used_at, created_at";
+
+const LIVE_QR_SECRET_COLUMNS: &str = "gift_card_id, secret, created_at";
+const CARD_CODE_COLUMNS: &str = "gift_card_id, code, pin_hash, pin_attempts, locked_until, created_at";
+
+const BATCH_COLUMNS: &str =
+    "id, issuer_id, mode, status, total_rows, issued, failed, results, error, created_at, completed_at";
+
+const AUDIT_COLUMNS: &str = "id, gift_card_id, account_id, action, reason, previous_status, status, \
+     previous_expiration_date, expiration_date, currency, restored_amount, fee_amount, created_at";
+
+const MERCHANT_COLUMNS: &str = "id, name, contact_email, active, created_at, updated_at";
+
+const TRANSACTION_COLUMNS: &str =
+    "id, gift_card_id, kind, amount, currency, merchant_id, merchant, original_transaction_id, transaction_date";
+
+/// PostgreSQL repository, matching the schema in `migrations/postgres/`
+pub struct PostgresRepository {
+    pool: PgPool,
+}
+
+impl PostgresRepository {
+    /// Open a connection pool for `database_url`
+    pub async fn connect(database_url: &str, max_connections: u32) -> Result<Self, AppError> {
+        let mut options = PgConnectOptions::from_str(database_url)?;
+        options.log_statements(log::LevelFilter::Debug);
+
+        let pool = PgPoolOptions::new()
+            .max_connections(max_connections)
+            .connect_with(options)
+            .await?;
+
+        Ok(Self::new(pool))
+    }
+
+    /// Wrap an existing pool
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+fn card_from_row(row: &PgRow) -> Result<GiftCard, sqlx::Error> {
+    let currency: Currency = row.try_get("currency")?;
+    Ok(GiftCard {
+        id: row.try_get("id")?,
+        issuer_id: row.try_get("issuer_id")?,
+        issuer_name: row.try_get("issuer_name")?,
+        recipient_name: row.try_get("recipient_name")?,
+        recipient_phone: row.try_get("recipient_phone")?,
+        balance: Money::new(row.try_get("balance")?, currency),
+        initial_balance: Money::new(row.try_get("initial_balance")?, currency),
+        total_loaded: Money::new(row.try_get("total_loaded")?, currency),
+        held: Money::zero(currency),
+        expiration_date: row.try_get("expiration_date")?,
+        status: row.try_get("status")?,
+        status_before_expiry: row.try_get("status_before_expiry")?,
+        accepted_at: row.try_get("accepted_at")?,
+        replaces_id: row.try_get("replaces_id")?,
+        first_issued_at: row.try_get("first_issued_at")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+fn transaction_from_row(row: &PgRow) -> Result<GiftCardTransaction, sqlx::Error> {
+    Ok(GiftCardTransaction {
+        id: row.try_get("id")?,
+        gift_card_id: row.try_get("gift_card_id")?,
+        kind: row.try_get("kind")?,
+        amount: Money::new(row.try_get("amount")?, row.try_get("currency")?),
+        merchant_id: row.try_get("merchant_id")?,
+        merchant: row.try_get("merchant")?,
+        original_transaction_id: row.try_get("original_transaction_id")?,
+        transaction_date: row.try_get("transaction_date")?,
+    })
+}
+
+async fn insert_card(tx: &mut Transaction<'_, Postgres>, card: &GiftCard) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO gift_cards (
+            id, issuer_id, issuer_name, recipient_name, recipient_phone,
+            balance, initial_balance, total_loaded, currency, expiration_date,
+            status, status_before_expiry, accepted_at, replaces_id, first_issued_at, created_at, updated_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17)
+        "#,
+    )
+    .bind(card.id)
+    .bind(card.issuer_id)
+    .bind(&card.issuer_name)
+    .bind(&card.recipient_name)
+    .bind(&card.recipient_phone)
+    .bind(card.balance.amount())
+    .bind(card.initial_balance.amount())
+    .bind(card.total_loaded.amount())
+    .bind(card.currency())
+    .bind(card.expiration_date)
+    .bind(card.status)
+    .bind(card.status_before_expiry)
+    .bind(card.accepted_at)
+    .bind(card.replaces_id)
+    .bind(card.first_issued_at)
+    .bind(card.created_at)
+    .bind(card.updated_at)
+    .execute(tx)
+    .await?;
+
+    Ok(())
+}
+
+async fn update_card(tx: &mut Transaction<'_, Postgres>, card: &GiftCard) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE gift_cards
+        SET issuer_name = $2, recipient_name = $3, recipient_phone = $4,
+            balance = $5, initial_balance = $6, total_loaded = $7, currency = $8,
+            expiration_date = $9, status = $10, status_before_expiry = $11, accepted_at = $12, updated_at = $13
+        WHERE id = $1
+        "#,
+    )
+    .bind(card.id)
+    .bind(&card.issuer_name)
+    .bind(&card.recipient_name)
+    .bind(&card.recipient_phone)
+    .bind(card.balance.amount())
+    .bind(card.initial_balance.amount())
+    .bind(card.total_loaded.amount())
+    .bind(card.currency())
+    .bind(card.expiration_date)
+    .bind(card.status)
+    .bind(card.status_before_expiry)
+    .bind(card.accepted_at)
+    .bind(card.updated_at)
+    .execute(tx)
+    .await?;
+
+    Ok(())
+}
+
+async fn insert_transaction(
+    tx: &mut Transaction<'_, Postgres>,
+    txn: &GiftCardTransaction,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO gift_card_transactions (
+            id, gift_card_id, kind, amount, currency, merchant_id, merchant,
+            original_transaction_id, transaction_date
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        "#,
+    )
+    .bind(txn.id)
+    .bind(txn.gift_card_id)
+    .bind(txn.kind)
+    .bind(txn.amount.amount())
+    .bind(txn.amount.currency())
+    .bind(txn.merchant_id)
+    .bind(&txn.merchant)
+    .bind(txn.original_transaction_id)
+    .bind(txn.transaction_date)
+    .execute(tx)
+    .await?;
+
+    Ok(())
+}
+
+async fn insert_hold(tx: &mut Transaction<'_, Postgres>, hold: &Hold) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO holds (
+            id, gift_card_id, amount, captured_amount, currency, merchant_id, merchant,
+            status, transaction_id, expires_at, created_at, updated_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+        "#,
+    )
+    .bind(hold.id)
+    .bind(hold.gift_card_id)
+    .bind(hold.amount.amount())
+    .bind(hold.captured_amount.map(Money::amount))
+    .bind(hold.amount.currency())
+    .bind(hold.merchant_id)
+    .bind(&hold.merchant)
+    .bind(hold.status)
+    .bind(hold.transaction_id)
+    .bind(hold.expires_at)
+    .bind(hold.created_at)
+    .bind(hold.updated_at)
+    .execute(tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Settle a hold, returning whether it was still active
+async fn update_hold(tx: &mut Transaction<'_, Postgres>, hold: &Hold) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        UPDATE holds
+        SET captured_amount = $2, status = $3, transaction_id = $4, updated_at = $5
+        WHERE id = $1 AND status = $6
+        "#,
+    )
+    .bind(hold.id)
+    .bind(hold.captured_amount.map(Money::amount))
+    .bind(hold.status)
+    .bind(hold.transaction_id)
+    .bind(hold.updated_at)
+    .bind(HoldStatus::Active)
+    .execute(tx)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+async fn insert_entry(tx: &mut Transaction<'_, Postgres>, entry: &JournalEntry) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO ledger_entries (id, kind, description, currency, transaction_id, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+    )
+    .bind(entry.id)
+    .bind(entry.kind)
+    .bind(&entry.description)
+    .bind(entry.currency)
+    .bind(entry.transaction_id)
+    .bind(entry.created_at)
+    .execute(&mut *tx)
+    .await?;
+
+    for (line_no, line) in entry.lines.iter().enumerate() {
+        sqlx::query(
+            r#"
+            INSERT INTO ledger_lines (entry_id, line_no, account, gift_card_id, amount)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(entry.id)
+        .bind(line_no as i16)
+        .bind(line.account)
+        .bind(line.gift_card_id)
+        .bind(line.amount)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    Ok(())
+}
+
+async fn ledger_balance(executor: impl PgExecutor<'_>, gift_card_id: Uuid) -> Result<i64, sqlx::Error> {
+    // SUM over BIGINT is NUMERIC in PostgreSQL
+    let debits: i64 = sqlx::query_scalar(
+        "SELECT CAST(COALESCE(SUM(amount), 0) AS BIGINT) FROM ledger_lines \
+         WHERE gift_card_id = $1 AND account = $2",
+    )
+    .bind(gift_card_id)
+    .bind(LedgerAccount::CardLiability)
+    .fetch_one(executor)
+    .await?;
+
+    // Card liability carries a credit balance
+    Ok(-debits)
+}
+
+/// Sum of the open holds on a card, in minor units
+async fn held_amount(executor: impl PgExecutor<'_>, gift_card_id: Uuid, now: DateTime<Utc>) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT CAST(COALESCE(SUM(amount), 0) AS BIGINT) FROM holds \
+         WHERE gift_card_id = $1 AND status = $2 AND expires_at > $3",
+    )
+    .bind(gift_card_id)
+    .bind(HoldStatus::Active)
+    .bind(now)
+    .fetch_one(executor)
+    .await
+}
+
+fn account_from_row(row: &PgRow) -> Result<Account, sqlx::Error> {
+    Ok(Account {
+        id: row.try_get("id")?,
+        email: row.try_get("email")?,
+        name: row.try_get("name")?,
+        role: row.try_get("role")?,
+        merchant_id: row.try_get("merchant_id")?,
+        phone: row.try_get("phone")?,
+        password_hash: row.try_get("password_hash")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+fn acceptance_code_from_row(row: &PgRow) -> Result<AcceptanceCode, sqlx::Error> {
+    Ok(AcceptanceCode {
+        id: row.try_get("id")?,
+        gift_card_id: row.try_get("gift_card_id")?,
+        code_hash: row.try_get("code_hash")?,
+        attempts: row.try_get("attempts")?,
+        expires_at: row.try_get("expires_at")?,
+        used_at: row.try_get("used_at")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+fn live_qr_secret_from_row(row: &PgRow) -> Result<LiveQrSecret, sqlx::Error> {
+    Ok(LiveQrSecret {
+        gift_card_id: row.try_get("gift_card_id")?,
+        secret: row.try_get("secret")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+fn card_code_from_row(row: &PgRow) -> Result<CardCode, sqlx::Error> {
+    Ok(CardCode {
+        gift_card_id: row.try_get("gift_card_id")?,
+        code: row.try_get("code")?,
+        pin_hash: row.try_get("pin_hash")?,
+        pin_attempts: row.try_get("pin_attempts")?,
+        locked_until: row.try_get("locked_until")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+fn batch_from_row(row: &PgRow) -> Result<IssuanceBatch, sqlx::Error> {
+    Ok(IssuanceBatch {
+        id: row.try_get("id")?,
+        issuer_id: row.try_get("issuer_id")?,
+        mode: row.try_get("mode")?,
+        status: row.try_get("status")?,
+        total_rows: row.try_get("total_rows")?,
+        issued: row.try_get("issued")?,
+        failed: row.try_get("failed")?,
+        results: decode_batch_results(row.try_get("results")?)?,
+        error: row.try_get("error")?,
+        created_at: row.try_get("created_at")?,
+        completed_at: row.try_get("completed_at")?,
+    })
+}
+
+fn audit_from_row(row: &PgRow) -> Result<CardAudit, sqlx::Error> {
+    let currency: Option<Currency> = row.try_get("currency")?;
+    let money = |column| {
+        let amount: Option<i64> = row.try_get(column)?;
+        Ok::<_, sqlx::Error>(amount.zip(currency).map(|(amount, currency)| Money::new(amount, currency)))
+    };
+    Ok(CardAudit {
+        id: row.try_get("id")?,
+        gift_card_id: row.try_get("gift_card_id")?,
+        account_id: row.try_get("account_id")?,
+        action: row.try_get("action")?,
+        reason: row.try_get("reason")?,
+        previous_status: row.try_get("previous_status")?,
+        status: row.try_get("status")?,
+        previous_expiration_date: row.try_get("previous_expiration_date")?,
+        expiration_date: row.try_get("expiration_date")?,
+        restored: money("restored_amount")?,
+        fee: money("fee_amount")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+async fn insert_audit(tx: &mut Transaction<'_, Postgres>, audit: &CardAudit) -> Result<(), sqlx::Error> {
+    sqlx::query(&format!(
+        "INSERT INTO card_audit_log ({}) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)",
+        AUDIT_COLUMNS
+    ))
+    .bind(audit.id)
+    .bind(audit.gift_card_id)
+    .bind(audit.account_id)
+    .bind(audit.action)
+    .bind(&audit.reason)
+    .bind(audit.previous_status)
+    .bind(audit.status)
+    .bind(audit.previous_expiration_date)
+    .bind(audit.expiration_date)
+    .bind(audit.restored.or(audit.fee).map(|money| money.currency()))
+    .bind(audit.restored.map(Money::amount))
+    .bind(audit.fee.map(Money::amount))
+    .bind(audit.created_at)
+    .execute(tx)
+    .await?;
+
+    Ok(())
+}
+
+fn merchant_from_row(row: &PgRow) -> Result<Merchant, sqlx::Error> {
+    Ok(Merchant {
+        id: row.try_get("id")?,
+        name: row.try_get("name")?,
+        contact_email: row.try_get("contact_email")?,
+        active: row.try_get("active")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+fn hold_from_row(row: &PgRow) -> Result<Hold, sqlx::Error> {
+    let currency: Currency = row.try_get("currency")?;
+    Ok(Hold {
+        id: row.try_get("id")?,
+        gift_card_id: row.try_get("gift_card_id")?,
+        amount: Money::new(row.try_get("amount")?, currency),
+        captured_amount: row.try_get::<Option<i64>, _>("captured_amount")?.map(|amount| Money::new(amount, currency)),
+        merchant_id: row.try_get("merchant_id")?,
+        merchant: row.try_get("merchant")?,
+        status: row.try_get("status")?,
+        transaction_id: row.try_get("transaction_id")?,
+        expires_at: row.try_get("expires_at")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+async fn find_transaction(
+    executor: impl PgExecutor<'_>,
+    id: Uuid,
+) -> Result<Option<GiftCardTransaction>, sqlx::Error> {
+    let row = sqlx::query(&format!("SELECT {} FROM gift_card_transactions WHERE id = $1", TRANSACTION_COLUMNS))
+        .bind(id)
+        .fetch_optional(executor)
+        .await?;
+
+    row.as_ref().map(transaction_from_row).transpose()
+}
+
+async fn refunded_amount(executor: impl PgExecutor<'_>, transaction_id: Uuid) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT CAST(COALESCE(SUM(amount), 0) AS BIGINT) FROM gift_card_transactions \
+         WHERE original_transaction_id = $1 AND kind = $2",
+    )
+    .bind(transaction_id)
+    .bind(TransactionKind::Refund)
+    .fetch_one(executor)
+    .await
+}
+
+fn entry_line_from_row(row: &PgRow) -> Result<(JournalEntry, JournalLine), sqlx::Error> {
+    let entry = JournalEntry {
+        id: row.try_get("id")?,
+        kind: row.try_get("kind")?,
+        description: row.try_get("description")?,
+        currency: row.try_get("currency")?,
+        transaction_id: row.try_get("transaction_id")?,
+        created_at: row.try_get("created_at")?,
+        lines: Vec::new(),
+    };
+    let line = JournalLine {
+        account: row.try_get("account")?,
+        gift_card_id: row.try_get("gift_card_id")?,
+        amount: row.try_get("amount")?,
+    };
+    Ok((entry, line))
+}
+
+fn idempotency_record_from_row(row: &PgRow) -> Result<IdempotencyRecord, sqlx::Error> {
+    Ok(IdempotencyRecord {
+        key: row.try_get("idempotency_key")?,
+        fingerprint: row.try_get("fingerprint")?,
+        response_status: row.try_get::<Option<i16>, _>("response_status")?.map(|status| status as u16),
+        response_body: row.try_get("response_body")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+#[async_trait]
+impl GiftCardRepository for PostgresRepository {
+    async fn find_card(&self, id: Uuid) -> Result<Option<GiftCard>, AppError> {
+        let row = sqlx::query(&format!("SELECT {} FROM gift_cards WHERE id = $1", CARD_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        let Some(mut card) = row.as_ref().map(card_from_row).transpose()? else {
+            return Ok(None);
+        };
+        card.held = Money::new(held_amount(&self.pool, card.id, Utc::now()).await?, card.currency());
+        Ok(Some(card))
+    }
+
+    async fn list_cards_by_recipient(
+        &self,
+        recipient_phone: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<GiftCard>, AppError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM gift_cards WHERE recipient_phone = $1 \
+             ORDER BY created_at DESC LIMIT $2 OFFSET $3",
+            CARD_COLUMNS
+        ))
+        .bind(recipient_phone)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut cards = rows.iter().map(card_from_row).collect::<Result<Vec<_>, _>>()?;
+        let now = Utc::now();
+        for card in &mut cards {
+            card.held = Money::new(held_amount(&self.pool, card.id, now).await?, card.currency());
+        }
+        Ok(cards)
+    }
+
+    async fn list_transactions(
+        &self,
+        gift_card_id: Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<GiftCardTransaction>, AppError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM gift_card_transactions WHERE gift_card_id = $1 \
+             ORDER BY transaction_date DESC LIMIT $2 OFFSET $3",
+            TRANSACTION_COLUMNS
+        ))
+        .bind(gift_card_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(transaction_from_row).collect::<Result<_, _>>()?)
+    }
+
+    async fn find_transaction(&self, id: Uuid) -> Result<Option<GiftCardTransaction>, AppError> {
+        Ok(find_transaction(&self.pool, id).await?)
+    }
+
+    async fn find_hold(&self, id: Uuid) -> Result<Option<Hold>, AppError> {
+        let row = sqlx::query(&format!("SELECT {} FROM holds WHERE id = $1", HOLD_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.as_ref().map(hold_from_row).transpose()?)
+    }
+
+    async fn refunded_amount(&self, transaction_id: Uuid) -> Result<i64, AppError> {
+        Ok(refunded_amount(&self.pool, transaction_id).await?)
+    }
+
+    async fn loaded_amount(&self, gift_card_id: Uuid, since: DateTime<Utc>) -> Result<i64, AppError> {
+        let loaded = sqlx::query_scalar(
+            "SELECT CAST(COALESCE(SUM(amount), 0) AS BIGINT) FROM gift_card_transactions \
+             WHERE gift_card_id = $1 AND kind = $2 AND transaction_date >= $3",
+        )
+        .bind(gift_card_id)
+        .bind(TransactionKind::Load)
+        .bind(since)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(loaded)
+    }
+
+    async fn list_journal_entries(
+        &self,
+        gift_card_id: Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<JournalEntry>, AppError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT e.id, e.kind, e.description, e.currency, e.transaction_id, e.created_at,
+                   l.account, l.gift_card_id, l.amount
+            FROM ledger_entries e
+            JOIN (
+                SELECT DISTINCT e.id, e.created_at
+                FROM ledger_entries e
+                JOIN ledger_lines l ON l.entry_id = e.id
+                WHERE l.gift_card_id = $1
+                ORDER BY e.created_at DESC, e.id
+                LIMIT $2 OFFSET $3
+            ) page ON page.id = e.id
+            JOIN ledger_lines l ON l.entry_id = e.id
+            ORDER BY e.created_at DESC, e.id, l.line_no
+            "#,
+        )
+        .bind(gift_card_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let rows = rows.iter().map(entry_line_from_row).collect::<Result<_, _>>()?;
+        Ok(group_entries(rows))
+    }
+
+    async fn ledger_balance(&self, gift_card_id: Uuid) -> Result<i64, AppError> {
+        Ok(ledger_balance(&self.pool, gift_card_id).await?)
+    }
+
+    async fn insert_account(&self, account: &Account) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            INSERT INTO accounts (
+                id, email, name, role, merchant_id, phone, password_hash, created_at, updated_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#,
+        )
+        .bind(account.id)
+        .bind(&account.email)
+        .bind(&account.name)
+        .bind(account.role)
+        .bind(account.merchant_id)
+        .bind(&account.phone)
+        .bind(&account.password_hash)
+        .bind(account.created_at)
+        .bind(account.updated_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e {
+            // unique_violation on the email index
+            sqlx::Error::Database(ref db) if db.code().as_deref() == Some("23505") => {
+                AppError::ConflictError("An account with this email already exists".to_string())
+            }
+            e => e.into(),
+        })?;
+
+        Ok(())
+    }
+
+    async fn find_account(&self, id: Uuid) -> Result<Option<Account>, AppError> {
+        let row = sqlx::query(&format!("SELECT {} FROM accounts WHERE id = $1", ACCOUNT_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.as_ref().map(account_from_row).transpose()?)
+    }
+
+    async fn find_account_by_email(&self, email: &str) -> Result<Option<Account>, AppError> {
+        let row = sqlx::query(&format!("SELECT {} FROM accounts WHERE email = $1", ACCOUNT_COLUMNS))
+            .bind(email)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.as_ref().map(account_from_row).transpose()?)
+    }
+
+    async fn insert_acceptance_code(&self, code: &AcceptanceCode) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            INSERT INTO acceptance_codes (id, gift_card_id, code_hash, attempts, expires_at, used_at, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+        )
+        .bind(code.id)
+        .bind(code.gift_card_id)
+        .bind(&code.code_hash)
+        .bind(code.attempts)
+        .bind(code.expires_at)
+        .bind(code.used_at)
+        .bind(code.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn find_latest_acceptance_code(&self, gift_card_id: Uuid) -> Result<Option<AcceptanceCode>, AppError> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM acceptance_codes WHERE gift_card_id = $1 ORDER BY created_at DESC LIMIT 1",
+            ACCEPTANCE_CODE_COLUMNS
+        ))
+        .bind(gift_card_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(acceptance_code_from_row).transpose()?)
+    }
+
+    async fn claim_acceptance_attempt(&self, id: Uuid, max_attempts: i32) -> Result<bool, AppError> {
+        // Counted in one statement so concurrent guesses cannot exceed the limit
+        let claimed = sqlx::query(
+            "UPDATE acceptance_codes SET attempts = attempts + 1 \
+             WHERE id = $1 AND used_at IS NULL AND attempts < $2",
+        )
+        .bind(id)
+        .bind(max_attempts)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(claimed > 0)
+    }
+
+    async fn use_acceptance_code(&self, id: Uuid, now: DateTime<Utc>) -> Result<bool, AppError> {
+        let used = sqlx::query("UPDATE acceptance_codes SET used_at = $2 WHERE id = $1 AND used_at IS NULL")
+            .bind(id)
+            .bind(now)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+
+        Ok(used > 0)
+    }
+
+    async fn insert_live_qr_secret(&self, secret: &LiveQrSecret) -> Result<LiveQrSecret, AppError> {
+        // A card that already has a secret keeps it, so concurrent calls agree
+        sqlx::query(
+            r#"
+            INSERT INTO live_qr_secrets (gift_card_id, secret, created_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (gift_card_id) DO NOTHING
+            "#,
+        )
+        .bind(secret.gift_card_id)
+        .bind(&secret.secret)
+        .bind(secret.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        self.find_live_qr_secret(secret.gift_card_id)
+            .await?
+            .ok_or_else(|| AppError::InternalServerError("Live QR secret was not stored".to_string()))
+    }
+
+    async fn find_live_qr_secret(&self, gift_card_id: Uuid) -> Result<Option<LiveQrSecret>, AppError> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM live_qr_secrets WHERE gift_card_id = $1",
+            LIVE_QR_SECRET_COLUMNS
+        ))
+        .bind(gift_card_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(live_qr_secret_from_row).transpose()?)
+    }
+
+    async fn insert_card_code(&self, code: &CardCode) -> Result<CardCode, AppError> {
+        // A card that already has a code keeps it, so concurrent calls agree
+        sqlx::query(
+            r#"
+            INSERT INTO card_codes (gift_card_id, code, pin_hash, pin_attempts, locked_until, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (gift_card_id) DO NOTHING
+            "#,
+        )
+        .bind(code.gift_card_id)
+        .bind(&code.code)
+        .bind(&code.pin_hash)
+        .bind(code.pin_attempts)
+        .bind(code.locked_until)
+        .bind(code.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        self.find_card_code(code.gift_card_id)
+            .await?
+            .ok_or_else(|| AppError::InternalServerError("Short code was not stored".to_string()))
+    }
+
+    async fn find_card_code(&self, gift_card_id: Uuid) -> Result<Option<CardCode>, AppError> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM card_codes WHERE gift_card_id = $1",
+            CARD_CODE_COLUMNS
+        ))
+        .bind(gift_card_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(card_code_from_row).transpose()?)
+    }
+
+    async fn find_card_code_by_code(&self, code: &str) -> Result<Option<CardCode>, AppError> {
+        let row = sqlx::query(&format!("SELECT {} FROM card_codes WHERE code = $1", CARD_CODE_COLUMNS))
+            .bind(code)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.as_ref().map(card_code_from_row).transpose()?)
+    }
+
+    async fn set_card_pin(&self, gift_card_id: Uuid, pin_hash: Option<&str>) -> Result<(), AppError> {
+        let result =
+            sqlx::query("UPDATE card_codes SET pin_hash = $2, pin_attempts = 0, locked_until = NULL WHERE gift_card_id = $1")
+                .bind(gift_card_id)
+                .bind(pin_hash)
+                .execute(&self.pool)
+                .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFoundError("Card code not found".to_string()));
+        }
+        Ok(())
+    }
+
+    async fn claim_pin_attempt(
+        &self,
+        gift_card_id: Uuid,
+        max_attempts: i32,
+        now: DateTime<Utc>,
+        lock_until: DateTime<Utc>,
+    ) -> Result<bool, AppError> {
+        // Counted in one statement so concurrent guesses cannot exceed the limit
+        let claimed = sqlx::query(
+            r#"
+            UPDATE card_codes
+            SET locked_until = CASE WHEN pin_attempts + 1 >= $2 THEN $3 ELSE NULL END,
+                pin_attempts = CASE WHEN pin_attempts + 1 >= $2 THEN 0 ELSE pin_attempts + 1 END
+            WHERE gift_card_id = $1 AND (locked_until IS NULL OR locked_until <= $4)
+            "#,
+        )
+        .bind(gift_card_id)
+        .bind(max_attempts)
+        .bind(lock_until)
+        .bind(now)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(claimed > 0)
+    }
+
+    async fn reset_pin_attempts(&self, gift_card_id: Uuid) -> Result<(), AppError> {
+        sqlx::query("UPDATE card_codes SET pin_attempts = 0, locked_until = NULL WHERE gift_card_id = $1")
+            .bind(gift_card_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn insert_batch(&self, batch: &IssuanceBatch) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            INSERT INTO issuance_batches (id, issuer_id, mode, status, total_rows, issued, failed, results, error, created_at, completed_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            "#,
+        )
+        .bind(batch.id)
+        .bind(batch.issuer_id)
+        .bind(batch.mode)
+        .bind(batch.status)
+        .bind(batch.total_rows)
+        .bind(batch.issued)
+        .bind(batch.failed)
+        .bind(encode_batch_results(&batch.results)?)
+        .bind(&batch.error)
+        .bind(batch.created_at)
+        .bind(batch.completed_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn update_batch(&self, batch: &IssuanceBatch) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            UPDATE issuance_batches
+            SET status = $2, issued = $3, failed = $4, results = $5, error = $6, completed_at = $7
+            WHERE id = $1
+            "#,
+        )
+        .bind(batch.id)
+        .bind(batch.status)
+        .bind(batch.issued)
+        .bind(batch.failed)
+        .bind(encode_batch_results(&batch.results)?)
+        .bind(&batch.error)
+        .bind(batch.completed_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn find_batch(&self, id: Uuid) -> Result<Option<IssuanceBatch>, AppError> {
+        let row = sqlx::query(&format!("SELECT {} FROM issuance_batches WHERE id = $1", BATCH_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.as_ref().map(batch_from_row).transpose()?)
+    }
+
+    async fn list_card_audits(&self, gift_card_id: Uuid, limit: i64, offset: i64) -> Result<Vec<CardAudit>, AppError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM card_audit_log WHERE gift_card_id = $1 ORDER BY created_at DESC, id LIMIT $2 OFFSET $3",
+            AUDIT_COLUMNS
+        ))
+        .bind(gift_card_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(audit_from_row).collect::<Result<_, _>>()?)
+    }
+
+    async fn export_cards(
+        &self,
+        filter: &ExportFilter,
+        after: Option<(DateTime<Utc>, Uuid)>,
+        limit: i64,
+    ) -> Result<Vec<GiftCard>, AppError> {
+        let mut query = QueryBuilder::<Postgres>::new(format!("SELECT {} FROM gift_cards WHERE TRUE", CARD_COLUMNS));
+        if let Some(from) = filter.from {
+            query.push(" AND created_at >= ").push_bind(from);
+        }
+        if let Some(to) = filter.to {
+            query.push(" AND created_at < ").push_bind(to);
+        }
+        if let Some(status) = filter.status {
+            query.push(" AND status = ").push_bind(status);
+        }
+        if let Some(issuer_id) = filter.issuer_id {
+            query.push(" AND issuer_id = ").push_bind(issuer_id);
+        }
+        if let Some((created_at, id)) = after {
+            query.push(" AND (created_at, id) > (").push_bind(created_at).push(", ").push_bind(id).push(")");
+        }
+        query.push(" ORDER BY created_at, id LIMIT ").push_bind(limit);
+
+        let rows = query.build().fetch_all(&self.pool).await?;
+        Ok(rows.iter().map(card_from_row).collect::<Result<_, _>>()?)
+    }
+
+    async fn export_transactions(
+        &self,
+        filter: &ExportFilter,
+        after: Option<(DateTime<Utc>, Uuid)>,
+        limit: i64,
+    ) -> Result<Vec<GiftCardTransaction>, AppError> {
+        let columns: Vec<String> = TRANSACTION_COLUMNS.split(", ").map(|column| format!("t.{}", column)).collect();
+        let mut query = QueryBuilder::<Postgres>::new(format!(
+            "SELECT {} FROM gift_card_transactions t JOIN gift_cards c ON c.id = t.gift_card_id WHERE TRUE",
+            columns.join(", ")
+        ));
+        if let Some(from) = filter.from {
+            query.push(" AND t.transaction_date >= ").push_bind(from);
+        }
+        if let Some(to) = filter.to {
+            query.push(" AND t.transaction_date < ").push_bind(to);
+        }
+        if let Some(kind) = filter.kind {
+            query.push(" AND t.kind = ").push_bind(kind);
+        }
+        if let Some(issuer_id) = filter.issuer_id {
+            query.push(" AND c.issuer_id = ").push_bind(issuer_id);
+        }
+        if let Some(merchant_id) = filter.merchant_id {
+            query.push(" AND t.merchant_id = ").push_bind(merchant_id);
+        }
+        if let Some((transaction_date, id)) = after {
+            query
+                .push(" AND (t.transaction_date, t.id) > (")
+                .push_bind(transaction_date)
+                .push(", ")
+                .push_bind(id)
+                .push(")");
+        }
+        query.push(" ORDER BY t.transaction_date, t.id LIMIT ").push_bind(limit);
+
+        let rows = query.build().fetch_all(&self.pool).await?;
+        Ok(rows.iter().map(transaction_from_row).collect::<Result<_, _>>()?)
+    }
+
+    async fn find_expiring_cards(
+        &self,
+        now: DateTime<Utc>,
+        after: Option<(DateTime<Utc>, Uuid)>,
+        limit: i64,
+    ) -> Result<Vec<GiftCard>, AppError> {
+        let mut query = QueryBuilder::<Postgres>::new(format!("SELECT {} FROM gift_cards WHERE status IN (", CARD_COLUMNS));
+        let mut statuses = query.separated(", ");
+        for status in CardStatus::expirable() {
+            statuses.push_bind(status);
+        }
+        query.push(") AND expiration_date <= ").push_bind(now);
+        if let Some((expiration_date, id)) = after {
+            query.push(" AND (expiration_date, id) > (").push_bind(expiration_date).push(", ").push_bind(id).push(")");
+        }
+        query.push(" ORDER BY expiration_date, id LIMIT ").push_bind(limit);
+
+        let rows = query.build().fetch_all(&self.pool).await?;
+        Ok(rows.iter().map(card_from_row).collect::<Result<_, _>>()?)
+    }
+
+    async fn find_open_holds(&self, gift_card_id: Uuid, now: DateTime<Utc>) -> Result<Vec<Hold>, AppError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM holds WHERE gift_card_id = $1 AND status = $2 AND expires_at > $3 ORDER BY created_at, id",
+            HOLD_COLUMNS
+        ))
+        .bind(gift_card_id)
+        .bind(HoldStatus::Active)
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(hold_from_row).collect::<Result<_, _>>()?)
+    }
+
+    async fn insert_merchant(&self, merchant: &Merchant) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            INSERT INTO merchants (id, name, contact_email, active, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(merchant.id)
+        .bind(&merchant.name)
+        .bind(&merchant.contact_email)
+        .bind(merchant.active)
+        .bind(merchant.created_at)
+        .bind(merchant.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn find_merchant(&self, id: Uuid) -> Result<Option<Merchant>, AppError> {
+        let row = sqlx::query(&format!("SELECT {} FROM merchants WHERE id = $1", MERCHANT_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.as_ref().map(merchant_from_row).transpose()?)
+    }
+
+    async fn list_merchants(&self, limit: i64, offset: i64) -> Result<Vec<Merchant>, AppError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM merchants ORDER BY name, id LIMIT $1 OFFSET $2",
+            MERCHANT_COLUMNS
+        ))
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(merchant_from_row).collect::<Result<_, _>>()?)
+    }
+
+    async fn update_merchant(&self, merchant: &Merchant) -> Result<(), AppError> {
+        let result = sqlx::query(
+            "UPDATE merchants SET name = $2, contact_email = $3, active = $4, updated_at = $5 WHERE id = $1",
+        )
+        .bind(merchant.id)
+        .bind(&merchant.name)
+        .bind(&merchant.contact_email)
+        .bind(merchant.active)
+        .bind(merchant.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFoundError("Merchant not found".to_string()));
+        }
+        Ok(())
+    }
+
+    async fn list_merchant_transactions(
+        &self,
+        merchant_id: Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<GiftCardTransaction>, AppError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM gift_card_transactions WHERE merchant_id = $1 \
+             ORDER BY transaction_date DESC LIMIT $2 OFFSET $3",
+            TRANSACTION_COLUMNS
+        ))
+        .bind(merchant_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(transaction_from_row).collect::<Result<_, _>>()?)
+    }
+
+    async fn update_cards(&self, ids: &[Uuid], update: CardUpdateFn) -> Result<CardUpdate, AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        let select = format!("SELECT {} FROM gift_cards WHERE id = $1 FOR UPDATE", CARD_COLUMNS);
+        let now = Utc::now();
+        let mut locked = Vec::new();
+        for id in lock_order(ids) {
+            let row = sqlx::query(&select)
+                .bind(id)
+                .fetch_optional(&mut tx)
+                .await?
+                .ok_or_else(|| AppError::NotFoundError("Gift card not found".to_string()))?;
+            let mut card = card_from_row(&row)?;
+            card.held = Money::new(held_amount(&mut tx, card.id, now).await?, card.currency());
+            locked.push(card);
+        }
+
+        let cards = ids
+            .iter()
+            .filter_map(|id| locked.iter().find(|card| card.id == *id).cloned())
+            .collect();
+
+        // Dropping `tx` on error rolls the transaction back
+        let changes = update(cards)?;
+        check_entries(&changes)?;
+
+        for card in &changes.cards {
+            if ids.contains(&card.id) {
+                update_card(&mut tx, card).await?;
+            } else {
+                insert_card(&mut tx, card).await?;
+            }
+        }
+        for txn in &changes.transactions {
+            insert_transaction(&mut tx, txn).await?;
+        }
+        for entry in &changes.entries {
+            insert_entry(&mut tx, entry).await?;
+        }
+        for hold in &changes.holds {
+            if hold.status == HoldStatus::Active {
+                insert_hold(&mut tx, hold).await?;
+            } else if !update_hold(&mut tx, hold).await? {
+                return Err(AppError::ConflictError("Hold is no longer active".to_string()));
+            }
+        }
+        for audit in &changes.audits {
+            insert_audit(&mut tx, audit).await?;
+        }
+        for card in &changes.cards {
+            check_ledger_balance(card, ledger_balance(&mut tx, card.id).await?)?;
+            check_available(card, held_amount(&mut tx, card.id, now).await?)?;
+        }
+        for txn in changes.transactions.iter().filter(|txn| txn.kind == TransactionKind::Refund) {
+            let original_id = txn.original_transaction_id.unwrap_or_default();
+            let original = find_transaction(&mut tx, original_id).await?;
+            check_refund(txn, original.as_ref(), refunded_amount(&mut tx, original_id).await?)?;
+        }
+
+        tx.commit().await?;
+        Ok(changes)
+    }
+
+    async fn claim_idempotency_key(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        now: DateTime<Utc>,
+        stale_before: DateTime<Utc>,
+    ) -> Result<Option<IdempotencyRecord>, AppError> {
+        // A record released between the insert and the select is claimed on the next pass
+        for _ in 0..2 {
+            // Insert the key, or take over a stale record; nothing changes if a
+            // current record exists
+            let claimed = sqlx::query(
+                r#"
+                INSERT INTO idempotency_keys (idempotency_key, fingerprint, created_at)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (idempotency_key) DO UPDATE
+                SET fingerprint = EXCLUDED.fingerprint, response_status = NULL,
+                    response_body = NULL, created_at = EXCLUDED.created_at
+                WHERE idempotency_keys.created_at < $4
+                "#,
+            )
+            .bind(key)
+            .bind(fingerprint)
+            .bind(now)
+            .bind(stale_before)
+            .execute(&self.pool)
+            .await?
+            .rows_affected()
+                > 0;
+            if claimed {
+                return Ok(None);
+            }
+
+            let row = sqlx::query(
+                "SELECT idempotency_key, fingerprint, response_status, response_body, created_at \
+                 FROM idempotency_keys WHERE idempotency_key = $1",
+            )
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await?;
+
+            if let Some(row) = row {
+                return Ok(Some(idempotency_record_from_row(&row)?));
+            }
+        }
+
+        Err(AppError::ConflictError("Idempotency key is in use, please retry".to_string()))
+    }
+
+    async fn complete_idempotency_key(&self, key: &str, status: u16, body: &str) -> Result<(), AppError> {
+        sqlx::query("UPDATE idempotency_keys SET response_status = $2, response_body = $3 WHERE idempotency_key = $1")
+            .bind(key)
+            .bind(status as i16)
+            .bind(body)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn release_idempotency_key(&self, key: &str) -> Result<(), AppError> {
+        sqlx::query("DELETE FROM idempotency_keys WHERE idempotency_key = $1 AND response_status IS NULL")
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,464 @@
+//! Checks run against a real database for each SQL dialect
+//!
+//! These tests are ignored by default. Point `DATABASE_URL` at a scratch
+//! Postgres or MySQL database and run `cargo test -- --ignored`; pending
+//! migrations are applied first, and the tests for the other dialect are
+//! skipped.
+
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::middleware::from_fn;
+use actix_web::{test, web, App};
+use chrono::{Duration, Utc};
+use serde_json::{json, Value};
+use std::env;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use super::*;
+use crate::handlers::test_support::{issuer_auth, test_config};
+use crate::middleware::idempotency::{idempotency, IDEMPOTENCY_KEY_HEADER, REPLAYED_HEADER};
+use crate::migrations::Migrator;
+use crate::models::card_status::CardStatus;
+use crate::models::hold::HoldStatus;
+use crate::models::money::Currency;
+
+/// Held while migrating, so tests starting together do not race to apply them
+static MIGRATION_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Connect to `DATABASE_URL` with migrations applied, or `None` if it does
+/// not name a `backend` database
+async fn repository(backend: Backend) -> Option<Arc<dyn GiftCardRepository>> {
+    let database_url = match env::var("DATABASE_URL") {
+        Ok(url) if Backend::from_url(&url) == Some(backend) => url,
+        _ => {
+            eprintln!("DATABASE_URL is not a {:?} database, skipping", backend);
+            return None;
+        }
+    };
+
+    {
+        let _guard = MIGRATION_LOCK.lock().await;
+        let mut migrator = Migrator::connect(&database_url).await.unwrap();
+        migrator.run().await.unwrap();
+    }
+    Some(connect(&database_url, 2).await.unwrap())
+}
+
+fn usd(cents: i64) -> Money {
+    Money::new(cents, Currency::USD)
+}
+
+/// A card with a recipient phone no other run will have used
+fn sample_card() -> GiftCard {
+    let now = Utc::now();
+    GiftCard {
+        id: Uuid::new_v4(),
+        issuer_id: None,
+        issuer_name: "Alice".to_string(),
+        recipient_name: "Bob".to_string(),
+        recipient_phone: format!("1{:09}", Uuid::new_v4().as_u128() % 1_000_000_000),
+        balance: usd(5000),
+        initial_balance: usd(5000),
+        total_loaded: usd(5000),
+        held: usd(0),
+        expiration_date: now + Duration::days(30),
+        status: CardStatus::Accepted,
+        status_before_expiry: None,
+        accepted_at: Some(now),
+        replaces_id: None,
+        first_issued_at: now,
+        created_at: now,
+        updated_at: now,
+    }
+}
+
+async fn issue(repo: &dyn GiftCardRepository) -> GiftCard {
+    let card = sample_card();
+    repo.insert_card(&card, &JournalEntry::issuance(&card)).await.unwrap();
+    card
+}
+
+async fn add_merchant(repo: &dyn GiftCardRepository) -> Merchant {
+    let merchant = Merchant::new(format!("Cafe {}", Uuid::new_v4()), None, Utc::now());
+    repo.insert_merchant(&merchant).await.unwrap();
+    merchant
+}
+
+async fn check_update_cards(repo: &dyn GiftCardRepository) {
+    let card = issue(repo).await;
+    let merchant = add_merchant(repo).await;
+
+    let found = repo.find_card(card.id).await.unwrap().unwrap();
+    assert_eq!(found.recipient_phone, card.recipient_phone);
+    assert_eq!(found.balance, usd(5000));
+    assert_eq!(repo.list_cards_by_recipient(&card.recipient_phone, 10, 0).await.unwrap().len(), 1);
+
+    // Nothing is written when the update fails or leaves the ledger behind
+    let result = repo
+        .update_cards(
+            &[card.id],
+            Box::new(|mut cards| {
+                cards[0].balance = usd(0);
+                Err(AppError::ValidationError("rejected".to_string()))
+            }),
+        )
+        .await;
+    assert!(result.is_err());
+    let result = repo
+        .update_cards(
+            &[card.id],
+            Box::new(|mut cards| {
+                cards[0].balance = usd(6000);
+                Ok(CardUpdate { cards, ..Default::default() })
+            }),
+        )
+        .await;
+    assert!(result.is_err());
+    assert_eq!(repo.find_card(card.id).await.unwrap().unwrap().balance, usd(5000));
+
+    let redeemed = merchant.clone();
+    repo.update_cards(
+        &[card.id],
+        Box::new(move |mut cards| {
+            let mut card = cards.remove(0);
+            card.balance = usd(3500);
+            let txn = GiftCardTransaction::redemption(card.id, usd(1500), &redeemed, Utc::now());
+            let entry = JournalEntry::redemption(&txn);
+            Ok(CardUpdate {
+                cards: vec![card],
+                transactions: vec![txn],
+                entries: vec![entry],
+                ..Default::default()
+            })
+        }),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(repo.find_card(card.id).await.unwrap().unwrap().balance, usd(3500));
+    assert_eq!(repo.ledger_balance(card.id).await.unwrap(), 3500);
+    assert_eq!(repo.list_journal_entries(card.id, 10, 0).await.unwrap().len(), 2);
+    let transactions = repo.list_transactions(card.id, 10, 0).await.unwrap();
+    assert_eq!(transactions.len(), 1);
+    assert_eq!(transactions[0].merchant_id, Some(merchant.id));
+    assert_eq!(repo.list_merchant_transactions(merchant.id, 10, 0).await.unwrap().len(), 1);
+
+    // A refund above what is left of its redemption is refused
+    let redemption = transactions[0].clone();
+    let result = repo
+        .update_cards(
+            &[card.id],
+            Box::new(move |mut cards| {
+                let mut card = cards.remove(0);
+                card.balance = usd(5500);
+                let txn = redemption.refund(usd(2000), Utc::now());
+                let entry = JournalEntry::refund(&txn);
+                Ok(CardUpdate {
+                    cards: vec![card],
+                    transactions: vec![txn],
+                    entries: vec![entry],
+                    ..Default::default()
+                })
+            }),
+        )
+        .await;
+    assert!(result.is_err());
+    assert_eq!(repo.ledger_balance(card.id).await.unwrap(), 3500);
+
+    // The state a card expired from is kept with it
+    repo.update_cards(
+        &[card.id],
+        Box::new(|mut cards| {
+            cards[0].status_before_expiry = Some(cards[0].status);
+            cards[0].status = CardStatus::Expired;
+            Ok(CardUpdate { cards, ..Default::default() })
+        }),
+    )
+    .await
+    .unwrap();
+    let found = repo.find_card(card.id).await.unwrap().unwrap();
+    assert_eq!((found.status, found.status_before_expiry), (CardStatus::Expired, Some(CardStatus::Accepted)));
+}
+
+async fn check_holds(repo: &dyn GiftCardRepository) {
+    let card = issue(repo).await;
+    let merchant = add_merchant(repo).await;
+    let now = Utc::now();
+    let hold = Hold {
+        id: Uuid::new_v4(),
+        gift_card_id: card.id,
+        amount: usd(2000),
+        captured_amount: None,
+        merchant_id: merchant.id,
+        merchant: merchant.name.clone(),
+        status: HoldStatus::Active,
+        transaction_id: None,
+        expires_at: now + Duration::minutes(15),
+        created_at: now,
+        updated_at: now,
+    };
+
+    let placed = hold.clone();
+    repo.update_cards(
+        &[card.id],
+        Box::new(move |mut cards| {
+            let mut card = cards.remove(0);
+            card.held = placed.amount;
+            Ok(CardUpdate { cards: vec![card], holds: vec![placed], ..Default::default() })
+        }),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(repo.find_card(card.id).await.unwrap().unwrap().held, usd(2000));
+    assert_eq!(repo.find_open_holds(card.id, Utc::now()).await.unwrap().len(), 1);
+    assert_eq!(repo.find_hold(hold.id).await.unwrap().unwrap().status, HoldStatus::Active);
+
+    // Spending into the held amount is refused
+    let spender = merchant.clone();
+    let result = repo
+        .update_cards(
+            &[card.id],
+            Box::new(move |mut cards| {
+                let mut card = cards.remove(0);
+                card.balance = usd(1000);
+                let txn = GiftCardTransaction::redemption(card.id, usd(4000), &spender, Utc::now());
+                let entry = JournalEntry::redemption(&txn);
+                Ok(CardUpdate {
+                    cards: vec![card],
+                    transactions: vec![txn],
+                    entries: vec![entry],
+                    ..Default::default()
+                })
+            }),
+        )
+        .await;
+    assert!(result.is_err());
+
+    let capture = |hold: Hold, merchant: Merchant| -> CardUpdateFn {
+        Box::new(move |mut cards| {
+            let mut card = cards.remove(0);
+            let mut hold = hold;
+            let now = Utc::now();
+            card.held = usd(0);
+            card.balance = usd(4000);
+            let txn = GiftCardTransaction::redemption(card.id, usd(1000), &merchant, now);
+            let entry = JournalEntry::redemption(&txn);
+            hold.status = HoldStatus::Captured;
+            hold.captured_amount = Some(usd(1000));
+            hold.transaction_id = Some(txn.id);
+            hold.updated_at = now;
+            Ok(CardUpdate {
+                cards: vec![card],
+                transactions: vec![txn],
+                entries: vec![entry],
+                holds: vec![hold],
+                ..Default::default()
+            })
+        })
+    };
+    repo.update_cards(&[card.id], capture(hold.clone(), merchant.clone())).await.unwrap();
+
+    let captured = repo.find_hold(hold.id).await.unwrap().unwrap();
+    assert_eq!(captured.status, HoldStatus::Captured);
+    assert_eq!(captured.captured_amount, Some(usd(1000)));
+    assert!(captured.transaction_id.is_some());
+    let found = repo.find_card(card.id).await.unwrap().unwrap();
+    assert_eq!(found.held, usd(0));
+    assert_eq!(found.balance, usd(4000));
+    assert!(repo.find_open_holds(card.id, Utc::now()).await.unwrap().is_empty());
+
+    // A hold that is no longer active cannot be settled twice
+    assert!(repo.update_cards(&[card.id], capture(hold, merchant)).await.is_err());
+    assert_eq!(repo.ledger_balance(card.id).await.unwrap(), 4000);
+}
+
+async fn check_merchants(repo: &dyn GiftCardRepository) {
+    let mut merchant = add_merchant(repo).await;
+
+    let found = repo.find_merchant(merchant.id).await.unwrap().unwrap();
+    assert_eq!(found.name, merchant.name);
+    assert!(found.active);
+    assert!(repo.find_merchant(Uuid::new_v4()).await.unwrap().is_none());
+
+    merchant.name = format!("Bakery {}", merchant.id);
+    merchant.contact_email = Some("owner@bakery.example".to_string());
+    merchant.active = false;
+    merchant.updated_at = Utc::now();
+    repo.update_merchant(&merchant).await.unwrap();
+
+    let found = repo.find_merchant(merchant.id).await.unwrap().unwrap();
+    assert_eq!(found.name, merchant.name);
+    assert_eq!(found.contact_email.as_deref(), Some("owner@bakery.example"));
+    assert!(!found.active);
+
+    let unknown = Merchant::new("Nowhere", None, Utc::now());
+    assert!(matches!(repo.update_merchant(&unknown).await, Err(AppError::NotFoundError(_))));
+}
+
+async fn check_card_codes(repo: &dyn GiftCardRepository) {
+    let card = issue(repo).await;
+    let now = Utc::now();
+
+    let code = repo.insert_card_code(&CardCode::new(card.id, None, now)).await.unwrap();
+    let again = repo.insert_card_code(&CardCode::new(card.id, None, now)).await.unwrap();
+    assert_eq!(again.code, code.code);
+    let found = repo.find_card_code_by_code(&code.code).await.unwrap().unwrap();
+    assert_eq!(found.gift_card_id, card.id);
+    assert!(found.pin_hash.is_none());
+
+    repo.set_card_pin(card.id, Some("pin-hash")).await.unwrap();
+    assert_eq!(repo.find_card_code(card.id).await.unwrap().unwrap().pin_hash.as_deref(), Some("pin-hash"));
+    assert!(matches!(
+        repo.set_card_pin(Uuid::new_v4(), Some("pin-hash")).await,
+        Err(AppError::NotFoundError(_))
+    ));
+
+    // The attempt that reaches the limit locks the card out
+    let lock_until = now + Duration::minutes(15);
+    assert!(repo.claim_pin_attempt(card.id, 2, now, lock_until).await.unwrap());
+    assert_eq!(repo.find_card_code(card.id).await.unwrap().unwrap().pin_attempts, 1);
+    assert!(repo.claim_pin_attempt(card.id, 2, now, lock_until).await.unwrap());
+    let locked = repo.find_card_code(card.id).await.unwrap().unwrap();
+    assert_eq!(locked.pin_attempts, 0);
+    assert!(locked.locked_until.is_some());
+    assert!(!repo.claim_pin_attempt(card.id, 2, now, lock_until).await.unwrap());
+
+    repo.set_card_pin(card.id, None).await.unwrap();
+    let cleared = repo.find_card_code(card.id).await.unwrap().unwrap();
+    assert!(cleared.pin_hash.is_none());
+    assert!(cleared.locked_until.is_none());
+}
+
+async fn check_idempotency_claims(repo: &dyn GiftCardRepository) {
+    // Fingerprints are stored as fixed-width SHA-256 hex digests
+    let (first, second, third) = ("a".repeat(64), "b".repeat(64), "c".repeat(64));
+    let key = format!("test-{}", Uuid::new_v4());
+    let now = Utc::now();
+    let stale_before = now - Duration::hours(1);
+
+    assert!(repo.claim_idempotency_key(&key, &first, now, stale_before).await.unwrap().is_none());
+    let pending = repo.claim_idempotency_key(&key, &first, now, stale_before).await.unwrap().unwrap();
+    assert_eq!(pending.fingerprint, first);
+    assert!(!pending.is_complete());
+
+    // A duplicate once the first request has finished replays its response
+    repo.complete_idempotency_key(&key, 201, "{\"success\":true}").await.unwrap();
+    let replay = repo.claim_idempotency_key(&key, &first, now, stale_before).await.unwrap().unwrap();
+    assert_eq!(replay.fingerprint, first);
+    assert_eq!(replay.response_status, Some(201));
+    assert_eq!(replay.response_body.as_deref(), Some("{\"success\":true}"));
+
+    // A stale record is taken over as if the key were unused
+    let later = now + Duration::hours(2);
+    assert!(repo.claim_idempotency_key(&key, &second, later, later - Duration::hours(1)).await.unwrap().is_none());
+    let taken = repo.claim_idempotency_key(&key, &second, later, stale_before).await.unwrap().unwrap();
+    assert_eq!(taken.fingerprint, second);
+    assert!(!taken.is_complete());
+
+    // A released key can be claimed again
+    repo.release_idempotency_key(&key).await.unwrap();
+    assert!(repo.claim_idempotency_key(&key, &third, later, stale_before).await.unwrap().is_none());
+}
+
+async fn check_idempotent_retries(repo: Arc<dyn GiftCardRepository>) {
+    let repo = web::Data::from(repo);
+    let config = test_config();
+    let auth = issuer_auth(&repo, &config, &format!("Issuer {}", Uuid::new_v4())).await;
+    let app = test::init_service(
+        App::new()
+            .app_data(repo.clone())
+            .app_data(config)
+            .wrap(from_fn(idempotency))
+            .configure(crate::routes::gift_cards::config),
+    )
+    .await;
+
+    let key = format!("order-{}", Uuid::new_v4());
+    let phone = sample_card().recipient_phone;
+    let request = || {
+        test::TestRequest::post()
+            .uri("/gift-cards")
+            .insert_header((AUTHORIZATION, auth.as_str()))
+            .insert_header((IDEMPOTENCY_KEY_HEADER, key.as_str()))
+            .set_json(json!({
+                "recipient_name": "Bob",
+                "recipient_phone": phone,
+                "balance": { "amount": 5000, "currency": "USD" },
+                "expiration_days": 30
+            }))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, request()).await;
+    assert_eq!(resp.status(), 201);
+    assert!(resp.headers().get(REPLAYED_HEADER).is_none());
+    let first: Value = test::read_body_json(resp).await;
+
+    let resp = test::call_service(&app, request()).await;
+    assert_eq!(resp.status(), 201);
+    assert_eq!(resp.headers().get(REPLAYED_HEADER).unwrap(), "true");
+    let retry: Value = test::read_body_json(resp).await;
+    assert_eq!(retry["data"]["id"], first["data"]["id"]);
+    assert_eq!(repo.list_cards_by_recipient(&phone, 10, 0).await.unwrap().len(), 1);
+}
+
+macro_rules! sql_repository_tests {
+    ($dialect:ident, $backend:expr) => {
+        mod $dialect {
+            use super::*;
+
+            #[actix_web::test]
+            #[ignore = "needs DATABASE_URL"]
+            async fn test_update_cards() {
+                if let Some(repo) = repository($backend).await {
+                    check_update_cards(repo.as_ref()).await;
+                }
+            }
+
+            #[actix_web::test]
+            #[ignore = "needs DATABASE_URL"]
+            async fn test_holds() {
+                if let Some(repo) = repository($backend).await {
+                    check_holds(repo.as_ref()).await;
+                }
+            }
+
+            #[actix_web::test]
+            #[ignore = "needs DATABASE_URL"]
+            async fn test_merchants() {
+                if let Some(repo) = repository($backend).await {
+                    check_merchants(repo.as_ref()).await;
+                }
+            }
+
+            #[actix_web::test]
+            #[ignore = "needs DATABASE_URL"]
+            async fn test_card_codes() {
+                if let Some(repo) = repository($backend).await {
+                    check_card_codes(repo.as_ref()).await;
+                }
+            }
+
+            #[actix_web::test]
+            #[ignore = "needs DATABASE_URL"]
+            async fn test_idempotency_claims() {
+                if let Some(repo) = repository($backend).await {
+                    check_idempotency_claims(repo.as_ref()).await;
+                }
+            }
+
+            #[actix_web::test]
+            #[ignore = "needs DATABASE_URL"]
+            async fn test_idempotent_retries() {
+                if let Some(repo) = repository($backend).await {
+                    check_idempotent_retries(repo).await;
+                }
+            }
+        }
+    };
+}
+
+sql_repository_tests!(postgres, Backend::Postgres);
+sql_repository_tests!(mysql, Backend::MySql);
@@ -0,0 +1,21 @@
+use actix_web::web;
+use crate::handlers::auth;
+
+/// Configure account API routes
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/auth")
+            // Create an issuer account (anyone)
+            .route("/register", web::post().to(auth::register))
+            
+            // Sign in, and renew tokens before the access token expires (anyone)
+            .route("/login", web::post().to(auth::login))
+            .route("/refresh", web::post().to(auth::refresh))
+            
+            // The signed-in account (any account)
+            .route("/me", web::get().to(auth::me))
+            
+            // Create an account of any role (admin)
+            .route("/accounts", web::post().to(auth::create_account))
+    );
+}
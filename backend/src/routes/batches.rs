@@ -0,0 +1,14 @@
+use actix_web::web;
+use crate::handlers::batches;
+
+/// Configure issuance batch API routes
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/gift-card-batches")
+            // Issue gift cards from CSV or JSON rows (admin, issuer)
+            .route("", web::post().to(batches::create_batch))
+            
+            // Get a batch's status and per-row results (admin, the issuer that sent it)
+            .route("/{id}", web::get().to(batches::get_batch))
+    );
+}
\ No newline at end of file
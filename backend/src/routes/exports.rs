@@ -0,0 +1,14 @@
+use actix_web::web;
+use crate::handlers::exports;
+
+/// Configure export API routes
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/exports")
+            // Stream gift cards as CSV or NDJSON (admin, issuer for their own cards)
+            .route("/gift-cards", web::get().to(exports::export_gift_cards))
+            
+            // Stream transactions as CSV or NDJSON (admin, issuer for their own cards, merchant for their own)
+            .route("/transactions", web::get().to(exports::export_transactions))
+    );
+}
\ No newline at end of file
@@ -5,28 +5,71 @@ use crate::handlers::gift_cards;
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/gift-cards")
-            // Issue a new gift card
+            // Issue a new gift card (admin, issuer)
             .route("", web::post().to(gift_cards::create_gift_card))
             
-            // Get gift card by ID
+            // Verify or pay with a gift card by its short code and PIN (admin or merchant; merchant, for itself)
+            .route("/by-code/{code}/verify", web::post().to(gift_cards::verify_by_code))
+            .route("/by-code/{code}/use", web::post().to(gift_cards::use_by_code))
+            
+            // Get gift card by ID (admin, its issuer or recipient; merchants without recipient details)
             .route("/{id}", web::get().to(gift_cards::get_gift_card))
             
-            // Accept a gift card
+            // Send a one-time code to the recipient phone, then accept the card with it (its recipient)
+            .route("/{id}/acceptance-code", web::post().to(gift_cards::send_acceptance_code))
             .route("/{id}/accept", web::post().to(gift_cards::accept_gift_card))
             
-            // Use a gift card for payment
+            // Suspend, resume or cancel a gift card (admin, its issuer)
+            .route("/{id}/suspend", web::post().to(gift_cards::suspend_gift_card))
+            .route("/{id}/resume", web::post().to(gift_cards::resume_gift_card))
+            .route("/{id}/cancel", web::post().to(gift_cards::cancel_gift_card))
+            
+            // Use a gift card for payment (merchant, for itself)
             .route("/{id}/use", web::post().to(gift_cards::use_gift_card))
             
-            // Generate QR code for a gift card
+            // Place a hold, then capture or void it (merchant, for itself)
+            .route("/{id}/holds", web::post().to(gift_cards::authorize_hold))
+            .route("/{id}/holds/{hold_id}/capture", web::post().to(gift_cards::capture_hold))
+            .route("/{id}/holds/{hold_id}/void", web::post().to(gift_cards::void_hold))
+            
+            // Refund a payment back to the gift card (admin, the merchant that took it)
+            .route("/{id}/refunds", web::post().to(gift_cards::refund_gift_card))
+            
+            // Add value to a gift card (admin, its issuer)
+            .route("/{id}/top-ups", web::post().to(gift_cards::top_up_gift_card))
+            
+            // Move a card's balance to a new card for someone else (admin, its recipient)
+            .route("/{id}/transfers", web::post().to(gift_cards::transfer_gift_card))
+            
+            // Generate QR code for a gift card (admin, its issuer or recipient)
             .route("/{id}/qr-code", web::get().to(gift_cards::generate_qr_code))
             
-            // List gift cards by recipient phone (with pagination)
+            // Download a printable PDF or PNG voucher (admin, its issuer or recipient)
+            .route("/{id}/voucher", web::get().to(gift_cards::get_voucher))
+            
+            // Set, change or remove the PIN asked for with the short code (its recipient)
+            .route("/{id}/pin", web::put().to(gift_cards::set_card_pin))
+            
+            // Turn on rotating live QR codes, and poll for the current one (its recipient)
+            .route("/{id}/live-qr", web::post().to(gift_cards::enable_live_qr))
+            .route("/{id}/live-qr", web::get().to(gift_cards::get_live_qr))
+            
+            // List gift cards by recipient phone (with pagination; admin, that recipient)
             .route("/by-recipient/{phone}", web::get().to(gift_cards::list_by_recipient))
             
-            // Verify gift card (used when scanning QR code)
+            // Verify gift card (used when scanning QR code; admin, merchant)
             .route("/{id}/verify", web::get().to(gift_cards::verify_gift_card))
             
-            // List transactions for a gift card
+            // List transactions for a gift card (admin, its issuer or recipient)
             .route("/{id}/transactions", web::get().to(gift_cards::list_transactions))
+            
+            // Ledger entries for a gift card, and manual balance adjustments (admin)
+            .route("/{id}/ledger", web::get().to(gift_cards::get_ledger))
+            .route("/{id}/adjustments", web::post().to(gift_cards::adjust_balance))
+            
+            // Extend, reactivate or replace a gift card, and the audit log of such changes (admin)
+            .route("/{id}/extension", web::post().to(gift_cards::extend_gift_card))
+            .route("/{id}/replacement", web::post().to(gift_cards::replace_gift_card))
+            .route("/{id}/audit", web::get().to(gift_cards::list_card_audits))
     );
 }
\ No newline at end of file
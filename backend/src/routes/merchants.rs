@@ -0,0 +1,22 @@
+use actix_web::web;
+use crate::handlers::merchants;
+
+/// Configure merchant API routes
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/merchants")
+            // Register a merchant, or list them (admin)
+            .route("", web::post().to(merchants::create_merchant))
+            .route("", web::get().to(merchants::list_merchants))
+            
+            // Get a merchant (admin, or the merchant's own accounts)
+            .route("/{id}", web::get().to(merchants::get_merchant))
+            
+            // Change or deactivate a merchant (admin)
+            .route("/{id}", web::patch().to(merchants::update_merchant))
+            .route("/{id}", web::delete().to(merchants::deactivate_merchant))
+            
+            // Transactions taken by a merchant (admin, or the merchant's own accounts)
+            .route("/{id}/transactions", web::get().to(merchants::list_merchant_transactions))
+    );
+}
@@ -1 +1,6 @@
-pub mod gift_cards;
\ No newline at end of file
+pub mod auth;
+pub mod batches;
+pub mod exports;
+pub mod gift_cards;
+pub mod merchants;
+pub mod qr_keys;
\ No newline at end of file
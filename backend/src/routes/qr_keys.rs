@@ -0,0 +1,11 @@
+use actix_web::web;
+use crate::handlers::qr_keys;
+
+/// Configure QR signing key routes
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/qr-keys")
+            // Public keys for verifying QR codes offline, newest first (anyone)
+            .route("", web::get().to(qr_keys::list_qr_keys))
+    );
+}
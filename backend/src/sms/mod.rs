@@ -0,0 +1,124 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::utils::error::AppError;
+
+/// Delivers text messages to phone numbers
+///
+/// Register an implementation as `web::Data<dyn SmsSender>`; the backend is
+/// chosen with `SMS_SENDER`.
+#[async_trait]
+pub trait SmsSender: Send + Sync {
+    /// Send `message` to `phone`
+    async fn send(&self, phone: &str, message: &str) -> Result<(), AppError>;
+}
+
+/// SMS backend, chosen from the `SMS_SENDER` setting
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SmsBackend {
+    Log,            // "log": write messages to the application log
+    File(PathBuf),  // "file:<path>": append messages to a local file
+}
+
+impl SmsBackend {
+    /// Parse an `SMS_SENDER` value
+    pub fn from_spec(spec: &str) -> Option<Self> {
+        match spec.trim() {
+            "log" => Some(SmsBackend::Log),
+            spec => spec
+                .strip_prefix("file:")
+                .filter(|path| !path.trim().is_empty())
+                .map(|path| SmsBackend::File(PathBuf::from(path.trim()))),
+        }
+    }
+}
+
+/// Build the SMS sender selected by an `SMS_SENDER` value
+pub fn connect(spec: &str) -> Result<Arc<dyn SmsSender>, AppError> {
+    match SmsBackend::from_spec(spec) {
+        Some(SmsBackend::Log) => Ok(Arc::new(LogSmsSender)),
+        Some(SmsBackend::File(path)) => Ok(Arc::new(FileSmsSender::new(path))),
+        None => Err(AppError::ValidationError(format!("Unsupported SMS_SENDER: {}", spec))),
+    }
+}
+
+/// Writes messages to the application log instead of sending them, for local use
+pub struct LogSmsSender;
+
+#[async_trait]
+impl SmsSender for LogSmsSender {
+    async fn send(&self, phone: &str, message: &str) -> Result<(), AppError> {
+        log::info!("SMS to {}: {}", phone, message);
+        Ok(())
+    }
+}
+
+/// Appends messages to a file, one tab-separated line each, for local use and tests
+pub struct FileSmsSender {
+    path: PathBuf,
+    lock: Mutex<()>,  // Keeps concurrent lines from interleaving
+}
+
+impl FileSmsSender {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+#[async_trait]
+impl SmsSender for FileSmsSender {
+    async fn send(&self, phone: &str, message: &str) -> Result<(), AppError> {
+        let line = format!("{}\t{}\t{}\n", Utc::now().to_rfc3339(), phone, message.replace('\n', " "));
+
+        let _guard = self.lock.lock().await;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(|e| AppError::InternalServerError(format!("Failed to open SMS outbox: {}", e)))?;
+        // tokio writes in the background until flushed
+        let written = match file.write_all(line.as_bytes()).await {
+            Ok(()) => file.flush().await,
+            Err(e) => Err(e),
+        };
+        written.map_err(|e| AppError::InternalServerError(format!("Failed to write SMS outbox: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backend_from_spec() {
+        assert_eq!(SmsBackend::from_spec("log"), Some(SmsBackend::Log));
+        assert_eq!(SmsBackend::from_spec("file:/tmp/sms.txt"), Some(SmsBackend::File(PathBuf::from("/tmp/sms.txt"))));
+        assert_eq!(SmsBackend::from_spec("file:"), None);
+        assert_eq!(SmsBackend::from_spec("twilio"), None);
+    }
+
+    #[actix_web::test]
+    async fn test_file_sender_appends_lines() {
+        let path = std::env::temp_dir().join(format!("sms-{}.txt", uuid::Uuid::new_v4()));
+        let sender = FileSmsSender::new(&path);
+
+        sender.send("1234567890", "first").await.unwrap();
+        sender.send("1234567890", "second\nline").await.unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("\t1234567890\tfirst"));
+        assert!(lines[1].ends_with("\tsecond line"));
+    }
+}
@@ -0,0 +1,150 @@
+use argon2::password_hash::SaltString;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::models::account::AuthTokensDto;
+use crate::utils::error::AppError;
+
+/// What a token may be used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenKind {
+    Access,   // Sent as a bearer token on API requests
+    Refresh,  // Only exchanged for a new pair of tokens
+}
+
+/// Claims carried by access and refresh tokens
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: Uuid,        // Account ID
+    pub kind: TokenKind,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+/// Hash a password into an Argon2 PHC string with a random salt
+pub fn hash_password(password: &str) -> Result<String, AppError> {
+    let salt = SaltString::encode_b64(Uuid::new_v4().as_bytes())
+        .map_err(|e| AppError::InternalServerError(format!("Failed to encode salt: {}", e)))?;
+
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| AppError::InternalServerError(format!("Failed to hash password: {}", e)))
+}
+
+/// Check a password against a stored hash; a malformed hash never matches
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash)
+        .map(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+        .unwrap_or(false)
+}
+
+/// Digits in a one-time acceptance code
+pub const OTP_DIGITS: u32 = 6;
+
+/// Generate a random numeric one-time code of [`OTP_DIGITS`] digits
+///
+/// The randomness comes from a v4 UUID, which is drawn from the operating
+/// system's secure generator.
+pub fn generate_otp() -> String {
+    let random = u128::from_le_bytes(*Uuid::new_v4().as_bytes());
+    let code = random % 10u128.pow(OTP_DIGITS);
+    format!("{:0width$}", code, width = OTP_DIGITS as usize)
+}
+
+/// Sign a token of `kind` for `subject`
+///
+/// Access tokens last `jwt_expiration` seconds and refresh tokens
+/// `jwt_refresh_expiration` seconds.
+pub fn issue_token(config: &Config, subject: Uuid, kind: TokenKind, now: DateTime<Utc>) -> Result<String, AppError> {
+    let lifetime = match kind {
+        TokenKind::Access => config.jwt_expiration,
+        TokenKind::Refresh => config.jwt_refresh_expiration,
+    };
+    let claims = Claims {
+        sub: subject,
+        kind,
+        iat: now.timestamp(),
+        exp: (now + Duration::seconds(lifetime)).timestamp(),
+    };
+
+    encode(&Header::default(), &claims, &EncodingKey::from_secret(config.jwt_secret.as_bytes()))
+        .map_err(|e| AppError::InternalServerError(format!("Failed to sign token: {}", e)))
+}
+
+/// Sign a new access and refresh token pair for `subject`
+pub fn issue_tokens(config: &Config, subject: Uuid, now: DateTime<Utc>) -> Result<AuthTokensDto, AppError> {
+    Ok(AuthTokensDto {
+        access_token: issue_token(config, subject, TokenKind::Access, now)?,
+        refresh_token: issue_token(config, subject, TokenKind::Refresh, now)?,
+        token_type: "Bearer",
+        expires_in: config.jwt_expiration,
+    })
+}
+
+/// Verify a token's signature and expiry, and that it is of the expected kind
+pub fn decode_token(config: &Config, token: &str, kind: TokenKind) -> Result<Claims, AppError> {
+    let claims = decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(config.jwt_secret.as_bytes()),
+        &Validation::default(),
+    )
+    .map_err(|_| AppError::UnauthorizedError("Invalid or expired token".to_string()))?
+    .claims;
+
+    if claims.kind != kind {
+        return Err(AppError::UnauthorizedError("Invalid or expired token".to_string()));
+    }
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config() -> Config {
+        Config::from_sources(None, &|key| (key == "DATABASE_URL").then(|| "memory://".to_string())).unwrap()
+    }
+
+    #[test]
+    fn test_password_round_trip() {
+        let hash = hash_password("correct horse").unwrap();
+
+        assert!(hash.starts_with("$argon2"));
+        assert!(verify_password("correct horse", &hash));
+        assert!(!verify_password("wrong horse", &hash));
+        assert!(!verify_password("correct horse", "not-a-hash"));
+    }
+
+    #[test]
+    fn test_otp_is_a_fixed_length_number() {
+        for _ in 0..100 {
+            let code = generate_otp();
+            assert_eq!(code.len(), OTP_DIGITS as usize);
+            assert!(code.chars().all(|c| c.is_ascii_digit()));
+        }
+    }
+
+    #[test]
+    fn test_tokens_are_checked_for_kind_and_expiry() {
+        let config = test_config();
+        let subject = Uuid::new_v4();
+        let now = Utc::now();
+
+        let tokens = issue_tokens(&config, subject, now).unwrap();
+        assert_eq!(decode_token(&config, &tokens.access_token, TokenKind::Access).unwrap().sub, subject);
+        assert_eq!(decode_token(&config, &tokens.refresh_token, TokenKind::Refresh).unwrap().sub, subject);
+
+        // A refresh token cannot be used as an access token, or the reverse
+        assert!(decode_token(&config, &tokens.refresh_token, TokenKind::Access).is_err());
+        assert!(decode_token(&config, &tokens.access_token, TokenKind::Refresh).is_err());
+
+        let expired = issue_token(&config, subject, TokenKind::Access, now - Duration::days(2)).unwrap();
+        assert!(decode_token(&config, &expired, TokenKind::Access).is_err());
+    }
+}
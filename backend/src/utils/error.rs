@@ -2,6 +2,9 @@ use actix_web::{http::StatusCode, HttpResponse, ResponseError};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
+use crate::models::card_status::InvalidTransition;
+use crate::models::money::MoneyError;
+
 /// API Error response structure
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ErrorResponse {
@@ -17,6 +20,10 @@ pub enum AppError {
     NotFoundError(String),
     ValidationError(String),
     UnauthorizedError(String),
+    ForbiddenError(String),
+    ConflictError(String),
+    TooManyRequestsError(String),
+    InvalidTransition(InvalidTransition),
     InternalServerError(String),
 }
 
@@ -27,6 +34,10 @@ impl fmt::Display for AppError {
             AppError::NotFoundError(e) => write!(f, "Not found: {}", e),
             AppError::ValidationError(e) => write!(f, "Validation error: {}", e),
             AppError::UnauthorizedError(e) => write!(f, "Unauthorized: {}", e),
+            AppError::ForbiddenError(e) => write!(f, "Forbidden: {}", e),
+            AppError::ConflictError(e) => write!(f, "Conflict: {}", e),
+            AppError::TooManyRequestsError(e) => write!(f, "Too many requests: {}", e),
+            AppError::InvalidTransition(e) => write!(f, "Invalid status change: {}", e),
             AppError::InternalServerError(e) => write!(f, "Internal server error: {}", e),
         }
     }
@@ -51,6 +62,10 @@ impl ResponseError for AppError {
             AppError::NotFoundError(_) => StatusCode::NOT_FOUND,
             AppError::ValidationError(_) => StatusCode::BAD_REQUEST,
             AppError::UnauthorizedError(_) => StatusCode::UNAUTHORIZED,
+            AppError::ForbiddenError(_) => StatusCode::FORBIDDEN,
+            AppError::ConflictError(_) => StatusCode::CONFLICT,
+            AppError::TooManyRequestsError(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::InvalidTransition(_) => StatusCode::CONFLICT,
             AppError::InternalServerError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -75,6 +90,18 @@ impl From<std::io::Error> for AppError {
     }
 }
 
+impl From<InvalidTransition> for AppError {
+    fn from(error: InvalidTransition) -> Self {
+        AppError::InvalidTransition(error)
+    }
+}
+
+impl From<MoneyError> for AppError {
+    fn from(error: MoneyError) -> Self {
+        AppError::ValidationError(error.to_string())
+    }
+}
+
 impl From<uuid::Error> for AppError {
     fn from(_: uuid::Error) -> Self {
         AppError::ValidationError("Invalid UUID format".to_string())
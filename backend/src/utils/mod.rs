@@ -1,2 +1,6 @@
+pub mod auth;
 pub mod error;
+pub mod qr_render;
+pub mod qr_token;
+pub mod short_code;
 pub mod validation;
\ No newline at end of file
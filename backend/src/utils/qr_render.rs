@@ -0,0 +1,420 @@
+use base64::{engine::general_purpose, Engine};
+use image::{imageops, DynamicImage, ImageFormat, Rgba, RgbaImage};
+use qrcode::{Color, EcLevel, QrCode};
+use serde::Deserialize;
+use std::fmt;
+use std::io::Cursor;
+use std::sync::Arc;
+
+use crate::utils::error::AppError;
+
+/// Smallest and largest `size` a QR code can be requested at, in pixels
+const SIZE_RANGE: (u32, u32) = (64, 2048);
+
+/// Largest quiet zone that can be requested, in modules
+const MAX_QUIET_ZONE: u32 = 16;
+
+/// Share of the symbol's width a logo may cover
+const LOGO_SCALE: f64 = 0.2;
+
+/// Image format a QR code is rendered in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QrFormat {
+    #[default]
+    Svg,
+    Png,
+}
+
+impl QrFormat {
+    /// MIME type of images in this format
+    pub fn content_type(self) -> &'static str {
+        match self {
+            QrFormat::Svg => "image/svg+xml",
+            QrFormat::Png => "image/png",
+        }
+    }
+}
+
+/// Error correction level; higher levels survive more damage, or a logo, at
+/// the cost of a denser code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QrErrorCorrection {
+    L,  // About 7% of the code can be restored
+    M,  // About 15%
+    Q,  // About 25%
+    H,  // About 30%
+}
+
+impl From<QrErrorCorrection> for EcLevel {
+    fn from(level: QrErrorCorrection) -> Self {
+        match level {
+            QrErrorCorrection::L => EcLevel::L,
+            QrErrorCorrection::M => EcLevel::M,
+            QrErrorCorrection::Q => EcLevel::Q,
+            QrErrorCorrection::H => EcLevel::H,
+        }
+    }
+}
+
+/// Query parameters for rendering a card's QR code; every one is optional
+#[derive(Debug, Default, Deserialize)]
+pub struct QrCodeQuery {
+    pub format: Option<QrFormat>,                       // svg (default) or png
+    pub size: Option<u32>,                              // Width and height in pixels, 200 by default
+    pub quiet_zone: Option<u32>,                        // Blank border in modules, 4 by default
+    pub error_correction: Option<QrErrorCorrection>,    // l, m (default), q or h; h with a logo
+    pub foreground: Option<String>,                     // Hex colour of dark modules, 000000 by default
+    pub background: Option<String>,                     // Hex colour of light modules, ffffff by default
+    pub logo: Option<bool>,                             // Centre the configured brand logo in the code
+    pub raw: Option<bool>,                              // Return the image itself instead of JSON
+}
+
+/// A brand logo that can be centred in QR codes, loaded from `QR_LOGO_PATH`
+#[derive(Clone)]
+pub struct QrLogo {
+    image: Arc<RgbaImage>,
+    png: Arc<Vec<u8>>,     // The image re-encoded as PNG, for embedding in SVG
+}
+
+impl fmt::Debug for QrLogo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "QrLogo({}x{})", self.image.width(), self.image.height())
+    }
+}
+
+impl QrLogo {
+    /// Read a logo from any image file the `image` crate can decode
+    pub fn load(path: &str) -> Result<Self, String> {
+        let image = image::open(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+        let png = encode_png(&image).map_err(|e| format!("cannot encode {}: {}", path, e))?;
+        Ok(Self {
+            image: Arc::new(image.to_rgba8()),
+            png: Arc::new(png),
+        })
+    }
+}
+
+/// How a QR code is drawn
+#[derive(Debug, Clone)]
+pub struct QrStyle {
+    pub format: QrFormat,
+    pub size: u32,
+    pub quiet_zone: u32,
+    pub error_correction: QrErrorCorrection,
+    pub foreground: Rgba<u8>,
+    pub background: Rgba<u8>,
+    pub logo: Option<QrLogo>,
+}
+
+impl Default for QrStyle {
+    /// A 200 pixel black on white SVG
+    fn default() -> Self {
+        Self {
+            format: QrFormat::Svg,
+            size: 200,
+            quiet_zone: 4,
+            error_correction: QrErrorCorrection::M,
+            foreground: Rgba([0, 0, 0, 255]),
+            background: Rgba([255, 255, 255, 255]),
+            logo: None,
+        }
+    }
+}
+
+/// Where modules land in the image
+struct Layout {
+    modules: u32,  // Width of the symbol, without the quiet zone
+    scale: u32,    // Pixels per module
+    origin: u32,   // Pixel offset of the symbol's first module on both axes
+}
+
+impl QrStyle {
+    /// Resolve query parameters against the defaults
+    ///
+    /// A logo needs `logo` (the configured one) and error correction of `q`
+    /// or `h`; it defaults to `h` when a logo is asked for.
+    pub fn from_query(query: &QrCodeQuery, logo: Option<&QrLogo>) -> Result<Self, AppError> {
+        let defaults = Self::default();
+        let invalid = |message: String| Err(AppError::ValidationError(message));
+
+        let size = query.size.unwrap_or(defaults.size);
+        if size < SIZE_RANGE.0 || size > SIZE_RANGE.1 {
+            return invalid(format!("Size must be between {} and {} pixels", SIZE_RANGE.0, SIZE_RANGE.1));
+        }
+
+        let quiet_zone = query.quiet_zone.unwrap_or(defaults.quiet_zone);
+        if quiet_zone > MAX_QUIET_ZONE {
+            return invalid(format!("Quiet zone must be at most {} modules", MAX_QUIET_ZONE));
+        }
+
+        let foreground = match &query.foreground {
+            Some(hex) => parse_color(hex)?,
+            None => defaults.foreground,
+        };
+        let background = match &query.background {
+            Some(hex) => parse_color(hex)?,
+            None => defaults.background,
+        };
+        if foreground == background {
+            return invalid("Foreground and background colours must differ".to_string());
+        }
+
+        let logo = match query.logo {
+            Some(true) => match logo {
+                Some(logo) => Some(logo.clone()),
+                None => return invalid("No logo is configured".to_string()),
+            },
+            _ => None,
+        };
+        let error_correction = match (query.error_correction, &logo) {
+            (None, Some(_)) => QrErrorCorrection::H,
+            (None, None) => defaults.error_correction,
+            (Some(QrErrorCorrection::L | QrErrorCorrection::M), Some(_)) => {
+                return invalid("A logo needs error correction q or h".to_string())
+            }
+            (Some(level), _) => level,
+        };
+
+        Ok(Self {
+            format: query.format.unwrap_or(defaults.format),
+            size,
+            quiet_zone,
+            error_correction,
+            foreground,
+            background,
+            logo,
+        })
+    }
+
+    /// Render `content` as an image in this style
+    pub fn render(&self, content: &str) -> Result<Vec<u8>, AppError> {
+        let code = QrCode::with_error_correction_level(content.as_bytes(), self.error_correction.into())
+            .map_err(|e| AppError::InternalServerError(format!("Failed to encode QR code: {}", e)))?;
+        let layout = self.layout(&code)?;
+
+        match self.format {
+            QrFormat::Png => self.render_png(&code, &layout),
+            QrFormat::Svg => Ok(self.render_svg(&code, &layout).into_bytes()),
+        }
+    }
+
+    /// Wrap an image rendered in this style in a base64 `data:` URI
+    pub fn data_uri(&self, image: &[u8]) -> String {
+        format!("data:{};base64,{}", self.format.content_type(), general_purpose::STANDARD.encode(image))
+    }
+
+    /// Fit the symbol and quiet zone into `size` pixels, centred
+    fn layout(&self, code: &QrCode) -> Result<Layout, AppError> {
+        let modules = code.width() as u32;
+        let total = modules + 2 * self.quiet_zone;
+        let scale = self.size / total;
+        if scale == 0 {
+            return Err(AppError::ValidationError(format!(
+                "Size must be at least {} pixels for this QR code",
+                total
+            )));
+        }
+
+        Ok(Layout {
+            modules,
+            scale,
+            origin: (self.size - scale * total) / 2 + self.quiet_zone * scale,
+        })
+    }
+
+    /// Side of the square a logo is fitted into, and its top-left pixel
+    fn logo_box(&self, layout: &Layout) -> (u32, u32) {
+        let side = (f64::from(layout.modules * layout.scale) * LOGO_SCALE).round() as u32;
+        (side, (self.size - side) / 2)
+    }
+
+    fn render_png(&self, code: &QrCode, layout: &Layout) -> Result<Vec<u8>, AppError> {
+        let mut image = RgbaImage::from_pixel(self.size, self.size, self.background);
+        for (i, color) in code.to_colors().into_iter().enumerate() {
+            if color == Color::Dark {
+                let (x, y) = module_origin(layout, i);
+                for py in y..y + layout.scale {
+                    for px in x..x + layout.scale {
+                        image.put_pixel(px, py, self.foreground);
+                    }
+                }
+            }
+        }
+
+        if let Some(logo) = &self.logo {
+            let (side, at) = self.logo_box(layout);
+            let pad = layout.scale;
+            for py in at.saturating_sub(pad)..(at + side + pad).min(self.size) {
+                for px in at.saturating_sub(pad)..(at + side + pad).min(self.size) {
+                    image.put_pixel(px, py, self.background);
+                }
+            }
+            let fitted = imageops::resize(logo.image.as_ref(), side, side, imageops::FilterType::Lanczos3);
+            imageops::overlay(&mut image, &fitted, i64::from(at), i64::from(at));
+        }
+
+        encode_png(&DynamicImage::ImageRgba8(image))
+            .map_err(|e| AppError::InternalServerError(format!("Failed to encode QR code: {}", e)))
+    }
+
+    fn render_svg(&self, code: &QrCode, layout: &Layout) -> String {
+        let s = layout.scale;
+        let path: String = code
+            .to_colors()
+            .into_iter()
+            .enumerate()
+            .filter(|(_, color)| *color == Color::Dark)
+            .map(|(i, _)| {
+                let (x, y) = module_origin(layout, i);
+                format!("M{},{}h{}v{}h-{}z", x, y, s, s, s)
+            })
+            .collect();
+
+        let mut svg = format!(
+            concat!(
+                r#"<?xml version="1.0" standalone="yes"?>"#,
+                r#"<svg xmlns="http://www.w3.org/2000/svg" version="1.1" width="{size}" height="{size}" "#,
+                r#"viewBox="0 0 {size} {size}" shape-rendering="crispEdges">"#,
+                r#"<rect width="{size}" height="{size}" fill="{bg}"/><path fill="{fg}" d="{path}"/>"#
+            ),
+            size = self.size,
+            bg = hex_color(self.background),
+            fg = hex_color(self.foreground),
+            path = path,
+        );
+
+        if let Some(logo) = &self.logo {
+            let (side, at) = self.logo_box(layout);
+            let pad = layout.scale;
+            svg.push_str(&format!(
+                r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+                at.saturating_sub(pad),
+                at.saturating_sub(pad),
+                side + 2 * pad,
+                side + 2 * pad,
+                hex_color(self.background)
+            ));
+            svg.push_str(&format!(
+                r#"<image x="{}" y="{}" width="{}" height="{}" href="data:image/png;base64,{}"/>"#,
+                at,
+                at,
+                side,
+                side,
+                general_purpose::STANDARD.encode(logo.png.as_slice())
+            ));
+        }
+
+        svg.push_str("</svg>");
+        svg
+    }
+}
+
+/// Top-left pixel of the `index`th module, counted row by row
+fn module_origin(layout: &Layout, index: usize) -> (u32, u32) {
+    let index = index as u32;
+    (
+        layout.origin + (index % layout.modules) * layout.scale,
+        layout.origin + (index / layout.modules) * layout.scale,
+    )
+}
+
+/// Parse `rrggbb`, with or without a leading `#`
+pub(crate) fn parse_color(hex: &str) -> Result<Rgba<u8>, AppError> {
+    let digits = hex.trim().trim_start_matches('#');
+    let invalid = || AppError::ValidationError(format!("{:?} is not a colour like 1a2b3c", hex));
+    if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| invalid());
+    Ok(Rgba([channel(0)?, channel(2)?, channel(4)?, 255]))
+}
+
+fn hex_color(color: Rgba<u8>) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+pub(crate) fn encode_png(image: &DynamicImage) -> Result<Vec<u8>, image::ImageError> {
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style(query: QrCodeQuery) -> Result<QrStyle, AppError> {
+        QrStyle::from_query(&query, None)
+    }
+
+    #[test]
+    fn test_query_is_validated() {
+        assert!(style(QrCodeQuery { size: Some(10), ..Default::default() }).is_err());
+        assert!(style(QrCodeQuery { quiet_zone: Some(40), ..Default::default() }).is_err());
+        assert!(style(QrCodeQuery { foreground: Some("red".to_string()), ..Default::default() }).is_err());
+        assert!(style(QrCodeQuery { background: Some("#000000".to_string()), ..Default::default() }).is_err());
+        assert!(style(QrCodeQuery { logo: Some(true), ..Default::default() }).is_err());
+
+        let resolved = style(QrCodeQuery { foreground: Some("#1A2b3c".to_string()), ..Default::default() }).unwrap();
+        assert_eq!(resolved.foreground, Rgba([0x1a, 0x2b, 0x3c, 255]));
+        assert_eq!(resolved.error_correction, QrErrorCorrection::M);
+    }
+
+    #[test]
+    fn test_logo_raises_error_correction() {
+        let logo = QrLogo {
+            image: Arc::new(RgbaImage::from_pixel(8, 8, Rgba([255, 0, 0, 255]))),
+            png: Arc::new(Vec::new()),
+        };
+        let with_logo = |level| QrCodeQuery { logo: Some(true), error_correction: level, ..Default::default() };
+
+        let resolved = QrStyle::from_query(&with_logo(None), Some(&logo)).unwrap();
+        assert_eq!(resolved.error_correction, QrErrorCorrection::H);
+        assert!(QrStyle::from_query(&with_logo(Some(QrErrorCorrection::L)), Some(&logo)).is_err());
+
+        let png = QrStyle { format: QrFormat::Png, ..resolved }.render("giftcard:logo").unwrap();
+        let image = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert_eq!(*image.get_pixel(100, 100), Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_png_is_exactly_the_requested_size() {
+        let resolved = style(QrCodeQuery {
+            format: Some(QrFormat::Png),
+            size: Some(300),
+            quiet_zone: Some(2),
+            foreground: Some("003366".to_string()),
+            background: Some("ffeecc".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        let image = image::load_from_memory(&resolved.render("giftcard:size").unwrap()).unwrap().to_rgba8();
+
+        assert_eq!(image.dimensions(), (300, 300));
+        assert_eq!(*image.get_pixel(0, 0), Rgba([0xff, 0xee, 0xcc, 255]));
+
+        // The top-left finder pattern starts right after the quiet zone
+        let code = QrCode::new(b"giftcard:size").unwrap();
+        let layout = resolved.layout(&code).unwrap();
+        assert_eq!(*image.get_pixel(layout.origin, layout.origin), Rgba([0x00, 0x33, 0x66, 255]));
+        assert_eq!(*image.get_pixel(layout.origin - 1, layout.origin), Rgba([0xff, 0xee, 0xcc, 255]));
+    }
+
+    #[test]
+    fn test_svg_uses_the_colours() {
+        let svg = String::from_utf8(
+            style(QrCodeQuery { background: Some("fafafa".to_string()), ..Default::default() })
+                .unwrap()
+                .render("giftcard:svg")
+                .unwrap(),
+        )
+        .unwrap();
+
+        assert!(svg.contains(r#"width="200" height="200""#));
+        assert!(svg.contains(r##"fill="#fafafa""##));
+        assert!(svg.contains(r##"<path fill="#000000" d="M"##));
+    }
+}
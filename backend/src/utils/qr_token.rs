@@ -0,0 +1,345 @@
+use base64::{engine::general_purpose, Engine};
+use chrono::{DateTime, Utc};
+use jsonwebtoken::{decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use ring::hmac;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use uuid::Uuid;
+
+use crate::models::qr_key::{QrKeySetDto, QrPublicKeyDto};
+use crate::utils::error::AppError;
+
+/// Prefix of the text encoded in a gift card's QR code
+pub const QR_PREFIX: &str = "giftcard:";
+
+/// Prefix of the text encoded in a gift card's live QR code
+pub const LIVE_QR_PREFIX: &str = "giftcard-live:";
+
+/// PKCS#8 v1 header that precedes a 32-byte Ed25519 seed
+const PKCS8_ED25519_PREFIX: [u8; 16] = [
+    0x30, 0x2e, 0x02, 0x01, 0x00, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22, 0x04, 0x20,
+];
+
+/// Claims carried by a QR token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QrClaims {
+    pub sub: Uuid,        // Gift card ID
+    pub iat: i64,
+}
+
+/// A QR token whose signature checked out
+#[derive(Debug, Clone)]
+pub struct VerifiedQrToken {
+    pub gift_card_id: Uuid,
+    pub key_id: String,
+    pub issued_at: DateTime<Utc>,
+}
+
+/// An Ed25519 key that signs or verifies QR tokens
+#[derive(Clone)]
+pub struct QrSigningKey {
+    pub kid: String,
+    seed: [u8; 32],
+    public_key: Vec<u8>,
+}
+
+impl QrSigningKey {
+    /// Derive the key pair for `kid` from a 32-byte seed
+    pub fn from_seed(kid: &str, seed: [u8; 32]) -> Result<Self, String> {
+        let pair = Ed25519KeyPair::from_seed_unchecked(&seed)
+            .map_err(|_| format!("key {:?} is not a valid Ed25519 seed", kid))?;
+        Ok(Self {
+            kid: kid.to_string(),
+            seed,
+            public_key: pair.public_key().as_ref().to_vec(),
+        })
+    }
+
+    fn encoding_key(&self) -> EncodingKey {
+        let mut der = PKCS8_ED25519_PREFIX.to_vec();
+        der.extend_from_slice(&self.seed);
+        EncodingKey::from_ed_der(&der)
+    }
+
+    fn decoding_key(&self) -> DecodingKey {
+        DecodingKey::from_ed_der(&self.public_key)
+    }
+}
+
+/// The keys QR tokens are signed and verified with, newest first
+///
+/// The first key signs new tokens. The others only verify tokens signed before
+/// a rotation, until they are dropped from the ring.
+#[derive(Clone)]
+pub struct QrKeyRing {
+    keys: Vec<QrSigningKey>,
+}
+
+impl fmt::Debug for QrKeyRing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.keys.iter().map(|key| &key.kid)).finish()
+    }
+}
+
+impl QrKeyRing {
+    /// Parse comma-separated `<kid>:<base64 seed>` entries, newest first
+    pub fn from_spec(spec: &str) -> Result<Self, String> {
+        let mut keys: Vec<QrSigningKey> = Vec::new();
+        for entry in spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (kid, seed) = entry
+                .split_once(':')
+                .ok_or_else(|| format!("{:?} is not of the form <kid>:<base64 seed>", entry))?;
+            let kid = kid.trim();
+            if kid.is_empty() || !kid.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)) {
+                return Err(format!("key id {:?} must be letters, digits, '-', '_' or '.'", kid));
+            }
+            if keys.iter().any(|key| key.kid == kid) {
+                return Err(format!("key id {:?} is listed twice", kid));
+            }
+
+            let seed: [u8; 32] = general_purpose::STANDARD
+                .decode(seed.trim())
+                .ok()
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or_else(|| format!("key {:?} must be 32 bytes of base64", kid))?;
+            keys.push(QrSigningKey::from_seed(kid, seed)?);
+        }
+
+        if keys.is_empty() {
+            return Err("at least one key is required".to_string());
+        }
+        Ok(Self { keys })
+    }
+
+    /// The key new tokens are signed with
+    pub fn active(&self) -> &QrSigningKey {
+        &self.keys[0]
+    }
+
+    /// Sign a token for `gift_card_id` with the active key
+    pub fn sign(&self, gift_card_id: Uuid, now: DateTime<Utc>) -> Result<String, AppError> {
+        let key = self.active();
+        let mut header = Header::new(Algorithm::EdDSA);
+        header.typ = None;
+        header.kid = Some(key.kid.clone());
+        let claims = QrClaims { sub: gift_card_id, iat: now.timestamp() };
+
+        encode(&header, &claims, &key.encoding_key())
+            .map_err(|e| AppError::InternalServerError(format!("Failed to sign QR token: {}", e)))
+    }
+
+    /// Check a token, with or without the [`QR_PREFIX`], against the key it names
+    pub fn verify(&self, token: &str) -> Result<VerifiedQrToken, AppError> {
+        let invalid = || AppError::ValidationError("QR code is not a genuine gift card code".to_string());
+        let token = token.strip_prefix(QR_PREFIX).unwrap_or(token);
+
+        let kid = decode_header(token).ok().and_then(|header| header.kid).ok_or_else(invalid)?;
+        let key = self.keys.iter().find(|key| key.kid == kid).ok_or_else(invalid)?;
+
+        let mut validation = Validation::new(Algorithm::EdDSA);
+        validation.required_spec_claims = HashSet::new();
+        validation.validate_exp = false;
+        let claims = decode::<QrClaims>(token, &key.decoding_key(), &validation)
+            .map_err(|_| invalid())?
+            .claims;
+
+        Ok(VerifiedQrToken {
+            gift_card_id: claims.sub,
+            key_id: kid,
+            issued_at: DateTime::from_timestamp(claims.iat, 0).ok_or_else(invalid)?,
+        })
+    }
+
+    /// The public keys, for terminals that verify tokens offline
+    pub fn public_keys(&self) -> QrKeySetDto {
+        QrKeySetDto {
+            keys: self
+                .keys
+                .iter()
+                .enumerate()
+                .map(|(i, key)| QrPublicKeyDto {
+                    kty: "OKP",
+                    crv: "Ed25519",
+                    alg: "EdDSA",
+                    key_use: "sig",
+                    kid: key.kid.clone(),
+                    x: general_purpose::URL_SAFE_NO_PAD.encode(&key.public_key),
+                    active: i == 0,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A scanned live QR code: `giftcard-live:<card id>:<window>:<tag>`
+///
+/// The tag is an HMAC-SHA256 of the card ID and window under the card's live
+/// QR secret, so only the server and the recipient's device can produce it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LiveQrCode {
+    pub gift_card_id: Uuid,
+    pub window: i64,
+    tag: String,
+}
+
+impl LiveQrCode {
+    /// The code for `window`, derived from the card's secret
+    pub fn new(secret: &str, gift_card_id: Uuid, window: i64) -> Self {
+        let tag = hmac::sign(&live_qr_key(secret), live_qr_message(gift_card_id, window).as_bytes());
+        Self {
+            gift_card_id,
+            window,
+            tag: general_purpose::URL_SAFE_NO_PAD.encode(tag.as_ref()),
+        }
+    }
+
+    /// Parse scanned text, returning `None` if it is not a live code
+    pub fn parse(scanned: &str) -> Option<Self> {
+        let mut parts = scanned.strip_prefix(LIVE_QR_PREFIX)?.split(':');
+        let code = Self {
+            gift_card_id: parts.next()?.parse().ok()?,
+            window: parts.next()?.parse().ok()?,
+            tag: parts.next()?.to_string(),
+        };
+        parts.next().is_none().then_some(code)
+    }
+
+    /// Whether the tag was made with `secret`, compared in constant time
+    pub fn is_signed_by(&self, secret: &str) -> bool {
+        general_purpose::URL_SAFE_NO_PAD
+            .decode(&self.tag)
+            .map(|tag| {
+                let message = live_qr_message(self.gift_card_id, self.window);
+                hmac::verify(&live_qr_key(secret), message.as_bytes(), &tag).is_ok()
+            })
+            .unwrap_or(false)
+    }
+}
+
+impl fmt::Display for LiveQrCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}:{}:{}", LIVE_QR_PREFIX, self.gift_card_id, self.window, self.tag)
+    }
+}
+
+fn live_qr_key(secret: &str) -> hmac::Key {
+    hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes())
+}
+
+fn live_qr_message(gift_card_id: Uuid, window: i64) -> String {
+    format!("{}:{}", gift_card_id, window)
+}
+
+/// The time window `now` falls in, counting `window_seconds` windows from the epoch
+pub fn live_qr_window(now: DateTime<Utc>, window_seconds: i64) -> i64 {
+    now.timestamp().div_euclid(window_seconds)
+}
+
+/// Generate a random 256-bit live QR secret, hex encoded
+///
+/// The randomness comes from v4 UUIDs, which are drawn from the operating
+/// system's secure generator.
+pub fn generate_live_qr_secret() -> String {
+    (0..2).map(|_| Uuid::new_v4().simple().to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ring(spec: &str) -> QrKeyRing {
+        QrKeyRing::from_spec(spec).unwrap()
+    }
+
+    fn seed(byte: u8) -> String {
+        general_purpose::STANDARD.encode([byte; 32])
+    }
+
+    #[test]
+    fn test_spec_is_validated() {
+        assert!(QrKeyRing::from_spec("").is_err());
+        assert!(QrKeyRing::from_spec("no-separator").is_err());
+        assert!(QrKeyRing::from_spec("k1:c2hvcnQ=").is_err());
+        assert!(QrKeyRing::from_spec(&format!("bad kid:{}", seed(1))).is_err());
+        assert!(QrKeyRing::from_spec(&format!("k1:{},k1:{}", seed(1), seed(2))).is_err());
+
+        let keys = ring(&format!("k2:{}, k1:{}", seed(2), seed(1)));
+        assert_eq!(keys.active().kid, "k2");
+        assert_eq!(format!("{:?}", keys), r#"["k2", "k1"]"#);
+    }
+
+    #[test]
+    fn test_tokens_survive_rotation_until_the_key_is_dropped() {
+        let card = Uuid::new_v4();
+        let now = Utc::now();
+        let old = ring(&format!("k1:{}", seed(1)));
+        let rotated = ring(&format!("k2:{},k1:{}", seed(2), seed(1)));
+        let dropped = ring(&format!("k2:{}", seed(2)));
+
+        let token = old.sign(card, now).unwrap();
+        let verified = rotated.verify(&format!("{}{}", QR_PREFIX, token)).unwrap();
+        assert_eq!(verified.gift_card_id, card);
+        assert_eq!(verified.key_id, "k1");
+        assert_eq!(verified.issued_at.timestamp(), now.timestamp());
+        assert!(dropped.verify(&token).is_err());
+
+        assert_eq!(rotated.verify(&rotated.sign(card, now).unwrap()).unwrap().key_id, "k2");
+    }
+
+    #[test]
+    fn test_forged_tokens_are_rejected() {
+        let keys = ring(&format!("k1:{}", seed(1)));
+        let token = keys.sign(Uuid::new_v4(), Utc::now()).unwrap();
+
+        // Same key id, different secret
+        let forger = ring(&format!("k1:{}", seed(9)));
+        assert!(keys.verify(&forger.sign(Uuid::new_v4(), Utc::now()).unwrap()).is_err());
+
+        // Claims swapped for another card's under the original signature
+        let parts: Vec<&str> = token.split('.').collect();
+        let claims = general_purpose::URL_SAFE_NO_PAD
+            .encode(serde_json::to_vec(&QrClaims { sub: Uuid::new_v4(), iat: 0 }).unwrap());
+        assert!(keys.verify(&format!("{}.{}.{}", parts[0], claims, parts[2])).is_err());
+
+        assert!(keys.verify(&Uuid::new_v4().to_string()).is_err());
+    }
+
+    #[test]
+    fn test_live_codes_round_trip_and_need_the_secret() {
+        let secret = generate_live_qr_secret();
+        assert_eq!(secret.len(), 64);
+
+        let card = Uuid::new_v4();
+        let window = live_qr_window(Utc::now(), 30);
+        let code = LiveQrCode::new(&secret, card, window);
+        let parsed = LiveQrCode::parse(&code.to_string()).unwrap();
+        assert_eq!(parsed, code);
+        assert!(parsed.is_signed_by(&secret));
+        assert!(!parsed.is_signed_by(&generate_live_qr_secret()));
+
+        // Moving the code to another window breaks the tag
+        let replayed = code.to_string().replace(&format!(":{}:", window), &format!(":{}:", window + 1));
+        assert!(!LiveQrCode::parse(&replayed).unwrap().is_signed_by(&secret));
+
+        assert!(LiveQrCode::parse(&format!("{}{}", QR_PREFIX, card)).is_none());
+        assert!(LiveQrCode::parse(&format!("{}:extra", code)).is_none());
+    }
+
+    #[test]
+    fn test_public_keys_verify_tokens() {
+        let keys = ring(&format!("k2:{},k1:{}", seed(2), seed(1)));
+        let set = keys.public_keys();
+        assert_eq!(set.keys.len(), 2);
+        assert!(set.keys[0].active && !set.keys[1].active);
+
+        // A terminal holding only the published key can check a token
+        let token = keys.sign(Uuid::new_v4(), Utc::now()).unwrap();
+        let public = DecodingKey::from_ed_components(&set.keys[0].x).unwrap();
+        let mut validation = Validation::new(Algorithm::EdDSA);
+        validation.required_spec_claims = HashSet::new();
+        validation.validate_exp = false;
+        assert!(decode::<QrClaims>(&token, &public, &validation).is_ok());
+    }
+}
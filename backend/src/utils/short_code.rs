@@ -0,0 +1,112 @@
+use uuid::Uuid;
+
+/// Characters short codes are made of: Crockford's base 32, which leaves out
+/// I, L, O and U so codes read aloud or typed in are not mistaken
+pub const SHORT_CODE_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Characters in a short code: 15 random ones and a check character
+pub const SHORT_CODE_LENGTH: usize = 16;
+
+/// Generate a random short code ending in its check character
+///
+/// The randomness comes from v4 UUIDs, skipping their fixed version and variant
+/// bits, so each character is drawn evenly from the secure generator.
+pub fn generate_short_code() -> String {
+    let mut payload = Vec::with_capacity(SHORT_CODE_LENGTH);
+    while payload.len() < SHORT_CODE_LENGTH - 1 {
+        let uuid = Uuid::new_v4();
+        for (i, byte) in uuid.as_bytes().iter().enumerate() {
+            if i != 6 && i != 8 && payload.len() < SHORT_CODE_LENGTH - 1 {
+                payload.push(SHORT_CODE_ALPHABET[usize::from(byte % 32)]);
+            }
+        }
+    }
+    payload.push(check_character(&payload));
+    String::from_utf8(payload).expect("the alphabet is ASCII")
+}
+
+/// Read a short code as typed by a person
+///
+/// Case, spaces and hyphens are ignored and the look-alikes O, I and L are read
+/// as 0, 1 and 1. Returns the code in canonical form, or `None` if it is the
+/// wrong length, has other characters or fails its check character.
+pub fn normalize_short_code(input: &str) -> Option<String> {
+    let code: Vec<u8> = input
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .map(|c| match c.to_ascii_uppercase() {
+            'O' => b'0',
+            'I' | 'L' => b'1',
+            c if c.is_ascii() => c as u8,
+            _ => b'?',
+        })
+        .collect();
+
+    if code.len() != SHORT_CODE_LENGTH || !code.iter().all(|c| SHORT_CODE_ALPHABET.contains(c)) {
+        return None;
+    }
+    let (payload, check) = code.split_at(SHORT_CODE_LENGTH - 1);
+    if check[0] != check_character(payload) {
+        return None;
+    }
+    String::from_utf8(code).ok()
+}
+
+/// Split a canonical short code into groups of four for display
+pub fn format_short_code(code: &str) -> String {
+    code.as_bytes()
+        .chunks(4)
+        .map(|group| String::from_utf8_lossy(group).into_owned())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Luhn mod 32 check character, which catches any single mistyped character
+/// and most swaps of neighbouring characters
+fn check_character(payload: &[u8]) -> u8 {
+    let n = SHORT_CODE_ALPHABET.len();
+    let sum: usize = payload
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, c)| {
+            let value = SHORT_CODE_ALPHABET.iter().position(|a| a == c).unwrap_or(0);
+            let addend = if i % 2 == 0 { value * 2 } else { value };
+            addend / n + addend % n
+        })
+        .sum();
+    SHORT_CODE_ALPHABET[(n - sum % n) % n]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_codes_pass_their_check() {
+        for _ in 0..100 {
+            let code = generate_short_code();
+            assert_eq!(code.len(), SHORT_CODE_LENGTH);
+            assert_eq!(normalize_short_code(&code), Some(code.clone()));
+        }
+    }
+
+    #[test]
+    fn test_typing_is_forgiven_but_mistakes_are_caught() {
+        let code = generate_short_code();
+        let typed = format_short_code(&code).to_lowercase().replace('0', "o").replace('1', "l");
+        assert_eq!(normalize_short_code(&typed), Some(code.clone()));
+
+        // Every single-character substitution is rejected
+        for i in 0..SHORT_CODE_LENGTH {
+            for &replacement in SHORT_CODE_ALPHABET.iter().filter(|&&c| c != code.as_bytes()[i]) {
+                let mut wrong = code.clone().into_bytes();
+                wrong[i] = replacement;
+                assert_eq!(normalize_short_code(&String::from_utf8(wrong).unwrap()), None);
+            }
+        }
+
+        assert_eq!(normalize_short_code(&code[1..]), None);
+        assert_eq!(normalize_short_code(&format!("{}U", &code[1..])), None);
+    }
+}
@@ -1,6 +1,8 @@
 use regex::Regex;
 use lazy_static::lazy_static;
 
+use crate::models::money::Money;
+
 lazy_static! {
     // Phone number regex - simple pattern for demo purposes
     // In production, use region-specific validation
@@ -8,6 +10,9 @@ lazy_static! {
     
     // Name regex - allows letters, spaces, and common special characters
     static ref NAME_REGEX: Regex = Regex::new(r"^[a-zA-Z\s\-'.]{2,50}$").unwrap();
+    
+    // Email regex - only checks the overall shape, not deliverability
+    static ref EMAIL_REGEX: Regex = Regex::new(r"^[^@\s]+@[^@\s]+\.[^@\s]+$").unwrap();
 }
 
 /// Validate phone number format
@@ -20,13 +25,24 @@ pub fn validate_name(name: &str) -> bool {
     NAME_REGEX.is_match(name)
 }
 
-/// Validate monetary amount (in cents)
+/// Validate email address format
+pub fn validate_email(email: &str) -> bool {
+    email.len() <= 254 && EMAIL_REGEX.is_match(email)
+}
+
+/// Validate monetary amount
 /// 
 /// Returns true if amount is positive and within reasonable range
-pub fn validate_amount(amount: i32) -> bool {
-    // Amount should be positive and less than $10,000 (1,000,000 cents)
+pub fn validate_amount(amount: Money) -> bool {
+    // Amount should be positive and at most 10,000 in major units (e.g. $10,000)
     // For demo purposes - actual limits would depend on business requirements
-    amount > 0 && amount <= 1_000_000
+    let max = 10_000 * 10i64.pow(amount.currency().minor_units() as u32);
+    amount.is_positive() && amount.amount() <= max
+}
+
+/// Validate a gift card PIN: 4 to 8 digits
+pub fn validate_pin(pin: &str) -> bool {
+    (4..=8).contains(&pin.len()) && pin.chars().all(|c| c.is_ascii_digit())
 }
 
 /// Validate expiration days
@@ -60,14 +76,28 @@ pub fn format_phone_for_display(phone: &str) -> String {
     }
 }
 
-/// Format money amount for display (cents to dollars)
-pub fn format_money(amount_cents: i32) -> String {
-    format!("${:.2}", amount_cents as f64 / 100.0)
+/// Format money amount for display, e.g. `$50.00` or `5000 KRW`
+pub fn format_money(amount: Money) -> String {
+    let symbol = match amount.currency().as_str() {
+        "USD" => "$",
+        "EUR" => "€",
+        "GBP" => "£",
+        _ => return amount.to_string(),
+    };
+    
+    // Reuse the decimal formatting of `Money` and swap the code for a symbol
+    let text = amount.to_string();
+    let number = text.trim_end_matches(amount.currency().as_str()).trim_end();
+    match number.strip_prefix('-') {
+        Some(number) => format!("-{}{}", symbol, number),
+        None => format!("{}{}", symbol, number),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::models::money::Currency;
 
     #[test]
     fn test_phone_validation() {
@@ -85,12 +115,42 @@ mod tests {
         assert!(!validate_name("Name with 123"));
     }
 
+    #[test]
+    fn test_email_validation() {
+        assert!(validate_email("billing@cafe.example"));
+        assert!(!validate_email("cafe.example"));
+        assert!(!validate_email("billing@cafe"));
+        assert!(!validate_email("two words@cafe.example"));
+    }
+
+    #[test]
+    fn test_pin_validation() {
+        assert!(validate_pin("0042"));
+        assert!(validate_pin("12345678"));
+        assert!(!validate_pin("123"));
+        assert!(!validate_pin("123456789"));
+        assert!(!validate_pin("12a4"));
+    }
+
     #[test]
     fn test_amount_validation() {
-        assert!(validate_amount(100));      // $1.00
-        assert!(validate_amount(999999));   // $9,999.99
-        assert!(!validate_amount(0));       // $0.00
-        assert!(!validate_amount(-100));    // Negative amount
-        assert!(!validate_amount(1000001)); // Over limit
+        let usd = |cents| Money::new(cents, Currency::USD);
+        assert!(validate_amount(usd(100)));      // $1.00
+        assert!(validate_amount(usd(999999)));   // $9,999.99
+        assert!(!validate_amount(usd(0)));       // $0.00
+        assert!(!validate_amount(usd(-100)));    // Negative amount
+        assert!(!validate_amount(usd(1000001))); // Over limit
+        
+        // Limits scale with the currency's minor units
+        let krw: Currency = "KRW".parse().unwrap();
+        assert!(validate_amount(Money::new(10_000, krw)));
+        assert!(!validate_amount(Money::new(10_001, krw)));
+    }
+
+    #[test]
+    fn test_format_money() {
+        assert_eq!(format_money(Money::new(5000, Currency::USD)), "$50.00");
+        assert_eq!(format_money(Money::new(-250, Currency::USD)), "-$2.50");
+        assert_eq!(format_money(Money::new(5000, "KRW".parse().unwrap())), "5000 KRW");
     }
 }
\ No newline at end of file
@@ -0,0 +1,289 @@
+use ab_glyph::FontArc;
+use image::{imageops, Rgba, RgbaImage};
+use qrcode::{EcLevel, QrCode};
+use serde::Deserialize;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::models::gift_card::GiftCard;
+use crate::utils::error::AppError;
+
+mod pdf;
+mod raster;
+
+/// Size of the artwork in layout units: a bank card (85.6 x 54 mm) at 300 dpi
+const WIDTH: f32 = 1012.0;
+const HEIGHT: f32 = 638.0;
+
+/// Space kept clear around the edge of the card
+const MARGIN: f32 = 48.0;
+
+/// Height of the coloured band across the top that holds the brand image
+const BAND: f32 = 150.0;
+
+/// Box the brand image is fitted into, at the left of the band
+const BRAND_BOX: (f32, f32) = (440.0, 96.0);
+
+/// Side of the QR code, at the right below the band
+const QR_SIZE: f32 = 380.0;
+
+/// Colours of the body text and its labels
+const INK: Rgba<u8> = Rgba([0x1f, 0x29, 0x37, 255]);
+const MUTED: Rgba<u8> = Rgba([0x6b, 0x72, 0x80, 255]);
+const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+/// File format a voucher is rendered in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VoucherFormat {
+    #[default]
+    Pdf,  // Vector artwork on a single page, using the built-in Helvetica fonts
+    Png,  // Raster artwork drawn with the fonts from VOUCHER_FONT_PATH
+}
+
+impl VoucherFormat {
+    /// MIME type of files in this format
+    pub fn content_type(self) -> &'static str {
+        match self {
+            VoucherFormat::Pdf => "application/pdf",
+            VoucherFormat::Png => "image/png",
+        }
+    }
+
+    /// File name extension for this format
+    pub fn extension(self) -> &'static str {
+        match self {
+            VoucherFormat::Pdf => "pdf",
+            VoucherFormat::Png => "png",
+        }
+    }
+}
+
+/// Query parameters for downloading a voucher
+#[derive(Debug, Default, Deserialize)]
+pub struct VoucherQuery {
+    pub format: Option<VoucherFormat>,  // pdf (default) or png
+}
+
+/// What is printed on a voucher
+#[derive(Debug, Clone)]
+pub struct Voucher {
+    pub issuer_name: String,
+    pub recipient_name: String,
+    pub balance: String,      // Formatted with its currency, e.g. `50.00 USD`
+    pub expires: String,      // Expiration date, e.g. `18 November 2026`
+    pub code: String,         // Human-readable code to type in when the QR code cannot be scanned
+    pub qr_content: String,   // Text the QR code holds
+}
+
+impl Voucher {
+    /// The voucher for `card`, with its short `code` and a QR code holding `qr_content`
+    pub fn for_card(card: &GiftCard, code: String, qr_content: String) -> Self {
+        Self {
+            issuer_name: card.issuer_name.clone(),
+            recipient_name: card.recipient_name.clone(),
+            balance: card.balance.to_string(),
+            expires: card.expiration_date.format("%-d %B %Y").to_string(),
+            code,
+            qr_content,
+        }
+    }
+}
+
+/// Fonts PNG vouchers are drawn with
+#[derive(Clone)]
+pub struct VoucherFonts {
+    pub regular: FontArc,
+    pub bold: FontArc,
+}
+
+impl fmt::Debug for VoucherFonts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("VoucherFonts")
+    }
+}
+
+/// Read a TrueType or OpenType font file
+pub fn load_font(path: &str) -> Result<FontArc, String> {
+    let data = std::fs::read(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    FontArc::try_from_vec(data).map_err(|_| format!("{} is not a TrueType or OpenType font", path))
+}
+
+/// Read a brand image from any file the `image` crate can decode
+pub fn load_brand_image(path: &str) -> Result<Arc<RgbaImage>, String> {
+    let image = image::open(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    Ok(Arc::new(image.to_rgba8()))
+}
+
+/// The look shared by every voucher, from the `VOUCHER_*` settings
+#[derive(Debug, Clone)]
+pub struct VoucherTemplate {
+    pub brand_image: Option<Arc<RgbaImage>>,  // Shown in the top band
+    pub fonts: Option<VoucherFonts>,          // Needed for PNG vouchers only
+    pub accent: Rgba<u8>,                     // Colour of the top band and the balance
+}
+
+/// Text weight; PDFs use Helvetica and Helvetica-Bold, PNGs the configured fonts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Weight {
+    Regular,
+    Bold,
+}
+
+/// Measures text the way a renderer will draw it
+trait Typeface {
+    /// Advance width of `text` at `size` units per em
+    fn text_width(&self, text: &str, size: f32, weight: Weight) -> f32;
+}
+
+/// One thing drawn on the card, positioned in layout units from the top-left corner
+enum Element {
+    Rect { x: f32, y: f32, width: f32, height: f32, color: Rgba<u8> },
+    Image { x: f32, y: f32, image: RgbaImage, matte: Rgba<u8> },  // Already at its drawn size; `matte` is what lies behind it
+    Text { x: f32, baseline: f32, size: f32, weight: Weight, color: Rgba<u8>, text: String },
+    Qr { x: f32, y: f32, size: f32, code: QrCode, color: Rgba<u8> },
+}
+
+impl VoucherTemplate {
+    /// Render `voucher` as a file in `format`
+    pub fn render(&self, voucher: &Voucher, format: VoucherFormat) -> Result<Vec<u8>, AppError> {
+        match format {
+            VoucherFormat::Pdf => {
+                let elements = self.layout(voucher, &pdf::Helvetica)?;
+                pdf::render(&elements, WIDTH, HEIGHT)
+            }
+            VoucherFormat::Png => {
+                let fonts = self.fonts.as_ref().ok_or_else(|| {
+                    AppError::ValidationError("No voucher font is configured, so only PDF vouchers are available".to_string())
+                })?;
+                let elements = self.layout(voucher, fonts)?;
+                raster::render(&elements, fonts, WIDTH as u32, HEIGHT as u32)
+            }
+        }
+    }
+
+    /// Lay the card out: the brand band on top, the recipient, balance, expiry
+    /// and code down the left, and the QR code on the right
+    fn layout(&self, voucher: &Voucher, typeface: &dyn Typeface) -> Result<Vec<Element>, AppError> {
+        // Printed codes get scuffed, so they carry more error correction than on screen
+        let code = QrCode::with_error_correction_level(voucher.qr_content.as_bytes(), EcLevel::Q)
+            .map_err(|e| AppError::InternalServerError(format!("Failed to encode QR code: {}", e)))?;
+
+        let mut elements = vec![Element::Rect { x: 0.0, y: 0.0, width: WIDTH, height: BAND, color: self.accent }];
+
+        if let Some(brand) = &self.brand_image {
+            let scale = (BRAND_BOX.0 / brand.width() as f32).min(BRAND_BOX.1 / brand.height() as f32);
+            let width = ((brand.width() as f32 * scale).round() as u32).max(1);
+            let height = ((brand.height() as f32 * scale).round() as u32).max(1);
+            elements.push(Element::Image {
+                x: MARGIN,
+                y: ((BAND - height as f32) / 2.0).round(),
+                image: imageops::resize(brand.as_ref(), width, height, imageops::FilterType::Lanczos3),
+                matte: self.accent,
+            });
+        }
+
+        let title = "GIFT CARD";
+        let title_size = 44.0;
+        elements.push(Element::Text {
+            x: WIDTH - MARGIN - typeface.text_width(title, title_size, Weight::Bold),
+            baseline: BAND / 2.0 + 16.0,
+            size: title_size,
+            weight: Weight::Bold,
+            color: WHITE,
+            text: title.to_string(),
+        });
+
+        // Each line shrinks to fit the column between the margin and the QR code
+        let column = WIDTH - 2.0 * MARGIN - QR_SIZE - 40.0;
+        let mut line = |baseline: f32, size: f32, weight: Weight, color: Rgba<u8>, text: String| {
+            let width = typeface.text_width(&text, size, weight);
+            let size = if width > column { size * column / width } else { size };
+            elements.push(Element::Text { x: MARGIN, baseline, size, weight, color, text });
+        };
+
+        line(214.0, 22.0, Weight::Bold, MUTED, "FOR".to_string());
+        line(262.0, 44.0, Weight::Bold, INK, voucher.recipient_name.clone());
+        line(298.0, 24.0, Weight::Regular, MUTED, format!("From {}", voucher.issuer_name));
+        line(358.0, 22.0, Weight::Bold, MUTED, "BALANCE".to_string());
+        line(420.0, 60.0, Weight::Bold, self.accent, voucher.balance.clone());
+        line(470.0, 22.0, Weight::Bold, MUTED, "VALID UNTIL".to_string());
+        line(508.0, 30.0, Weight::Regular, INK, voucher.expires.clone());
+        line(556.0, 22.0, Weight::Bold, MUTED, "CARD CODE".to_string());
+        line(590.0, 26.0, Weight::Regular, INK, voucher.code.clone());
+
+        elements.push(Element::Qr {
+            x: WIDTH - MARGIN - QR_SIZE,
+            y: BAND + (HEIGHT - BAND - QR_SIZE) / 2.0,
+            size: QR_SIZE,
+            code,
+            color: INK,
+        });
+
+        Ok(elements)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn voucher(recipient_name: &str) -> Voucher {
+        Voucher {
+            issuer_name: "Alice".to_string(),
+            recipient_name: recipient_name.to_string(),
+            balance: "50.00 USD".to_string(),
+            expires: "18 November 2026".to_string(),
+            code: "7K3D-9QXW-M2PA-T4HC".to_string(),
+            qr_content: "giftcard:voucher".to_string(),
+        }
+    }
+
+    fn template() -> VoucherTemplate {
+        VoucherTemplate { brand_image: None, fonts: None, accent: Rgba([0x1f, 0x3a, 0x93, 255]) }
+    }
+
+    #[test]
+    fn test_long_text_is_shrunk_to_fit_its_column() {
+        let elements = template().layout(&voucher(&"Bartholomew ".repeat(6)), &pdf::Helvetica).unwrap();
+
+        for element in &elements {
+            if let Element::Text { x, size, weight, text, .. } = element {
+                let right = x + pdf::Helvetica.text_width(text, *size, *weight);
+                assert!(*x >= MARGIN && right <= WIDTH - MARGIN + 0.01, "{:?} overflows", text);
+                if text.starts_with("Bartholomew") {
+                    assert!(right <= WIDTH - MARGIN - QR_SIZE - 40.0 + 0.01);
+                    assert!(*size < 44.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_pdf_voucher_is_a_single_page() {
+        let brand = RgbaImage::from_pixel(200, 50, Rgba([255, 200, 0, 255]));
+        let template = VoucherTemplate { brand_image: Some(Arc::new(brand)), ..template() };
+        let pdf = template.render(&voucher("Bob (\"Bobby\")"), VoucherFormat::Pdf).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+
+        assert!(text.starts_with("%PDF-1.4"));
+        assert!(text.ends_with("%%EOF\n"));
+        assert!(text.contains("/Count 1"));
+        assert!(text.contains("/Filter /DCTDecode"));
+        assert!(text.contains(r#"(Bob \("Bobby"\)) Tj"#));
+
+        // The cross-reference table points at each object
+        let startxref: usize = text.rsplit("startxref\n").next().unwrap().lines().next().unwrap().parse().unwrap();
+        let xref = String::from_utf8(pdf[startxref..].to_vec()).unwrap();
+        for (number, entry) in xref.lines().skip(3).take_while(|l| l.ends_with(" n ")).enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(pdf[offset..].starts_with(format!("{} 0 obj", number + 1).as_bytes()));
+        }
+    }
+
+    #[test]
+    fn test_png_voucher_needs_a_font() {
+        let err = template().render(&voucher("Bob"), VoucherFormat::Png).unwrap_err();
+        assert!(matches!(err, AppError::ValidationError(_)));
+    }
+}
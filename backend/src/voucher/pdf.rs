@@ -0,0 +1,237 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::{ExtendedColorType, Rgba, RgbaImage};
+use qrcode::Color;
+use std::fmt::Write;
+
+use crate::utils::error::AppError;
+use super::{Element, Typeface, Weight};
+
+/// Points per layout unit, which lays the card out at 300 dpi
+const POINTS_PER_UNIT: f32 = 72.0 / 300.0;
+
+/// Advance widths of ASCII 32 to 126 in Helvetica, in thousandths of an em
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556,
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556,
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556,
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+/// Advance widths of ASCII 32 to 126 in Helvetica-Bold, in thousandths of an em
+const HELVETICA_BOLD_WIDTHS: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611,
+    975, 722, 722, 722, 722, 667, 611, 778, 722, 278, 556, 722, 611, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 333, 278, 333, 584, 556,
+    333, 556, 611, 556, 611, 556, 333, 611, 611, 278, 278, 556, 278, 889, 611, 611,
+    611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584,
+];
+
+/// The standard Helvetica fonts every PDF reader provides, so nothing is embedded
+pub(super) struct Helvetica;
+
+impl Typeface for Helvetica {
+    fn text_width(&self, text: &str, size: f32, weight: Weight) -> f32 {
+        let widths = match weight {
+            Weight::Regular => &HELVETICA_WIDTHS,
+            Weight::Bold => &HELVETICA_BOLD_WIDTHS,
+        };
+        // Characters outside ASCII are measured at the width of a digit
+        let units: u32 = win_ansi(text)
+            .into_iter()
+            .map(|byte| match byte {
+                32..=126 => u32::from(widths[usize::from(byte - 32)]),
+                _ => 556,
+            })
+            .sum();
+        units as f32 * size / 1000.0
+    }
+}
+
+/// Write `elements` as a one-page PDF of `width` by `height` layout units
+pub(super) fn render(elements: &[Element], width: f32, height: f32) -> Result<Vec<u8>, AppError> {
+    // Layout units run down from the top-left corner, so the page is flipped
+    // once here and text is flipped back where it is drawn
+    let mut content = format!("{} 0 0 {} 0 {} cm\n", num(POINTS_PER_UNIT), num(-POINTS_PER_UNIT), num(height * POINTS_PER_UNIT));
+    let mut images = Vec::new();
+
+    for element in elements {
+        match element {
+            Element::Rect { x, y, width, height, color } => {
+                let _ = writeln!(content, "{} rg {} {} {} {} re f", rgb(*color), num(*x), num(*y), num(*width), num(*height));
+            }
+            Element::Image { x, y, image, matte } => {
+                images.push(image_object(image, *matte)?);
+                let (w, h) = (image.width() as f32, image.height() as f32);
+                let _ = writeln!(content, "q {} 0 0 {} {} {} cm /Im{} Do Q", num(w), num(-h), num(*x), num(y + h), images.len());
+            }
+            Element::Text { x, baseline, size, weight, color, text } => {
+                let font = match weight {
+                    Weight::Regular => "F1",
+                    Weight::Bold => "F2",
+                };
+                let _ = writeln!(
+                    content,
+                    "BT /{} {} Tf {} rg 1 0 0 -1 {} {} Tm ({}) Tj ET",
+                    font,
+                    num(*size),
+                    rgb(*color),
+                    num(*x),
+                    num(*baseline),
+                    escape(&win_ansi(text))
+                );
+            }
+            Element::Qr { x, y, size, code, color } => {
+                let modules = code.width();
+                let scale = size / modules as f32;
+                let _ = writeln!(content, "{} rg", rgb(*color));
+                // One rectangle per run of dark modules along a row
+                for (row, colors) in code.to_colors().chunks(modules).enumerate() {
+                    let mut column = 0;
+                    while column < modules {
+                        if colors[column] == Color::Light {
+                            column += 1;
+                            continue;
+                        }
+                        let start = column;
+                        while column < modules && colors[column] == Color::Dark {
+                            column += 1;
+                        }
+                        let _ = writeln!(
+                            content,
+                            "{} {} {} {} re",
+                            num(x + start as f32 * scale),
+                            num(y + row as f32 * scale),
+                            num((column - start) as f32 * scale),
+                            num(scale)
+                        );
+                    }
+                }
+                content.push_str("f\n");
+            }
+        }
+    }
+
+    let image_names: String = (1..=images.len()).map(|i| format!(" /Im{} {} 0 R", i, 6 + i)).collect();
+    let mut objects: Vec<Vec<u8>> = vec![
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+        b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_vec(),
+        format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Contents 4 0 R /Resources << /Font << /F1 5 0 R /F2 6 0 R >> /XObject <<{} >> >> >>",
+            num(width * POINTS_PER_UNIT),
+            num(height * POINTS_PER_UNIT),
+            image_names
+        )
+        .into_bytes(),
+        stream("", content.as_bytes()),
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_vec(),
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>".to_vec(),
+    ];
+    objects.extend(images);
+
+    let mut pdf = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+        pdf.extend_from_slice(object);
+        pdf.extend_from_slice(b"\nendobj\n");
+    }
+
+    let startxref = pdf.len();
+    let mut trailer = format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        let _ = writeln!(trailer, "{:010} 00000 n ", offset);
+    }
+    let _ = write!(
+        trailer,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        startxref
+    );
+    pdf.extend_from_slice(trailer.as_bytes());
+    Ok(pdf)
+}
+
+/// A stream object with `entries` added to its dictionary
+fn stream(entries: &str, data: &[u8]) -> Vec<u8> {
+    let mut object = format!("<< /Length {}{} >>\nstream\n", data.len(), entries).into_bytes();
+    object.extend_from_slice(data);
+    object.extend_from_slice(b"\nendstream");
+    object
+}
+
+/// An image XObject holding `image` as a JPEG, flattened onto `matte`
+fn image_object(image: &RgbaImage, matte: Rgba<u8>) -> Result<Vec<u8>, AppError> {
+    let rgb: Vec<u8> = image
+        .pixels()
+        .flat_map(|pixel| {
+            let alpha = u16::from(pixel[3]);
+            (0..3).map(move |c| ((u16::from(pixel[c]) * alpha + u16::from(matte[c]) * (255 - alpha)) / 255) as u8)
+        })
+        .collect();
+
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg, 90)
+        .encode(&rgb, image.width(), image.height(), ExtendedColorType::Rgb8)
+        .map_err(|e| AppError::InternalServerError(format!("Failed to encode brand image: {}", e)))?;
+
+    Ok(stream(
+        &format!(
+            " /Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /DCTDecode",
+            image.width(),
+            image.height()
+        ),
+        &jpeg,
+    ))
+}
+
+/// Encode text for the WinAnsi encoding the fonts use; characters it lacks become `?`
+fn win_ansi(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c {
+            ' '..='~' | '\u{a0}'..='\u{ff}' => c as u8,
+            '€' => 0x80,
+            '‘' => 0x91,
+            '’' => 0x92,
+            '“' => 0x93,
+            '”' => 0x94,
+            '•' => 0x95,
+            '–' => 0x96,
+            '—' => 0x97,
+            _ => b'?',
+        })
+        .collect()
+}
+
+/// Escape encoded text for a PDF string literal
+fn escape(bytes: &[u8]) -> String {
+    let mut escaped = String::with_capacity(bytes.len());
+    for &byte in bytes {
+        match byte {
+            b'(' | b')' | b'\\' => {
+                escaped.push('\\');
+                escaped.push(byte as char);
+            }
+            32..=126 => escaped.push(byte as char),
+            _ => {
+                let _ = write!(escaped, "\\{:03o}", byte);
+            }
+        }
+    }
+    escaped
+}
+
+/// Fill colour operands for `color`
+fn rgb(color: Rgba<u8>) -> String {
+    format!("{} {} {}", num(f32::from(color[0]) / 255.0), num(f32::from(color[1]) / 255.0), num(f32::from(color[2]) / 255.0))
+}
+
+/// A number with at most three decimals and no trailing zeros
+fn num(value: f32) -> String {
+    let formatted = format!("{:.3}", value);
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+    if trimmed == "-0" { "0".to_string() } else { trimmed.to_string() }
+}
@@ -0,0 +1,120 @@
+use ab_glyph::{point, Font, FontArc, PxScale, ScaleFont};
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+use qrcode::Color;
+
+use crate::utils::error::AppError;
+use crate::utils::qr_render::encode_png;
+use super::{Element, Typeface, VoucherFonts, Weight};
+
+impl VoucherFonts {
+    fn font(&self, weight: Weight) -> &FontArc {
+        match weight {
+            Weight::Regular => &self.regular,
+            Weight::Bold => &self.bold,
+        }
+    }
+}
+
+impl Typeface for VoucherFonts {
+    fn text_width(&self, text: &str, size: f32, weight: Weight) -> f32 {
+        let font = self.font(weight);
+        let scaled = font.as_scaled(em_scale(font, size));
+        let mut width = 0.0;
+        let mut previous = None;
+        for c in text.chars() {
+            let id = scaled.glyph_id(c);
+            if let Some(previous) = previous {
+                width += scaled.kern(previous, id);
+            }
+            width += scaled.h_advance(id);
+            previous = Some(id);
+        }
+        width
+    }
+}
+
+/// Draw `elements` onto a white `width` by `height` image and encode it as PNG
+///
+/// A layout unit is one pixel.
+pub(super) fn render(elements: &[Element], fonts: &VoucherFonts, width: u32, height: u32) -> Result<Vec<u8>, AppError> {
+    let mut image = RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 255]));
+
+    for element in elements {
+        match element {
+            Element::Rect { x, y, width, height, color } => {
+                fill(&mut image, *x as i64, *y as i64, *width as u32, *height as u32, *color);
+            }
+            Element::Image { x, y, image: picture, .. } => {
+                imageops::overlay(&mut image, picture, *x as i64, *y as i64);
+            }
+            Element::Text { x, baseline, size, weight, color, text } => {
+                draw_text(&mut image, fonts.font(*weight), *x, *baseline, *size, *color, text);
+            }
+            Element::Qr { x, y, size, code, color } => {
+                // Whole pixels per module keep the edges sharp; the symbol is centred in its box
+                let modules = code.width() as u32;
+                let scale = (*size as u32 / modules).max(1);
+                let offset = (*size as u32).saturating_sub(scale * modules) / 2;
+                for (i, module) in code.to_colors().into_iter().enumerate() {
+                    if module == Color::Dark {
+                        let (column, row) = (i as u32 % modules, i as u32 / modules);
+                        let left = *x as i64 + i64::from(offset + column * scale);
+                        let top = *y as i64 + i64::from(offset + row * scale);
+                        fill(&mut image, left, top, scale, scale, *color);
+                    }
+                }
+            }
+        }
+    }
+
+    encode_png(&DynamicImage::ImageRgba8(image))
+        .map_err(|e| AppError::InternalServerError(format!("Failed to encode voucher: {}", e)))
+}
+
+/// The `PxScale` that gives `font` an em of `size` pixels, matching PDF font sizes
+fn em_scale(font: &FontArc, size: f32) -> PxScale {
+    let units_per_em = font.units_per_em().unwrap_or(1000.0);
+    PxScale::from(size * font.height_unscaled() / units_per_em)
+}
+
+fn fill(image: &mut RgbaImage, x: i64, y: i64, width: u32, height: u32, color: Rgba<u8>) {
+    for py in y.max(0)..(y + i64::from(height)).min(i64::from(image.height())) {
+        for px in x.max(0)..(x + i64::from(width)).min(i64::from(image.width())) {
+            image.put_pixel(px as u32, py as u32, color);
+        }
+    }
+}
+
+/// Draw `text` with its baseline starting at `x`, `baseline`, blending
+/// anti-aliased edges into what is already there
+fn draw_text(image: &mut RgbaImage, font: &FontArc, x: f32, baseline: f32, size: f32, color: Rgba<u8>, text: &str) {
+    let scale = em_scale(font, size);
+    let scaled = font.as_scaled(scale);
+    let mut caret = x;
+    let mut previous = None;
+
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, id);
+        }
+        let glyph = id.with_scale_and_position(scale, point(caret, baseline));
+        caret += scaled.h_advance(id);
+        previous = Some(id);
+
+        let Some(outlined) = font.outline_glyph(glyph) else { continue };
+        let bounds = outlined.px_bounds();
+        outlined.draw(|gx, gy, coverage| {
+            let px = bounds.min.x as i64 + i64::from(gx);
+            let py = bounds.min.y as i64 + i64::from(gy);
+            if px < 0 || py < 0 || px >= i64::from(image.width()) || py >= i64::from(image.height()) {
+                return;
+            }
+            let pixel = image.get_pixel_mut(px as u32, py as u32);
+            let coverage = coverage.clamp(0.0, 1.0);
+            for c in 0..3 {
+                pixel[c] = (f32::from(color[c]) * coverage + f32::from(pixel[c]) * (1.0 - coverage)).round() as u8;
+            }
+        });
+    }
+}
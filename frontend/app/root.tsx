@@ -1,4 +1,5 @@
 import {
+  Form,
   Links,
   LiveReload,
   Meta,
@@ -6,9 +7,12 @@ import {
   Scripts,
   ScrollRestoration,
   isRouteErrorResponse,
+  useLoaderData,
   useRouteError,
 } from "@remix-run/react";
-import type { LinksFunction, MetaFunction } from "@remix-run/node";
+import { json } from "@remix-run/node";
+import type { LinksFunction, LoaderFunction, MetaFunction } from "@remix-run/node";
+import { getAccount } from "./session.server";
 import globalStyles from "./styles/global.css";
 import customStyles from "./styles/custom.css";
 
@@ -28,7 +32,13 @@ export const meta: MetaFunction = () => {
   ];
 };
 
+export const loader: LoaderFunction = async ({ request }) => {
+  return json({ account: await getAccount(request) });
+};
+
 export default function App() {
+  const { account } = useLoaderData<typeof loader>();
+
   return (
     <html lang="en">
       <head>
@@ -53,6 +63,19 @@ export default function App() {
                   <li>
                     <a href="/gift-cards/search" className="hover:underline">Find Your Cards</a>
                   </li>
+                  {account ? (
+                    <li>
+                      <Form method="post" action="/logout">
+                        <button type="submit" className="hover:underline">
+                          Log Out {account.name}
+                        </button>
+                      </Form>
+                    </li>
+                  ) : (
+                    <li>
+                      <a href="/login" className="hover:underline">Log In</a>
+                    </li>
+                  )}
                 </ul>
               </nav>
             </div>
@@ -3,6 +3,7 @@ import { json, redirect } from "@remix-run/node";
 import type { LoaderFunction, ActionFunction, MetaFunction } from "@remix-run/node";
 import { useState } from "react";
 import dayjs from "dayjs";
+import { API_BASE_URL, authHeaders, requireAccessToken, requireLogin } from "~/session.server";
 
 export const meta: MetaFunction = ({ data }) => {
   return [
@@ -11,16 +12,39 @@ export const meta: MetaFunction = ({ data }) => {
   ];
 };
 
+type CardStatus =
+  | "issued"
+  | "accepted"
+  | "depleted"
+  | "expired"
+  | "suspended"
+  | "cancelled"
+  | "replaced";
+
+// Only issued and accepted cards can still be accepted or used
+const isLive = (status: CardStatus) => status === "issued" || status === "accepted";
+
+type Money = {
+  amount: number; // In the currency's minor unit, e.g. cents
+  currency: string;
+};
+
+const formatMoney = ({ amount, currency }: Money) => {
+  const format = new Intl.NumberFormat("en-US", { style: "currency", currency });
+  const digits = format.resolvedOptions().maximumFractionDigits ?? 2;
+  return format.format(amount / 10 ** digits);
+};
+
 type GiftCard = {
   id: string;
   issuer_name: string;
   recipient_name: string;
   recipient_phone: string;
-  balance: number;
-  initial_balance: number;
+  balance: Money;
+  initial_balance: Money;
   expiration_date: string;
-  is_accepted: boolean;
-  is_active: boolean;
+  status: CardStatus;
+  accepted_at: string | null;
   qr_code: string | null;
   created_at: string;
 };
@@ -34,16 +58,24 @@ type LoaderData = {
   isExpired: boolean;
 };
 
-export const loader: LoaderFunction = async ({ params }) => {
+export const loader: LoaderFunction = async ({ request, params }) => {
   const { id } = params;
   
   if (!id) {
     throw new Response("Gift card ID is required", { status: 400 });
   }
   
+  const token = await requireAccessToken(request);
+  
   try {
     // Fetch gift card data from API
-    const response = await fetch(`http://localhost:8080/api/gift-cards/${id}`);
+    const response = await fetch(`${API_BASE_URL}/api/gift-cards/${id}`, {
+      headers: authHeaders(token),
+    });
+    if (response.status === 401) {
+      return requireLogin(request);
+    }
+    
     const responseData = await response.json();
     
     if (!response.ok) {
@@ -55,8 +87,8 @@ export const loader: LoaderFunction = async ({ params }) => {
     const giftCard = responseData.data;
     
     // Format data for display
-    const formattedBalance = (giftCard.balance / 100).toFixed(2);
-    const formattedInitialBalance = (giftCard.initial_balance / 100).toFixed(2);
+    const formattedBalance = formatMoney(giftCard.balance);
+    const formattedInitialBalance = formatMoney(giftCard.initial_balance);
     
     // Format dates
     const expirationDate = dayjs(giftCard.expiration_date);
@@ -74,42 +106,62 @@ export const loader: LoaderFunction = async ({ params }) => {
       isExpired
     });
   } catch (error) {
+    // Keep the API's answer, e.g. a 403 for someone else's card
+    if (error instanceof Response) {
+      throw error;
+    }
     throw new Response("Failed to load gift card", { status: 500 });
   }
 };
 
+// Accepting takes two steps: a one-time code is texted to the recipient phone,
+// then the recipient enters it to accept the card
 export const action: ActionFunction = async ({ request, params }) => {
   const { id } = params;
+  const token = await requireAccessToken(request);
   const formData = await request.formData();
-  const phone = formData.get("phone") as string;
+  const intent = formData.get("intent");
+  const code = (formData.get("code") as string | null)?.trim();
   
-  if (!phone) {
+  if (intent === "accept" && !code) {
     return json({ 
-      errors: { phone: "Phone number is required" } 
+      codeSent: true,
+      errors: { code: "Enter the code we texted you" } 
     }, { status: 400 });
   }
   
   try {
-    // Accept gift card API call
-    const response = await fetch(`http://localhost:8080/api/gift-cards/${id}/accept`, {
-      method: "POST",
-      headers: {
-        "Content-Type": "application/json",
-      },
-      body: JSON.stringify({
-        gift_card_id: id,
-        recipient_phone: phone,
-      }),
-    });
+    // Send the code, or accept the card with it
+    const response = intent === "accept"
+      ? await fetch(`${API_BASE_URL}/api/gift-cards/${id}/accept`, {
+          method: "POST",
+          headers: authHeaders(token),
+          body: JSON.stringify({
+            gift_card_id: id,
+            code,
+          }),
+        })
+      : await fetch(`${API_BASE_URL}/api/gift-cards/${id}/acceptance-code`, {
+          method: "POST",
+          headers: authHeaders(token),
+        });
+    if (response.status === 401) {
+      return requireLogin(request);
+    }
     
     const responseData = await response.json();
     
     if (!response.ok) {
       return json({ 
+        codeSent: intent === "accept",
         errors: { 
           _form: responseData.message || "Failed to accept gift card" 
         } 
-      }, { status: 400 });
+      }, { status: response.status });
+    }
+    
+    if (intent !== "accept") {
+      return json({ codeSent: true, sentTo: responseData.data.sent_to });
     }
     
     // Redirect to same page to show updated status
@@ -152,12 +204,12 @@ export default function GiftCardDetails() {
             <div className="gift-card-label">Status</div>
             <div className="flex items-center">
               <span className={`inline-block w-2 h-2 rounded-full mr-2 ${
-                !giftCard.is_active ? 'bg-red-500' : 
-                giftCard.is_accepted ? 'bg-green-500' : 'bg-yellow-500'
+                !isLive(giftCard.status) ? 'bg-red-500' : 
+                giftCard.accepted_at !== null ? 'bg-green-500' : 'bg-yellow-500'
               }`}></span>
               <span>
-                {!giftCard.is_active ? 'Inactive' : 
-                 giftCard.is_accepted ? 'Active' : 'Pending Acceptance'}
+                {!isLive(giftCard.status) ? 'Inactive' : 
+                 giftCard.accepted_at !== null ? 'Active' : 'Pending Acceptance'}
               </span>
             </div>
           </div>
@@ -179,9 +231,9 @@ export default function GiftCardDetails() {
           <div className="flex justify-between items-end mt-4">
             <div>
               <div className="gift-card-label">Balance</div>
-              <div className="gift-card-amount">${formattedBalance}</div>
+              <div className="gift-card-amount">{formattedBalance}</div>
               <div className="text-sm opacity-80">
-                Original: ${formattedInitialBalance}
+                Original: {formattedInitialBalance}
               </div>
             </div>
             <div className="text-right">
@@ -201,7 +253,7 @@ export default function GiftCardDetails() {
             <div className="gift-card-label">Created</div>
             <div>{dayjs(giftCard.created_at).format("MMM D, YYYY")}</div>
           </div>
-          {giftCard.is_accepted && (
+          {giftCard.accepted_at !== null && (
             <div className="text-right">
               <div className="gift-card-label">Card ID</div>
               <div>{giftCard.id}</div>
@@ -211,7 +263,7 @@ export default function GiftCardDetails() {
       </div>
       
       {/* QR Code Display */}
-      {giftCard.is_accepted && giftCard.is_active && !isExpired && (
+      {giftCard.accepted_at !== null && isLive(giftCard.status) && !isExpired && (
         <div className="card p-6 mb-8 text-center">
           <h2 className="text-xl font-semibold mb-4">Payment QR Code</h2>
           {giftCard.qr_code ? (
@@ -230,7 +282,7 @@ export default function GiftCardDetails() {
       )}
       
       {/* Accept Gift Card Form */}
-      {!giftCard.is_accepted && (
+      {giftCard.accepted_at === null && (
         <div className="card p-6 mb-8">
           <h2 className="text-xl font-semibold mb-4">Accept this Gift Card</h2>
           <p className="mb-4 text-gray-600">
@@ -252,50 +304,85 @@ export default function GiftCardDetails() {
                 </div>
               )}
               
-              <Form method="post" className="space-y-4">
-                <div>
-                  <label htmlFor="phone" className="form-label">
-                    Confirm your phone number
-                  </label>
-                  <input
-                    type="tel"
-                    id="phone"
-                    name="phone"
-                    className={`form-input ${actionData?.errors?.phone ? 'border-red-500' : ''}`}
-                    placeholder="Enter your phone number"
-                  />
-                  {actionData?.errors?.phone && (
-                    <p className="form-error">{actionData.errors.phone}</p>
-                  )}
-                  <p className="mt-1 text-sm text-gray-500">
-                    Must match the recipient phone number
+              {!actionData?.codeSent ? (
+                <Form method="post" className="space-y-4">
+                  <p className="text-gray-600">
+                    We will text a one-time code to the phone number this gift card was sent to.
                   </p>
-                </div>
-                
-                <div className="flex space-x-4">
-                  <button
-                    type="submit"
-                    disabled={isSubmitting}
-                    className="btn btn-primary"
-                  >
-                    {isSubmitting ? "Accepting..." : "Accept Gift Card"}
-                  </button>
-                  <button
-                    type="button"
-                    onClick={() => setShowAcceptForm(false)}
-                    className="btn btn-secondary"
-                  >
-                    Cancel
-                  </button>
-                </div>
-              </Form>
+                  
+                  <div className="flex space-x-4">
+                    <button
+                      type="submit"
+                      name="intent"
+                      value="send-code"
+                      disabled={isSubmitting}
+                      className="btn btn-primary"
+                    >
+                      {isSubmitting ? "Sending..." : "Send Me a Code"}
+                    </button>
+                    <button
+                      type="button"
+                      onClick={() => setShowAcceptForm(false)}
+                      className="btn btn-secondary"
+                    >
+                      Cancel
+                    </button>
+                  </div>
+                </Form>
+              ) : (
+                <Form method="post" className="space-y-4">
+                  <div>
+                    <label htmlFor="code" className="form-label">
+                      Enter the code we texted you
+                    </label>
+                    <input
+                      type="text"
+                      id="code"
+                      name="code"
+                      inputMode="numeric"
+                      autoComplete="one-time-code"
+                      className={`form-input ${actionData?.errors?.code ? 'border-red-500' : ''}`}
+                      placeholder="6-digit code"
+                    />
+                    {actionData?.errors?.code && (
+                      <p className="form-error">{actionData.errors.code}</p>
+                    )}
+                    {actionData?.sentTo && (
+                      <p className="mt-1 text-sm text-gray-500">
+                        Sent to {actionData.sentTo}
+                      </p>
+                    )}
+                  </div>
+                  
+                  <div className="flex space-x-4">
+                    <button
+                      type="submit"
+                      name="intent"
+                      value="accept"
+                      disabled={isSubmitting}
+                      className="btn btn-primary"
+                    >
+                      {isSubmitting ? "Accepting..." : "Accept Gift Card"}
+                    </button>
+                    <button
+                      type="submit"
+                      name="intent"
+                      value="send-code"
+                      disabled={isSubmitting}
+                      className="btn btn-secondary"
+                    >
+                      Send a New Code
+                    </button>
+                  </div>
+                </Form>
+              )}
             </>
           )}
         </div>
       )}
       
       {/* Expired or Inactive Notice */}
-      {(isExpired || !giftCard.is_active) && (
+      {(isExpired || !isLive(giftCard.status)) && (
         <div className="bg-red-50 border border-red-200 text-red-700 p-4 rounded-md mb-8">
           <h3 className="font-semibold mb-2">
             {isExpired ? "Gift Card Expired" : "Gift Card Inactive"}
@@ -312,7 +399,7 @@ export default function GiftCardDetails() {
       <div className="card p-6">
         <h2 className="text-xl font-semibold mb-4">How to Use Your Gift Card</h2>
         <ol className="list-decimal ml-5 space-y-2">
-          <li>Accept the gift card by confirming your phone number</li>
+          <li>Accept the gift card with the code texted to your phone</li>
           <li>Present the QR code at any payment location</li>
           <li>The merchant will scan your code and enter the payment amount</li>
           <li>Your gift card balance will be updated automatically</li>
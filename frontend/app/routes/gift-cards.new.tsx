@@ -1,8 +1,9 @@
-import { useActionData, Form, useNavigation } from "@remix-run/react";
+import { useActionData, useLoaderData, Form, useNavigation } from "@remix-run/react";
 import { json, redirect } from "@remix-run/node";
-import type { ActionFunction, MetaFunction } from "@remix-run/node";
+import type { ActionFunction, LoaderFunction, MetaFunction } from "@remix-run/node";
 import { useState } from "react";
 import { z } from "zod";
+import { API_BASE_URL, authHeaders, getAccount, requireAccessToken, requireLogin } from "~/session.server";
 
 export const meta: MetaFunction = () => {
   return [
@@ -11,9 +12,8 @@ export const meta: MetaFunction = () => {
   ];
 };
 
-// Form validation schema
+// Form validation schema; the card is issued by the logged-in account
 const giftCardSchema = z.object({
-  issuerName: z.string().min(2, "Issuer name is required"),
   recipientName: z.string().min(2, "Recipient name is required"),
   recipientPhone: z.string().min(10, "Valid phone number is required"),
   amount: z.string().refine(
@@ -39,7 +39,13 @@ type FormErrors = {
   _form?: string;
 };
 
+export const loader: LoaderFunction = async ({ request }) => {
+  await requireAccessToken(request);
+  return json({ account: await getAccount(request) });
+};
+
 export const action: ActionFunction = async ({ request }) => {
+  const token = await requireAccessToken(request);
   const formData = await request.formData();
   const rawFormData = Object.fromEntries(formData);
   
@@ -51,20 +57,21 @@ export const action: ActionFunction = async ({ request }) => {
     const amountCents = Math.floor(parseFloat(validatedData.amount) * 100);
     
     // API call to create gift card
-    const response = await fetch("http://localhost:8080/api/gift-cards", {
+    const response = await fetch(`${API_BASE_URL}/api/gift-cards`, {
       method: "POST",
-      headers: {
-        "Content-Type": "application/json",
-      },
+      headers: authHeaders(token),
       body: JSON.stringify({
-        issuer_name: validatedData.issuerName,
         recipient_name: validatedData.recipientName,
         recipient_phone: validatedData.recipientPhone,
-        balance: amountCents,
+        balance: { amount: amountCents, currency: "USD" },
         expiration_days: parseInt(validatedData.expirationDays),
       }),
     });
     
+    if (response.status === 401) {
+      return requireLogin(request);
+    }
+    
     const responseData = await response.json();
     
     if (!response.ok) {
@@ -94,6 +101,7 @@ export const action: ActionFunction = async ({ request }) => {
 };
 
 export default function CreateGiftCard() {
+  const { account } = useLoaderData<typeof loader>();
   const actionData = useActionData<typeof action>();
   const navigation = useNavigation();
   const isSubmitting = navigation.state === "submitting";
@@ -124,19 +132,9 @@ export default function CreateGiftCard() {
           {/* Issuer Information */}
           <div>
             <h2 className="text-xl font-semibold mb-4">Your Information</h2>
-            <div className="mb-4">
-              <label htmlFor="issuerName" className="form-label">Your Name</label>
-              <input
-                type="text"
-                id="issuerName"
-                name="issuerName"
-                className={`form-input ${actionData?.errors?.issuerName ? 'border-red-500' : ''}`}
-                placeholder="Enter your name"
-              />
-              {actionData?.errors?.issuerName && (
-                <p className="form-error">{actionData.errors.issuerName}</p>
-              )}
-            </div>
+            <p className="text-gray-600">
+              The gift card will be from {account.name}, the account you are logged in as.
+            </p>
           </div>
           
           {/* Recipient Information */}
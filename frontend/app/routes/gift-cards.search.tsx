@@ -1,8 +1,9 @@
 import { useState } from "react";
 import { json } from "@remix-run/node";
 import { useActionData, Form, useNavigation, Link } from "@remix-run/react";
-import type { ActionFunction, MetaFunction } from "@remix-run/node";
+import type { ActionFunction, LoaderFunction, MetaFunction } from "@remix-run/node";
 import dayjs from "dayjs";
+import { API_BASE_URL, authHeaders, requireAccessToken, requireLogin } from "~/session.server";
 
 export const meta: MetaFunction = () => {
   return [
@@ -11,16 +12,39 @@ export const meta: MetaFunction = () => {
   ];
 };
 
+type CardStatus =
+  | "issued"
+  | "accepted"
+  | "depleted"
+  | "expired"
+  | "suspended"
+  | "cancelled"
+  | "replaced";
+
+// Only issued and accepted cards can still be accepted or used
+const isLive = (status: CardStatus) => status === "issued" || status === "accepted";
+
+type Money = {
+  amount: number; // In the currency's minor unit, e.g. cents
+  currency: string;
+};
+
+const formatMoney = ({ amount, currency }: Money) => {
+  const format = new Intl.NumberFormat("en-US", { style: "currency", currency });
+  const digits = format.resolvedOptions().maximumFractionDigits ?? 2;
+  return format.format(amount / 10 ** digits);
+};
+
 type GiftCard = {
   id: string;
   issuer_name: string;
   recipient_name: string;
   recipient_phone: string;
-  balance: number;
-  initial_balance: number;
+  balance: Money;
+  initial_balance: Money;
   expiration_date: string;
-  is_accepted: boolean;
-  is_active: boolean;
+  status: CardStatus;
+  accepted_at: string | null;
   created_at: string;
 };
 
@@ -33,7 +57,14 @@ type ActionData = {
   searchPerformed?: boolean;
 };
 
+// Searching needs a login; recipients only find the cards sent to their own number
+export const loader: LoaderFunction = async ({ request }) => {
+  await requireAccessToken(request);
+  return null;
+};
+
 export const action: ActionFunction = async ({ request }) => {
+  const token = await requireAccessToken(request);
   const formData = await request.formData();
   const phone = formData.get("phone") as string;
   
@@ -46,7 +77,13 @@ export const action: ActionFunction = async ({ request }) => {
   
   try {
     // API call to search for gift cards
-    const response = await fetch(`http://localhost:8080/api/gift-cards/by-recipient/${encodeURIComponent(phone)}`);
+    const response = await fetch(`${API_BASE_URL}/api/gift-cards/by-recipient/${encodeURIComponent(phone)}`, {
+      headers: authHeaders(token),
+    });
+    if (response.status === 401) {
+      return requireLogin(request);
+    }
+    
     const data = await response.json();
     
     if (!response.ok) {
@@ -136,10 +173,10 @@ export default function SearchGiftCards() {
           {actionData.giftCards && actionData.giftCards.length > 0 ? (
             <div className="space-y-4">
               {actionData.giftCards.map((card) => {
-                const balance = (card.balance / 100).toFixed(2);
+                const balance = formatMoney(card.balance);
                 const expirationDate = dayjs(card.expiration_date);
                 const isExpired = expirationDate.isBefore(dayjs());
-                const isActive = card.is_active && card.is_accepted && !isExpired;
+                const isActive = isLive(card.status) && card.accepted_at !== null && !isExpired;
                 
                 return (
                   <div 
@@ -161,7 +198,7 @@ export default function SearchGiftCards() {
                           From: {card.issuer_name}
                         </p>
                         <p className="text-sm text-gray-600">
-                          Balance: <span className="font-semibold">${balance}</span>
+                          Balance: <span className="font-semibold">{balance}</span>
                         </p>
                         <p className="text-sm text-gray-600">
                           Expires: {dayjs(card.expiration_date).format('MMM D, YYYY')}
@@ -180,7 +217,7 @@ export default function SearchGiftCards() {
                             ? 'Active'
                             : isExpired
                               ? 'Expired'
-                              : card.is_accepted
+                              : card.accepted_at !== null
                                 ? 'Inactive'
                                 : 'Pending'
                           }
@@ -0,0 +1,113 @@
+import { useActionData, useSearchParams, Form, useNavigation } from "@remix-run/react";
+import { json, redirect } from "@remix-run/node";
+import type { ActionFunction, LoaderFunction, MetaFunction } from "@remix-run/node";
+import { API_BASE_URL, createUserSession, getAccount } from "~/session.server";
+
+export const meta: MetaFunction = () => {
+  return [
+    { title: "Log In - Gift Card System" },
+    { name: "description", content: "Log in to issue, accept and find your gift cards" },
+  ];
+};
+
+// Only paths on this site, so the login page cannot send anyone elsewhere
+const safeRedirect = (to: FormDataEntryValue | null) =>
+  typeof to === "string" && to.startsWith("/") && !to.startsWith("//") ? to : "/";
+
+export const loader: LoaderFunction = async ({ request }) => {
+  if (await getAccount(request)) {
+    return redirect(safeRedirect(new URL(request.url).searchParams.get("redirectTo")));
+  }
+  return null;
+};
+
+export const action: ActionFunction = async ({ request }) => {
+  const formData = await request.formData();
+  const email = formData.get("email") as string;
+  const password = formData.get("password") as string;
+  
+  if (!email || !password) {
+    return json({ 
+      errors: { _form: "Email and password are required" } 
+    }, { status: 400 });
+  }
+  
+  try {
+    const response = await fetch(`${API_BASE_URL}/api/auth/login`, {
+      method: "POST",
+      headers: {
+        "Content-Type": "application/json",
+      },
+      body: JSON.stringify({ email, password }),
+    });
+    
+    const responseData = await response.json();
+    
+    if (!response.ok) {
+      return json({ 
+        errors: { _form: responseData.message || "Failed to log in" } 
+      }, { status: response.status });
+    }
+    
+    return createUserSession(responseData.data, safeRedirect(formData.get("redirectTo")));
+  } catch (error) {
+    return json({ 
+      errors: { _form: "An unexpected error occurred" } 
+    }, { status: 500 });
+  }
+};
+
+export default function Login() {
+  const actionData = useActionData<typeof action>();
+  const navigation = useNavigation();
+  const isSubmitting = navigation.state === "submitting";
+  const [searchParams] = useSearchParams();
+  
+  return (
+    <div className="max-w-md mx-auto">
+      <h1 className="text-3xl font-bold mb-6">Log In</h1>
+      
+      <div className="card p-6">
+        {actionData?.errors?._form && (
+          <div className="bg-red-50 text-red-600 p-4 rounded-md mb-6">
+            {actionData.errors._form}
+          </div>
+        )}
+        
+        <Form method="post" className="space-y-6">
+          <input type="hidden" name="redirectTo" value={searchParams.get("redirectTo") ?? "/"} />
+          <div>
+            <label htmlFor="email" className="form-label">Email</label>
+            <input
+              type="email"
+              id="email"
+              name="email"
+              autoComplete="email"
+              className="form-input"
+              placeholder="Enter your email"
+            />
+          </div>
+          <div>
+            <label htmlFor="password" className="form-label">Password</label>
+            <input
+              type="password"
+              id="password"
+              name="password"
+              autoComplete="current-password"
+              className="form-input"
+              placeholder="Enter your password"
+            />
+          </div>
+          
+          <button
+            type="submit"
+            disabled={isSubmitting}
+            className="btn btn-primary w-full py-3"
+          >
+            {isSubmitting ? "Logging In..." : "Log In"}
+          </button>
+        </Form>
+      </div>
+    </div>
+  );
+}
\ No newline at end of file
@@ -0,0 +1,8 @@
+import { redirect } from "@remix-run/node";
+import type { ActionFunction, LoaderFunction } from "@remix-run/node";
+import { logout } from "~/session.server";
+
+export const action: ActionFunction = async ({ request }) => logout(request);
+
+// Logging out changes state, so it only happens from the header's form
+export const loader: LoaderFunction = async () => redirect("/");
\ No newline at end of file
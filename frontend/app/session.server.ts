@@ -0,0 +1,89 @@
+import { createCookieSessionStorage, redirect } from "@remix-run/node";
+
+export const API_BASE_URL = process.env.API_BASE_URL ?? "http://localhost:8080";
+
+const sessionSecret = process.env.SESSION_SECRET;
+if (!sessionSecret && process.env.NODE_ENV === "production") {
+  throw new Error("SESSION_SECRET must be set");
+}
+
+// The API's access token is kept in a signed, HTTP-only cookie and only ever
+// sent to the API from loaders and actions
+const storage = createCookieSessionStorage({
+  cookie: {
+    name: "gift_card_session",
+    httpOnly: true,
+    path: "/",
+    sameSite: "lax",
+    secure: process.env.NODE_ENV === "production",
+    secrets: [sessionSecret ?? "dev-session-secret"],
+  },
+});
+
+type Account = {
+  name: string;
+  role: "admin" | "issuer" | "merchant" | "recipient";
+};
+
+type AccountSession = {
+  account: Account;
+  tokens: {
+    access_token: string;
+    expires_in: number; // Access token lifetime, in seconds
+  };
+};
+
+// Start a session after logging in, ending when the access token does
+export async function createUserSession({ account, tokens }: AccountSession, redirectTo: string) {
+  const session = await storage.getSession();
+  session.set("accessToken", tokens.access_token);
+  session.set("account", account);
+
+  return redirect(redirectTo, {
+    headers: {
+      "Set-Cookie": await storage.commitSession(session, { maxAge: tokens.expires_in }),
+    },
+  });
+}
+
+export async function getAccount(request: Request): Promise<Account | null> {
+  const session = await storage.getSession(request.headers.get("Cookie"));
+  return session.has("accessToken") ? (session.get("account") as Account) : null;
+}
+
+// Send the visitor to the login page, coming back to where they were after
+export async function requireLogin(request: Request): Promise<never> {
+  const session = await storage.getSession(request.headers.get("Cookie"));
+  const url = new URL(request.url);
+  const params = new URLSearchParams({ redirectTo: url.pathname + url.search });
+
+  throw redirect(`/login?${params}`, {
+    headers: {
+      "Set-Cookie": await storage.destroySession(session),
+    },
+  });
+}
+
+// The API token of the logged-in account, or a redirect to the login page
+export async function requireAccessToken(request: Request): Promise<string> {
+  const session = await storage.getSession(request.headers.get("Cookie"));
+  const token = session.get("accessToken");
+
+  return typeof token === "string" ? token : requireLogin(request);
+}
+
+// Headers for an API call made on behalf of the logged-in account
+export const authHeaders = (token: string) => ({
+  "Content-Type": "application/json",
+  Authorization: `Bearer ${token}`,
+});
+
+export async function logout(request: Request) {
+  const session = await storage.getSession(request.headers.get("Cookie"));
+
+  return redirect("/", {
+    headers: {
+      "Set-Cookie": await storage.destroySession(session),
+    },
+  });
+}
\ No newline at end of file